@@ -0,0 +1,181 @@
+use chrono::{DateTime, Local, TimeDelta};
+
+use crate::diagnostics::Diagnostics;
+use crate::indoor::Sample;
+use crate::point::Point;
+
+
+/// Totals of a lap or session as recorded by the device.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    /// The sport of the lap or session, which differs between the legs of a multisport activity.
+    pub sport: Option<String>,
+
+    pub sub_sport: Option<String>,
+
+    /// The predominant swimming stroke (e.g. `freestyle` or `breaststroke`) of a swimming lap or
+    /// session.
+    pub swim_stroke: Option<String>,
+
+    pub start_time: Option<DateTime<Local>>,
+    pub end_time: Option<DateTime<Local>>,
+    pub total_elapsed_time_s: Option<f64>,
+    pub total_timer_time_s: Option<f64>,
+    pub total_distance_m: Option<f64>,
+    pub total_calories_kcal: Option<u64>,
+    pub avg_heart_rate_bpm: Option<u64>,
+    pub max_heart_rate_bpm: Option<u64>,
+}
+
+
+/// An interval during which the timer was stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pause {
+    /// The time at which the timer was stopped.
+    pub start_time: DateTime<Local>,
+
+    /// The time at which the timer was started again.
+    pub end_time: DateTime<Local>,
+
+    /// The position of the last point recorded before the timer was stopped.
+    pub position_deg: geo::Point<f64>,
+}
+impl Pause {
+    /// The duration of the pause, in seconds.
+    pub fn duration_s(&self) -> f64 {
+        (self.end_time - self.start_time).num_milliseconds() as f64 / 1000.0
+    }
+}
+
+
+/// Information about the kind of activity and the device that recorded it.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Metadata {
+    /// The sport, e.g. `walking` or `cycling`.
+    pub sport: Option<String>,
+
+    /// The more specific kind of sport, e.g. `trail`.
+    pub sub_sport: Option<String>,
+
+    /// The manufacturer of the recording device.
+    pub manufacturer: Option<String>,
+
+    /// The product name of the recording device (or the application that created the file).
+    pub product: Option<String>,
+
+    pub serial_number: Option<u64>,
+
+    /// The firmware version of the recording device.
+    pub software_version: Option<String>,
+
+    /// The name of the place at which the track starts, if it has been geocoded.
+    pub start_place: Option<String>,
+
+    /// The name of the place at which the track ends, if it has been geocoded.
+    pub end_place: Option<String>,
+}
+
+
+/// Everything read from an input file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Activity {
+    /// The recorded track, split into lines at discontinuities.
+    pub lines: Vec<Vec<Point>>,
+
+    /// The measurements of the records without positions, in order. Activities recorded indoors
+    /// (e.g. on trainers and treadmills) consist of nothing else.
+    pub samples: Vec<Sample>,
+
+    /// The laps, in order.
+    pub laps: Vec<Summary>,
+
+    /// The sessions, in order. Most files contain exactly one.
+    pub sessions: Vec<Summary>,
+
+    /// The pauses during which the timer was stopped, in order. Pauses at the end of the activity,
+    /// after which the timer was not started again, are not included.
+    pub pauses: Vec<Pause>,
+
+    pub metadata: Metadata,
+
+    /// What was skipped while reading the activity and dropped while processing it.
+    pub diagnostics: Diagnostics,
+}
+impl Activity {
+    pub fn from_lines(lines: Vec<Vec<Point>>) -> Self {
+        Self {
+            lines,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the timestamp of the first point of the activity that has one or, if there is none,
+    /// the timestamp of the first sample.
+    pub fn start_time(&self) -> Option<DateTime<Local>> {
+        self.lines.iter()
+            .flatten()
+            .find_map(|p| p.timestamp)
+            .or_else(|| self.samples.iter().find_map(|s| s.timestamp))
+    }
+
+    /// Moves every timestamp of the activity (of the points, samples, laps, sessions and pauses) by the
+    /// given offset.
+    pub fn shift_times(&mut self, offset: TimeDelta) {
+        for point in self.lines.iter_mut().flatten() {
+            point.timestamp = point.timestamp.map(|t| t + offset);
+            point.unix_timestamp = point.unix_timestamp.map(|t| t + offset.num_milliseconds() as f64 / 1000.0);
+        }
+        for sample in &mut self.samples {
+            sample.timestamp = sample.timestamp.map(|t| t + offset);
+            sample.unix_timestamp = sample.unix_timestamp.map(|t| t + offset.num_milliseconds() as f64 / 1000.0);
+        }
+        for summary in self.laps.iter_mut().chain(self.sessions.iter_mut()) {
+            summary.start_time = summary.start_time.map(|t| t + offset);
+            summary.end_time = summary.end_time.map(|t| t + offset);
+        }
+        for pause in &mut self.pauses {
+            pause.start_time += offset;
+            pause.end_time += offset;
+        }
+    }
+
+    /// Returns the number of points in all lines of the activity.
+    pub fn point_count(&self) -> usize {
+        self.lines.iter()
+            .map(|l| l.len())
+            .sum()
+    }
+
+    /// Returns the time at which recording started: the start time of the first session or, if
+    /// there is none, the timestamp of the first point.
+    pub fn recording_start_time(&self) -> Option<DateTime<Local>> {
+        self.sessions.iter()
+            .find_map(|s| s.start_time)
+            .or_else(|| self.start_time())
+    }
+
+    /// Combines multiple activities into one, ordered by their start times. The lines of each
+    /// activity are kept separate, so there is a discontinuity between consecutive activities.
+    /// Activities without timestamps are placed at the end, in their original order. The metadata
+    /// is taken from the first activity.
+    pub fn merge(mut activities: Vec<Activity>) -> Self {
+        activities.sort_by_key(|a| {
+            let start_time = a.start_time();
+            (start_time.is_none(), start_time)
+        });
+
+        let mut merged = Self::default();
+        if let Some(first) = activities.first() {
+            merged.metadata = first.metadata.clone();
+        }
+        for activity in activities {
+            merged.lines.extend(activity.lines);
+            merged.samples.extend(activity.samples);
+            merged.laps.extend(activity.laps);
+            merged.sessions.extend(activity.sessions);
+            merged.pauses.extend(activity.pauses);
+            merged.diagnostics.extend(activity.diagnostics);
+        }
+        merged
+    }
+}
@@ -0,0 +1,302 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use geo::{Distance, Geodesic};
+use geo::prelude::Contains;
+
+use crate::error::WalkingError;
+use crate::point::{DistanceAlgorithm, Point};
+
+
+/// Loads a censor polygon from a file containing one latitude-longitude pair per line.
+///
+/// Underscores within numbers are ignored; `#` starts a comment.
+pub fn load_censor_polygon(path: &Path) -> Result<geo::Polygon<f64>, WalkingError> {
+    let buf = {
+        let mut file = File::open(path)?;
+        let mut buf = String::new();
+        file.read_to_string(&mut buf)?;
+        buf
+    };
+
+    let mut points = Vec::new();
+    for (i, mut line) in buf.split('\n').enumerate() {
+        if let Some(hash_index) = line.find('#') {
+            line = &line[..hash_index];
+        }
+        line = line.trim();
+
+        if line.is_empty() {
+            // empty line (or comment only)
+            continue;
+        }
+
+        let coord_pieces: Vec<&str> = line.split_whitespace().collect();
+        if coord_pieces.len() != 2 {
+            return Err(WalkingError::InvalidPolygon {
+                line_number: i + 1,
+                message: format!("{} coordinate pieces; expected 2", coord_pieces.len()),
+            });
+        }
+
+        let lat: f64 = match coord_pieces[0].replace("_", "").parse() {
+            Ok(l) => l,
+            Err(e) => return Err(WalkingError::InvalidPolygon {
+                line_number: i + 1,
+                message: format!("failed to parse {:?} as a latitude: {}", coord_pieces[0], e),
+            }),
+        };
+        let lon: f64 = match coord_pieces[1].replace("_", "").parse() {
+            Ok(l) => l,
+            Err(e) => return Err(WalkingError::InvalidPolygon {
+                line_number: i + 1,
+                message: format!("failed to parse {:?} as a longitude: {}", coord_pieces[1], e),
+            }),
+        };
+        points.push(geo::Coord { x: lon, y: lat });
+    }
+
+    Ok(geo::Polygon::new(geo::LineString::from(points), vec![]))
+}
+
+
+pub(crate) fn geojson_position(value: &serde_json::Value) -> Result<geo::Coord<f64>, WalkingError> {
+    let position = value.as_array()
+        .ok_or_else(|| WalkingError::InvalidGeoJson(format!("position {} is not an array", value)))?;
+    if position.len() < 2 {
+        return Err(WalkingError::InvalidGeoJson(format!("position {} has fewer than 2 coordinates", value)));
+    }
+    let lon = position[0].as_f64()
+        .ok_or_else(|| WalkingError::InvalidGeoJson(format!("longitude {} is not a number", position[0])))?;
+    let lat = position[1].as_f64()
+        .ok_or_else(|| WalkingError::InvalidGeoJson(format!("latitude {} is not a number", position[1])))?;
+    Ok(geo::Coord { x: lon, y: lat })
+}
+
+fn geojson_polygon(coordinates: &serde_json::Value) -> Result<geo::Polygon<f64>, WalkingError> {
+    let rings = coordinates.as_array()
+        .ok_or_else(|| WalkingError::InvalidGeoJson("polygon coordinates are not an array".to_owned()))?;
+    let mut line_strings = Vec::with_capacity(rings.len());
+    for ring in rings {
+        let positions = ring.as_array()
+            .ok_or_else(|| WalkingError::InvalidGeoJson("polygon ring is not an array".to_owned()))?;
+        let coords: Vec<geo::Coord<f64>> = positions.iter()
+            .map(geojson_position)
+            .collect::<Result<_, _>>()?;
+        line_strings.push(geo::LineString::from(coords));
+    }
+    if line_strings.is_empty() {
+        return Err(WalkingError::InvalidGeoJson("polygon has no rings".to_owned()));
+    }
+
+    // the first ring is the exterior, the others are holes
+    let exterior = line_strings.remove(0);
+    Ok(geo::Polygon::new(exterior, line_strings))
+}
+
+/// Collects the polygons from a GeoJSON object, descending into features, feature collections and
+/// geometry collections. Geometries other than polygons are ignored.
+fn collect_geojson_polygons(object: &serde_json::Value, polygons: &mut Vec<geo::Polygon<f64>>) -> Result<(), WalkingError> {
+    let object_type = object.get("type")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| WalkingError::InvalidGeoJson("object without type".to_owned()))?;
+    match object_type {
+        "FeatureCollection" => {
+            let features = object.get("features")
+                .and_then(|f| f.as_array())
+                .ok_or_else(|| WalkingError::InvalidGeoJson("FeatureCollection without features".to_owned()))?;
+            for feature in features {
+                collect_geojson_polygons(feature, polygons)?;
+            }
+        },
+        "Feature" => {
+            match object.get("geometry") {
+                None|Some(serde_json::Value::Null) => {},
+                Some(geometry) => collect_geojson_polygons(geometry, polygons)?,
+            }
+        },
+        "GeometryCollection" => {
+            let geometries = object.get("geometries")
+                .and_then(|g| g.as_array())
+                .ok_or_else(|| WalkingError::InvalidGeoJson("GeometryCollection without geometries".to_owned()))?;
+            for geometry in geometries {
+                collect_geojson_polygons(geometry, polygons)?;
+            }
+        },
+        "Polygon" => {
+            let coordinates = object.get("coordinates")
+                .ok_or_else(|| WalkingError::InvalidGeoJson("Polygon without coordinates".to_owned()))?;
+            polygons.push(geojson_polygon(coordinates)?);
+        },
+        "MultiPolygon" => {
+            let coordinates = object.get("coordinates")
+                .and_then(|c| c.as_array())
+                .ok_or_else(|| WalkingError::InvalidGeoJson("MultiPolygon without coordinates".to_owned()))?;
+            for polygon_coordinates in coordinates {
+                polygons.push(geojson_polygon(polygon_coordinates)?);
+            }
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+
+/// Loads censor polygons from a GeoJSON file. All Polygon and MultiPolygon geometries (including
+/// those within features and collections) are loaded, holes included.
+pub fn load_censor_geojson(path: &Path) -> Result<Vec<geo::Polygon<f64>>, WalkingError> {
+    let file = File::open(path)?;
+    let document: serde_json::Value = serde_json::from_reader(std::io::BufReader::new(file))?;
+    let mut polygons = Vec::new();
+    collect_geojson_polygons(&document, &mut polygons)?;
+    Ok(polygons)
+}
+
+
+/// Loads censor polygons from a file, which is read as GeoJSON if it has a `.geojson` or `.json`
+/// extension and as a latitude-longitude list (see [`load_censor_polygon`]) otherwise.
+pub fn load_censor_file(path: &Path) -> Result<Vec<geo::Polygon<f64>>, WalkingError> {
+    let extension = path.extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("geojson")|Some("json") => load_censor_geojson(path),
+        _ => Ok(vec![load_censor_polygon(path)?]),
+    }
+}
+
+
+/// A circular area around a center point, e.g. `--censor-circle 48.2082,16.3738,500`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CensorCircle {
+    pub center_deg: geo::Point<f64>,
+    pub radius_m: f64,
+}
+impl CensorCircle {
+    pub fn contains(&self, point: &geo::Point<f64>) -> bool {
+        Geodesic::distance(self.center_deg, *point) <= self.radius_m
+    }
+}
+impl FromStr for CensorCircle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pieces: Vec<&str> = s.split(',').collect();
+        if pieces.len() != 3 {
+            return Err(format!("censor circle {:?} is not in the format LAT,LON,RADIUS_M", s));
+        }
+        let lat: f64 = pieces[0].trim().parse()
+            .map_err(|e| format!("failed to parse {:?} as a latitude: {}", pieces[0], e))?;
+        let lon: f64 = pieces[1].trim().parse()
+            .map_err(|e| format!("failed to parse {:?} as a longitude: {}", pieces[1], e))?;
+        let radius_m: f64 = pieces[2].trim().parse()
+            .map_err(|e| format!("failed to parse {:?} as a radius: {}", pieces[2], e))?;
+        if radius_m < 0.0 {
+            return Err(format!("censor circle radius {} is negative", radius_m));
+        }
+        Ok(Self {
+            center_deg: geo::Point::new(lon, lat),
+            radius_m,
+        })
+    }
+}
+
+
+/// An area within which points are censored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CensorArea {
+    Polygon(geo::Polygon<f64>),
+    Circle(CensorCircle),
+}
+impl CensorArea {
+    pub fn contains(&self, point: &geo::Point<f64>) -> bool {
+        match self {
+            Self::Polygon(polygon) => polygon.contains(point),
+            Self::Circle(circle) => circle.contains(point),
+        }
+    }
+}
+
+
+/// What happens to a line where points have been censored.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum CensorMode {
+    /// The points before and after the censored region are connected as if nothing happened.
+    #[default]
+    Skip,
+
+    /// The line is split at the censored region, like when the timer is stopped.
+    Split,
+
+    /// The points before and after the censored region are connected and the connecting segment
+    /// is flagged as censored.
+    Bridge,
+}
+
+
+/// Removes all points that lie within any of the censor areas, handling the resulting gaps
+/// according to the mode. Lines left without points are removed as well.
+pub fn censor_lines(lines: &mut Vec<Vec<Point>>, censor_areas: &[CensorArea], mode: CensorMode) {
+    let mut censored_lines = Vec::with_capacity(lines.len());
+    for line in lines.drain(..) {
+        let mut censored_line = Vec::with_capacity(line.len());
+        let mut in_gap = false;
+        for mut point in line {
+            if censor_areas.iter().any(|ca| ca.contains(&point.coordinates_deg)) {
+                in_gap = true;
+                continue;
+            }
+
+            if in_gap && !censored_line.is_empty() {
+                match mode {
+                    CensorMode::Skip => {},
+                    CensorMode::Split => censored_lines.push(std::mem::take(&mut censored_line)),
+                    CensorMode::Bridge => point.follows_censored_gap = true,
+                }
+            }
+            in_gap = false;
+            censored_line.push(point);
+        }
+        if !censored_line.is_empty() {
+            censored_lines.push(censored_line);
+        }
+    }
+    *lines = censored_lines;
+}
+
+
+/// Removes the points within the given distances (in meters) from the start and the end of the
+/// track. Distance is measured along the track; gaps between lines are not counted. Lines left
+/// without points are removed.
+pub fn trim_lines(lines: &mut Vec<Vec<Point>>, start_m: f64, end_m: f64, distance_algorithm: DistanceAlgorithm) {
+    if start_m <= 0.0 && end_m <= 0.0 {
+        return;
+    }
+
+    // calculate the distance from the start for each point
+    let mut running_distances_m = Vec::with_capacity(lines.len());
+    let mut running_distance_m = 0.0;
+    for line in lines.iter() {
+        let mut line_distances_m = Vec::with_capacity(line.len());
+        for (i, point) in line.iter().enumerate() {
+            if i > 0 {
+                running_distance_m += line[i-1].distance_m(point, distance_algorithm);
+            }
+            line_distances_m.push(running_distance_m);
+        }
+        running_distances_m.push(line_distances_m);
+    }
+    let total_distance_m = running_distance_m;
+
+    for (line, line_distances_m) in lines.iter_mut().zip(running_distances_m.iter()) {
+        let mut distance_iter = line_distances_m.iter();
+        line.retain(|_| {
+            let dist_m = *distance_iter.next().unwrap();
+            dist_m >= start_m && dist_m <= total_distance_m - end_m
+        });
+    }
+    lines.retain(|l| !l.is_empty());
+}
@@ -0,0 +1,139 @@
+//! Classifying the features of the `points` collection by a measurement and assigning them colors,
+//! for viewers that cannot style features based on their properties.
+
+
+use clap::ValueEnum;
+
+use crate::point::Point;
+
+
+/// The number of classes into which the range of the measurement is divided.
+pub const COLOR_CLASSES: usize = 8;
+
+
+/// The measurement by which the track is colored.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum ColorBy {
+    #[default]
+    Speed,
+    #[value(alias = "hr")]
+    HeartRate,
+    Elevation,
+}
+impl ColorBy {
+    /// The name of the corresponding layer in the HTML viewer.
+    pub fn layer_name(&self) -> &'static str {
+        match self {
+            Self::Speed => "speed",
+            Self::HeartRate => "heart rate",
+            Self::Elevation => "elevation",
+        }
+    }
+
+    /// Returns the measurement of the point, in metric units.
+    pub fn value(&self, point: &Point) -> Option<f64> {
+        match self {
+            Self::Speed => point.speed_km_per_h,
+            Self::HeartRate => point.heart_rate_bpm.map(|hr| hr as f64),
+            Self::Elevation => point.elevation_m,
+        }
+        .filter(|v| v.is_finite())
+    }
+
+    /// Returns the measurement of the segment between the points, which is the mean of their
+    /// measurements (or the measurement of one of them if the other has none).
+    pub fn segment_value(&self, point1: &Point, point2: &Point) -> Option<f64> {
+        match (self.value(point1), self.value(point2)) {
+            (Some(v1), Some(v2)) => Some((v1 + v2) / 2.0),
+            (v1, v2) => v1.or(v2),
+        }
+    }
+}
+
+
+/// The sequence of colors onto which the classes are mapped, from the lowest to the highest.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum ColorScale {
+    /// From dark blue through green to yellow; perceptually uniform and readable with color vision
+    /// deficiencies.
+    #[default]
+    Viridis,
+
+    /// From dark blue through magenta to yellow.
+    Plasma,
+
+    /// From black through purple and red to pale yellow.
+    Magma,
+}
+impl ColorScale {
+    /// The colors at evenly spaced positions along the scale, as RGB.
+    fn stops(&self) -> &'static [[u8; 3]] {
+        match self {
+            Self::Viridis => &[
+                [0x44, 0x01, 0x54], [0x48, 0x28, 0x78], [0x3e, 0x4a, 0x89], [0x31, 0x68, 0x8e],
+                [0x26, 0x82, 0x8e], [0x1f, 0x9e, 0x89], [0x35, 0xb7, 0x79], [0x6e, 0xce, 0x58],
+                [0xb5, 0xde, 0x2b], [0xfd, 0xe7, 0x25],
+            ],
+            Self::Plasma => &[
+                [0x0d, 0x08, 0x87], [0x46, 0x03, 0x9f], [0x72, 0x01, 0xa8], [0x9c, 0x17, 0x9e],
+                [0xbd, 0x37, 0x86], [0xd8, 0x57, 0x6b], [0xed, 0x79, 0x53], [0xfb, 0x9f, 0x3a],
+                [0xfd, 0xca, 0x26], [0xf0, 0xf9, 0x21],
+            ],
+            Self::Magma => &[
+                [0x00, 0x00, 0x04], [0x18, 0x0f, 0x3d], [0x44, 0x0f, 0x76], [0x72, 0x1f, 0x81],
+                [0x9e, 0x2f, 0x7f], [0xcd, 0x40, 0x71], [0xf1, 0x60, 0x5d], [0xfd, 0x96, 0x68],
+                [0xfe, 0xca, 0x8d], [0xfc, 0xfd, 0xbf],
+            ],
+        }
+    }
+
+    /// Returns the color at the given position along the scale (between 0 and 1) as `#rrggbb`.
+    pub fn color_hex(&self, position: f64) -> String {
+        let stops = self.stops();
+        let scaled = position.clamp(0.0, 1.0) * (stops.len() - 1) as f64;
+        let index = (scaled.floor() as usize).min(stops.len() - 2);
+        let fraction = scaled - index as f64;
+        let [r, g, b] = [0, 1, 2].map(|c| {
+            let (c1, c2) = (stops[index][c] as f64, stops[index + 1][c] as f64);
+            (c1 + (c2 - c1) * fraction).round() as u8
+        });
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+}
+
+
+/// Divides the range of a measurement into [`COLOR_CLASSES`] classes of equal width and assigns
+/// each of them a color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorClassifier {
+    pub by: ColorBy,
+    pub scale: ColorScale,
+    min: f64,
+    max: f64,
+}
+impl ColorClassifier {
+    /// Creates a classifier covering the given values of the measurement. Returns `None` if there
+    /// are no values.
+    pub fn new<I: IntoIterator<Item = f64>>(by: ColorBy, scale: ColorScale, values: I) -> Option<Self> {
+        let (min, max) = values.into_iter()
+            .fold(None, |range: Option<(f64, f64)>, v| Some(match range {
+                None => (v, v),
+                Some((min, max)) => (min.min(v), max.max(v)),
+            }))?;
+        Some(Self { by, scale, min, max })
+    }
+
+    /// Returns the class of the value, counted from 0 for the lowest values.
+    pub fn class(&self, value: f64) -> usize {
+        if self.max <= self.min {
+            return 0;
+        }
+        let fraction = (value - self.min) / (self.max - self.min);
+        ((fraction * COLOR_CLASSES as f64).floor().max(0.0) as usize).min(COLOR_CLASSES - 1)
+    }
+
+    /// Returns the color of the class as `#rrggbb`.
+    pub fn class_color_hex(&self, class: usize) -> String {
+        self.scale.color_hex(class as f64 / (COLOR_CLASSES - 1) as f64)
+    }
+}
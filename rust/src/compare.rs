@@ -0,0 +1,265 @@
+//! Comparison of two recordings of the same route, e.g. this year's hike and last year's.
+
+
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use crate::error::WalkingError;
+use crate::geojson::write_json;
+use crate::point::{DistanceAlgorithm, GeoPoint, Point};
+use crate::units::Units;
+
+
+/// The default distance between the samples at which the tracks are compared, in meters.
+pub const DEFAULT_COMPARE_INTERVAL_M: f64 = 100.0;
+
+/// The default maximum distance between the tracks at which a sample counts as matched, in meters.
+pub const DEFAULT_COMPARE_TOLERANCE_M: f64 = 50.0;
+
+
+/// The format in which the comparison is output.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum CompareFormat {
+    /// A JSON object with the units and an array of samples.
+    #[default]
+    Json,
+
+    /// CSV with one row per sample.
+    Csv,
+}
+
+
+/// Options influencing how two tracks are compared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CompareOptions {
+    /// The distance along the first track between two samples, in meters.
+    pub interval_m: f64,
+
+    /// The maximum distance between the tracks at which a sample counts as matched, in meters.
+    pub tolerance_m: f64,
+
+    pub distance_algorithm: DistanceAlgorithm,
+
+    /// The units in which distances and speeds are output.
+    pub units: Units,
+}
+impl Default for CompareOptions {
+    fn default() -> Self {
+        Self {
+            interval_m: DEFAULT_COMPARE_INTERVAL_M,
+            tolerance_m: DEFAULT_COMPARE_TOLERANCE_M,
+            distance_algorithm: DistanceAlgorithm::default(),
+            units: Units::default(),
+        }
+    }
+}
+
+
+/// The values of both tracks at one sample. Values of the second track are `None` if no point of
+/// it is within the tolerance of the sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ComparisonSample {
+    /// The distance along the first track, in meters (the gaps between lines are not counted).
+    pub distance_m: f64,
+
+    /// The distance between the sample and the matched point of the second track, in meters.
+    pub offset_m: Option<f64>,
+
+    /// The time since the first sample at which both tracks matched, in seconds.
+    pub elapsed_time_s: [Option<f64>; 2],
+
+    pub speed_km_per_h: [Option<f64>; 2],
+    pub heart_rate_bpm: [Option<u64>; 2],
+}
+impl ComparisonSample {
+    /// How much more time the second track took to get here than the first, in seconds.
+    pub fn time_delta_s(&self) -> Option<f64> {
+        Some(self.elapsed_time_s[1]? - self.elapsed_time_s[0]?)
+    }
+
+    /// How much faster the second track was here than the first, in km/h.
+    pub fn speed_delta_km_per_h(&self) -> Option<f64> {
+        Some(self.speed_km_per_h[1]? - self.speed_km_per_h[0]?)
+    }
+
+    /// How much higher the heart rate of the second track was here than that of the first, in BPM.
+    pub fn heart_rate_delta_bpm(&self) -> Option<i64> {
+        Some(self.heart_rate_bpm[1]? as i64 - self.heart_rate_bpm[0]? as i64)
+    }
+}
+
+
+/// Returns the points of all lines along with their running distance, not counting the gaps
+/// between lines.
+fn points_with_distances(lines: &[Vec<Point>], distance_algorithm: DistanceAlgorithm) -> Vec<(&Point, f64)> {
+    let mut ret = Vec::new();
+    let mut running_dist_m = 0.0;
+    for line in lines {
+        for (i, point) in line.iter().enumerate() {
+            if i > 0 {
+                running_dist_m += line[i - 1].distance_m(point, distance_algorithm);
+            }
+            ret.push((point, running_dist_m));
+        }
+    }
+    ret
+}
+
+
+/// Compares two tracks along the first one.
+///
+/// The first track is sampled every `options.interval_m` meters, interpolating the position and
+/// time between its points. Each sample is matched to the nearest point of the second track,
+/// searching forward from the previous match along a window of a few intervals so that routes
+/// passing the same place twice are matched in order; after a sample without a match within the
+/// tolerance, the window grows until the tracks meet again. Elapsed times of both tracks count from
+/// the first sample at which they matched.
+pub fn compare_tracks(first: &[Vec<Point>], second: &[Vec<Point>], options: &CompareOptions) -> Vec<ComparisonSample> {
+    let first_points = points_with_distances(first, options.distance_algorithm);
+    let second_points = points_with_distances(second, options.distance_algorithm);
+    let total_dist_m = match first_points.last() {
+        Some((_, d)) => *d,
+        None => return Vec::new(),
+    };
+
+    let mut samples = Vec::new();
+    let mut segment_index = 0;
+    let mut last_match: Option<usize> = None;
+    let mut misses = 0;
+    let mut start_times: Option<(f64, f64)> = None;
+    let mut sample_index = 0;
+    loop {
+        let sample_dist_m = sample_index as f64 * options.interval_m;
+        if sample_dist_m > total_dist_m {
+            break;
+        }
+        sample_index += 1;
+
+        // find the segment of the first track containing the sample
+        while segment_index + 1 < first_points.len() - 1 && first_points[segment_index + 1].1 <= sample_dist_m {
+            segment_index += 1;
+        }
+        let (point1, dist1_m) = first_points[segment_index];
+        let (point2, dist2_m) = first_points[(segment_index + 1).min(first_points.len() - 1)];
+        let fraction = if dist2_m > dist1_m { (sample_dist_m - dist1_m) / (dist2_m - dist1_m) } else { 0.0 };
+        let lerp = |v1: f64, v2: f64| v1 + (v2 - v1) * fraction;
+        let mut sample_point = Point::new(geo::Point::new(
+            lerp(point1.longitude(), point2.longitude()),
+            lerp(point1.latitude(), point2.latitude()),
+        ));
+        sample_point.unix_timestamp = match (point1.unix_timestamp, point2.unix_timestamp) {
+            (Some(t1), Some(t2)) => Some(lerp(t1, t2)),
+            (t1, t2) => t1.or(t2),
+        };
+        let nearer_point = if fraction < 0.5 { point1 } else { point2 };
+
+        let mut sample = ComparisonSample {
+            distance_m: sample_dist_m,
+            speed_km_per_h: [nearer_point.speed_km_per_h, None],
+            heart_rate_bpm: [nearer_point.heart_rate_bpm, None],
+            ..ComparisonSample::default()
+        };
+
+        // find the nearest point of the second track within the search window
+        let candidates = match last_match {
+            None => 0..second_points.len(),
+            Some(from_index) => {
+                let window_m = (misses + 2) as f64 * options.interval_m + options.tolerance_m;
+                let max_dist_m = second_points[from_index].1 + window_m;
+                let to_index = second_points[from_index..].iter()
+                    .position(|(_, d)| *d > max_dist_m)
+                    .map(|i| from_index + i)
+                    .unwrap_or(second_points.len());
+                from_index..to_index.max(from_index + 1)
+            },
+        };
+        let nearest = candidates
+            .map(|j| (j, sample_point.distance_m(second_points[j].0, options.distance_algorithm)))
+            .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+        match nearest {
+            Some((j, offset_m)) if offset_m <= options.tolerance_m => {
+                let matched = second_points[j].0;
+                last_match = Some(j);
+                misses = 0;
+                sample.offset_m = Some(offset_m);
+                sample.speed_km_per_h[1] = matched.speed_km_per_h;
+                sample.heart_rate_bpm[1] = matched.heart_rate_bpm;
+                if let (Some(t1), Some(t2)) = (sample_point.unix_timestamp, matched.unix_timestamp) {
+                    let (start1, start2) = *start_times.get_or_insert((t1, t2));
+                    sample.elapsed_time_s = [Some(t1 - start1), Some(t2 - start2)];
+                }
+            },
+            _ => {
+                misses += 1;
+                if let (Some(t1), Some((start1, _))) = (sample_point.unix_timestamp, start_times) {
+                    sample.elapsed_time_s[0] = Some(t1 - start1);
+                }
+            },
+        }
+        samples.push(sample);
+    }
+    samples
+}
+
+
+/// Converts a sample into a JSON object. Values of the first and second track are suffixed with
+/// `_a` and `_b`; the deltas are the values of the second track minus those of the first.
+pub fn sample_to_json(sample: &ComparisonSample, units: Units) -> Map<String, Value> {
+    let mut ret = Map::new();
+    ret.insert("distance".to_owned(), units.distance(sample.distance_m).into());
+    ret.insert("offset".to_owned(), sample.offset_m.map(|o| units.distance(o)).into());
+    ret.insert("time_a".to_owned(), sample.elapsed_time_s[0].into());
+    ret.insert("time_b".to_owned(), sample.elapsed_time_s[1].into());
+    ret.insert("time_delta".to_owned(), sample.time_delta_s().into());
+    ret.insert("speed_a".to_owned(), sample.speed_km_per_h[0].map(|s| units.speed(s)).into());
+    ret.insert("speed_b".to_owned(), sample.speed_km_per_h[1].map(|s| units.speed(s)).into());
+    ret.insert("speed_delta".to_owned(), sample.speed_delta_km_per_h().map(|s| units.speed(s)).into());
+    ret.insert("heart_rate_a".to_owned(), sample.heart_rate_bpm[0].into());
+    ret.insert("heart_rate_b".to_owned(), sample.heart_rate_bpm[1].into());
+    ret.insert("heart_rate_delta".to_owned(), sample.heart_rate_delta_bpm().into());
+    ret
+}
+
+
+/// Writes the comparison as a JSON object with the members `units` and `samples` (pretty-printed
+/// unless `compact` is set).
+pub fn write_comparison_json<W: Write>(writer: W, samples: &[ComparisonSample], units: Units, compact: bool) -> Result<(), WalkingError> {
+    let samples_json: Vec<Value> = samples.iter()
+        .map(|s| Value::Object(sample_to_json(s, units)))
+        .collect();
+    let mut document = Map::new();
+    document.insert("units".to_owned(), units.names_to_json().into());
+    document.insert("samples".to_owned(), samples_json.into());
+    let mut document = Value::Object(document);
+    document.sort_all_objects();
+    if compact {
+        write_json(&document, writer, serde_json::ser::CompactFormatter)
+    } else {
+        write_json(&document, writer, serde_json::ser::PrettyFormatter::new())
+    }
+}
+
+
+/// Writes the comparison as CSV with one row per sample and the same columns as the members of
+/// [`sample_to_json`]; missing values are left empty.
+pub fn write_comparison_csv<W: Write>(mut writer: W, samples: &[ComparisonSample], units: Units) -> Result<(), WalkingError> {
+    const COLUMNS: [&str; 11] = [
+        "distance", "offset", "time_a", "time_b", "time_delta", "speed_a", "speed_b", "speed_delta",
+        "heart_rate_a", "heart_rate_b", "heart_rate_delta",
+    ];
+    writeln!(writer, "{}", COLUMNS.join(","))?;
+    for sample in samples {
+        let object = sample_to_json(sample, units);
+        let cells: Vec<String> = COLUMNS.iter()
+            .map(|c| match &object[*c] {
+                Value::Null => String::new(),
+                v => v.to_string(),
+            })
+            .collect();
+        writeln!(writer, "{}", cells.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,178 @@
+//! Default options read from a TOML configuration file.
+//!
+//! Each top-level key is the name of a command-line option with underscores instead of dashes,
+//! e.g.:
+//!
+//! ```toml
+//! units = "imperial"
+//! precision = 6
+//! output_as_files = true
+//! output_dir = "~/walks"
+//! censor_polygon = ["~/.config/walking/home.txt"]
+//! properties = ["speed", "elevation", "heart_rate"]
+//! ```
+//!
+//! Booleans stand for flags, which are given if `true`; arrays stand for options that are given
+//! once per element. Strings starting with `~/` are relative to the home directory.
+//!
+//! Sets of censor areas can be defined as named profiles and activated using `--censor-profile`:
+//!
+//! ```toml
+//! [censor_profiles.home]
+//! polygon = "~/.config/walking/home.txt"
+//! circle = ["48.2082,16.3738,200"]
+//!
+//! [censor_profiles.work]
+//! geojson = ["~/.config/walking/work.geojson"]
+//! ```
+
+
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::censor::CensorCircle;
+use crate::error::WalkingError;
+use crate::toml::{self, Table, Value};
+
+
+/// The name of the section containing the censor profiles.
+pub const CENSOR_PROFILES_SECTION: &str = "censor_profiles";
+
+
+/// A named set of censor areas.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CensorProfile {
+    /// Files of censor polygons, as given to `--censor-polygon`.
+    pub polygons: Vec<PathBuf>,
+
+    /// GeoJSON files of censor polygons, as given to `--censor-geojson`.
+    pub geojsons: Vec<PathBuf>,
+
+    pub circles: Vec<CensorCircle>,
+}
+
+
+/// Returns the path of the default configuration file, `walking/config.toml` in the user's
+/// configuration directory (`$XDG_CONFIG_HOME`, falling back to `~/.config`). Returns `None` if
+/// neither that nor the home directory is known.
+pub fn default_config_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".config")))?;
+    Some(config_dir.join("walking").join("config.toml"))
+}
+
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+}
+
+
+/// Replaces a leading `~/` by the home directory.
+fn expand_home(text: &str) -> String {
+    match (text.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).display().to_string(),
+        _ => text.to_owned(),
+    }
+}
+
+
+/// The contents of a configuration file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub path: PathBuf,
+    pub table: Table,
+}
+impl Config {
+    /// Reads and parses the configuration file at the given path.
+    pub fn load(path: &Path) -> Result<Self, WalkingError> {
+        let text = std::fs::read_to_string(path)?;
+        let table = toml::parse(&text)
+            .map_err(|e| WalkingError::Config(e.to_string()))?;
+        Ok(Self {
+            path: path.to_owned(),
+            table,
+        })
+    }
+
+    /// Converts the options of the configuration into command-line arguments, skipping those with
+    /// the given keys.
+    pub fn to_args(&self, ignored_keys: &[&str]) -> Result<Vec<String>, WalkingError> {
+        let mut args = Vec::new();
+        for (key, value) in &self.table {
+            if ignored_keys.contains(&key.as_str()) {
+                continue;
+            }
+            let option = format!("--{}", key.replace('_', "-"));
+            let scalar_arg = |value: &Value| match value {
+                Value::String(s) => Ok(expand_home(s)),
+                Value::Integer(i) => Ok(i.to_string()),
+                Value::Float(f) => Ok(f.to_string()),
+                other => Err(WalkingError::Config(format!("{:?} cannot contain {}", key, other.type_name()))),
+            };
+            match value {
+                Value::Boolean(true) => args.push(option),
+                Value::Boolean(false) => {},
+                Value::Array(elements) => {
+                    for element in elements {
+                        args.push(option.clone());
+                        args.push(scalar_arg(element)?);
+                    }
+                },
+                Value::Table(_) if key == CENSOR_PROFILES_SECTION => {},
+                Value::Table(_) => return Err(WalkingError::Config(format!("unknown section {:?}", key))),
+                scalar => {
+                    args.push(option);
+                    args.push(scalar_arg(scalar)?);
+                },
+            }
+        }
+        Ok(args)
+    }
+
+    /// Returns the censor profiles defined in the configuration, by name.
+    ///
+    /// Each profile is a table with the optional keys `polygon`, `geojson` and `circle`, whose
+    /// values are a string or an array of strings.
+    pub fn censor_profiles(&self) -> Result<BTreeMap<String, CensorProfile>, WalkingError> {
+        let mut profiles = BTreeMap::new();
+        let section = match self.table.get(CENSOR_PROFILES_SECTION) {
+            Some(Value::Table(t)) => t,
+            Some(other) => return Err(WalkingError::Config(format!("{:?} is {}, not a section", CENSOR_PROFILES_SECTION, other.type_name()))),
+            None => return Ok(profiles),
+        };
+        for (name, value) in section {
+            let table = value.as_table()
+                .ok_or_else(|| WalkingError::Config(format!("censor profile {:?} is not a section", name)))?;
+            let mut profile = CensorProfile::default();
+            for (key, value) in table {
+                let strings: Vec<&str> = match value {
+                    Value::String(s) => vec![s.as_str()],
+                    Value::Array(elements) => elements.iter()
+                        .map(|e| e.as_str())
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| WalkingError::Config(format!("censor profile {:?}: {:?} must only contain strings", name, key)))?,
+                    other => return Err(WalkingError::Config(format!("censor profile {:?}: {:?} cannot be {}", name, key, other.type_name()))),
+                };
+                match key.as_str() {
+                    "polygon" => profile.polygons.extend(strings.iter().map(|s| PathBuf::from(expand_home(s)))),
+                    "geojson" => profile.geojsons.extend(strings.iter().map(|s| PathBuf::from(expand_home(s)))),
+                    "circle" => {
+                        for s in strings {
+                            let circle = s.parse()
+                                .map_err(|e| WalkingError::Config(format!("censor profile {:?}: {}", name, e)))?;
+                            profile.circles.push(circle);
+                        }
+                    },
+                    _ => return Err(WalkingError::Config(format!("censor profile {:?}: unknown key {:?}", name, key))),
+                }
+            }
+            profiles.insert(name.clone(), profile);
+        }
+        Ok(profiles)
+    }
+}
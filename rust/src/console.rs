@@ -0,0 +1,242 @@
+//! Output of error messages, log messages and progress to the console (standard error).
+//!
+//! All messages go through this module so that they do not garble the progress display: the
+//! progress line is cleared before a message is written and drawn again afterwards. Progress is
+//! only displayed if standard error is a terminal that understands the escape sequence clearing a
+//! line and output has not been quietened.
+
+
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::LevelFilter;
+
+
+/// The minimum time between two updates of the progress line, so that reading many records does
+/// not spend its time drawing.
+const PROGRESS_REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The width of the progress bar, in characters.
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+
+/// How much is output to the console.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    /// Only errors are output.
+    Quiet,
+
+    /// Errors, warnings and the progress are output.
+    #[default]
+    Normal,
+
+    /// Informational messages are output as well.
+    Verbose,
+}
+
+
+/// The progress of processing a batch of files.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+struct Progress {
+    total_files: usize,
+    finished_files: usize,
+    failed_files: usize,
+    records_read: u64,
+}
+impl Progress {
+    /// Returns the progress line after the given time since the start, estimating the time left
+    /// from the files finished so far.
+    fn line(&self, elapsed: Duration) -> String {
+        let filled = (PROGRESS_BAR_WIDTH * self.finished_files).checked_div(self.total_files)
+            .map_or(PROGRESS_BAR_WIDTH, |f| f.min(PROGRESS_BAR_WIDTH));
+        let mut line = format!(
+            "[{}{}] {}/{} files, {} records",
+            "=".repeat(filled), " ".repeat(PROGRESS_BAR_WIDTH - filled),
+            self.finished_files, self.total_files, self.records_read,
+        );
+        if self.failed_files > 0 {
+            line.push_str(&format!(", {} failed", self.failed_files));
+        }
+        line.push_str(&format!(", {} elapsed", format_duration(elapsed)));
+        if self.finished_files > 0 && self.finished_files < self.total_files {
+            let left = elapsed.mul_f64((self.total_files - self.finished_files) as f64 / self.finished_files as f64);
+            line.push_str(&format!(", {} left", format_duration(left)));
+        }
+        line
+    }
+}
+
+
+/// Formats a duration as hours, minutes and seconds.
+fn format_duration(duration: Duration) -> String {
+    let total_s = duration.as_secs();
+    format!("{}:{:02}:{:02}", total_s / 3600, (total_s / 60) % 60, total_s % 60)
+}
+
+
+/// Returns the width of the terminal in characters, if it is known from the environment.
+fn terminal_width() -> Option<usize> {
+    std::env::var("COLUMNS").ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+}
+
+
+/// Returns whether the progress can be displayed on standard error, which requires a terminal that
+/// is able to clear lines.
+fn progress_supported() -> bool {
+    io::stderr().is_terminal()
+        && std::env::var_os("TERM").is_none_or(|t| t != "dumb")
+}
+
+
+struct Console {
+    verbosity: Verbosity,
+    progress: Option<Progress>,
+    progress_started: Option<Instant>,
+    last_drawn: Option<Instant>,
+}
+impl Console {
+    /// Writes the message on a line of its own, keeping the progress line below it.
+    fn write_message(&mut self, message: &dyn fmt::Display) {
+        let mut stderr = io::stderr().lock();
+        if self.progress.is_some() {
+            let _ = write!(stderr, "\r\x1b[K");
+        }
+        let _ = writeln!(stderr, "{}", message);
+        self.draw_progress(&mut stderr);
+    }
+
+    fn draw_progress(&mut self, stderr: &mut io::StderrLock) {
+        if let Some(progress) = &self.progress {
+            let elapsed = self.progress_started.map_or(Duration::ZERO, |t| t.elapsed());
+            let mut line = progress.line(elapsed);
+            // a line wrapping around would not be cleared before being drawn again
+            if let Some(width) = terminal_width() {
+                if let Some((i, _)) = line.char_indices().nth(width.saturating_sub(1)) {
+                    line.truncate(i);
+                }
+            }
+            let _ = write!(stderr, "\r\x1b[K{}", line);
+            let _ = stderr.flush();
+            self.last_drawn = Some(Instant::now());
+        }
+    }
+
+    /// Draws the progress line unless it has been drawn very recently.
+    fn update_progress(&mut self) {
+        if self.last_drawn.is_none_or(|t| t.elapsed() >= PROGRESS_REDRAW_INTERVAL) {
+            self.draw_progress(&mut io::stderr().lock());
+        }
+    }
+}
+
+static CONSOLE: Mutex<Console> = Mutex::new(Console {
+    verbosity: Verbosity::Normal,
+    progress: None,
+    progress_started: None,
+    last_drawn: None,
+});
+
+
+fn console() -> MutexGuard<'static, Console> {
+    // a panic while writing a message leaves nothing inconsistent
+    CONSOLE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+
+/// Outputs log messages through the console.
+struct ConsoleLogger;
+impl log::Log for ConsoleLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            console().write_message(&format_args!("[{}] {}", record.level(), record.args()));
+        }
+    }
+
+    fn flush(&self) {}
+}
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+
+/// Sets up the console with the given verbosity: log messages up to `log_level` are output, except
+/// that quiet output is limited to errors and verbose output includes informational messages.
+/// Panic messages are output through the console as well.
+pub fn init(verbosity: Verbosity, log_level: LevelFilter) {
+    console().verbosity = verbosity;
+    std::panic::set_hook(Box::new(|info| console().write_message(info)));
+    let level = match verbosity {
+        Verbosity::Quiet => log_level.min(LevelFilter::Error),
+        Verbosity::Normal => log_level,
+        Verbosity::Verbose => log_level.max(LevelFilter::Info),
+    };
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+
+/// Outputs an error message, which happens regardless of the verbosity.
+pub fn error<D: fmt::Display>(message: D) {
+    console().write_message(&message);
+}
+
+
+/// Starts displaying the progress of processing the given number of files, if standard error is
+/// a terminal and output has not been quietened.
+pub fn start_progress(total_files: usize) {
+    let mut console = console();
+    if console.verbosity == Verbosity::Quiet || !progress_supported() {
+        return;
+    }
+    console.progress = Some(Progress {
+        total_files,
+        ..Progress::default()
+    });
+    console.progress_started = Some(Instant::now());
+    console.draw_progress(&mut io::stderr().lock());
+}
+
+
+/// Counts records that have been read towards the progress.
+pub fn add_records(count: usize) {
+    let mut console = console();
+    match &mut console.progress {
+        Some(progress) => progress.records_read += count as u64,
+        None => return,
+    }
+    console.update_progress();
+}
+
+
+/// Counts a file as finished (successfully or not) towards the progress.
+pub fn finish_file(success: bool) {
+    let mut console = console();
+    match &mut console.progress {
+        Some(progress) => {
+            progress.finished_files += 1;
+            if !success {
+                progress.failed_files += 1;
+            }
+        },
+        None => return,
+    }
+    console.draw_progress(&mut io::stderr().lock());
+}
+
+
+/// Stops displaying the progress, removing the progress line.
+pub fn finish_progress() {
+    let mut console = console();
+    if console.progress.take().is_some() {
+        let _ = write!(io::stderr().lock(), "\r\x1b[K");
+        console.progress_started = None;
+        console.last_drawn = None;
+    }
+}
@@ -0,0 +1,57 @@
+//! Export of the per-point data as CSV for analysis in spreadsheets and data frame libraries.
+
+
+use std::fmt::Display;
+use std::io::Write;
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::point::{DistanceAlgorithm, GeoPoint};
+use crate::timezone::Timezone;
+
+
+/// The header row of the CSV output.
+const HEADER: &str = "line,timestamp,latitude,longitude,elevation_m,heart_rate_bpm,speed_km_per_h,cadence_rpm,temperature_degc,power_w,distance_m";
+
+
+/// Formats an optional value as a CSV cell, leaving the cell empty if the value is missing.
+fn cell<T: Display>(value: Option<T>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => String::new(),
+    }
+}
+
+
+/// Writes one row per point of the activity. The `line` column counts the lines from 0 and changes
+/// at every discontinuity; `distance_m` is the distance travelled along the lines up to the point,
+/// not counting the gaps between lines. Timestamps are output in the given time zone.
+pub fn write_csv<W: Write>(mut writer: W, activity: &Activity, distance_algorithm: DistanceAlgorithm, timezone: &Timezone) -> Result<(), WalkingError> {
+    writeln!(writer, "{}", HEADER)?;
+
+    let mut distance_m = 0.0;
+    for (line_index, line) in activity.lines.iter().enumerate() {
+        for (point_index, point) in line.iter().enumerate() {
+            if point_index > 0 {
+                distance_m += line[point_index - 1].distance_m(point, distance_algorithm);
+            }
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                line_index,
+                cell(point.timestamp.map(|t| timezone.convert(&t).to_rfc3339())),
+                point.latitude(),
+                point.longitude(),
+                cell(point.elevation_m),
+                cell(point.heart_rate_bpm),
+                cell(point.speed_km_per_h),
+                cell(point.cadence_rpm),
+                cell(point.temperature_degc),
+                cell(point.power_w),
+                distance_m,
+            )?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
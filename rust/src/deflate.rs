@@ -0,0 +1,427 @@
+//! Decoding and encoding of DEFLATE-compressed data (RFC 1951), as used by gzip and ZIP files.
+//!
+//! The encoder is simple: it finds matches greedily and only emits blocks with the fixed Huffman
+//! codes, which still compresses JSON documents well.
+
+
+/// The maximum length of a Huffman code in bits.
+const MAX_CODE_LENGTH: usize = 15;
+
+/// The base match lengths of the length symbols 257 to 285.
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+
+/// The number of extra bits following the length symbols 257 to 285.
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// The base distances of the distance symbols 0 to 29.
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+
+/// The number of extra bits following the distance symbols 0 to 29.
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+/// The size of the window within which matches are searched, in bytes.
+const WINDOW_SIZE: usize = 32768;
+
+/// The shortest and longest match that can be encoded.
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+
+/// How many previous occurrences of a hash are examined when looking for a match.
+const MAX_CHAIN_LENGTH: usize = 64;
+
+/// The number of bits of the hash of the next three bytes.
+const HASH_BITS: u32 = 15;
+
+/// The order in which the code lengths of the code length alphabet are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+
+/// Reads a byte slice bit by bit, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    byte_pos: usize,
+    bit_pos: u32,
+}
+impl<'a> BitReader<'a> {
+    fn bit(&mut self) -> Result<u32, String> {
+        let byte = *self.data.get(self.byte_pos)
+            .ok_or_else(|| "unexpected end of compressed data".to_owned())?;
+        let bit = (u32::from(byte) >> self.bit_pos) & 1;
+        self.bit_pos += 1;
+        if self.bit_pos == 8 {
+            self.bit_pos = 0;
+            self.byte_pos += 1;
+        }
+        Ok(bit)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    /// Skips the remaining bits of the current byte.
+    fn align(&mut self) {
+        if self.bit_pos > 0 {
+            self.bit_pos = 0;
+            self.byte_pos += 1;
+        }
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], String> {
+        debug_assert_eq!(self.bit_pos, 0);
+        let end = self.byte_pos + count;
+        let slice = self.data.get(self.byte_pos..end)
+            .ok_or_else(|| "unexpected end of compressed data".to_owned())?;
+        self.byte_pos = end;
+        Ok(slice)
+    }
+}
+
+
+/// A canonical Huffman code, stored as the number of codes of each length and the symbols ordered
+/// by code.
+struct Huffman {
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, String> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for &length in lengths {
+            counts[usize::from(length)] += 1;
+        }
+        counts[0] = 0;
+
+        // reject over-subscribed codes (incomplete codes are allowed)
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = 2 * left - i32::from(count);
+            if left < 0 {
+                return Err("over-subscribed Huffman code".to_owned());
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 2];
+        for length in 1..=MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; usize::from(offsets[MAX_CODE_LENGTH + 1])];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                let offset = &mut offsets[usize::from(length)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        // codes are stored most significant bit first
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for &count in &self.counts[1..] {
+            code |= reader.bit()? as i32;
+            let count = i32::from(count);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_owned())
+    }
+}
+
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    let literal_length = Huffman::new(&lengths).unwrap();
+    let distance = Huffman::new(&[5u8; 30]).unwrap();
+    (literal_length, distance)
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_length_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_length_count > 286 || distance_count > 30 {
+        return Err("too many codes in dynamic block".to_owned());
+    }
+
+    let mut code_length_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_length_lengths)?;
+
+    let mut lengths = Vec::with_capacity(literal_length_count + distance_count);
+    while lengths.len() < literal_length_count + distance_count {
+        let symbol = code_length_code.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last()
+                    .ok_or_else(|| "repeated code length without a previous one".to_owned())?;
+                (previous, 3 + reader.bits(2)?)
+            },
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return Err("invalid code length symbol".to_owned()),
+        };
+        for _ in 0..repeat {
+            lengths.push(value);
+        }
+    }
+    if lengths.len() > literal_length_count + distance_count {
+        return Err("code lengths exceed the announced number of codes".to_owned());
+    }
+    if lengths[256] == 0 {
+        return Err("dynamic block without end-of-block code".to_owned());
+    }
+
+    let literal_length = Huffman::new(&lengths[..literal_length_count])?;
+    let distance = Huffman::new(&lengths[literal_length_count..])?;
+    Ok((literal_length, distance))
+}
+
+fn inflate_block(reader: &mut BitReader, output: &mut Vec<u8>, literal_length: &Huffman, distance: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = literal_length.decode(reader)?;
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let length_index = usize::from(symbol - 257);
+                let length = usize::from(LENGTH_BASES[length_index])
+                    + reader.bits(LENGTH_EXTRA_BITS[length_index])? as usize;
+
+                let distance_index = usize::from(distance.decode(reader)?);
+                if distance_index >= DISTANCE_BASES.len() {
+                    return Err("invalid distance symbol".to_owned());
+                }
+                let dist = usize::from(DISTANCE_BASES[distance_index])
+                    + reader.bits(DISTANCE_EXTRA_BITS[distance_index])? as usize;
+                if dist > output.len() {
+                    return Err("distance reaches back before the start of the data".to_owned());
+                }
+
+                // the source and destination may overlap, so copy byte by byte
+                let start = output.len() - dist;
+                for i in 0..length {
+                    output.push(output[start + i]);
+                }
+            },
+            _ => return Err("invalid literal/length symbol".to_owned()),
+        }
+    }
+}
+
+
+/// Decompresses raw DEFLATE data. Returns the decompressed data and the number of bytes of `data`
+/// that were consumed; anything after the final block is left alone.
+pub fn inflate(data: &[u8]) -> Result<(Vec<u8>, usize), String> {
+    let mut reader = BitReader { data, byte_pos: 0, bit_pos: 0 };
+    let mut output = Vec::new();
+
+    loop {
+        let is_final = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let header = reader.bytes(4)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                let inverted_length = u16::from_le_bytes([header[2], header[3]]);
+                if length != !inverted_length {
+                    return Err("stored block length check failed".to_owned());
+                }
+                output.extend_from_slice(reader.bytes(usize::from(length))?);
+            },
+            1 => {
+                let (literal_length, distance) = fixed_codes();
+                inflate_block(&mut reader, &mut output, &literal_length, &distance)?;
+            },
+            2 => {
+                let (literal_length, distance) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, &literal_length, &distance)?;
+            },
+            _ => return Err("invalid block type".to_owned()),
+        }
+        if is_final {
+            break;
+        }
+    }
+
+    reader.align();
+    Ok((output, reader.byte_pos))
+}
+
+
+/// Compresses data into DEFLATE blocks, writing bits least significant bit first.
+///
+/// Blocks are compressed independently of each other; the compressed bytes can be taken out after
+/// every block.
+#[derive(Debug, Default)]
+pub struct Deflater {
+    bit_buffer: u64,
+    bit_count: u32,
+    output: Vec<u8>,
+}
+impl Deflater {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn write_bits(&mut self, value: u32, count: u32) {
+        self.bit_buffer |= u64::from(value) << self.bit_count;
+        self.bit_count += count;
+        while self.bit_count >= 8 {
+            self.output.push(self.bit_buffer as u8);
+            self.bit_buffer >>= 8;
+            self.bit_count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which is stored most significant bit first.
+    fn write_code(&mut self, code: u32, length: u32) {
+        let reversed = code.reverse_bits() >> (32 - length);
+        self.write_bits(reversed, length);
+    }
+
+    fn write_literal_length(&mut self, symbol: u16) {
+        let symbol = u32::from(symbol);
+        match symbol {
+            0..=143 => self.write_code(0x30 + symbol, 8),
+            144..=255 => self.write_code(0x190 + (symbol - 144), 9),
+            256..=279 => self.write_code(symbol - 256, 7),
+            _ => self.write_code(0xC0 + (symbol - 280), 8),
+        }
+    }
+
+    fn write_match(&mut self, length: usize, distance: usize) {
+        let length_index = LENGTH_BASES.iter()
+            .rposition(|&b| usize::from(b) <= length)
+            .unwrap();
+        self.write_literal_length(257 + length_index as u16);
+        self.write_bits(
+            (length - usize::from(LENGTH_BASES[length_index])) as u32,
+            LENGTH_EXTRA_BITS[length_index].into(),
+        );
+
+        let distance_index = DISTANCE_BASES.iter()
+            .rposition(|&b| usize::from(b) <= distance)
+            .unwrap();
+        self.write_code(distance_index as u32, 5);
+        self.write_bits(
+            (distance - usize::from(DISTANCE_BASES[distance_index])) as u32,
+            DISTANCE_EXTRA_BITS[distance_index].into(),
+        );
+    }
+
+    /// Compresses the data as one block with the fixed Huffman codes.
+    pub fn compress_block(&mut self, data: &[u8], is_final: bool) {
+        self.write_bits(u32::from(is_final), 1);
+        self.write_bits(1, 2); // fixed Huffman codes
+
+        let hash = |pos: usize| -> usize {
+            let value = u32::from(data[pos]) << 16 | u32::from(data[pos + 1]) << 8 | u32::from(data[pos + 2]);
+            (value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+        };
+        let mut head = vec![usize::MAX; 1 << HASH_BITS];
+        let mut previous = vec![usize::MAX; WINDOW_SIZE];
+        let insert = |pos: usize, head: &mut [usize], previous: &mut [usize]| {
+            if pos + MIN_MATCH <= data.len() {
+                let h = hash(pos);
+                previous[pos % WINDOW_SIZE] = head[h];
+                head[h] = pos;
+            }
+        };
+
+        let mut pos = 0;
+        while pos < data.len() {
+            let mut best_length = 0;
+            let mut best_distance = 0;
+            if pos + MIN_MATCH <= data.len() {
+                let max_length = MAX_MATCH.min(data.len() - pos);
+                let mut candidate = head[hash(pos)];
+                let mut chain = 0;
+                while candidate != usize::MAX && pos - candidate <= WINDOW_SIZE && chain < MAX_CHAIN_LENGTH {
+                    let length = data[candidate..].iter()
+                        .zip(&data[pos..pos + max_length])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    if length > best_length {
+                        best_length = length;
+                        best_distance = pos - candidate;
+                        if length == max_length {
+                            break;
+                        }
+                    }
+                    let next = previous[candidate % WINDOW_SIZE];
+                    if next == usize::MAX || next >= candidate {
+                        break;
+                    }
+                    candidate = next;
+                    chain += 1;
+                }
+            }
+
+            if best_length >= MIN_MATCH {
+                self.write_match(best_length, best_distance);
+                for p in pos..pos + best_length {
+                    insert(p, &mut head, &mut previous);
+                }
+                pos += best_length;
+            } else {
+                self.write_literal_length(u16::from(data[pos]));
+                insert(pos, &mut head, &mut previous);
+                pos += 1;
+            }
+        }
+
+        self.write_literal_length(256);
+    }
+
+    /// Takes out the bytes that have been completed so far.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Pads the last byte with zero bits and returns the remaining output.
+    pub fn finish(mut self) -> Vec<u8> {
+        if self.bit_count > 0 {
+            self.output.push(self.bit_buffer as u8);
+        }
+        self.output
+    }
+}
+
+
+/// Compresses data into a single DEFLATE block.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut deflater = Deflater::new();
+    deflater.compress_block(data, true);
+    deflater.finish()
+}
@@ -0,0 +1,132 @@
+//! Elevation lookup in a digital elevation model (DEM) stored as SRTM `.hgt` tiles.
+//!
+//! Each tile covers one degree of latitude and longitude and is named after its south-western
+//! corner (e.g. `N48E016.hgt`). It consists of a square grid of big-endian 16-bit elevations in
+//! meters (1201 × 1201 samples for 3 arc-second data, 3601 × 3601 for 1 arc-second data), with
+//! the northernmost row first.
+
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use crate::error::WalkingError;
+use crate::point::{GeoPoint, Point};
+
+
+/// The value marking a sample without data.
+const VOID_SAMPLE: i16 = i16::MIN;
+
+
+/// How elevations from the DEM are combined with the recorded elevations.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum DemMode {
+    /// Replace recorded elevations wherever the DEM has data.
+    #[default]
+    Replace,
+
+    /// Only add elevations to points without a recorded elevation.
+    Fill,
+}
+
+
+struct Tile {
+    samples_per_side: usize,
+    samples: Vec<i16>,
+}
+impl Tile {
+    fn sample(&self, row: usize, col: usize) -> Option<f64> {
+        let value = self.samples[row * self.samples_per_side + col];
+        if value == VOID_SAMPLE {
+            None
+        } else {
+            Some(value.into())
+        }
+    }
+
+    /// Interpolates the elevation at the given offsets (0 to 1) from the south-western corner.
+    fn elevation_m(&self, lat_offset: f64, lon_offset: f64) -> Option<f64> {
+        let last = (self.samples_per_side - 1) as f64;
+        let row_f = (1.0 - lat_offset) * last;
+        let col_f = lon_offset * last;
+        let row = (row_f.floor() as usize).min(self.samples_per_side - 2);
+        let col = (col_f.floor() as usize).min(self.samples_per_side - 2);
+        let row_frac = row_f - row as f64;
+        let col_frac = col_f - col as f64;
+
+        // bilinear interpolation between the four surrounding samples
+        let top = self.sample(row, col)? * (1.0 - col_frac) + self.sample(row, col + 1)? * col_frac;
+        let bottom = self.sample(row + 1, col)? * (1.0 - col_frac) + self.sample(row + 1, col + 1)? * col_frac;
+        Some(top * (1.0 - row_frac) + bottom * row_frac)
+    }
+}
+
+
+fn tile_name(lat_deg: i32, lon_deg: i32) -> String {
+    format!(
+        "{}{:02}{}{:03}.hgt",
+        if lat_deg < 0 { 'S' } else { 'N' }, lat_deg.abs(),
+        if lon_deg < 0 { 'W' } else { 'E' }, lon_deg.abs(),
+    )
+}
+
+fn load_tile(path: &Path) -> Result<Option<Tile>, WalkingError> {
+    let data = match std::fs::read(path) {
+        Ok(d) => d,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let sample_count = data.len() / 2;
+    let samples_per_side = (sample_count as f64).sqrt().round() as usize;
+    if data.len() % 2 != 0 || samples_per_side < 2 || samples_per_side * samples_per_side != sample_count {
+        return Err(WalkingError::InvalidDem(format!("{}: {} bytes is not a square grid of samples", path.display(), data.len())));
+    }
+    let samples = data.chunks_exact(2)
+        .map(|c| i16::from_be_bytes([c[0], c[1]]))
+        .collect();
+    Ok(Some(Tile { samples_per_side, samples }))
+}
+
+
+/// A directory of DEM tiles, which are loaded as they are needed.
+pub struct Dem {
+    directory: PathBuf,
+    tiles: HashMap<(i32, i32), Option<Tile>>,
+}
+impl Dem {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            tiles: HashMap::new(),
+        }
+    }
+
+    /// Returns the elevation at the given coordinates in meters, or `None` if the tile is missing
+    /// or has no data there.
+    pub fn elevation_m(&mut self, lat_deg: f64, lon_deg: f64) -> Result<Option<f64>, WalkingError> {
+        let tile_lat = lat_deg.floor() as i32;
+        let tile_lon = lon_deg.floor() as i32;
+        if !self.tiles.contains_key(&(tile_lat, tile_lon)) {
+            let tile = load_tile(&self.directory.join(tile_name(tile_lat, tile_lon)))?;
+            self.tiles.insert((tile_lat, tile_lon), tile);
+        }
+        Ok(self.tiles[&(tile_lat, tile_lon)].as_ref()
+            .and_then(|t| t.elevation_m(lat_deg - tile_lat as f64, lon_deg - tile_lon as f64)))
+    }
+
+    /// Corrects the elevations of the points according to the mode. Points outside of the
+    /// available tiles keep their recorded elevation.
+    pub fn correct_elevations(&mut self, lines: &mut [Vec<Point>], mode: DemMode) -> Result<(), WalkingError> {
+        for point in lines.iter_mut().flatten() {
+            if mode == DemMode::Fill && point.elevation_m.is_some() {
+                continue;
+            }
+            if let Some(ele) = self.elevation_m(point.latitude(), point.longitude())? {
+                point.elevation_m = Some(ele);
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Collection of warnings and of the numbers of points dropped during conversion, so that they can
+//! be logged and summarized in a report.
+
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::{json, Map, Value};
+
+
+/// The reason why points were dropped.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DropReason {
+    /// The record has no position (e.g. recorded indoors).
+    NoPosition,
+
+    /// The record could not be converted into a point.
+    InvalidRecord,
+
+    /// The point lies outside of the requested time range.
+    OutsideTimeRange,
+
+    /// The point implies an implausible speed.
+    SpeedOutlier,
+
+    /// The point lies within the trimmed distance from the start or end.
+    Trimmed,
+
+    /// The point lies within a censor area.
+    Censored,
+
+    /// The point lies outside of the bounding box to which the track is clipped.
+    OutsideBoundingBox,
+}
+impl DropReason {
+    /// The name of the reason as used in the report.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NoPosition => "no_position",
+            Self::InvalidRecord => "invalid_record",
+            Self::OutsideTimeRange => "outside_time_range",
+            Self::SpeedOutlier => "speed_outlier",
+            Self::Trimmed => "trimmed",
+            Self::Censored => "censored",
+            Self::OutsideBoundingBox => "outside_bbox",
+        }
+    }
+}
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+
+/// A problem with a single record of an input file that did not prevent the file from being read.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Warning {
+    /// The index of the affected record within the input file.
+    pub record_index: usize,
+
+    /// The name of the affected field, or `None` if the whole record was skipped.
+    pub field: Option<String>,
+
+    pub message: String,
+}
+impl Warning {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "record_index": self.record_index,
+            "field": self.field,
+            "message": self.message,
+        })
+    }
+}
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "record {}: ignoring field {}: {}", self.record_index, field, self.message),
+            None => write!(f, "record {}: skipping record: {}", self.record_index, self.message),
+        }
+    }
+}
+
+
+/// Everything that was skipped or dropped while reading and processing an activity.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Diagnostics {
+    pub warnings: Vec<Warning>,
+
+    /// The number of points dropped for each reason.
+    pub dropped_points: BTreeMap<DropReason, usize>,
+}
+impl Diagnostics {
+    /// Records that a field of a record could not be interpreted and has been ignored.
+    pub fn ignore_field(&mut self, record_index: usize, field: &str, message: String) {
+        self.warnings.push(Warning {
+            record_index,
+            field: Some(field.to_owned()),
+            message,
+        });
+    }
+
+    /// Records that a record could not be converted into a point and has been skipped.
+    pub fn skip_record(&mut self, record_index: usize, message: String) {
+        self.warnings.push(Warning {
+            record_index,
+            field: None,
+            message,
+        });
+        self.drop_points(DropReason::InvalidRecord, 1);
+    }
+
+    /// Records that the given number of points has been dropped for the given reason.
+    pub fn drop_points(&mut self, reason: DropReason, count: usize) {
+        if count > 0 {
+            *self.dropped_points.entry(reason).or_insert(0) += count;
+        }
+    }
+
+    /// Adds the diagnostics of another activity to these.
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.warnings.extend(other.warnings);
+        for (reason, count) in other.dropped_points {
+            self.drop_points(reason, count);
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let dropped_points: Map<String, Value> = self.dropped_points.iter()
+            .map(|(reason, count)| (reason.as_str().to_owned(), (*count).into()))
+            .collect();
+        json!({
+            "dropped_points": dropped_points,
+            "warnings": self.warnings.iter().map(|w| w.to_json()).collect::<Vec<Value>>(),
+        })
+    }
+}
@@ -0,0 +1,165 @@
+//! Output of the raw messages of FIT files, for debugging devices and the conversion itself.
+
+
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use crate::error::WalkingError;
+
+
+/// The format in which messages are dumped.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum DumpFormat {
+    /// One JSON object per line and message.
+    #[default]
+    JsonLines,
+
+    /// A plain-text table with one row per message and one column per field.
+    Table,
+}
+
+
+/// Restricts which messages and fields are dumped.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DumpFilter {
+    /// The kinds of messages to dump (e.g. `record` or `event`); all kinds if `None`.
+    pub kinds: Option<BTreeSet<String>>,
+
+    /// The names of the fields to dump (e.g. `heart_rate`); all fields if `None`. Messages
+    /// containing none of these fields are skipped.
+    pub fields: Option<BTreeSet<String>>,
+}
+impl DumpFilter {
+    /// Returns the messages and fields passing the filter, along with the index of each message
+    /// within the file.
+    pub fn apply(&self, records: &[fitparser::FitDataRecord]) -> Vec<(usize, fitparser::FitDataRecord)> {
+        let mut ret = Vec::new();
+        for (index, record) in records.iter().enumerate() {
+            if let Some(kinds) = &self.kinds {
+                if !kinds.contains(&record.kind().to_string()) {
+                    continue;
+                }
+            }
+            let record = match &self.fields {
+                Some(fields) => {
+                    let mut filtered = fitparser::FitDataRecord::new(record.kind());
+                    filtered.extend(
+                        record.fields().iter()
+                            .filter(|f| fields.contains(f.name()))
+                            .cloned()
+                            .collect()
+                    );
+                    if filtered.fields().is_empty() {
+                        continue;
+                    }
+                    filtered
+                },
+                None => record.clone(),
+            };
+            ret.push((index, record));
+        }
+        ret
+    }
+}
+
+
+/// Returns the units of the field, or `None` if it has none. The seconds that timestamps are
+/// nominally counted in are omitted, as the timestamps are output as dates and times.
+fn field_units(field: &fitparser::FitDataField) -> Option<&str> {
+    if field.units().is_empty() || matches!(field.value(), fitparser::Value::Timestamp(_)) {
+        None
+    } else {
+        Some(field.units())
+    }
+}
+
+
+/// Converts a FIT message into a JSON object with its index within the file, its kind, the values
+/// of its fields and the units of those fields that have one.
+pub fn record_to_json(index: usize, record: &fitparser::FitDataRecord) -> Result<Map<String, Value>, WalkingError> {
+    let mut fields = Map::new();
+    let mut units = Map::new();
+    for field in record.fields() {
+        fields.insert(field.name().to_owned(), serde_json::to_value(field.value())?);
+        if let Some(field_units) = field_units(field) {
+            units.insert(field.name().to_owned(), field_units.into());
+        }
+    }
+
+    let mut ret = Map::new();
+    ret.insert("index".to_owned(), index.into());
+    ret.insert("kind".to_owned(), record.kind().to_string().into());
+    ret.insert("fields".to_owned(), fields.into());
+    ret.insert("units".to_owned(), units.into());
+    Ok(ret)
+}
+
+
+/// Writes the FIT messages as JSON lines, one object per message (see [`record_to_json`]).
+pub fn write_records_json_lines<W: Write>(mut writer: W, records: &[(usize, fitparser::FitDataRecord)]) -> Result<(), WalkingError> {
+    for (index, record) in records {
+        let mut object = Value::Object(record_to_json(*index, record)?);
+        object.sort_all_objects();
+        serde_json::to_writer(&mut writer, &object)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+
+/// Writes the FIT messages as a plain-text table. The columns after the index and the kind of the
+/// message are the fields, in the order in which they first occur; values are followed by their
+/// units, and fields a message does not contain are left empty.
+pub fn write_records_table<W: Write>(mut writer: W, records: &[(usize, fitparser::FitDataRecord)]) -> Result<(), WalkingError> {
+    let mut field_names: Vec<&str> = Vec::new();
+    for (_, record) in records {
+        for field in record.fields() {
+            if !field_names.contains(&field.name()) {
+                field_names.push(field.name());
+            }
+        }
+    }
+
+    let mut header = vec!["index".to_owned(), "kind".to_owned()];
+    header.extend(field_names.iter().map(|n| (*n).to_owned()));
+    let mut rows = vec![header];
+    for (index, record) in records {
+        let mut row = vec![index.to_string(), record.kind().to_string()];
+        for name in &field_names {
+            let cell = record.fields().iter()
+                .find(|f| f.name() == *name)
+                .map(|f| match field_units(f) {
+                    Some(units) => format!("{} {}", f.value(), units),
+                    None => f.value().to_string(),
+                })
+                .unwrap_or_default();
+            row.push(cell);
+        }
+        rows.push(row);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|c| rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in &rows {
+        let mut line = String::new();
+        for (c, cell) in row.iter().enumerate() {
+            if c > 0 {
+                line.push_str("  ");
+            }
+            if c == 0 {
+                write!(line, "{:>width$}", cell, width = widths[c]).unwrap();
+            } else {
+                write!(line, "{:<width$}", cell, width = widths[c]).unwrap();
+            }
+        }
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,272 @@
+//! Best efforts: the fastest time over a distance (such as the fastest kilometer) and the highest
+//! average power or heart rate over a duration (such as the best 20 minutes).
+
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::point::{DistanceAlgorithm, Point};
+
+
+/// The span of a best effort.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EffortSpan {
+    /// The fastest time over the distance, in meters.
+    Distance(f64),
+
+    /// The highest average power and heart rate over the duration, in seconds.
+    Duration(f64),
+}
+
+
+/// A window over which best efforts are searched, given as a number with a unit, e.g. `1km`,
+/// `400m`, `1mi`, `30s`, `20min` or `1h`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EffortWindow {
+    /// The window as given.
+    pub label: String,
+
+    pub span: EffortSpan,
+}
+impl fmt::Display for EffortWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+impl FromStr for EffortWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let label = s.trim();
+        let unit_start = label.find(|c: char| c.is_ascii_alphabetic())
+            .ok_or_else(|| format!("best effort window {:?} has no unit (e.g. km, mi, min)", s))?;
+        let (number, unit) = label.split_at(unit_start);
+        let value: f64 = number.trim().parse()
+            .map_err(|e| format!("failed to parse best effort window {:?}: {}", s, e))?;
+        if !(value > 0.0 && value.is_finite()) {
+            return Err(format!("best effort window {:?} must be positive", s));
+        }
+        let span = match unit {
+            "m" => EffortSpan::Distance(value),
+            "km" => EffortSpan::Distance(value * 1000.0),
+            "mi" => EffortSpan::Distance(value * 1609.344),
+            "s" => EffortSpan::Duration(value),
+            "min" => EffortSpan::Duration(value * 60.0),
+            "h" => EffortSpan::Duration(value * 3600.0),
+            other => return Err(format!("unknown unit {:?} of best effort window {:?} (use m, km, mi, s, min or h)", other, s)),
+        };
+        Ok(Self {
+            label: label.to_owned(),
+            span,
+        })
+    }
+}
+
+
+/// The measurement in which an effort is the best.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EffortMeasure {
+    /// The shortest time over a distance.
+    Time,
+
+    /// The highest average power over a duration.
+    Power,
+
+    /// The highest average heart rate over a duration.
+    HeartRate,
+}
+impl EffortMeasure {
+    /// The name of the measurement as output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Time => "time",
+            Self::Power => "power",
+            Self::HeartRate => "heart_rate",
+        }
+    }
+}
+
+
+/// The best effort within a window.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BestEffort {
+    pub window: EffortWindow,
+    pub measure: EffortMeasure,
+
+    /// The duration of the effort, in seconds; for distance windows, this is the best time.
+    pub duration_s: f64,
+
+    /// The distance covered during the effort, in meters; for distance windows, this is the
+    /// distance of the window.
+    pub distance_m: f64,
+
+    /// The average power (in W) or heart rate (in bpm) for duration windows; `None` for distance
+    /// windows.
+    pub average: Option<f64>,
+
+    /// The points from the one at or before the start of the effort to the one at its end.
+    pub points: Vec<Point>,
+}
+impl BestEffort {
+    /// The average speed during the effort, in km/h.
+    pub fn speed_km_per_h(&self) -> Option<f64> {
+        (self.duration_s > 0.0).then(|| self.distance_m / self.duration_s * 3.6)
+    }
+}
+
+
+/// A point with a timestamp, along with the distance and the time with the timer running from the
+/// start of the activity.
+struct TimedPoint<'a> {
+    point: &'a Point,
+    distance_m: f64,
+    time_s: f64,
+}
+
+
+/// Collects the points with timestamps along with their running distances and times. As for the
+/// splits, neither distance nor time is counted across the gaps between lines.
+fn timed_points(lines: &[Vec<Point>], distance_algorithm: DistanceAlgorithm) -> Vec<TimedPoint<'_>> {
+    let mut ret: Vec<TimedPoint> = Vec::new();
+    let mut distance_m = 0.0;
+    let mut time_s = 0.0;
+    for line in lines {
+        let mut previous: Option<(&Point, f64)> = None;
+        for point in line {
+            let timestamp = match point.unix_timestamp {
+                Some(t) => t,
+                None => continue,
+            };
+            if let Some((previous_point, previous_timestamp)) = previous {
+                distance_m += previous_point.distance_m(point, distance_algorithm);
+                time_s += (timestamp - previous_timestamp).max(0.0);
+            }
+            ret.push(TimedPoint { point, distance_m, time_s });
+            previous = Some((point, timestamp));
+        }
+    }
+    ret
+}
+
+
+fn effort_points(points: &[TimedPoint], start_index: usize, end_index: usize) -> Vec<Point> {
+    points[start_index..=end_index].iter()
+        .map(|p| p.point.clone())
+        .collect()
+}
+
+
+/// Finds the shortest time in which the distance was covered. The start of the effort is
+/// interpolated between the points.
+fn fastest_over_distance(points: &[TimedPoint], window: &EffortWindow, window_m: f64) -> Option<BestEffort> {
+    let mut best: Option<(f64, usize, usize)> = None;
+    let mut start_index = 0;
+    for end_index in 0..points.len() {
+        let end = &points[end_index];
+        while start_index + 1 < end_index && end.distance_m - points[start_index + 1].distance_m >= window_m {
+            start_index += 1;
+        }
+        let start = &points[start_index];
+        if end.distance_m - start.distance_m < window_m {
+            continue;
+        }
+
+        // the point after the start may still be before the start of the window
+        let start_distance_m = end.distance_m - window_m;
+        let next = &points[start_index + 1];
+        let start_time_s = if next.distance_m > start.distance_m {
+            let fraction = ((start_distance_m - start.distance_m) / (next.distance_m - start.distance_m)).clamp(0.0, 1.0);
+            start.time_s + fraction * (next.time_s - start.time_s)
+        } else {
+            start.time_s
+        };
+        let duration_s = end.time_s - start_time_s;
+        if duration_s > 0.0 && best.is_none_or(|(d, _, _)| duration_s < d) {
+            best = Some((duration_s, start_index, end_index));
+        }
+    }
+
+    let (duration_s, start_index, end_index) = best?;
+    Some(BestEffort {
+        window: window.clone(),
+        measure: EffortMeasure::Time,
+        duration_s,
+        distance_m: window_m,
+        average: None,
+        points: effort_points(points, start_index, end_index),
+    })
+}
+
+
+/// Finds the highest average of the value over the duration. The values are resampled to one per
+/// second, each point's value holding until the next point; windows in which a value is missing
+/// are skipped.
+fn highest_over_duration<F>(points: &[TimedPoint], window: &EffortWindow, window_s: f64, measure: EffortMeasure, value: F) -> Option<BestEffort>
+    where F: Fn(&Point) -> Option<f64>
+{
+    // the values of each second along with the index of the point at or before it
+    let mut samples: Vec<(Option<f64>, usize)> = Vec::new();
+    for (i, pair) in points.windows(2).enumerate() {
+        let seconds = (pair[1].time_s - pair[0].time_s).round() as usize;
+        samples.extend(std::iter::repeat_n((value(pair[0].point), i), seconds));
+    }
+    let window_len = window_s.round() as usize;
+    if window_len == 0 || samples.len() < window_len {
+        return None;
+    }
+
+    let mut best: Option<(f64, usize)> = None;
+    let mut sum = 0.0;
+    let mut missing = 0;
+    for (i, (sample, _)) in samples.iter().enumerate() {
+        match sample {
+            Some(v) => sum += v,
+            None => missing += 1,
+        }
+        if i >= window_len {
+            match samples[i - window_len].0 {
+                Some(v) => sum -= v,
+                None => missing -= 1,
+            }
+        }
+        if i + 1 < window_len || missing > 0 {
+            continue;
+        }
+        let average = sum / window_len as f64;
+        if best.is_none_or(|(b, _)| average > b) {
+            best = Some((average, i + 1 - window_len));
+        }
+    }
+
+    let (average, first_sample) = best?;
+    let start_index = samples[first_sample].1;
+    let end_index = samples[first_sample + window_len - 1].1 + 1;
+    Some(BestEffort {
+        window: window.clone(),
+        measure,
+        duration_s: window_len as f64,
+        distance_m: points[end_index].distance_m - points[start_index].distance_m,
+        average: Some(average),
+        points: effort_points(points, start_index, end_index),
+    })
+}
+
+
+/// Finds the best efforts within the lines: for each distance window, the fastest time; for each
+/// duration window, the highest average power and the highest average heart rate. Only the time
+/// during which the timer was running is considered. Windows longer than the activity, or without
+/// the necessary measurements, yield no effort.
+pub fn best_efforts(lines: &[Vec<Point>], windows: &[EffortWindow], distance_algorithm: DistanceAlgorithm) -> Vec<BestEffort> {
+    let points = timed_points(lines, distance_algorithm);
+    let mut efforts = Vec::new();
+    for window in windows {
+        match window.span {
+            EffortSpan::Distance(window_m) => efforts.extend(fastest_over_distance(&points, window, window_m)),
+            EffortSpan::Duration(window_s) => {
+                efforts.extend(highest_over_duration(&points, window, window_s, EffortMeasure::Power, |p| p.power_w.map(|w| w as f64)));
+                efforts.extend(highest_over_duration(&points, window, window_s, EffortMeasure::HeartRate, |p| p.heart_rate_bpm.map(|hr| hr as f64)));
+            },
+        }
+    }
+    efforts
+}
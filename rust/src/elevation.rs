@@ -0,0 +1,132 @@
+use crate::point::{DistanceAlgorithm, Point};
+
+
+/// The default minimum elevation change that counts towards gain or loss, in meters.
+pub const DEFAULT_ELEVATION_THRESHOLD_M: f64 = 3.0;
+
+/// Below this horizontal distance between two points, no gradient is calculated, as GPS jitter
+/// would produce absurd values.
+pub const MIN_GRADIENT_DISTANCE_M: f64 = 1.0;
+
+
+/// Calculates the total elevation gain and loss (both positive) of the lines in meters.
+///
+/// To suppress sensor noise, a change in elevation is only counted once it reaches the given
+/// threshold relative to the last counted elevation. Points without elevation are skipped, and
+/// changes between lines (across pauses) are not counted.
+pub fn elevation_gain_loss(lines: &[Vec<Point>], threshold_m: f64) -> (f64, f64) {
+    let mut gain_m = 0.0;
+    let mut loss_m = 0.0;
+
+    for line in lines {
+        let mut reference_m: Option<f64> = None;
+        for elevation_m in line.iter().filter_map(|p| p.elevation_m) {
+            let ref_m = match reference_m {
+                Some(r) => r,
+                None => {
+                    reference_m = Some(elevation_m);
+                    continue;
+                },
+            };
+
+            let delta_m = elevation_m - ref_m;
+            if delta_m >= threshold_m {
+                gain_m += delta_m;
+                reference_m = Some(elevation_m);
+            } else if -delta_m >= threshold_m {
+                loss_m -= delta_m;
+                reference_m = Some(elevation_m);
+            }
+        }
+    }
+
+    (gain_m, loss_m)
+}
+
+
+/// Calculates the gradient between two points in percent (positive uphill). Returns `None` if
+/// either point has no elevation or the points are too close together.
+pub fn gradient_percent(point1: &Point, point2: &Point, distance_algorithm: DistanceAlgorithm) -> Option<f64> {
+    let (ele1_m, ele2_m) = match (point1.elevation_m, point2.elevation_m) {
+        (Some(e1), Some(e2)) => (e1, e2),
+        _ => return None,
+    };
+    let distance_m = point1.distance_m(point2, distance_algorithm);
+    if distance_m < MIN_GRADIENT_DISTANCE_M {
+        return None;
+    }
+    Some((ele2_m - ele1_m) / distance_m * 100.0)
+}
+
+
+/// Finds the minimum and maximum gradient (in percent) over the segments of the lines.
+pub fn gradient_extrema(lines: &[Vec<Point>], distance_algorithm: DistanceAlgorithm) -> Option<(f64, f64)> {
+    lines.iter()
+        .flat_map(|l| l.windows(2))
+        .filter_map(|s| gradient_percent(&s[0], &s[1], distance_algorithm))
+        .fold(None, |extrema, gradient| match extrema {
+            None => Some((gradient, gradient)),
+            Some((min, max)) => Some((f64::min(min, gradient), f64::max(max, gradient))),
+        })
+}
+
+
+/// Calculates the rate of ascent (positive) or descent (negative) between two points in meters per
+/// hour, known as VAM (velocità ascensionale media). Returns `None` if either point lacks an
+/// elevation or a timestamp, or if no time passes between them.
+pub fn vertical_speed_m_per_h(point1: &Point, point2: &Point) -> Option<f64> {
+    let climb_m = point2.elevation_m? - point1.elevation_m?;
+    let duration_s = point2.unix_timestamp? - point1.unix_timestamp?;
+    if duration_s <= 0.0 {
+        return None;
+    }
+    Some(climb_m / duration_s * 3600.0)
+}
+
+
+/// Finds the minimum and maximum vertical speed (in meters per hour) over the segments of the
+/// lines.
+pub fn vertical_speed_extrema(lines: &[Vec<Point>]) -> Option<(f64, f64)> {
+    lines.iter()
+        .flat_map(|l| l.windows(2))
+        .filter_map(|s| vertical_speed_m_per_h(&s[0], &s[1]))
+        .fold(None, |extrema, vertical_speed| match extrema {
+            None => Some((vertical_speed, vertical_speed)),
+            Some((min, max)) => Some((f64::min(min, vertical_speed), f64::max(max, vertical_speed))),
+        })
+}
+
+
+/// The steepest gradient (as a fraction, in either direction) covered by the model of the energy
+/// cost of running; steeper gradients are treated as this steep.
+const MAX_GRADE_ADJUSTMENT_GRADIENT: f64 = 0.45;
+
+
+/// Returns the energy cost of running at the given gradient (as a fraction) in J/(kg·m), according
+/// to the polynomial fitted by Minetti et al. (2002).
+fn running_energy_cost(gradient: f64) -> f64 {
+    let i = gradient.clamp(-MAX_GRADE_ADJUSTMENT_GRADIENT, MAX_GRADE_ADJUSTMENT_GRADIENT);
+    ((((155.4 * i - 30.4) * i - 43.3) * i + 46.3) * i + 19.5) * i + 3.6
+}
+
+
+/// Returns the factor by which a speed at the given gradient (in percent) is multiplied to obtain
+/// the speed on flat ground requiring the same effort (grade-adjusted speed). The factor is greater
+/// than 1 uphill and on steep descents and less than 1 on gentle descents.
+pub fn grade_adjustment_factor(gradient_percent: f64) -> f64 {
+    running_energy_cost(gradient_percent / 100.0) / running_energy_cost(0.0)
+}
+
+
+/// Calculates the grade-adjusted speed (see [`grade_adjustment_factor`]) between two points from
+/// the mean of their recorded speeds in km/h. Returns `None` if the speed or the gradient is
+/// unknown.
+pub fn grade_adjusted_speed_km_per_h(point1: &Point, point2: &Point, distance_algorithm: DistanceAlgorithm) -> Option<f64> {
+    let speed_km_per_h = match (point1.speed_km_per_h, point2.speed_km_per_h) {
+        (Some(s1), Some(s2)) => (s1 + s2) / 2.0,
+        (Some(s), None) | (None, Some(s)) => s,
+        (None, None) => return None,
+    };
+    let gradient = gradient_percent(point1, point2, distance_algorithm)?;
+    Some(speed_km_per_h * grade_adjustment_factor(gradient))
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::io;
+
+use crate::input::xml::XmlError;
+
+
+#[derive(Debug)]
+pub enum WalkingError {
+    /// Reading or writing a file failed.
+    Io(io::Error),
+
+    /// The FIT data could not be decoded.
+    Fit(fitparser::Error),
+
+    /// The structure of a FIT file is invalid, so that it cannot be rewritten.
+    InvalidFit(String),
+
+    /// An XML-based input file (GPX, TCX) is not well-formed.
+    Xml(XmlError),
+
+    /// A record (a FIT message or an XML trackpoint) contains an invalid value. The index counts
+    /// the records from 0 in the order they appear in the file.
+    InvalidRecord { record_index: usize, message: String },
+
+    /// A line of a censor polygon file is invalid. The line number counts from 1.
+    InvalidPolygon { line_number: usize, message: String },
+
+    /// A JSON file could not be parsed.
+    Json(serde_json::Error),
+
+    /// A GeoJSON document does not have the expected structure.
+    InvalidGeoJson(String),
+
+    /// The track does not contain any points with coordinates.
+    NoPoints,
+
+    /// A compressed input file (gzip or ZIP) could not be decompressed.
+    Decompression(String),
+
+    /// A DEM tile is invalid.
+    InvalidDem(String),
+
+    /// A gazetteer file is invalid or a geocoding service returned an unexpected response.
+    Geocoding(String),
+
+    /// A configuration file is invalid.
+    Config(String),
+
+    /// Writing to a database failed.
+    Database(String),
+
+    /// An HTTP request (e.g. a WebSocket handshake) is invalid.
+    Http(String),
+
+    /// Downloading activities from an online service failed.
+    Fetch(String),
+
+    /// Processing panicked because of a bug, which was caught so that the other files can still be
+    /// processed.
+    Internal(String),
+}
+impl WalkingError {
+    /// A short identifier of the kind of error, as used in machine-readable error reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Io(_) => "io",
+            Self::Fit(_) => "fit",
+            Self::InvalidFit(_) => "invalid_fit",
+            Self::Xml(_) => "xml",
+            Self::InvalidRecord { .. } => "invalid_record",
+            Self::InvalidPolygon { .. } => "invalid_polygon",
+            Self::Json(_) => "json",
+            Self::InvalidGeoJson(_) => "invalid_geojson",
+            Self::NoPoints => "no_points",
+            Self::Decompression(_) => "decompression",
+            Self::InvalidDem(_) => "invalid_dem",
+            Self::Geocoding(_) => "geocoding",
+            Self::Config(_) => "config",
+            Self::Database(_) => "database",
+            Self::Http(_) => "http",
+            Self::Fetch(_) => "fetch",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    /// Whether the error may go away when trying again without changing the input, e.g. an
+    /// interrupted read or a connection that timed out. Invalid input fails the same way every
+    /// time.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+            ),
+            _ => false,
+        }
+    }
+}
+impl fmt::Display for WalkingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e)
+                => write!(f, "I/O error: {}", e),
+            Self::Fit(e)
+                => write!(f, "failed to decode FIT data: {}", e),
+            Self::InvalidFit(message)
+                => write!(f, "invalid FIT file: {}", message),
+            Self::Xml(e)
+                => write!(f, "failed to parse XML: {}", e),
+            Self::InvalidRecord { record_index, message }
+                => write!(f, "record {}: {}", record_index, message),
+            Self::InvalidPolygon { line_number, message }
+                => write!(f, "line {}: {}", line_number, message),
+            Self::Json(e)
+                => write!(f, "failed to parse JSON: {}", e),
+            Self::InvalidGeoJson(message)
+                => write!(f, "invalid GeoJSON: {}", message),
+            Self::NoPoints
+                => write!(f, "track contains no points with coordinates"),
+            Self::Decompression(message)
+                => write!(f, "failed to decompress: {}", message),
+            Self::InvalidDem(message)
+                => write!(f, "invalid DEM tile: {}", message),
+            Self::Geocoding(message)
+                => write!(f, "geocoding failed: {}", message),
+            Self::Config(message)
+                => write!(f, "invalid configuration: {}", message),
+            Self::Database(message)
+                => write!(f, "database error: {}", message),
+            Self::Http(message)
+                => write!(f, "invalid HTTP request: {}", message),
+            Self::Fetch(message)
+                => write!(f, "failed to fetch activities: {}", message),
+            Self::Internal(message)
+                => write!(f, "internal error: {}", message),
+        }
+    }
+}
+impl std::error::Error for WalkingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Fit(e) => Some(e.as_ref()),
+            Self::InvalidFit(_) => None,
+            Self::Xml(e) => Some(e),
+            Self::InvalidRecord { .. } => None,
+            Self::InvalidPolygon { .. } => None,
+            Self::Json(e) => Some(e),
+            Self::InvalidGeoJson(_) => None,
+            Self::NoPoints => None,
+            Self::Decompression(_) => None,
+            Self::InvalidDem(_) => None,
+            Self::Geocoding(_) => None,
+            Self::Config(_) => None,
+            Self::Database(_) => None,
+            Self::Http(_) => None,
+            Self::Fetch(_) => None,
+            Self::Internal(_) => None,
+        }
+    }
+}
+impl From<io::Error> for WalkingError {
+    fn from(value: io::Error) -> Self { Self::Io(value) }
+}
+impl From<fitparser::Error> for WalkingError {
+    fn from(value: fitparser::Error) -> Self { Self::Fit(value) }
+}
+impl From<serde_json::Error> for WalkingError {
+    fn from(value: serde_json::Error) -> Self { Self::Json(value) }
+}
+impl From<XmlError> for WalkingError {
+    fn from(value: XmlError) -> Self { Self::Xml(value) }
+}
@@ -0,0 +1,394 @@
+//! Downloading recent activities from Garmin Connect or Strava, using OAuth tokens stored in a
+//! token file.
+//!
+//! Both services are only reachable via HTTPS, so requests are made using the `curl` command-line
+//! tool. Tokens are passed to it on its standard input instead of the command line, where other
+//! users could see them.
+//!
+//! The token file (by default `tokens.json` in the directory of the configuration file) is a JSON
+//! object with one member per service:
+//!
+//! ```json
+//! {
+//!   "strava": {"access_token": "…", "refresh_token": "…", "expires_at": 1700000000, "client_id": "…", "client_secret": "…"},
+//!   "garmin": {"access_token": "…", "expires_at": 1700000000}
+//! }
+//! ```
+//!
+//! Strava access tokens expire after a few hours and are refreshed (and the token file updated) if
+//! the refresh token and the client credentials of the application are stored. Garmin Connect
+//! tokens cannot be refreshed this way and must be renewed when they expire.
+//!
+//! Garmin Connect provides the original FIT files, which are stored as `garmin-<id>.fit`. Strava
+//! does not; the recorded streams of an activity are stored as a GPX file `strava-<id>.gpx`
+//! instead. Activities whose files already exist are not downloaded again.
+
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::default_config_path;
+use crate::error::WalkingError;
+use crate::kml::escape_xml;
+use crate::zip::read_zip_entries;
+
+
+/// The time before the expiry of a token at which it is refreshed, in seconds.
+const EXPIRY_MARGIN_S: i64 = 60;
+
+/// The time after which a request is given up, in seconds.
+const REQUEST_TIMEOUT_S: u32 = 120;
+
+const STRAVA_API: &str = "https://www.strava.com/api/v3";
+const STRAVA_TOKEN_URL: &str = "https://www.strava.com/oauth/token";
+const GARMIN_API: &str = "https://connectapi.garmin.com";
+
+/// The streams of a Strava activity that are stored.
+const STRAVA_STREAMS: &str = "time,latlng,altitude,heartrate,cadence,watts,temp";
+
+
+/// The service from which activities are downloaded.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, ValueEnum)]
+pub enum FetchService {
+    Garmin,
+    Strava,
+}
+impl FetchService {
+    /// The name of the service in the token file and in the names of downloaded files.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::Garmin => "garmin",
+            Self::Strava => "strava",
+        }
+    }
+}
+
+
+/// Returns the default path of the token file, `tokens.json` next to the default configuration
+/// file.
+pub fn default_token_path() -> Option<PathBuf> {
+    default_config_path()
+        .map(|p| p.with_file_name("tokens.json"))
+}
+
+
+/// The OAuth token of a service.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Token {
+    pub access_token: String,
+
+    /// The time at which the access token expires, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+
+    /// The client id of the application the token has been issued to, required for refreshing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+}
+impl Token {
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at
+            .map(|e| e <= now.timestamp() + EXPIRY_MARGIN_S)
+            .unwrap_or(false)
+    }
+}
+
+
+/// The tokens of all services, stored as a JSON file.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TokenFile {
+    path: PathBuf,
+    tokens: BTreeMap<String, Token>,
+}
+impl TokenFile {
+    /// Loads the token file at the given path.
+    pub fn load(path: &Path) -> Result<Self, WalkingError> {
+        let text = fs::read_to_string(path)?;
+        let tokens = serde_json::from_str(&text)?;
+        Ok(Self { path: path.to_owned(), tokens })
+    }
+
+    /// Returns the token of the service, if the file contains one.
+    pub fn token(&self, service: FetchService) -> Option<&Token> {
+        self.tokens.get(service.key())
+    }
+
+    /// Replaces the token of the service and saves the file.
+    pub fn update(&mut self, service: FetchService, token: Token) -> Result<(), WalkingError> {
+        self.tokens.insert(service.key().to_owned(), token);
+        let mut text = serde_json::to_string_pretty(&self.tokens)?;
+        text.push('\n');
+        fs::write(&self.path, text)?;
+        Ok(())
+    }
+}
+
+
+/// Quotes a value for a curl configuration file.
+fn curl_config_string(value: &str) -> String {
+    let mut ret = String::with_capacity(value.len() + 2);
+    ret.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            other => ret.push(other),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+
+/// Performs a request using the `curl` command-line tool, configured by the given options (in the
+/// format of a curl configuration file, which may contain secrets). Returns the body of the
+/// response, which must have a successful status.
+fn curl(config: &str) -> Result<Vec<u8>, WalkingError> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => WalkingError::Fetch("the curl command-line tool is required to download activities".to_owned()),
+            _ => WalkingError::Io(e),
+        })?;
+
+    let write_result = {
+        let mut stdin = child.stdin.take().unwrap();
+        writeln!(stdin, "max-time = {}", REQUEST_TIMEOUT_S)
+            .and_then(|_| writeln!(stdin, "user-agent = {}", curl_config_string(&format!("fit2walking/{}", env!("CARGO_PKG_VERSION")))))
+            .and_then(|_| stdin.write_all(config.as_bytes()))
+    };
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(WalkingError::Fetch(message.trim().to_owned()));
+    }
+    write_result?;
+    Ok(output.stdout)
+}
+
+/// Performs a GET request authorized by the access token and parses the response as JSON.
+fn get_json(url: &str, access_token: &str) -> Result<Value, WalkingError> {
+    let body = get(url, access_token)?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Performs a GET request authorized by the access token.
+fn get(url: &str, access_token: &str) -> Result<Vec<u8>, WalkingError> {
+    let mut config = String::new();
+    writeln!(config, "url = {}", curl_config_string(url)).unwrap();
+    writeln!(config, "header = {}", curl_config_string(&format!("Authorization: Bearer {}", access_token))).unwrap();
+    log::debug!("GET {}", url);
+    curl(&config)
+}
+
+
+/// Returns a token for the service that has not expired, refreshing it if necessary and possible.
+/// The token file is updated with the refreshed token.
+pub fn valid_token(service: FetchService, tokens: &mut TokenFile) -> Result<Token, WalkingError> {
+    let token = tokens.token(service)
+        .ok_or_else(|| WalkingError::Fetch(format!("the token file contains no {} token", service.key())))?
+        .clone();
+    let now = Utc::now();
+    if !token.is_expired(now) {
+        return Ok(token);
+    }
+
+    let refreshable = match (service, &token.refresh_token, &token.client_id, &token.client_secret) {
+        (FetchService::Strava, Some(r), Some(id), Some(secret)) => Some((r, id, secret)),
+        _ => None,
+    };
+    let (refresh_token, client_id, client_secret) = match refreshable {
+        Some(r) => r,
+        None => return Err(WalkingError::Fetch(format!(
+            "the {} token has expired and cannot be refreshed; store a new one in the token file",
+            service.key(),
+        ))),
+    };
+
+    log::info!("refreshing the {} token", service.key());
+    let mut config = String::new();
+    writeln!(config, "url = {}", curl_config_string(STRAVA_TOKEN_URL)).unwrap();
+    for (name, value) in [("client_id", client_id), ("client_secret", client_secret), ("refresh_token", refresh_token)] {
+        writeln!(config, "data-urlencode = {}", curl_config_string(&format!("{}={}", name, value))).unwrap();
+    }
+    writeln!(config, "data = \"grant_type=refresh_token\"").unwrap();
+    let response: Value = serde_json::from_slice(&curl(&config)?)?;
+
+    let refreshed = Token {
+        access_token: response["access_token"].as_str()
+            .ok_or_else(|| WalkingError::Fetch("token response without access token".to_owned()))?
+            .to_owned(),
+        expires_at: response["expires_at"].as_i64(),
+        refresh_token: response["refresh_token"].as_str()
+            .map(|r| r.to_owned())
+            .or_else(|| token.refresh_token.clone()),
+        ..token
+    };
+    tokens.update(service, refreshed.clone())?;
+    Ok(refreshed)
+}
+
+
+/// Returns the id of an activity in a list returned by a service, which may be a number or a
+/// string.
+fn activity_id(activity: &Value, key: &str) -> Option<String> {
+    match &activity[key] {
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) if !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric()) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+
+/// Downloads the `count` most recent activities of the service into the directory, skipping those
+/// downloaded before. Returns the paths of the newly downloaded files, oldest first.
+pub fn fetch_activities(service: FetchService, token: &Token, count: usize, dir: &Path) -> Result<Vec<PathBuf>, WalkingError> {
+    let (list_url, id_key, extension) = match service {
+        FetchService::Garmin => (
+            format!("{}/activitylist-service/activities/search/activities?start=0&limit={}", GARMIN_API, count),
+            "activityId",
+            "fit",
+        ),
+        FetchService::Strava => (
+            format!("{}/athlete/activities?per_page={}", STRAVA_API, count),
+            "id",
+            "gpx",
+        ),
+    };
+    let list = get_json(&list_url, &token.access_token)?;
+    let activities = list.as_array()
+        .ok_or_else(|| WalkingError::Fetch("the list of activities is not an array".to_owned()))?;
+
+    let mut ret = Vec::new();
+    // the most recent activities come first
+    for activity in activities.iter().take(count).rev() {
+        let id = match activity_id(activity, id_key) {
+            Some(id) => id,
+            None => {
+                log::warn!("skipping activity without id in the list of {} activities", service.key());
+                continue;
+            },
+        };
+        let path = dir.join(format!("{}-{}.{}", service.key(), id, extension));
+        if path.exists() {
+            log::debug!("{}: already downloaded", path.display());
+            continue;
+        }
+
+        let data = match service {
+            FetchService::Garmin => {
+                let archive = get(&format!("{}/download-service/files/activity/{}", GARMIN_API, id), &token.access_token)?;
+                garmin_fit_file(&archive)?
+            },
+            FetchService::Strava => {
+                let streams = get_json(
+                    &format!("{}/activities/{}/streams?keys={}&key_by_type=true", STRAVA_API, id, STRAVA_STREAMS),
+                    &token.access_token,
+                )?;
+                strava_streams_to_gpx(activity, &streams)?.into_bytes()
+            },
+        };
+
+        // written under a temporary name first, so that an interrupted download is not skipped
+        // the next time
+        let partial_path = path.with_extension(format!("{}.part", extension));
+        fs::write(&partial_path, &data)?;
+        fs::rename(&partial_path, &path)?;
+        log::info!("{}: downloaded", path.display());
+        ret.push(path);
+    }
+    Ok(ret)
+}
+
+
+/// Returns the FIT file within the ZIP archive in which Garmin Connect provides the original file
+/// of an activity.
+fn garmin_fit_file(archive: &[u8]) -> Result<Vec<u8>, WalkingError> {
+    let entries = read_zip_entries(archive)
+        .map_err(WalkingError::Decompression)?;
+    let entry = entries.iter()
+        .find(|e| !e.is_directory() && e.name.to_ascii_lowercase().ends_with(".fit"))
+        .ok_or_else(|| WalkingError::Fetch("the downloaded archive contains no FIT file".to_owned()))?;
+    entry.extract(archive)
+        .map_err(WalkingError::Decompression)
+}
+
+
+/// Converts the streams of a Strava activity (requested with `key_by_type=true`) into a GPX file.
+/// The times of the points are calculated from the start time in the summary of the activity.
+/// Points without a position are skipped.
+pub fn strava_streams_to_gpx(activity: &Value, streams: &Value) -> Result<String, WalkingError> {
+    let start_time = activity["start_date"].as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|t| t.with_timezone(&Utc));
+    let stream = |key: &str| streams[key]["data"].as_array();
+    let latlng = stream("latlng")
+        .ok_or_else(|| WalkingError::Fetch("the activity has no positions".to_owned()))?;
+
+    let mut gpx = String::new();
+    gpx.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    gpx.push_str("<gpx version=\"1.1\" creator=\"fit2walking\" xmlns=\"http://www.topografix.com/GPX/1/1\" xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v1\">\n");
+    gpx.push_str(" <trk>\n");
+    if let Some(name) = activity["name"].as_str() {
+        writeln!(gpx, "  <name>{}</name>", escape_xml(name)).unwrap();
+    }
+    if let Some(sport) = activity["sport_type"].as_str().or_else(|| activity["type"].as_str()) {
+        writeln!(gpx, "  <type>{}</type>", escape_xml(&sport.to_lowercase())).unwrap();
+    }
+    gpx.push_str("  <trkseg>\n");
+    for (i, position) in latlng.iter().enumerate() {
+        let (lat, lon) = match (position[0].as_f64(), position[1].as_f64()) {
+            (Some(lat), Some(lon)) => (lat, lon),
+            _ => continue,
+        };
+        let value = |key: &str| stream(key).and_then(|s| s.get(i)).and_then(|v| v.as_f64());
+
+        writeln!(gpx, "   <trkpt lat=\"{}\" lon=\"{}\">", lat, lon).unwrap();
+        if let Some(elevation) = value("altitude") {
+            writeln!(gpx, "    <ele>{}</ele>", elevation).unwrap();
+        }
+        if let Some(time) = start_time.zip(value("time")).map(|(start, t)| start + TimeDelta::milliseconds((t * 1000.0) as i64)) {
+            writeln!(gpx, "    <time>{}</time>", time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)).unwrap();
+        }
+        let extensions: Vec<String> = [("heartrate", "gpxtpx:hr"), ("cadence", "gpxtpx:cad"), ("temp", "gpxtpx:atemp")].into_iter()
+            .filter_map(|(key, element)| value(key).map(|v| format!("<{}>{}</{}>", element, v, element)))
+            .collect();
+        let power = value("watts");
+        if !extensions.is_empty() || power.is_some() {
+            gpx.push_str("    <extensions>");
+            if let Some(power) = power {
+                write!(gpx, "<power>{}</power>", power).unwrap();
+            }
+            if !extensions.is_empty() {
+                write!(gpx, "<gpxtpx:TrackPointExtension>{}</gpxtpx:TrackPointExtension>", extensions.concat()).unwrap();
+            }
+            gpx.push_str("</extensions>\n");
+        }
+        gpx.push_str("   </trkpt>\n");
+    }
+    gpx.push_str("  </trkseg>\n </trk>\n</gpx>\n");
+    Ok(gpx)
+}
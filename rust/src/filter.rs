@@ -0,0 +1,349 @@
+//! Removal of implausible or unwanted points, such as GPS spikes or points recorded outside of a
+//! time window, and splitting of lines at discontinuities.
+
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveTime, TimeZone};
+
+use crate::point::{DistanceAlgorithm, GeoPoint, Point};
+
+
+/// A boundary of a time window.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TimeBound {
+    /// A specific point in time.
+    Absolute(DateTime<FixedOffset>),
+
+    /// A local time of day on the day the activity started.
+    TimeOfDay(NaiveTime),
+}
+impl TimeBound {
+    /// Resolves the bound into a specific point in time. Times of day are placed on the (local)
+    /// date of `start`; returns `None` if that is impossible (e.g. within a DST gap).
+    pub fn resolve(&self, start: &DateTime<Local>) -> Option<DateTime<Local>> {
+        match self {
+            Self::Absolute(t) => Some(t.with_timezone(&Local)),
+            Self::TimeOfDay(t) => Local.from_local_datetime(&start.date_naive().and_time(*t)).earliest(),
+        }
+    }
+}
+impl fmt::Display for TimeBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Absolute(t) => write!(f, "{}", t.to_rfc3339()),
+            Self::TimeOfDay(t) => write!(f, "{}", t.format("%H:%M:%S")),
+        }
+    }
+}
+impl FromStr for TimeBound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(t) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Absolute(t));
+        }
+        NaiveTime::parse_from_str(s, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+            .map(Self::TimeOfDay)
+            .map_err(|_| format!("time {:?} is neither in RFC 3339 nor in HH:MM format", s))
+    }
+}
+
+
+/// Removes the points recorded before `start` or after `end`. Points without timestamps are
+/// removed as well, as are lines left without points.
+pub fn clip_time_range(lines: &mut Vec<Vec<Point>>, start: Option<TimeBound>, end: Option<TimeBound>) {
+    if start.is_none() && end.is_none() {
+        return;
+    }
+
+    let first_timestamp = match lines.iter().flatten().find_map(|p| p.timestamp) {
+        Some(ft) => ft,
+        None => {
+            // nothing can be within the window
+            lines.clear();
+            return;
+        },
+    };
+    // a bound that cannot be resolved does not restrict anything
+    let start_time = start.and_then(|s| s.resolve(&first_timestamp));
+    let end_time = end.and_then(|e| e.resolve(&first_timestamp));
+
+    for line in lines.iter_mut() {
+        line.retain(|p| match p.timestamp {
+            Some(ts) => start_time.is_none_or(|s| ts >= s) && end_time.is_none_or(|e| ts <= e),
+            None => false,
+        });
+    }
+    lines.retain(|l| !l.is_empty());
+}
+
+
+/// A rectangular area delimited by lines of longitude and latitude, e.g.
+/// `--clip-bbox 16.18,48.11,16.58,48.33`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+impl BoundingBox {
+    /// Whether the point lies within the bounding box or on its boundary.
+    pub fn contains(&self, point: &Point) -> bool {
+        (self.min_lon..=self.max_lon).contains(&point.longitude())
+            && (self.min_lat..=self.max_lat).contains(&point.latitude())
+    }
+
+    /// Clips the straight segment between the points to the bounding box (Liang–Barsky). Returns
+    /// the fractions of the segment at which it enters and leaves the box, or `None` if the
+    /// segment does not pass through the box.
+    fn clip_segment(&self, p1: &Point, p2: &Point) -> Option<(f64, f64)> {
+        let (dx, dy) = (p2.longitude() - p1.longitude(), p2.latitude() - p1.latitude());
+        let mut t0: f64 = 0.0;
+        let mut t1: f64 = 1.0;
+        let edges = [
+            (-dx, p1.longitude() - self.min_lon),
+            (dx, self.max_lon - p1.longitude()),
+            (-dy, p1.latitude() - self.min_lat),
+            (dy, self.max_lat - p1.latitude()),
+        ];
+        for (p, q) in edges {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else if p < 0.0 {
+                t0 = t0.max(q / p);
+            } else {
+                t1 = t1.min(q / p);
+            }
+        }
+        (t0 <= t1).then_some((t0, t1))
+    }
+}
+impl fmt::Display for BoundingBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.min_lon, self.min_lat, self.max_lon, self.max_lat)
+    }
+}
+impl FromStr for BoundingBox {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pieces: Vec<&str> = s.split(',').collect();
+        if pieces.len() != 4 {
+            return Err(format!("bounding box {:?} is not in the format MINLON,MINLAT,MAXLON,MAXLAT", s));
+        }
+        let mut values = [0.0; 4];
+        for (value, piece) in values.iter_mut().zip(pieces.iter()) {
+            *value = piece.trim().parse()
+                .map_err(|e| format!("failed to parse {:?} as a coordinate: {}", piece, e))?;
+        }
+        let [min_lon, min_lat, max_lon, max_lat] = values;
+        if min_lon > max_lon || min_lat > max_lat {
+            return Err(format!("bounding box {:?} has its minimum above its maximum", s));
+        }
+        Ok(Self {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+}
+
+
+/// Creates the point at the given fraction of the way from `p1` to `p2`. The position, elevation
+/// and time are interpolated; the other values are taken from `nearest`, which is the endpoint
+/// inside the bounding box.
+fn boundary_point(p1: &Point, p2: &Point, fraction: f64, nearest: &Point) -> Point {
+    let lerp = |v1: f64, v2: f64| v1 + (v2 - v1) * fraction;
+    let mut point = nearest.clone();
+    point.coordinates_deg = geo::Point::new(
+        lerp(p1.longitude(), p2.longitude()),
+        lerp(p1.latitude(), p2.latitude()),
+    );
+    point.elevation_m = match (p1.elevation_m, p2.elevation_m) {
+        (Some(e1), Some(e2)) => Some(lerp(e1, e2)),
+        _ => nearest.elevation_m,
+    };
+    if let (Some(t1), Some(t2)) = (p1.unix_timestamp, p2.unix_timestamp) {
+        let unix_timestamp = lerp(t1, t2);
+        point.unix_timestamp = Some(unix_timestamp);
+        point.timestamp = Local.timestamp_millis_opt((unix_timestamp * 1000.0).round() as i64).single();
+    }
+    point.follows_censored_gap = false;
+    point
+}
+
+
+/// Keeps only the parts of the lines within the bounding box. Lines are split where they leave the
+/// box and end (or start again) with a point interpolated onto its boundary; a segment that merely
+/// passes through the box becomes a line of its own. Lines left without points are removed.
+/// Returns the number of boundary points that have been added.
+pub fn clip_to_bbox(lines: &mut Vec<Vec<Point>>, bbox: &BoundingBox) -> usize {
+    let mut boundary_count = 0;
+    let mut clipped_lines = Vec::with_capacity(lines.len());
+    for line in lines.drain(..) {
+        let mut clipped_line: Vec<Point> = Vec::new();
+        if let Some(first) = line.first() {
+            if bbox.contains(first) {
+                clipped_line.push(first.clone());
+            }
+        }
+        for segment in line.windows(2) {
+            let (point1, point2) = (&segment[0], &segment[1]);
+            let inside1 = bbox.contains(point1);
+            let inside2 = bbox.contains(point2);
+            if inside1 && inside2 {
+                clipped_line.push(point2.clone());
+                continue;
+            }
+
+            let (t0, t1) = match bbox.clip_segment(point1, point2) {
+                Some(ts) => ts,
+                None => continue,
+            };
+            if !inside1 && (inside2 || t0 < t1) && t0 < 1.0 {
+                // entering the box
+                clipped_line.push(boundary_point(point1, point2, t0, point2));
+                boundary_count += 1;
+            }
+            if inside2 {
+                clipped_line.push(point2.clone());
+            } else if !clipped_line.is_empty() {
+                // leaving the box
+                if t1 > 0.0 && (inside1 || t0 < t1) {
+                    clipped_line.push(boundary_point(point1, point2, t1, point1));
+                    boundary_count += 1;
+                }
+                clipped_lines.push(std::mem::take(&mut clipped_line));
+            }
+        }
+        if !clipped_line.is_empty() {
+            clipped_lines.push(clipped_line);
+        }
+    }
+    *lines = clipped_lines;
+    boundary_count
+}
+
+
+/// Calculates the speed required to get from one point to the other in the time between their
+/// timestamps. Returns `None` if either point has no timestamp.
+fn implied_speed_km_per_h(p1: &Point, p2: &Point, distance_algorithm: DistanceAlgorithm) -> Option<f64> {
+    let (t1, t2) = match (p1.unix_timestamp, p2.unix_timestamp) {
+        (Some(t1), Some(t2)) => (t1, t2),
+        _ => return None,
+    };
+    let distance_m = p1.distance_m(p2, distance_algorithm);
+    let duration_s = (t2 - t1).abs();
+    if duration_s > 0.0 {
+        Some(distance_m / duration_s * 3.6)
+    } else if distance_m > 0.0 {
+        Some(f64::INFINITY)
+    } else {
+        Some(0.0)
+    }
+}
+
+
+/// Removes the points that could only have been reached at more than the given speed, connecting
+/// their neighbors directly instead.
+///
+/// A point is only considered an outlier if both the way there (from the previous remaining point)
+/// and the way onward (to the next point) are too fast, so a single genuine jump (e.g. when the
+/// position is reacquired after a tunnel) does not remove the rest of the line. The first point of
+/// a line is compared to the two points following it instead. Points without timestamps are never
+/// removed. Returns the number of removed points.
+pub fn remove_speed_outliers(lines: &mut Vec<Vec<Point>>, max_speed_km_per_h: f64, distance_algorithm: DistanceAlgorithm) -> usize {
+    let too_fast = |p1: &Point, p2: &Point| implied_speed_km_per_h(p1, p2, distance_algorithm)
+        .map(|s| s > max_speed_km_per_h);
+
+    let mut removed_count = 0;
+    for line in lines.iter_mut() {
+        let mut kept: Vec<Point> = Vec::with_capacity(line.len());
+        for (i, point) in line.iter().enumerate() {
+            let to_next = match line.get(i + 1) {
+                Some(next) => too_fast(point, next),
+                None => None,
+            };
+            let outlier = match kept.last() {
+                Some(previous) => {
+                    let from_previous = too_fast(previous, point);
+                    match (from_previous, to_next) {
+                        (Some(fp), Some(tn)) => fp && tn,
+                        (Some(fp), None) => fp,
+                        (None, _) => false,
+                    }
+                },
+                None => {
+                    let to_after_next = match line.get(i + 2) {
+                        Some(after_next) => too_fast(point, after_next),
+                        None => None,
+                    };
+                    match (to_next, to_after_next) {
+                        (Some(tn), Some(tan)) => tn && tan,
+                        (Some(tn), None) => tn,
+                        (None, _) => false,
+                    }
+                },
+            };
+
+            if outlier {
+                removed_count += 1;
+            } else {
+                kept.push(point.clone());
+            }
+        }
+        *line = kept;
+    }
+    lines.retain(|l| !l.is_empty());
+    removed_count
+}
+
+
+/// Splits the lines between each pair of consecutive points for which `is_discontinuity` returns
+/// `true`. Returns the number of splits.
+fn split_lines<F: Fn(&Point, &Point) -> bool>(lines: &mut Vec<Vec<Point>>, is_discontinuity: F) -> usize {
+    let mut split_count = 0;
+    let mut split_lines = Vec::with_capacity(lines.len());
+    for line in lines.drain(..) {
+        let mut current_line: Vec<Point> = Vec::with_capacity(line.len());
+        for point in line {
+            if let Some(previous) = current_line.last() {
+                if is_discontinuity(previous, &point) {
+                    split_lines.push(std::mem::take(&mut current_line));
+                    split_count += 1;
+                }
+            }
+            current_line.push(point);
+        }
+        if !current_line.is_empty() {
+            split_lines.push(current_line);
+        }
+    }
+    *lines = split_lines;
+    split_count
+}
+
+
+/// Splits the lines wherever more than the given number of seconds have passed between two
+/// consecutive points (e.g. because the device ran out of battery or the timer was not paused).
+/// Points without timestamps never cause a split. Returns the number of splits.
+pub fn split_at_time_gaps(lines: &mut Vec<Vec<Point>>, max_gap_s: f64) -> usize {
+    split_lines(lines, |p1, p2| match (p1.unix_timestamp, p2.unix_timestamp) {
+        (Some(t1), Some(t2)) => t2 - t1 > max_gap_s,
+        _ => false,
+    })
+}
+
+
+/// Splits the lines wherever two consecutive points are more than the given distance apart (e.g.
+/// when the position is reacquired after a long loss of reception). Returns the number of splits.
+pub fn split_at_jumps(lines: &mut Vec<Vec<Point>>, max_jump_m: f64, distance_algorithm: DistanceAlgorithm) -> usize {
+    split_lines(lines, |p1, p2| p1.distance_m(p2, distance_algorithm) > max_jump_m)
+}
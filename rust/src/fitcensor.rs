@@ -0,0 +1,338 @@
+//! Censoring of FIT files in place, producing a FIT file that can be uploaded to other services
+//! instead of the original.
+//!
+//! The file is rewritten at the level of its messages: the positions within censor areas are
+//! removed or blanked out, while all other messages (including developer data and those unknown to
+//! this program) are copied byte for byte. Only the data size in the file header and the checksums
+//! are recalculated.
+
+
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+
+use crate::censor::CensorArea;
+use crate::error::WalkingError;
+
+
+/// The global message number of record messages.
+const RECORD_MESSAGE: u16 = 20;
+
+/// The global message number of GPS metadata messages.
+const GPS_METADATA_MESSAGE: u16 = 160;
+
+/// The global message number of session messages.
+const SESSION_MESSAGE: u16 = 18;
+
+/// The global message number of location messages, which are saved locations (waypoints).
+const LOCATION_MESSAGE: u16 = 29;
+
+/// The messages containing positions, with the field numbers of the latitude and longitude of each
+/// position.
+const POSITION_FIELDS: [(u16, &[(u8, u8)]); 10] = [
+    (RECORD_MESSAGE, &[(0, 1)]),
+    (19, &[(3, 4), (5, 6)]), // lap: start and end position
+    (SESSION_MESSAGE, &[(3, 4), (38, 39)]), // start and end position
+    (LOCATION_MESSAGE, &[(1, 2)]),
+    (32, &[(2, 3)]), // course point
+    (128, &[(10, 11)]), // weather conditions: observed location
+    (GPS_METADATA_MESSAGE, &[(1, 2)]),
+    (285, &[(5, 6)]), // jump
+    (312, &[(21, 22), (23, 24)]), // split: start and end position
+    (317, &[(0, 1)]), // climb pro
+];
+
+/// The field numbers of the corners of the bounding box of a session (northeast and southwest),
+/// which are blanked out if any position has been censored.
+const SESSION_BOUNDS_FIELDS: [(u8, u8); 2] = [(29, 30), (31, 32)];
+
+/// The invalid value of a `sint32` field, used for blanked-out coordinates.
+const INVALID_SINT32: i32 = 0x7FFF_FFFF;
+
+const CRC_TABLE: [u16; 16] = [
+    0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401,
+    0xA001, 0x6C00, 0x7800, 0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+];
+
+
+/// What happens to the messages whose positions lie within a censor area.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum FitCensorMode {
+    /// Records, GPS metadata and saved locations within censor areas are removed entirely.
+    /// Positions of other messages (such as the start position of a lap) are blanked out.
+    #[default]
+    Remove,
+
+    /// Only the positions are blanked out, keeping the other values (such as the heart rate) of the
+    /// records.
+    Blank,
+}
+
+
+/// Calculates the CRC of the data as used in FIT files.
+pub fn fit_crc(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| {
+        let mut crc = crc;
+        for nibble in [byte & 0x0F, byte >> 4] {
+            let tmp = CRC_TABLE[(crc & 0x0F) as usize];
+            crc = ((crc >> 4) & 0x0FFF) ^ tmp ^ CRC_TABLE[nibble as usize];
+        }
+        crc
+    })
+}
+
+
+/// The location of a field within a data message.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct FieldLocation {
+    offset: usize,
+    size: usize,
+}
+
+
+/// The layout of the data messages of a local message type, as given by its definition message.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Definition {
+    global_message_number: u16,
+    big_endian: bool,
+    fields: HashMap<u8, FieldLocation>,
+
+    /// The size of a data message without its header byte, including developer fields.
+    size: usize,
+}
+impl Definition {
+    /// Reads the definition message at the start of `data` (after the header byte). Returns the
+    /// definition and the length of the message content.
+    fn parse(data: &[u8], has_developer_fields: bool) -> Result<(Self, usize), WalkingError> {
+        let truncated = || WalkingError::InvalidFit("truncated definition message".to_owned());
+        let fixed = data.get(0..5).ok_or_else(truncated)?;
+        let big_endian = fixed[1] == 1;
+        let number_bytes = [fixed[2], fixed[3]];
+        let global_message_number = if big_endian { u16::from_be_bytes(number_bytes) } else { u16::from_le_bytes(number_bytes) };
+        let field_count = fixed[4] as usize;
+
+        let mut definition = Self {
+            global_message_number,
+            big_endian,
+            ..Self::default()
+        };
+        let mut length = 5;
+        let field_definitions = data.get(length..length + 3 * field_count).ok_or_else(truncated)?;
+        for field in field_definitions.chunks_exact(3) {
+            let size = field[1] as usize;
+            definition.fields.insert(field[0], FieldLocation { offset: definition.size, size });
+            definition.size += size;
+        }
+        length += 3 * field_count;
+
+        if has_developer_fields {
+            let developer_field_count = *data.get(length).ok_or_else(truncated)? as usize;
+            length += 1;
+            let developer_field_definitions = data.get(length..length + 3 * developer_field_count).ok_or_else(truncated)?;
+            definition.size += developer_field_definitions.chunks_exact(3)
+                .map(|f| f[1] as usize)
+                .sum::<usize>();
+            length += 3 * developer_field_count;
+        }
+        Ok((definition, length))
+    }
+
+    /// Returns the value of a `sint32` field of the message, or `None` if the message lacks the
+    /// field or its value is invalid.
+    fn sint32(&self, message: &[u8], field_number: u8) -> Option<i32> {
+        let location = self.fields.get(&field_number).filter(|l| l.size == 4)?;
+        let bytes: [u8; 4] = message[location.offset..location.offset + 4].try_into().unwrap();
+        let value = if self.big_endian { i32::from_be_bytes(bytes) } else { i32::from_le_bytes(bytes) };
+        (value != INVALID_SINT32).then_some(value)
+    }
+
+    /// Sets a `sint32` field of the message to the invalid value, if the message has the field.
+    fn invalidate_sint32(&self, message: &mut [u8], field_number: u8) {
+        if let Some(location) = self.fields.get(&field_number).filter(|l| l.size == 4) {
+            let bytes = if self.big_endian { INVALID_SINT32.to_be_bytes() } else { INVALID_SINT32.to_le_bytes() };
+            message[location.offset..location.offset + 4].copy_from_slice(&bytes);
+        }
+    }
+
+    /// Returns the position given by the latitude and longitude fields of the message, if both are
+    /// valid.
+    fn position(&self, message: &[u8], (lat_field, lon_field): (u8, u8)) -> Option<geo::Point<f64>> {
+        const DEG_PER_SEMICIRCLE: f64 = 180.0 / 2_147_483_648.0;
+        let lat = self.sint32(message, lat_field)?;
+        let lon = self.sint32(message, lon_field)?;
+        Some(geo::Point::new(lon as f64 * DEG_PER_SEMICIRCLE, lat as f64 * DEG_PER_SEMICIRCLE))
+    }
+}
+
+
+/// The numbers of messages affected by censoring a FIT file.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct FitCensorCounts {
+    /// The number of record messages with a valid position in the original file.
+    pub positions_read: usize,
+
+    /// The number of record messages whose position lies within a censor area.
+    pub records_censored: usize,
+
+    /// The number of messages removed from the file.
+    pub messages_removed: usize,
+}
+
+
+/// Censors the messages of a single FIT file (without its header and CRC).
+///
+/// If `blank_bounds` is set, the bounding box of sessions is blanked out as well.
+fn censor_messages(data: &[u8], censor_areas: &[CensorArea], mode: FitCensorMode, blank_bounds: bool, counts: &mut FitCensorCounts) -> Result<Vec<u8>, WalkingError> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut definitions: HashMap<u8, Definition> = HashMap::new();
+    let mut position = 0;
+    while position < data.len() {
+        let header = data[position];
+        let content = &data[position + 1..];
+
+        if header & 0x80 == 0 && header & 0x40 != 0 {
+            // definition message
+            let (definition, length) = Definition::parse(content, header & 0x20 != 0)?;
+            definitions.insert(header & 0x0F, definition);
+            output.extend_from_slice(&data[position..position + 1 + length]);
+            position += 1 + length;
+            continue;
+        }
+
+        // data message, with a normal or compressed timestamp header
+        let local_message_type = if header & 0x80 != 0 { (header >> 5) & 0x03 } else { header & 0x0F };
+        let definition = definitions.get(&local_message_type)
+            .ok_or_else(|| WalkingError::InvalidFit(format!("data message of undefined local type {}", local_message_type)))?;
+        let mut message = content.get(..definition.size)
+            .ok_or_else(|| WalkingError::InvalidFit("truncated data message".to_owned()))?
+            .to_vec();
+        position += 1 + definition.size;
+
+        let global_message_number = definition.global_message_number;
+        let position_fields = POSITION_FIELDS.iter()
+            .find(|(number, _)| *number == global_message_number)
+            .map(|(_, fields)| *fields)
+            .unwrap_or(&[]);
+        let mut censored = false;
+        for &fields in position_fields {
+            let point = match definition.position(&message, fields) {
+                Some(p) => p,
+                None => continue,
+            };
+            if global_message_number == RECORD_MESSAGE {
+                counts.positions_read += 1;
+            }
+            if censor_areas.iter().any(|a| a.contains(&point)) {
+                censored = true;
+                definition.invalidate_sint32(&mut message, fields.0);
+                definition.invalidate_sint32(&mut message, fields.1);
+            }
+        }
+        if global_message_number == SESSION_MESSAGE && blank_bounds {
+            for (lat_field, lon_field) in SESSION_BOUNDS_FIELDS {
+                definition.invalidate_sint32(&mut message, lat_field);
+                definition.invalidate_sint32(&mut message, lon_field);
+            }
+        }
+
+        if censored && global_message_number == RECORD_MESSAGE {
+            counts.records_censored += 1;
+        }
+        let removable = matches!(global_message_number, RECORD_MESSAGE | GPS_METADATA_MESSAGE | LOCATION_MESSAGE);
+        if censored && removable && mode == FitCensorMode::Remove {
+            counts.messages_removed += 1;
+            continue;
+        }
+        output.push(header);
+        output.extend_from_slice(&message);
+    }
+    Ok(output)
+}
+
+
+/// Returns the length of the complete messages at the start of `data` (the messages of a FIT file,
+/// without its header), i.e. the position of the first message that is truncated or cannot be
+/// decoded. Used for files that are still being written.
+pub(crate) fn complete_messages_length(data: &[u8]) -> usize {
+    let mut sizes: HashMap<u8, usize> = HashMap::new();
+    let mut position = 0;
+    while position < data.len() {
+        let header = data[position];
+        let content = &data[position + 1..];
+
+        let length = if header & 0x80 == 0 && header & 0x40 != 0 {
+            match Definition::parse(content, header & 0x20 != 0) {
+                Ok((definition, length)) => {
+                    sizes.insert(header & 0x0F, definition.size);
+                    length
+                },
+                Err(_) => break,
+            }
+        } else {
+            let local_message_type = if header & 0x80 != 0 { (header >> 5) & 0x03 } else { header & 0x0F };
+            match sizes.get(&local_message_type) {
+                Some(&size) if size <= content.len() => size,
+                _ => break,
+            }
+        };
+        position += 1 + length;
+    }
+    position
+}
+
+
+/// Censors a single FIT file at the start of `data`. Returns the censored file and the length of
+/// the original file.
+fn censor_fit_file(data: &[u8], censor_areas: &[CensorArea], mode: FitCensorMode, counts: &mut FitCensorCounts) -> Result<(Vec<u8>, usize), WalkingError> {
+    let header_size = *data.first()
+        .ok_or_else(|| WalkingError::InvalidFit("empty file".to_owned()))? as usize;
+    if header_size < 12 || data.get(8..12) != Some(b".FIT") {
+        return Err(WalkingError::InvalidFit("missing FIT header".to_owned()));
+    }
+    let header = data.get(..header_size)
+        .ok_or_else(|| WalkingError::InvalidFit("truncated header".to_owned()))?;
+    let data_size = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    let messages = data.get(header_size..header_size + data_size)
+        .ok_or_else(|| WalkingError::InvalidFit("truncated file".to_owned()))?;
+
+    // the bounding box of the session reveals the extent of the censored positions, but censoring
+    // only becomes apparent while going through the records, which usually come first
+    let mut file_counts = FitCensorCounts::default();
+    let mut censored_messages = censor_messages(messages, censor_areas, mode, false, &mut file_counts)?;
+    if file_counts.records_censored > 0 {
+        file_counts = FitCensorCounts::default();
+        censored_messages = censor_messages(messages, censor_areas, mode, true, &mut file_counts)?;
+    }
+    counts.positions_read += file_counts.positions_read;
+    counts.records_censored += file_counts.records_censored;
+    counts.messages_removed += file_counts.messages_removed;
+
+    let mut output = header.to_vec();
+    output[4..8].copy_from_slice(&(censored_messages.len() as u32).to_le_bytes());
+    if header_size >= 14 {
+        let header_crc = fit_crc(&output[..12]);
+        output[12..14].copy_from_slice(&header_crc.to_le_bytes());
+    }
+    output.extend_from_slice(&censored_messages);
+    let crc = fit_crc(&output);
+    output.extend_from_slice(&crc.to_le_bytes());
+    Ok((output, header_size + data_size + 2))
+}
+
+
+/// Censors the positions within the censor areas in the FIT data, which may consist of multiple
+/// chained FIT files. Returns the censored data and the numbers of affected messages.
+///
+/// Censoring depends on the positions alone; unlike the conversion into other formats, filtering,
+/// trimming and smoothing are not applied.
+pub fn censor_fit(data: &[u8], censor_areas: &[CensorArea], mode: FitCensorMode) -> Result<(Vec<u8>, FitCensorCounts), WalkingError> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut counts = FitCensorCounts::default();
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (file, length) = censor_fit_file(rest, censor_areas, mode, &mut counts)?;
+        output.extend_from_slice(&file);
+        rest = &rest[length.min(rest.len())..];
+    }
+    Ok((output, counts))
+}
@@ -0,0 +1,207 @@
+//! Reverse geocoding of track points into place names, using either a local gazetteer file or a
+//! (self-hosted) Nominatim server.
+
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+
+use geo::{Distance, Haversine};
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+
+
+/// Places in a gazetteer that are farther away than this from a point are not used as its name,
+/// in meters.
+pub const MAX_GAZETTEER_DISTANCE_M: f64 = 25_000.0;
+
+/// How long to wait for a Nominatim server to connect or respond.
+const NOMINATIM_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// A named place of a gazetteer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Place {
+    pub name: String,
+    pub coordinates_deg: geo::Point<f64>,
+}
+
+
+/// A source of place names.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Geocoder {
+    /// Places loaded from a local file; the nearest one is used.
+    Gazetteer(Vec<Place>),
+
+    /// A Nominatim server reachable via plain HTTP.
+    Nominatim {
+        host: String,
+        port: u16,
+
+        /// The path of the server's base URL, without a trailing slash.
+        base_path: String,
+    },
+}
+impl Geocoder {
+    /// Opens the given source of place names: an `http://` URL of a Nominatim server or the path
+    /// of a gazetteer file (see [`load_gazetteer`]).
+    pub fn open(source: &str) -> Result<Self, WalkingError> {
+        if source.starts_with("https://") {
+            return Err(WalkingError::Geocoding(
+                "HTTPS is not supported; use a Nominatim server reachable via http:// or a gazetteer file".to_owned()
+            ));
+        }
+        match source.strip_prefix("http://") {
+            Some(rest) => {
+                let (authority, path) = match rest.find('/') {
+                    Some(slash_index) => (&rest[..slash_index], &rest[slash_index..]),
+                    None => (rest, ""),
+                };
+                let (host, port) = match authority.rsplit_once(':') {
+                    Some((host, port_str)) => {
+                        let port = port_str.parse()
+                            .map_err(|_| WalkingError::Geocoding(format!("invalid port {:?}", port_str)))?;
+                        (host, port)
+                    },
+                    None => (authority, 80),
+                };
+                if host.is_empty() {
+                    return Err(WalkingError::Geocoding(format!("URL {:?} has no host", source)));
+                }
+                Ok(Self::Nominatim {
+                    host: host.to_owned(),
+                    port,
+                    base_path: path.trim_end_matches('/').to_owned(),
+                })
+            },
+            None => Ok(Self::Gazetteer(load_gazetteer(Path::new(source))?)),
+        }
+    }
+
+    /// Returns the name of the place at the given point (in degrees), or `None` if there is no
+    /// known place nearby.
+    pub fn place_name(&self, point: geo::Point<f64>) -> Result<Option<String>, WalkingError> {
+        match self {
+            Self::Gazetteer(places) => {
+                let nearest = places.iter()
+                    .map(|p| (p, Haversine::distance(p.coordinates_deg, point)))
+                    .filter(|(_, distance_m)| *distance_m <= MAX_GAZETTEER_DISTANCE_M)
+                    .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+                Ok(nearest.map(|(p, _)| p.name.clone()))
+            },
+            Self::Nominatim { host, port, base_path } => {
+                let path = format!(
+                    "{}/reverse?format=jsonv2&zoom=14&lat={}&lon={}",
+                    base_path, point.y(), point.x(),
+                );
+                let body = http_get(host, *port, &path)?;
+                let response: serde_json::Value = serde_json::from_slice(&body)?;
+                if response.get("error").is_some() {
+                    // nothing found at this location
+                    return Ok(None);
+                }
+                let name = response.get("name")
+                    .and_then(|n| n.as_str())
+                    .filter(|n| !n.is_empty())
+                    .or_else(|| response.get("display_name").and_then(|n| n.as_str()));
+                Ok(name.map(|n| n.to_owned()))
+            },
+        }
+    }
+}
+
+
+/// Loads places from a gazetteer file.
+///
+/// Each line contains tab-separated fields: either a name, a latitude and a longitude, or the
+/// fields of a GeoNames dump (e.g. `cities15000.txt`), of which the name, latitude and longitude
+/// are used. Empty lines and lines starting with `#` are ignored.
+pub fn load_gazetteer(path: &Path) -> Result<Vec<Place>, WalkingError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut places = Vec::new();
+    for (i, line_res) in reader.lines().enumerate() {
+        let line = line_res?;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (name, lat_str, lon_str) = match fields.len() {
+            3 => (fields[0], fields[1], fields[2]),
+            n if n >= 6 => (fields[1], fields[4], fields[5]),
+            n => return Err(WalkingError::Geocoding(format!(
+                "{} line {}: {} fields; expected 3 or at least 6", path.display(), i + 1, n,
+            ))),
+        };
+        let parse_coordinate = |s: &str| -> Result<f64, WalkingError> {
+            s.trim().parse()
+                .map_err(|e| WalkingError::Geocoding(format!(
+                    "{} line {}: failed to parse {:?} as a coordinate: {}", path.display(), i + 1, s, e,
+                )))
+        };
+        places.push(Place {
+            name: name.trim().to_owned(),
+            coordinates_deg: geo::Point::new(parse_coordinate(lon_str)?, parse_coordinate(lat_str)?),
+        });
+    }
+    Ok(places)
+}
+
+
+/// Performs an HTTP GET request and returns the body of the response, which must have status 200.
+fn http_get(host: &str, port: u16, path: &str) -> Result<Vec<u8>, WalkingError> {
+    let address = (host, port).to_socket_addrs()?
+        .next()
+        .ok_or_else(|| WalkingError::Geocoding(format!("failed to resolve {:?}", host)))?;
+    let mut stream = TcpStream::connect_timeout(&address, NOMINATIM_TIMEOUT)?;
+    stream.set_read_timeout(Some(NOMINATIM_TIMEOUT))?;
+    stream.set_write_timeout(Some(NOMINATIM_TIMEOUT))?;
+
+    // HTTP/1.0 ensures that the body is neither chunked nor followed by another response
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: fit2walking/{}\r\nAccept: application/json\r\n\r\n",
+        path, host, env!("CARGO_PKG_VERSION"),
+    )?;
+    stream.flush()?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    let header_end = response.windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| WalkingError::Geocoding("incomplete HTTP response".to_owned()))?;
+    let header = String::from_utf8_lossy(&response[..header_end]);
+    let status_line = header.lines().next().unwrap_or("");
+    let status = status_line.split_whitespace().nth(1).unwrap_or("");
+    if status != "200" {
+        return Err(WalkingError::Geocoding(format!("server responded with {:?}", status_line)));
+    }
+    Ok(response[header_end+4..].to_vec())
+}
+
+
+/// Sets the start and end place of the activity's metadata to the names of the places at the first
+/// point of the first line and the last point of the last line.
+pub fn geocode_activity(activity: &mut Activity, geocoder: &Geocoder) -> Result<(), WalkingError> {
+    let first_point = activity.lines.iter()
+        .flatten()
+        .next();
+    let last_point = activity.lines.iter()
+        .flatten()
+        .next_back();
+    let (first_point, last_point) = match (first_point, last_point) {
+        (Some(first), Some(last)) => (first.coordinates_deg, last.coordinates_deg),
+        _ => return Ok(()),
+    };
+    activity.metadata.start_place = geocoder.place_name(first_point)?;
+    activity.metadata.end_place = if last_point == first_point {
+        activity.metadata.start_place.clone()
+    } else {
+        geocoder.place_name(last_point)?
+    };
+    Ok(())
+}
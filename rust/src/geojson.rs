@@ -0,0 +1,1778 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, TimeZone};
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use serde::ser::{SerializeMap, SerializeSeq};
+
+use crate::activity::{Activity, Pause, Summary};
+use crate::color::{ColorBy, ColorClassifier, ColorScale};
+use crate::efforts::{best_efforts, BestEffort, EffortMeasure, EffortWindow};
+use crate::elevation::{
+    DEFAULT_ELEVATION_THRESHOLD_M, elevation_gain_loss, grade_adjusted_speed_km_per_h, gradient_extrema,
+    gradient_percent, vertical_speed_extrema, vertical_speed_m_per_h,
+};
+use crate::error::WalkingError;
+use crate::multisport::{Legs, split_legs};
+use crate::point::{DistanceAlgorithm, GeoPoint, Point};
+use crate::route::{match_route, Route};
+use crate::schema::{
+    CyclingDynamicsProperties, DocumentEntries, Feature, FeatureCollection, FORMAT_VERSION, Geometry, LegEntries,
+    MetadataEntries, PauseEntries, PointProperties, RunningDynamicsProperties, SegmentProperties, SummaryEntries,
+    Temperature, to_json, to_json_object, TransitionEntries,
+};
+use crate::series::lines_to_series;
+use crate::stats::{
+    compute_statistics, DEFAULT_AGE_YEARS, DEFAULT_MOVING_THRESHOLD_KM_PER_H, estimate_calories_kcal,
+    normalized_power, statistics_to_json, training_impulse,
+};
+use crate::stops::{DEFAULT_STOP_RADIUS_M, detect_stops, Stop};
+use crate::swim::stroke_times_s;
+use crate::timezone::Timezone;
+use crate::units::Units;
+use crate::viewport::{estimate_zoom, Viewport};
+use crate::zones::{HeartRateZones, segment_heart_rate_bpm, time_in_zones};
+
+
+/// The geometry of the features in the `points` collection.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum PointsGeometry {
+    /// One two-point LineString per pair of consecutive points, with averaged measurements.
+    #[default]
+    Segment,
+
+    /// One Point per recorded point, with the measurements as recorded.
+    Point,
+}
+
+
+/// The source of the `running_distance` and `segment_length` properties.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum DistanceSource {
+    /// The distance calculated from the coordinates of the points.
+    #[default]
+    Computed,
+
+    /// The cumulative distance recorded by the device (e.g. using a wheel sensor), counted from
+    /// the first point of the track; segment lengths are the differences between the recorded
+    /// distances. The calculated distance is used where the device has not recorded one. Both
+    /// running distances are also output as `running_distance_computed` and
+    /// `running_distance_device`.
+    Device,
+}
+
+
+/// A property of the features in the `points` collection.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, ValueEnum)]
+pub enum Property {
+    #[value(alias = "running_distance")]
+    RunningDistance,
+
+    /// The length of a segment; not output for Point features.
+    #[value(alias = "segment_length")]
+    SegmentLength,
+
+    /// The time elapsed along a segment, in seconds; not output for Point features.
+    #[value(alias = "segment_duration")]
+    SegmentDuration,
+    Speed,
+    Pace,
+
+    /// The pace adjusted for the gradient (the pace on flat ground requiring the same effort); not
+    /// output for Point features.
+    #[value(alias = "gap_pace", alias = "gap")]
+    GapPace,
+    Elevation,
+    Gradient,
+
+    /// The rate of ascent or descent along a segment; not output for Point features.
+    #[value(alias = "vertical_speed", alias = "vam")]
+    VerticalSpeed,
+    #[value(alias = "hr", alias = "heart_rate")]
+    HeartRate,
+    #[value(alias = "respiration_rate")]
+    RespirationRate,
+    Spo2,
+    #[value(alias = "hr_zone")]
+    HrZone,
+    Cadence,
+    Temperature,
+
+    /// The temperature felt at the speed of travel; only output with derived temperatures.
+    #[value(alias = "apparent_temperature")]
+    ApparentTemperature,
+    Power,
+    Timestamp,
+
+    /// The direction of travel along a segment; not output for Point features.
+    Bearing,
+
+    /// All developer fields read from the input file.
+    #[value(alias = "developer_fields")]
+    DeveloperFields,
+
+    /// The pedaling balance, torque effectiveness and pedal smoothness recorded by power meters;
+    /// only output with cycling dynamics.
+    #[value(alias = "cycling_dynamics")]
+    CyclingDynamics,
+
+    /// The stance time (in ms), vertical oscillation and step length (in mm) recorded by running
+    /// sensors; only output with running dynamics.
+    #[value(alias = "running_dynamics")]
+    RunningDynamics,
+}
+
+
+/// Options influencing the generated document.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentOptions {
+    /// The viewport for which the initial zoom level is estimated.
+    pub viewport: Viewport,
+
+    /// The units in which measurements and derived values (such as pace) are output.
+    pub units: Units,
+
+    /// Whether to output a Point feature at the end of each lap.
+    pub lap_markers: bool,
+
+    /// Whether to output a Point feature at the position of each pause.
+    pub pause_markers: bool,
+
+    /// Whether to output a Point feature at every kilometer or mile (depending on `units`).
+    pub splits: bool,
+
+    /// The number of points to which the chart series of the measurements are downsampled, if they
+    /// are to be output.
+    pub series_points: Option<usize>,
+
+    /// The distance between Point features indicating the direction of travel, in meters, if they
+    /// are to be output.
+    pub direction_arrows_m: Option<f64>,
+
+    /// The minimum duration of a stop, in seconds, if stops are to be output.
+    pub stop_min_duration_s: Option<f64>,
+
+    /// The radius within which the position must stay for a stop, in meters.
+    pub stop_radius_m: f64,
+
+    /// Whether to output the apparent temperature (see [`Point::apparent_temperature_degc`]) and
+    /// its range.
+    pub derived_temps: bool,
+
+    /// Whether to output the cycling dynamics recorded by power meters (pedaling balance, torque
+    /// effectiveness and pedal smoothness).
+    pub cycling_dynamics: bool,
+
+    /// Whether to output the running dynamics recorded by running sensors (stance time, vertical
+    /// oscillation and step length) and their ranges.
+    pub running_dynamics: bool,
+
+    /// Whether the activity is a swim: the cadence is output as the stroke rate, and the pace per
+    /// 100 meters or yards, its range and the time spent on each stroke are added.
+    pub swim: bool,
+
+    /// The body weight with which the energy expenditure is estimated if the device has not
+    /// recorded it, in kilograms.
+    pub weight_kg: Option<f64>,
+
+    /// The age with which the energy expenditure is estimated, in years.
+    pub age_years: f64,
+
+    /// The minimum change in elevation that counts towards elevation gain or loss, in meters.
+    pub elevation_threshold_m: f64,
+
+    /// The speed below which a segment does not count towards the moving time, in km/h.
+    pub moving_threshold_km_per_h: f64,
+
+    /// The method by which distances between points are calculated.
+    pub distance_algorithm: DistanceAlgorithm,
+
+    /// The heart rate zones into which segments are classified, if any.
+    pub heart_rate_zones: Option<HeartRateZones>,
+
+    /// The resting and maximum heart rates with which the training impulse (TRIMP) is calculated,
+    /// if it is to be output.
+    pub trimp_heart_rates_bpm: Option<(u64, u64)>,
+
+    /// The functional threshold power with which the intensity factor and the training stress
+    /// score are calculated, in watts, if they are to be output.
+    pub ftp_w: Option<f64>,
+
+    /// The windows over which best efforts are searched and output with the statistics.
+    pub best_effort_windows: Vec<EffortWindow>,
+
+    /// Whether to also output the sections of the track on which the best efforts were achieved.
+    pub best_effort_markers: bool,
+
+    /// The number of decimal places to which coordinates are rounded, if any.
+    pub coordinate_precision: Option<u32>,
+
+    /// Whether to also round the non-integer property values of the segments to
+    /// `coordinate_precision` decimal places.
+    pub round_properties: bool,
+
+    /// Whether to output minified instead of pretty-printed JSON.
+    pub compact: bool,
+
+    /// The time zone in which timestamps are output.
+    pub timezone: Timezone,
+
+    /// The geometry of the features in the `points` collection.
+    pub points_geometry: PointsGeometry,
+
+    /// Whether to output a bounding box for each LineString feature in addition to the bounding
+    /// boxes of the FeatureCollections.
+    pub feature_bbox: bool,
+
+    /// Whether to output the elevation (in meters, whatever the units) as the third coordinate of
+    /// each position whose point has one, as allowed by RFC 7946.
+    pub coords_3d: bool,
+
+    /// Whether to also output the recorded values of smoothed measurements (as `speed_raw` and
+    /// `heart_rate_raw`).
+    pub raw_values: bool,
+
+    /// Whether to output every selected property for all features of the `points` collection, as
+    /// `null` where its value is unknown, so that the features have the same properties.
+    pub null_missing: bool,
+
+    /// The properties output for the features of the `points` collection, or `None` to output
+    /// all of them.
+    pub properties: Option<BTreeSet<Property>>,
+
+    /// The measurement by which the features of the `points` collection are classified, if any.
+    /// Each feature with a value receives its `class` and the `color` of the class.
+    pub color_by: Option<ColorBy>,
+
+    /// The scale from which the colors of the classes are taken.
+    pub color_scale: ColorScale,
+
+    /// The source of the `running_distance` and `segment_length` properties.
+    pub distance_source: DistanceSource,
+
+    /// The reference route against which the track is matched, if any.
+    pub route: Option<Arc<Route>>,
+}
+impl DocumentOptions {
+    /// Returns whether the given property is output for the features of the `points` collection.
+    pub fn includes(&self, property: Property) -> bool {
+        match &self.properties {
+            Some(properties) => properties.contains(&property),
+            None => true,
+        }
+    }
+}
+impl Default for DocumentOptions {
+    fn default() -> Self {
+        Self {
+            viewport: Viewport::default(),
+            units: Units::default(),
+            lap_markers: false,
+            pause_markers: false,
+            splits: false,
+            series_points: None,
+            direction_arrows_m: None,
+            stop_min_duration_s: None,
+            stop_radius_m: DEFAULT_STOP_RADIUS_M,
+            derived_temps: false,
+            cycling_dynamics: false,
+            running_dynamics: false,
+            swim: false,
+            weight_kg: None,
+            age_years: DEFAULT_AGE_YEARS,
+            elevation_threshold_m: DEFAULT_ELEVATION_THRESHOLD_M,
+            moving_threshold_km_per_h: DEFAULT_MOVING_THRESHOLD_KM_PER_H,
+            distance_algorithm: DistanceAlgorithm::default(),
+            heart_rate_zones: None,
+            trimp_heart_rates_bpm: None,
+            ftp_w: None,
+            best_effort_windows: Vec::new(),
+            best_effort_markers: false,
+            coordinate_precision: None,
+            round_properties: false,
+            compact: false,
+            timezone: Timezone::default(),
+            points_geometry: PointsGeometry::default(),
+            feature_bbox: false,
+            coords_3d: false,
+            raw_values: false,
+            null_missing: false,
+            properties: None,
+            color_by: None,
+            color_scale: ColorScale::default(),
+            distance_source: DistanceSource::default(),
+            route: None,
+        }
+    }
+}
+
+
+fn avg<T, A>(v1: Option<T>, v2: Option<T>, average: A) -> Option<T>
+    where
+        A : FnOnce(T, T) -> T,
+{
+    match (v1, v2) {
+        (None, None) => None,
+        (Some(s1), None) => Some(s1),
+        (None, Some(s2)) => Some(s2),
+        (Some(s1), Some(s2)) => Some(average(s1, s2)),
+    }
+}
+
+/// Averages the values, ignoring those that are not finite.
+fn f64_mean(f1: Option<f64>, f2: Option<f64>) -> Option<f64> {
+    avg(f1.filter(|v| v.is_finite()), f2.filter(|v| v.is_finite()), |a, b| (a + b)/2.0)
+}
+
+fn i64_mean(i1: Option<i64>, i2: Option<i64>) -> Option<i64> {
+    avg(i1, i2, |a, b| (a + b)/2)
+}
+
+fn u64_mean(i1: Option<u64>, i2: Option<u64>) -> Option<u64> {
+    avg(i1, i2, |a, b| (a + b)/2)
+}
+
+fn timestamp_string(timestamp: &DateTime<Local>, timezone: &Timezone) -> String {
+    timezone.convert(timestamp).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+pub(crate) fn format_timestamp(timestamp: &DateTime<Local>, timezone: &Timezone) -> serde_json::Value {
+    serde_json::Value::String(timestamp_string(timestamp, timezone))
+}
+
+fn time_mean(t1: Option<DateTime<Local>>, t2: Option<DateTime<Local>>) -> Option<DateTime<Local>> {
+    avg(t1, t2, |a, b| Local.timestamp_opt((a.timestamp() + b.timestamp()) / 2, 0).unwrap())
+}
+
+/// A GeoJSON FeatureCollection whose features are generated one by one while it is being
+/// serialized, so that the whole collection never has to be held in memory.
+struct StreamedFeatureCollection<F> {
+    bbox: Option<Vec<f64>>,
+    make_features: F,
+}
+impl<F, I> Serialize for StreamedFeatureCollection<F>
+    where
+        F: Fn() -> I,
+        I: Iterator<Item = serde_json::Value>,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(bbox) = &self.bbox {
+            map.serialize_entry("bbox", bbox)?;
+        }
+        map.serialize_entry("features", &StreamedFeatures(&self.make_features))?;
+        map.serialize_entry("type", "FeatureCollection")?;
+        map.end()
+    }
+}
+
+struct StreamedFeatures<'a, F>(&'a F);
+
+type FeatureIter<'a> = Box<dyn Iterator<Item = serde_json::Value> + 'a>;
+impl<F, I> Serialize for StreamedFeatures<'_, F>
+    where
+        F: Fn() -> I,
+        I: Iterator<Item = serde_json::Value>,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        for mut feature in (self.0)() {
+            feature.sort_all_objects();
+            seq.serialize_element(&feature)?;
+        }
+        seq.end()
+    }
+}
+
+
+fn round_to(value: f64, decimals: u32) -> f64 {
+    let factor = 10.0_f64.powi(decimals as i32);
+    (value * factor).round() / factor
+}
+
+/// Outputs the coordinates of the point, rounded if requested, followed by its elevation in meters
+/// if 3D coordinates are requested and it has one.
+fn lonlat_list(point: &Point, options: &DocumentOptions) -> serde_json::Value {
+    let mut list = match options.coordinate_precision {
+        Some(decimals) => serde_json::json!([
+            round_to(point.longitude(), decimals),
+            round_to(point.latitude(), decimals),
+        ]),
+        None => point.as_lonlat_list(),
+    };
+    if let (Some(ele), true) = (point.elevation_m.filter(|e| e.is_finite()), options.coords_3d) {
+        if let Some(coordinates) = list.as_array_mut() {
+            coordinates.push(ele.into());
+        }
+    }
+    list
+}
+
+/// Calculates the bounding box (`[west, south, east, north]`) of the points, rounded like the
+/// coordinates. With 3D coordinates, the lowest and highest elevations are added (`[west, south,
+/// lowest, east, north, highest]`) if any of the points has an elevation. Returns `None` if there
+/// are no points.
+fn points_bbox<'a, I: IntoIterator<Item = &'a Point>>(points: I, options: &DocumentOptions) -> Option<Vec<f64>> {
+    let mut elevation_range: Option<(f64, f64)> = None;
+    let bbox = points.into_iter()
+        .inspect(|p| {
+            if let (Some(ele), true) = (p.elevation_m.filter(|e| e.is_finite()), options.coords_3d) {
+                elevation_range = Some(match elevation_range {
+                    None => (ele, ele),
+                    Some((lowest, highest)) => (lowest.min(ele), highest.max(ele)),
+                });
+            }
+        })
+        .fold(None, |bbox: Option<[f64; 4]>, p| {
+            let (lon, lat) = (p.longitude(), p.latitude());
+            Some(match bbox {
+                None => [lon, lat, lon, lat],
+                Some([west, south, east, north]) => [west.min(lon), south.min(lat), east.max(lon), north.max(lat)],
+            })
+        })?;
+    let [west, south, east, north] = match options.coordinate_precision {
+        Some(decimals) => bbox.map(|c| round_to(c, decimals)),
+        None => bbox,
+    };
+    match elevation_range {
+        Some((lowest, highest)) => Some(vec![west, south, lowest, east, north, highest]),
+        None => Some(vec![west, south, east, north]),
+    }
+}
+
+/// Rounds all floating-point values in the properties to the given number of decimal places.
+fn round_property_values(properties: &mut serde_json::Map<String, serde_json::Value>, decimals: u32) {
+    for value in properties.values_mut() {
+        if let Some(f) = value.as_f64().filter(|_| value.is_f64()) {
+            *value = round_to(f, decimals).into();
+        }
+    }
+}
+
+
+fn line_to_feature(line: &[Point], options: &DocumentOptions) -> serde_json::Value {
+    let coordinates: Vec<serde_json::Value> = line
+        .iter()
+        .map(|p| lonlat_list(p, options))
+        .collect();
+    let feature = Feature {
+        geometry: Geometry::LineString(coordinates),
+        properties: serde_json::Map::new(),
+        bbox: options.feature_bbox.then(|| points_bbox(line, options)).flatten(),
+    };
+    to_json(&feature)
+}
+
+fn track_collection<'a>(lines: &'a [Vec<Point>], options: &'a DocumentOptions) -> StreamedFeatureCollection<impl Fn() -> FeatureIter<'a>> {
+    StreamedFeatureCollection {
+        bbox: points_bbox(lines.iter().flatten(), options),
+        make_features: move || -> FeatureIter<'a> {
+            // a LineString needs at least two positions
+            Box::new(
+                lines.iter()
+                    .filter(|l| l.len() > 1)
+                    .map(|l| line_to_feature(l, options))
+            )
+        },
+    }
+}
+
+/// Converts the lines into a GeoJSON FeatureCollection with one LineString per line. Lines
+/// consisting of a single point are skipped.
+pub fn lines_to_track(lines: &[Vec<Point>], options: &DocumentOptions) -> serde_json::Value {
+    serde_json::to_value(track_collection(lines, options))
+        .expect("failed to convert track to JSON value")
+}
+
+
+/// The distance from the start of the track to a feature of the `points` collection.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct RunningDistance {
+    /// The distance calculated from the coordinates of the points, in meters.
+    computed_m: f64,
+
+    /// The distance recorded by the device, in meters, if requested and available.
+    device_m: Option<f64>,
+}
+
+/// Returns the running distance, and with device distances also the computed and device distances
+/// it has been chosen from, in the units of the document.
+fn running_distance_values(running_dist: RunningDistance, options: &DocumentOptions) -> (f64, Option<f64>, Option<f64>) {
+    let units = options.units;
+    match options.distance_source {
+        DistanceSource::Computed => (units.distance(running_dist.computed_m), None, None),
+        DistanceSource::Device => {
+            let dist_m = running_dist.device_m.unwrap_or(running_dist.computed_m);
+            (
+                units.distance(dist_m),
+                Some(units.distance(running_dist.computed_m)),
+                running_dist.device_m.map(|device_m| units.distance(device_m)),
+            )
+        },
+    }
+}
+
+/// Returns the length of the segment between the points in meters: with device distances the
+/// difference between the distances recorded at its ends, otherwise (or where the device has not
+/// recorded both) the distance calculated from the coordinates.
+fn segment_length_m(point1: &Point, point2: &Point, options: &DocumentOptions) -> f64 {
+    match (options.distance_source, point1.device_distance_m, point2.device_distance_m) {
+        (DistanceSource::Device, Some(device1_m), Some(device2_m)) => device2_m - device1_m,
+        _ => point1.distance_m(point2, options.distance_algorithm),
+    }
+}
+
+/// The names of the properties of the cycling dynamics, in the order of the fields of
+/// [`CyclingDynamicsProperties`].
+const CYCLING_DYNAMICS_KEYS: [&str; 6] = [
+    "left_right_balance", "left_torque_effectiveness", "right_torque_effectiveness",
+    "left_pedal_smoothness", "right_pedal_smoothness", "combined_pedal_smoothness",
+];
+
+/// The names of the properties of the running dynamics, in the order of the fields of
+/// [`RunningDynamicsProperties`].
+const RUNNING_DYNAMICS_KEYS: [&str; 3] = ["stance_time", "vertical_oscillation", "step_length"];
+
+/// Returns the cycling dynamics of the point, in the order of [`CYCLING_DYNAMICS_KEYS`].
+fn cycling_dynamics(point: &Point) -> [Option<f64>; 6] {
+    [
+        point.left_balance_percent,
+        point.left_torque_effectiveness_percent,
+        point.right_torque_effectiveness_percent,
+        point.left_pedal_smoothness_percent,
+        point.right_pedal_smoothness_percent,
+        point.combined_pedal_smoothness_percent,
+    ]
+}
+
+/// Returns the running dynamics of the point, in the order of [`RUNNING_DYNAMICS_KEYS`].
+fn running_dynamics(point: &Point) -> [Option<f64>; 3] {
+    [point.stance_time_ms, point.vertical_oscillation_mm, point.step_length_mm]
+}
+
+fn cycling_dynamics_properties([balance, left_torque, right_torque, left_smoothness, right_smoothness, combined_smoothness]: [Option<f64>; 6]) -> CyclingDynamicsProperties {
+    CyclingDynamicsProperties {
+        left_right_balance: balance,
+        left_torque_effectiveness: left_torque,
+        right_torque_effectiveness: right_torque,
+        left_pedal_smoothness: left_smoothness,
+        right_pedal_smoothness: right_smoothness,
+        combined_pedal_smoothness: combined_smoothness,
+    }
+}
+
+fn running_dynamics_properties([stance_time, vertical_oscillation, step_length]: [Option<f64>; 3]) -> RunningDynamicsProperties {
+    RunningDynamicsProperties { stance_time, vertical_oscillation, step_length }
+}
+
+/// Returns the class of the value and its color.
+fn class_and_color(classifier: &ColorClassifier, value: Option<f64>) -> (Option<usize>, Option<String>) {
+    match value {
+        Some(value) => {
+            let class = classifier.class(value);
+            (Some(class), Some(classifier.class_color_hex(class)))
+        },
+        None => (None, None),
+    }
+}
+
+/// The key of the cadence property, which is the stroke rate (in strokes per minute) of a swim.
+fn cadence_key(options: &DocumentOptions) -> &'static str {
+    if options.swim {
+        "stroke_rate"
+    } else {
+        "cadence"
+    }
+}
+
+/// Converts a feature of the `points` collection into JSON. Properties whose values are not finite
+/// (and have therefore been converted into null) are removed, as they are unknown; the remaining
+/// values are rounded if requested.
+fn points_feature_to_json<P: Serialize>(feature: &Feature<P>, options: &DocumentOptions) -> serde_json::Value {
+    let mut json = to_json(feature);
+    if let Some(properties) = json.get_mut("properties").and_then(|p| p.as_object_mut()) {
+        properties.retain(|_, v| !v.is_null());
+        if let (Some(decimals), true) = (options.coordinate_precision, options.round_properties) {
+            round_property_values(properties, decimals);
+        }
+    }
+    json
+}
+
+
+fn segment_to_feature(point1: &Point, point2: &Point, running_dist: Option<RunningDistance>, classifier: Option<&ColorClassifier>, options: &DocumentOptions) -> serde_json::Value {
+    let units = options.units;
+    let mut properties = SegmentProperties::default();
+    if let Some(rd) = running_dist {
+        let (dist, computed, device) = running_distance_values(rd, options);
+        properties.running_distance = Some(dist);
+        properties.running_distance_computed = computed;
+        properties.running_distance_device = device;
+    }
+    if options.includes(Property::SegmentLength) {
+        properties.segment_length = Some(units.distance(segment_length_m(point1, point2, options)));
+    }
+    if options.includes(Property::SegmentDuration) {
+        if let (Some(t1), Some(t2)) = (point1.unix_timestamp, point2.unix_timestamp) {
+            properties.segment_duration = Some(t2 - t1);
+        }
+    }
+    if options.includes(Property::Speed) {
+        properties.speed = f64_mean(point1.speed_km_per_h.map(|s| units.speed(s)), point2.speed_km_per_h.map(|s| units.speed(s)));
+    }
+    if options.includes(Property::Pace) {
+        let speed_opt = f64_mean(point1.speed_km_per_h, point2.speed_km_per_h);
+        properties.pace = speed_opt.and_then(|spd| options.units.pace_min_per_unit(spd));
+        if options.swim {
+            properties.swim_pace = speed_opt.and_then(|spd| options.units.swim_pace_min_per_100(spd));
+        }
+    }
+    if options.includes(Property::GapPace) {
+        properties.gap_pace = grade_adjusted_speed_km_per_h(point1, point2, options.distance_algorithm)
+            .and_then(|spd| options.units.pace_min_per_unit(spd));
+    }
+    if options.includes(Property::Elevation) {
+        properties.elevation = f64_mean(point1.elevation_m.map(|e| units.elevation(e)), point2.elevation_m.map(|e| units.elevation(e)));
+    }
+    if options.includes(Property::Gradient) {
+        properties.gradient = gradient_percent(point1, point2, options.distance_algorithm);
+    }
+    if options.includes(Property::VerticalSpeed) {
+        properties.vertical_speed = vertical_speed_m_per_h(point1, point2).map(|v| units.elevation(v));
+    }
+    if options.includes(Property::HeartRate) {
+        properties.heart_rate = u64_mean(point1.heart_rate_bpm, point2.heart_rate_bpm);
+    }
+    if options.raw_values {
+        if options.includes(Property::Speed) {
+            properties.speed_raw = f64_mean(point1.raw_speed_km_per_h.map(|s| units.speed(s)), point2.raw_speed_km_per_h.map(|s| units.speed(s)));
+        }
+        if options.includes(Property::HeartRate) {
+            properties.heart_rate_raw = u64_mean(point1.raw_heart_rate_bpm, point2.raw_heart_rate_bpm);
+        }
+    }
+    if options.includes(Property::RespirationRate) {
+        properties.respiration_rate = f64_mean(point1.respiration_rate_per_min, point2.respiration_rate_per_min);
+    }
+    if options.includes(Property::Spo2) {
+        properties.spo2 = u64_mean(point1.spo2_percent, point2.spo2_percent);
+    }
+    if let (Some(zones), true) = (&options.heart_rate_zones, options.includes(Property::HrZone)) {
+        properties.hr_zone = segment_heart_rate_bpm(point1, point2).map(|hr| zones.zone(hr));
+    }
+    if options.includes(Property::Cadence) {
+        let cadence = u64_mean(point1.cadence_rpm, point2.cadence_rpm);
+        if options.swim {
+            properties.stroke_rate = cadence;
+        } else {
+            properties.cadence = cadence;
+        }
+    }
+    if options.includes(Property::Temperature) {
+        properties.temperature = match units {
+            Units::Metric => i64_mean(point1.temperature_degc, point2.temperature_degc)
+                .map(Temperature::Recorded),
+            Units::Imperial => f64_mean(
+                point1.temperature_degc.map(|t| units.temperature(t as f64)),
+                point2.temperature_degc.map(|t| units.temperature(t as f64)),
+            ).map(Temperature::Converted),
+        };
+    }
+    if options.derived_temps && options.includes(Property::ApparentTemperature) {
+        properties.apparent_temperature = f64_mean(
+            point1.apparent_temperature_degc().map(|t| units.temperature(t)),
+            point2.apparent_temperature_degc().map(|t| units.temperature(t)),
+        );
+    }
+    if options.includes(Property::Power) {
+        properties.power = u64_mean(point1.power_w, point2.power_w);
+    }
+    if options.cycling_dynamics && options.includes(Property::CyclingDynamics) {
+        let (dynamics1, dynamics2) = (cycling_dynamics(point1), cycling_dynamics(point2));
+        properties.cycling_dynamics = cycling_dynamics_properties(std::array::from_fn(|i| f64_mean(dynamics1[i], dynamics2[i])));
+    }
+    if options.running_dynamics && options.includes(Property::RunningDynamics) {
+        let (dynamics1, dynamics2) = (running_dynamics(point1), running_dynamics(point2));
+        properties.running_dynamics = running_dynamics_properties(std::array::from_fn(|i| f64_mean(dynamics1[i], dynamics2[i])));
+    }
+    if options.includes(Property::Timestamp) {
+        properties.timestamp = time_mean(point1.timestamp, point2.timestamp)
+            .map(|t| timestamp_string(&t, &options.timezone));
+    }
+    if options.includes(Property::Bearing) {
+        properties.bearing = point1.bearing_deg(point2);
+    }
+    if options.includes(Property::DeveloperFields) {
+        let developer_field_names: BTreeSet<&String> = point1.developer_fields.keys()
+            .chain(point2.developer_fields.keys())
+            .collect();
+        properties.developer_fields = developer_field_names.into_iter()
+            .filter_map(|name| {
+                let value = f64_mean(point1.developer_fields.get(name).copied(), point2.developer_fields.get(name).copied())?;
+                Some((name.clone(), value))
+            })
+            .collect();
+    }
+    if let Some(classifier) = classifier {
+        (properties.class, properties.color) = class_and_color(classifier, classifier.by.segment_value(point1, point2));
+    }
+    properties.censored = point2.follows_censored_gap;
+
+    let feature = Feature {
+        geometry: Geometry::LineString(vec![
+            lonlat_list(point1, options),
+            lonlat_list(point2, options),
+        ]),
+        properties,
+        bbox: options.feature_bbox.then(|| points_bbox([point1, point2], options)).flatten(),
+    };
+    points_feature_to_json(&feature, options)
+}
+
+fn point_to_feature(point: &Point, running_dist: Option<RunningDistance>, classifier: Option<&ColorClassifier>, options: &DocumentOptions) -> serde_json::Value {
+    let units = options.units;
+    let mut properties = PointProperties::default();
+    if let Some(rd) = running_dist {
+        let (dist, computed, device) = running_distance_values(rd, options);
+        properties.running_distance = Some(dist);
+        properties.running_distance_computed = computed;
+        properties.running_distance_device = device;
+    }
+    if options.includes(Property::Speed) {
+        properties.speed = point.speed_km_per_h.map(|s| units.speed(s));
+    }
+    if let (Some(spd), true) = (point.speed_km_per_h, options.includes(Property::Pace)) {
+        properties.pace = options.units.pace_min_per_unit(spd);
+        if options.swim {
+            properties.swim_pace = options.units.swim_pace_min_per_100(spd);
+        }
+    }
+    if options.includes(Property::Elevation) {
+        properties.elevation = point.elevation_m.map(|e| units.elevation(e));
+    }
+    if options.includes(Property::HeartRate) {
+        properties.heart_rate = point.heart_rate_bpm;
+    }
+    if options.raw_values {
+        if options.includes(Property::Speed) {
+            properties.speed_raw = point.raw_speed_km_per_h.map(|s| units.speed(s));
+        }
+        if options.includes(Property::HeartRate) {
+            properties.heart_rate_raw = point.raw_heart_rate_bpm;
+        }
+    }
+    if options.includes(Property::RespirationRate) {
+        properties.respiration_rate = point.respiration_rate_per_min;
+    }
+    if options.includes(Property::Spo2) {
+        properties.spo2 = point.spo2_percent;
+    }
+    if let (Some(zones), true) = (&options.heart_rate_zones, options.includes(Property::HrZone)) {
+        properties.hr_zone = point.heart_rate_bpm.map(|hr| zones.zone(hr));
+    }
+    if options.includes(Property::Cadence) {
+        if options.swim {
+            properties.stroke_rate = point.cadence_rpm;
+        } else {
+            properties.cadence = point.cadence_rpm;
+        }
+    }
+    if options.includes(Property::Temperature) {
+        properties.temperature = point.temperature_degc.map(|temp| match units {
+            Units::Metric => Temperature::Recorded(temp),
+            Units::Imperial => Temperature::Converted(units.temperature(temp as f64)),
+        });
+    }
+    if options.derived_temps && options.includes(Property::ApparentTemperature) {
+        properties.apparent_temperature = point.apparent_temperature_degc().map(|t| units.temperature(t));
+    }
+    if options.includes(Property::Power) {
+        properties.power = point.power_w;
+    }
+    if options.cycling_dynamics && options.includes(Property::CyclingDynamics) {
+        properties.cycling_dynamics = cycling_dynamics_properties(cycling_dynamics(point));
+    }
+    if options.running_dynamics && options.includes(Property::RunningDynamics) {
+        properties.running_dynamics = running_dynamics_properties(running_dynamics(point));
+    }
+    if options.includes(Property::Timestamp) {
+        properties.timestamp = point.timestamp.map(|t| timestamp_string(&t, &options.timezone));
+    }
+    if options.includes(Property::DeveloperFields) {
+        properties.developer_fields = point.developer_fields.clone();
+    }
+    if let Some(classifier) = classifier {
+        (properties.class, properties.color) = class_and_color(classifier, classifier.by.value(point));
+    }
+
+    points_feature_to_json(&Feature {
+        geometry: Geometry::Point(lonlat_list(point, options)),
+        properties,
+        bbox: None,
+    }, options)
+}
+
+/// Calculates the distance from the start of the track to the end of each segment (pair of
+/// consecutive points within a line), in meters.
+fn segment_running_distances(lines: &[Vec<Point>], distance_algorithm: DistanceAlgorithm) -> Vec<f64> {
+    let mut running_distances_m = Vec::new();
+    let mut running_dist_m = 0.0;
+    for line in lines {
+        for segment in line.windows(2) {
+            running_dist_m += segment[0].distance_m(&segment[1], distance_algorithm);
+            running_distances_m.push(running_dist_m);
+        }
+    }
+    running_distances_m
+}
+
+/// Calculates the distance from the start of the track to each point, in meters. The gaps between
+/// lines are not counted.
+fn point_running_distances(lines: &[Vec<Point>], distance_algorithm: DistanceAlgorithm) -> Vec<f64> {
+    let mut running_distances_m = Vec::new();
+    let mut running_dist_m = 0.0;
+    for line in lines {
+        for (i, point) in line.iter().enumerate() {
+            if i > 0 {
+                running_dist_m += line[i-1].distance_m(point, distance_algorithm);
+            }
+            running_distances_m.push(running_dist_m);
+        }
+    }
+    running_distances_m
+}
+
+/// Returns the distances recorded by the device at the end of each feature of the `points`
+/// collection, counted from the first distance recorded within the lines.
+fn feature_device_distances(lines: &[Vec<Point>], options: &DocumentOptions) -> Vec<Option<f64>> {
+    let start_m = lines.iter()
+        .flatten()
+        .find_map(|p| p.device_distance_m)
+        .unwrap_or(0.0);
+    let relative = |p: &Point| p.device_distance_m.map(|d| d - start_m);
+    match options.points_geometry {
+        PointsGeometry::Segment => lines.iter()
+            .flat_map(|l| l.windows(2))
+            .map(|segment| relative(&segment[1]))
+            .collect(),
+        PointsGeometry::Point => lines.iter()
+            .flatten()
+            .map(relative)
+            .collect(),
+    }
+}
+
+/// Calculates the running distances of the features in the `points` collection, or returns `None`
+/// if they are not output.
+fn feature_running_distances(lines: &[Vec<Point>], options: &DocumentOptions) -> Option<Vec<RunningDistance>> {
+    if !options.includes(Property::RunningDistance) {
+        return None;
+    }
+    let computed_m = match options.points_geometry {
+        PointsGeometry::Segment => segment_running_distances(lines, options.distance_algorithm),
+        PointsGeometry::Point => point_running_distances(lines, options.distance_algorithm),
+    };
+    let running_distances = match options.distance_source {
+        DistanceSource::Computed => computed_m.into_iter()
+            .map(|computed_m| RunningDistance { computed_m, device_m: None })
+            .collect(),
+        DistanceSource::Device => computed_m.into_iter()
+            .zip(feature_device_distances(lines, options))
+            .map(|(computed_m, device_m)| RunningDistance { computed_m, device_m })
+            .collect(),
+    };
+    Some(running_distances)
+}
+
+/// Returns the keys of all properties that may be output for the features of the `points`
+/// collection, given the options and the developer fields recorded within the lines.
+fn property_keys(lines: &[Vec<Point>], options: &DocumentOptions) -> Vec<String> {
+    let segments = options.points_geometry == PointsGeometry::Segment;
+    let mut keys = Vec::new();
+    let mut add = |included: bool, key: &str| {
+        if included {
+            keys.push(key.to_owned());
+        }
+    };
+    add(options.includes(Property::RunningDistance), "running_distance");
+    let device_distances = options.includes(Property::RunningDistance) && options.distance_source == DistanceSource::Device;
+    add(device_distances, "running_distance_computed");
+    add(device_distances, "running_distance_device");
+    add(segments && options.includes(Property::SegmentLength), "segment_length");
+    add(segments && options.includes(Property::SegmentDuration), "segment_duration");
+    add(options.includes(Property::Speed), "speed");
+    add(options.includes(Property::Pace), "pace");
+    add(options.swim && options.includes(Property::Pace), "swim_pace");
+    add(segments && options.includes(Property::GapPace), "gap_pace");
+    add(options.includes(Property::Elevation), "elevation");
+    add(segments && options.includes(Property::Gradient), "gradient");
+    add(segments && options.includes(Property::VerticalSpeed), "vertical_speed");
+    add(options.includes(Property::HeartRate), "heart_rate");
+    add(options.raw_values && options.includes(Property::Speed), "speed_raw");
+    add(options.raw_values && options.includes(Property::HeartRate), "heart_rate_raw");
+    add(options.includes(Property::RespirationRate), "respiration_rate");
+    add(options.includes(Property::Spo2), "spo2");
+    add(options.heart_rate_zones.is_some() && options.includes(Property::HrZone), "hr_zone");
+    add(options.includes(Property::Cadence), cadence_key(options));
+    add(options.includes(Property::Temperature), "temperature");
+    add(options.derived_temps && options.includes(Property::ApparentTemperature), "apparent_temperature");
+    add(options.includes(Property::Power), "power");
+    for name in CYCLING_DYNAMICS_KEYS {
+        add(options.cycling_dynamics && options.includes(Property::CyclingDynamics), name);
+    }
+    for name in RUNNING_DYNAMICS_KEYS {
+        add(options.running_dynamics && options.includes(Property::RunningDynamics), name);
+    }
+    add(options.includes(Property::Timestamp), "timestamp");
+    add(segments && options.includes(Property::Bearing), "bearing");
+    add(options.color_by.is_some(), "class");
+    add(options.color_by.is_some(), "color");
+    if options.includes(Property::DeveloperFields) {
+        let developer_field_names: BTreeSet<&String> = lines.iter()
+            .flatten()
+            .flat_map(|p| p.developer_fields.keys())
+            .collect();
+        for name in developer_field_names {
+            if !keys.contains(name) {
+                keys.push(name.clone());
+            }
+        }
+    }
+    keys
+}
+
+/// Adds the properties with the given keys that are missing from the feature (including those
+/// whose values were not finite and have therefore been left out), with `null` values.
+fn insert_missing_properties(mut feature: serde_json::Value, keys: &[String]) -> serde_json::Value {
+    if let Some(properties) = feature.get_mut("properties").and_then(|p| p.as_object_mut()) {
+        for key in keys {
+            properties.entry(key.clone()).or_insert(serde_json::Value::Null);
+        }
+    }
+    feature
+}
+
+/// Creates the classifier covering the values of the features of the `points` collection, or
+/// returns `None` if none of them has a value.
+fn color_classifier(lines: &[Vec<Point>], by: ColorBy, options: &DocumentOptions) -> Option<ColorClassifier> {
+    match options.points_geometry {
+        PointsGeometry::Segment => ColorClassifier::new(
+            by, options.color_scale,
+            lines.iter()
+                .flat_map(|l| l.windows(2))
+                .filter_map(|segment| by.segment_value(&segment[0], &segment[1])),
+        ),
+        PointsGeometry::Point => ColorClassifier::new(
+            by, options.color_scale,
+            lines.iter()
+                .flatten()
+                .filter_map(|p| by.value(p)),
+        ),
+    }
+}
+
+fn points_collection<'a>(
+    lines: &'a [Vec<Point>],
+    running_distances: Option<&'a [RunningDistance]>,
+    options: &'a DocumentOptions,
+) -> StreamedFeatureCollection<impl Fn() -> FeatureIter<'a>> {
+    let null_keys: Option<Arc<[String]>> = options.null_missing
+        .then(|| property_keys(lines, options).into());
+    let classifier = options.color_by
+        .and_then(|by| color_classifier(lines, by, options));
+    StreamedFeatureCollection {
+        bbox: points_bbox(lines.iter().flatten(), options),
+        make_features: move || -> FeatureIter<'a> {
+            let features: FeatureIter<'a> = match options.points_geometry {
+                PointsGeometry::Segment => Box::new(
+                    lines.iter()
+                        .flat_map(|l| l.windows(2))
+                        .enumerate()
+                        .map(move |(i, segment)| segment_to_feature(&segment[0], &segment[1], running_distances.map(|d| d[i]), classifier.as_ref(), options))
+                ),
+                PointsGeometry::Point => Box::new(
+                    lines.iter()
+                        .flatten()
+                        .enumerate()
+                        .map(move |(i, point)| point_to_feature(point, running_distances.map(|d| d[i]), classifier.as_ref(), options))
+                ),
+            };
+            match null_keys.clone() {
+                Some(keys) => Box::new(features.map(move |f| insert_missing_properties(f, &keys))),
+                None => features,
+            }
+        },
+    }
+}
+
+/// Converts the lines into a GeoJSON FeatureCollection. Depending on `options.points_geometry`,
+/// it contains either one two-point LineString per pair of consecutive points, carrying the
+/// averaged measurements of both points as properties, or one Point per point, carrying its
+/// measurements as recorded. Lines consisting of a single point have no segments and are therefore
+/// skipped in the former case.
+pub fn lines_to_points(lines: &[Vec<Point>], options: &DocumentOptions) -> serde_json::Value {
+    let running_distances = feature_running_distances(lines, options);
+    serde_json::to_value(points_collection(lines, running_distances.as_deref(), options))
+        .expect("failed to convert points to JSON value")
+}
+
+
+/// Returns the minimum and maximum of the values extracted from each point, or `None` if no point
+/// has a value.
+pub fn coord_extrema<F>(lines: &[Vec<Point>], mut coord: F) -> Option<(f64, f64)>
+    where
+        F: FnMut(&Point) -> Option<f64>,
+{
+    let min = lines.iter()
+        .flat_map(|l| l.iter())
+        .filter_map(&mut coord)
+        .reduce(f64::min);
+    let max = lines.iter()
+        .flat_map(|l| l.iter())
+        .filter_map(&mut coord)
+        .reduce(f64::max);
+
+    if let (Some(mn), Some(mx)) = (min, max) {
+        Some((mn, mx))
+    } else {
+        None
+    }
+}
+
+
+/// Calculates the members of the document for the given lines except for the two large GeoJSON
+/// collections, `track` and `points`.
+fn document_entries(lines: &[Vec<Point>], options: &DocumentOptions) -> Result<serde_json::Map<String, serde_json::Value>, WalkingError> {
+    // find coordinate extrema (assume we never go over the 180° meridian)
+    let (min_lat, max_lat) = coord_extrema(lines, |p| Some(p.latitude()))
+        .ok_or(WalkingError::NoPoints)?;
+    let (min_lon, max_lon) = coord_extrema(lines, |p| Some(p.longitude()))
+        .ok_or(WalkingError::NoPoints)?;
+    let avg_lat = (min_lat + max_lat)/2.0;
+    let avg_lon = (min_lon + max_lon)/2.0;
+    let zoom = estimate_zoom(min_lat, max_lat, min_lon, max_lon, options.viewport);
+    let units = options.units;
+    let (min_ele, max_ele) = coord_extrema(lines, |p| p.elevation_m)
+        .unwrap_or((300.0, 400.0));
+    let (elevation_gain, elevation_loss) = elevation_gain_loss(lines, options.elevation_threshold_m);
+    let (min_gradient, max_gradient) = gradient_extrema(lines, options.distance_algorithm)
+        .unwrap_or((-10.0, 10.0));
+    let (min_vertical_speed, max_vertical_speed) = vertical_speed_extrema(lines)
+        .unwrap_or((-1000.0, 1000.0));
+    let stats = compute_statistics(lines, options.moving_threshold_km_per_h, options.distance_algorithm);
+    let (min_hr, max_hr) = coord_extrema(lines, |p| p.heart_rate_bpm.map(|hr| hr as f64))
+        .unwrap_or((80.0, 160.0));
+    let (min_resp, max_resp) = coord_extrema(lines, |p| p.respiration_rate_per_min)
+        .unwrap_or((10.0, 40.0));
+    let (min_spo2, max_spo2) = coord_extrema(lines, |p| p.spo2_percent.map(|s| s as f64))
+        .unwrap_or((85.0, 100.0));
+    let (min_speed, max_speed) = coord_extrema(lines, |p| p.speed_km_per_h)
+        .unwrap_or((0.0, 10.0));
+    let (min_pace, max_pace) = coord_extrema(lines, |p| p.speed_km_per_h.and_then(|s| options.units.pace_min_per_unit(s)))
+        .unwrap_or((
+            options.units.pace_min_per_unit(10.0).unwrap(),
+            options.units.pace_min_per_unit(2.0).unwrap(),
+        ));
+    let (min_cad, max_cad) = coord_extrema(lines, |p| p.cadence_rpm.map(|hr| hr as f64))
+        .unwrap_or((0.0, 120.0));
+    let (min_temp, max_temp) = coord_extrema(lines, |p| p.temperature_degc.map(|hr| hr as f64))
+        .unwrap_or((-10.0, 45.0));
+    let (min_power, max_power) = coord_extrema(lines, |p| p.power_w.map(|pwr| pwr as f64))
+        .unwrap_or((0.0, 300.0));
+
+    let mut stats_json = statistics_to_json(&stats, units);
+    if let Some(zones) = &options.heart_rate_zones {
+        stats_json.insert("hr_zone_times".to_owned(), time_in_zones(lines, zones).to_vec().into());
+    }
+    if let Some((resting_bpm, max_bpm)) = options.trimp_heart_rates_bpm {
+        if let Some(trimp) = training_impulse(lines, resting_bpm, max_bpm) {
+            stats_json.insert("trimp".to_owned(), trimp.into());
+        }
+    }
+    if let Some(np) = normalized_power(lines) {
+        stats_json.insert("normalized_power".to_owned(), np.normalized_power_w.into());
+        if let Some(ftp_w) = options.ftp_w {
+            stats_json.insert("intensity_factor".to_owned(), np.intensity_factor(ftp_w).into());
+            stats_json.insert("tss".to_owned(), np.training_stress_score(ftp_w).into());
+        }
+    }
+    if !options.best_effort_windows.is_empty() {
+        let efforts: Vec<serde_json::Value> = best_efforts(lines, &options.best_effort_windows, options.distance_algorithm).iter()
+            .map(|e| best_effort_to_json(e, options).into())
+            .collect();
+        stats_json.insert("best_efforts".to_owned(), efforts.into());
+    }
+    if options.swim {
+        if let Some(pace) = stats.avg_moving_speed_km_per_h.and_then(|s| units.swim_pace_min_per_100(s)) {
+            stats_json.insert("avg_swim_pace".to_owned(), pace.into());
+        }
+    }
+
+    let mut units_json = units.names_to_json();
+    if options.swim {
+        units_json.insert("swim_pace".to_owned(), units.swim_pace_name().into());
+    }
+    let range = |(min, max): (f64, f64)| [min, max];
+
+    let mut entries = DocumentEntries {
+        format_version: FORMAT_VERSION,
+        center: [avg_lat, avg_lon],
+        zoom,
+        elevation_range: [units.elevation(min_ele), units.elevation(max_ele)],
+        elevation_gain: units.elevation(elevation_gain),
+        elevation_loss: units.elevation(elevation_loss),
+        gradient_range: [min_gradient, max_gradient],
+        vertical_speed_range: [units.elevation(min_vertical_speed), units.elevation(max_vertical_speed)],
+        heart_rate_range: [min_hr, max_hr],
+        respiration_rate_range: [min_resp, max_resp],
+        spo2_range: [min_spo2, max_spo2],
+        speed_range: [units.speed(min_speed), units.speed(max_speed)],
+        pace_range: [min_pace, max_pace],
+        cadence_range: (!options.swim).then_some([min_cad, max_cad]),
+        stroke_rate_range: options.swim.then_some([min_cad, max_cad]),
+        temperature_range: [units.temperature(min_temp), units.temperature(max_temp)],
+        power_range: [min_power, max_power],
+        apparent_temperature_range: None,
+        swim_pace_range: None,
+        stance_time_range: None,
+        vertical_oscillation_range: None,
+        step_length_range: None,
+        stats: stats_json,
+        units: units_json,
+        series: options.series_points
+            .map(|target_count| lines_to_series(lines, target_count, units, options.distance_algorithm)),
+    };
+    if options.derived_temps {
+        let (min_apparent, max_apparent) = coord_extrema(lines, |p| p.apparent_temperature_degc())
+            .unwrap_or((-20.0, 45.0));
+        entries.apparent_temperature_range = Some([units.temperature(min_apparent), units.temperature(max_apparent)]);
+    }
+    if options.swim {
+        entries.swim_pace_range = Some(range(
+            coord_extrema(lines, |p| p.speed_km_per_h.and_then(|s| units.swim_pace_min_per_100(s)))
+                .unwrap_or((1.0, 4.0))
+        ));
+    }
+    if options.running_dynamics {
+        entries.stance_time_range = Some(range(
+            coord_extrema(lines, |p| p.stance_time_ms)
+                .unwrap_or((200.0, 350.0))
+        ));
+        entries.vertical_oscillation_range = Some(range(
+            coord_extrema(lines, |p| p.vertical_oscillation_mm)
+                .unwrap_or((60.0, 120.0))
+        ));
+        entries.step_length_range = Some(range(
+            coord_extrema(lines, |p| p.step_length_mm)
+                .unwrap_or((700.0, 1500.0))
+        ));
+    }
+    Ok(to_json_object(&entries))
+}
+
+
+/// Converts the lines into the complete document consumed by the map viewer: the track and
+/// per-segment GeoJSON collections, the map center and zoom level, and the value ranges of the
+/// measurements.
+pub fn lines_to_document(lines: &[Vec<Point>], options: &DocumentOptions) -> Result<serde_json::Value, WalkingError> {
+    let mut entries = document_entries(lines, options)?;
+    entries.insert("track".to_owned(), lines_to_track(lines, options));
+    entries.insert("points".to_owned(), lines_to_points(lines, options));
+    Ok(entries.into())
+}
+
+
+/// Returns the metadata of the activity, together with the time at which recording started. Values
+/// that are not known are omitted.
+pub fn metadata_entries(activity: &Activity, options: &DocumentOptions) -> MetadataEntries {
+    let metadata = &activity.metadata;
+    MetadataEntries {
+        sport: metadata.sport.clone(),
+        sub_sport: metadata.sub_sport.clone(),
+        manufacturer: metadata.manufacturer.clone(),
+        product: metadata.product.clone(),
+        serial_number: metadata.serial_number,
+        software_version: metadata.software_version.clone(),
+        start_place: metadata.start_place.clone(),
+        end_place: metadata.end_place.clone(),
+        start_time: activity.recording_start_time()
+            .map(|t| timestamp_string(&t, &options.timezone)),
+    }
+}
+
+/// Converts the metadata of the activity (see [`metadata_entries`]) into a JSON object.
+pub fn metadata_to_json(activity: &Activity, options: &DocumentOptions) -> serde_json::Map<String, serde_json::Value> {
+    to_json_object(&metadata_entries(activity, options))
+}
+
+
+/// Returns the lap or session totals in the units of the document. Values that have not been
+/// recorded are omitted.
+pub fn summary_entries(summary: &Summary, options: &DocumentOptions) -> SummaryEntries {
+    SummaryEntries {
+        sport: summary.sport.clone(),
+        sub_sport: summary.sub_sport.clone(),
+        swim_stroke: summary.swim_stroke.clone(),
+        start_time: summary.start_time.map(|t| timestamp_string(&t, &options.timezone)),
+        end_time: summary.end_time.map(|t| timestamp_string(&t, &options.timezone)),
+        elapsed_time: summary.total_elapsed_time_s,
+        timer_time: summary.total_timer_time_s,
+        distance: summary.total_distance_m.map(|d| options.units.distance(d)),
+        calories: summary.total_calories_kcal,
+        avg_heart_rate: summary.avg_heart_rate_bpm,
+        max_heart_rate: summary.max_heart_rate_bpm,
+    }
+}
+
+/// Converts lap or session totals (see [`summary_entries`]) into a JSON object.
+pub fn summary_to_json(summary: &Summary, options: &DocumentOptions) -> serde_json::Map<String, serde_json::Value> {
+    to_json_object(&summary_entries(summary, options))
+}
+
+
+/// Returns the energy expended during the activity in kilocalories, along with its source: the total
+/// recorded by the device in the sessions (`device`) or, if there is none and a body weight is
+/// given, an estimate from the heart rate (`estimate`).
+fn activity_calories(activity: &Activity, options: &DocumentOptions) -> Option<(f64, &'static str)> {
+    let recorded: Vec<u64> = activity.sessions.iter()
+        .filter_map(|s| s.total_calories_kcal)
+        .collect();
+    if !recorded.is_empty() {
+        return Some((recorded.iter().sum::<u64>() as f64, "device"));
+    }
+    let weight_kg = options.weight_kg?;
+    estimate_calories_kcal(&activity.lines, weight_kg, options.age_years)
+        .map(|kcal| (kcal, "estimate"))
+}
+
+
+/// Returns the last point recorded at or before the given time.
+fn point_at_time<'a>(lines: &'a [Vec<Point>], time: &DateTime<Local>) -> Option<&'a Point> {
+    lines.iter()
+        .rev()
+        .flat_map(|l| l.iter().rev())
+        .find(|p| p.timestamp.map(|ts| ts <= *time).unwrap_or(false))
+}
+
+
+/// Converts the laps into a GeoJSON FeatureCollection with one Point feature at the end of each
+/// lap. The marker is placed at the last (uncensored) point recorded before the lap ended; laps for
+/// which there is no such point are skipped.
+pub fn laps_to_markers(lines: &[Vec<Point>], laps: &[Summary], options: &DocumentOptions) -> serde_json::Value {
+    let mut features = Vec::new();
+    let mut marker_points = Vec::new();
+    for (i, lap) in laps.iter().enumerate() {
+        let point = match lap.end_time.as_ref().and_then(|et| point_at_time(lines, et)) {
+            Some(p) => p,
+            None => continue,
+        };
+        marker_points.push(point);
+
+        let mut properties = summary_to_json(lap, options);
+        properties.insert("lap".to_owned(), (i + 1).into());
+
+        features.push(to_json(&Feature {
+            geometry: Geometry::Point(lonlat_list(point, options)),
+            properties,
+            bbox: None,
+        }));
+    }
+
+    to_json(&FeatureCollection {
+        features,
+        bbox: points_bbox(marker_points, options),
+    })
+}
+
+
+/// Returns the pauses that took place at points still contained in the lines, along with those
+/// points. Pauses whose points have been removed, e.g. by censoring or trimming, are skipped so
+/// that they do not reveal the removed positions.
+fn visible_pauses<'a>(lines: &'a [Vec<Point>], pauses: &'a [Pause]) -> Vec<(&'a Pause, &'a Point)> {
+    pauses.iter()
+        .filter_map(|pause| {
+            let point = point_at_time(lines, &pause.start_time)?;
+            (point.coordinates_deg == pause.position_deg).then_some((pause, point))
+        })
+        .collect()
+}
+
+
+/// Converts a pause into a JSON object with its start and end time, its duration (in seconds) and
+/// its position (as latitude and longitude).
+fn pause_to_json(pause: &Pause, point: &Point, options: &DocumentOptions) -> serde_json::Map<String, serde_json::Value> {
+    let position = match options.coordinate_precision {
+        Some(decimals) => [round_to(point.latitude(), decimals), round_to(point.longitude(), decimals)],
+        None => [point.latitude(), point.longitude()],
+    };
+    to_json_object(&PauseEntries {
+        start_time: timestamp_string(&pause.start_time, &options.timezone),
+        end_time: timestamp_string(&pause.end_time, &options.timezone),
+        duration: pause.duration_s(),
+        position,
+    })
+}
+
+
+/// Converts the legs of a multisport activity into JSON objects, one per leg with its session
+/// totals, its statistics and its part of the track.
+pub fn legs_to_json(legs: &Legs, options: &DocumentOptions) -> Vec<serde_json::Value> {
+    legs.legs.iter()
+        .map(|leg| {
+            let stats = compute_statistics(&leg.lines, options.moving_threshold_km_per_h, options.distance_algorithm);
+            to_json(&LegEntries {
+                sport: leg.session.sport.clone(),
+                session: summary_entries(&leg.session, options),
+                stats: statistics_to_json(&stats, options.units),
+                track: lines_to_track(&leg.lines, options),
+            })
+        })
+        .collect()
+}
+
+
+/// Converts the transitions between the legs of a multisport activity into JSON objects with the
+/// sports before and after, the start and end time and the duration (in seconds).
+pub fn transitions_to_json(legs: &Legs, options: &DocumentOptions) -> Vec<serde_json::Value> {
+    let sport = |index: usize| legs.legs.get(index)
+        .and_then(|l| l.session.sport.clone());
+    legs.transitions.iter()
+        .map(|transition| to_json(&TransitionEntries {
+            from_sport: sport(transition.leg_index),
+            to_sport: sport(transition.leg_index + 1),
+            start_time: timestamp_string(&transition.start_time, &options.timezone),
+            end_time: timestamp_string(&transition.end_time, &options.timezone),
+            duration: transition.duration_s(),
+        }))
+        .collect()
+}
+
+
+/// Converts the pauses into a GeoJSON FeatureCollection with one Point feature at the position of
+/// each pause, carrying the same properties as the `pauses` array (except for the position).
+pub fn pauses_to_markers(lines: &[Vec<Point>], pauses: &[Pause], options: &DocumentOptions) -> serde_json::Value {
+    let mut features = Vec::new();
+    let mut marker_points = Vec::new();
+    for (i, (pause, point)) in visible_pauses(lines, pauses).into_iter().enumerate() {
+        marker_points.push(point);
+
+        let mut properties = pause_to_json(pause, point, options);
+        properties.remove("position");
+        properties.insert("pause".to_owned(), (i + 1).into());
+
+        features.push(to_json(&Feature {
+            geometry: Geometry::Point(lonlat_list(point, options)),
+            properties,
+            bbox: None,
+        }));
+    }
+
+    to_json(&FeatureCollection {
+        features,
+        bbox: points_bbox(marker_points, options),
+    })
+}
+
+
+/// Converts the stops into a GeoJSON FeatureCollection with one Point feature at the mean position
+/// of each stop, carrying its start and end time, its duration (in seconds) and the number of points
+/// recorded during it.
+pub fn stops_to_markers(stops: &[Stop], options: &DocumentOptions) -> serde_json::Value {
+    let stop_points: Vec<Point> = stops.iter()
+        .map(|s| Point::new(s.position_deg))
+        .collect();
+    let mut features = Vec::new();
+    for (i, (stop, point)) in stops.iter().zip(&stop_points).enumerate() {
+        let mut properties = serde_json::Map::new();
+        properties.insert("stop".to_owned(), (i + 1).into());
+        properties.insert("start_time".to_owned(), format_timestamp(&stop.start_time, &options.timezone));
+        properties.insert("end_time".to_owned(), format_timestamp(&stop.end_time, &options.timezone));
+        properties.insert("duration".to_owned(), stop.duration_s().into());
+        properties.insert("point_count".to_owned(), stop.point_count.into());
+
+        features.push(to_json(&Feature {
+            geometry: Geometry::Point(lonlat_list(point, options)),
+            properties,
+            bbox: None,
+        }));
+    }
+
+    to_json(&FeatureCollection {
+        features,
+        bbox: points_bbox(&stop_points, options),
+    })
+}
+
+
+/// Converts a best effort into a JSON object with its window, measure, duration, distance, speed and
+/// times, and depending on the measure its pace, average power or average heart rate.
+fn best_effort_to_json(effort: &BestEffort, options: &DocumentOptions) -> serde_json::Map<String, serde_json::Value> {
+    let units = options.units;
+    let mut ret = serde_json::Map::new();
+    ret.insert("window".to_owned(), effort.window.label.clone().into());
+    ret.insert("measure".to_owned(), effort.measure.name().into());
+    ret.insert("duration".to_owned(), effort.duration_s.into());
+    ret.insert("distance".to_owned(), units.distance(effort.distance_m).into());
+    if let Some(speed) = effort.speed_km_per_h() {
+        ret.insert("speed".to_owned(), units.speed(speed).into());
+        if effort.measure == EffortMeasure::Time {
+            if let Some(pace) = units.pace_min_per_unit(speed) {
+                ret.insert("pace".to_owned(), pace.into());
+            }
+        }
+    }
+    if let Some(average) = effort.average {
+        ret.insert(effort.measure.name().to_owned(), average.into());
+    }
+    if let Some(start_time) = effort.points.first().and_then(|p| p.timestamp) {
+        ret.insert("start_time".to_owned(), format_timestamp(&start_time, &options.timezone));
+    }
+    if let Some(end_time) = effort.points.last().and_then(|p| p.timestamp) {
+        ret.insert("end_time".to_owned(), format_timestamp(&end_time, &options.timezone));
+    }
+    ret
+}
+
+
+/// Converts the best efforts into a GeoJSON FeatureCollection with one LineString per effort,
+/// covering the section of the track on which it was achieved and carrying the same properties as
+/// the `best_efforts` of the statistics.
+pub fn best_efforts_to_features(efforts: &[BestEffort], options: &DocumentOptions) -> serde_json::Value {
+    let features: Vec<serde_json::Value> = efforts.iter()
+        .map(|effort| {
+            let coordinates: Vec<serde_json::Value> = effort.points.iter()
+                .map(|p| lonlat_list(p, options))
+                .collect();
+            to_json(&Feature {
+                geometry: Geometry::LineString(coordinates),
+                properties: best_effort_to_json(effort, options),
+                bbox: None,
+            })
+        })
+        .collect();
+
+    to_json(&FeatureCollection {
+        features,
+        bbox: points_bbox(efforts.iter().flat_map(|e| e.points.iter()), options),
+    })
+}
+
+
+/// Converts the lines into a GeoJSON FeatureCollection with a Point at every full kilometer or mile
+/// (depending on `options.units`) of running distance. Each feature carries the index of the split
+/// that ends there, the time taken for the split (`split_time`, in seconds) and the resulting pace
+/// (`split_pace`, in minutes per kilometer or mile); the times are only available if the points
+/// have timestamps. Positions and times are interpolated between the surrounding points; as with
+/// the running distance, the gaps between lines are not counted as distance.
+pub fn lines_to_splits(lines: &[Vec<Point>], options: &DocumentOptions) -> serde_json::Value {
+    let split_distance_m = options.units.distance_unit_km() * 1000.0;
+    let start_time = lines.iter()
+        .flatten()
+        .find_map(|p| p.unix_timestamp);
+
+    let mut features = Vec::new();
+    let mut split_points = Vec::new();
+    let mut running_dist_m = 0.0;
+    let mut previous_split_time = start_time;
+    for segment in lines.iter().flat_map(|l| l.windows(2)) {
+        let (point1, point2) = (&segment[0], &segment[1]);
+        let segment_dist_m = point1.distance_m(point2, options.distance_algorithm);
+        let next_dist_m = running_dist_m + segment_dist_m;
+
+        // a long segment may contain multiple splits
+        loop {
+            let split_index = split_points.len() + 1;
+            let split_dist_m = split_index as f64 * split_distance_m;
+            if split_dist_m > next_dist_m {
+                break;
+            }
+
+            let fraction = (split_dist_m - running_dist_m) / segment_dist_m;
+            let lerp = |v1: f64, v2: f64| v1 + (v2 - v1) * fraction;
+            let mut split_point = Point::new(geo::Point::new(
+                lerp(point1.longitude(), point2.longitude()),
+                lerp(point1.latitude(), point2.latitude()),
+            ));
+            if let (Some(t1), Some(t2)) = (point1.unix_timestamp, point2.unix_timestamp) {
+                split_point.unix_timestamp = Some(lerp(t1, t2));
+            }
+
+            let mut properties = serde_json::Map::new();
+            properties.insert("split".to_owned(), split_index.into());
+            properties.insert("running_distance".to_owned(), options.units.distance(split_dist_m).into());
+            if let (Some(t), Some(pt)) = (split_point.unix_timestamp, previous_split_time) {
+                let split_time_s = t - pt;
+                properties.insert("split_time".to_owned(), split_time_s.into());
+                properties.insert("split_pace".to_owned(), (split_time_s / 60.0).into());
+            }
+            if let (Some(t), Some(st)) = (split_point.unix_timestamp, start_time) {
+                properties.insert("elapsed_time".to_owned(), (t - st).into());
+            }
+            if let (Some(decimals), true) = (options.coordinate_precision, options.round_properties) {
+                round_property_values(&mut properties, decimals);
+            }
+            previous_split_time = split_point.unix_timestamp;
+
+            features.push(to_json(&Feature {
+                geometry: Geometry::Point(lonlat_list(&split_point, options)),
+                properties,
+                bbox: None,
+            }));
+            split_points.push(split_point);
+        }
+
+        running_dist_m = next_dist_m;
+    }
+
+    to_json(&FeatureCollection {
+        features,
+        bbox: points_bbox(&split_points, options),
+    })
+}
+
+
+/// Converts the lines into a GeoJSON FeatureCollection with a Point at every multiple of
+/// `interval_m` of running distance, carrying the `bearing` of the segment it lies on so that
+/// viewers can draw an arrow pointing in the direction of travel. Positions are interpolated
+/// between the surrounding points; the gaps between lines are not counted as distance.
+pub fn lines_to_direction_arrows(lines: &[Vec<Point>], interval_m: f64, options: &DocumentOptions) -> serde_json::Value {
+    let mut features = Vec::new();
+    let mut arrow_points = Vec::new();
+    let mut running_dist_m = 0.0;
+    for segment in lines.iter().flat_map(|l| l.windows(2)) {
+        let (point1, point2) = (&segment[0], &segment[1]);
+        let segment_dist_m = point1.distance_m(point2, options.distance_algorithm);
+        let next_dist_m = running_dist_m + segment_dist_m;
+        let bearing = match point1.bearing_deg(point2) {
+            Some(b) => b,
+            None => continue,
+        };
+
+        // a long segment may contain multiple arrows
+        loop {
+            let arrow_dist_m = (arrow_points.len() + 1) as f64 * interval_m;
+            if arrow_dist_m > next_dist_m {
+                break;
+            }
+
+            let fraction = (arrow_dist_m - running_dist_m) / segment_dist_m;
+            let lerp = |v1: f64, v2: f64| v1 + (v2 - v1) * fraction;
+            let arrow_point = Point::new(geo::Point::new(
+                lerp(point1.longitude(), point2.longitude()),
+                lerp(point1.latitude(), point2.latitude()),
+            ));
+
+            let mut properties = serde_json::Map::new();
+            properties.insert("bearing".to_owned(), bearing.into());
+            properties.insert("running_distance".to_owned(), options.units.distance(arrow_dist_m).into());
+            if let (Some(decimals), true) = (options.coordinate_precision, options.round_properties) {
+                round_property_values(&mut properties, decimals);
+            }
+
+            features.push(to_json(&Feature {
+                geometry: Geometry::Point(lonlat_list(&arrow_point, options)),
+                properties,
+                bbox: None,
+            }));
+            arrow_points.push(arrow_point);
+        }
+
+        running_dist_m = next_dist_m;
+    }
+
+    to_json(&FeatureCollection {
+        features,
+        bbox: points_bbox(&arrow_points, options),
+    })
+}
+
+
+/// Matches the lines against the route and returns the coverage (in percent) and the time taken
+/// from the start to the end of the route, if the track passes both.
+pub fn route_match_to_json(lines: &[Vec<Point>], route: &Route, options: &DocumentOptions) -> serde_json::Map<String, serde_json::Value> {
+    let route_match = match_route(lines, route, options.distance_algorithm);
+    let mut ret = serde_json::Map::new();
+    ret.insert("route_length".to_owned(), options.units.distance(route_match.route_length_m).into());
+    ret.insert("covered_length".to_owned(), options.units.distance(route_match.covered_length_m).into());
+    ret.insert("coverage".to_owned(), route_match.coverage_percent().into());
+    if let Some(start_time) = &route_match.start_time {
+        ret.insert("start_time".to_owned(), format_timestamp(start_time, &options.timezone));
+    }
+    if let Some(end_time) = &route_match.end_time {
+        ret.insert("end_time".to_owned(), format_timestamp(end_time, &options.timezone));
+    }
+    if let Some(elapsed_time_s) = route_match.elapsed_time_s() {
+        ret.insert("elapsed_time".to_owned(), elapsed_time_s.into());
+    }
+    ret
+}
+
+
+/// Converts the activity into the complete document consumed by the map viewer; in addition to the
+/// output of [`lines_to_document`], it contains the lap and session totals recorded by the device.
+pub fn activity_to_document(activity: &Activity, options: &DocumentOptions) -> Result<serde_json::Value, WalkingError> {
+    let mut entries = activity_entries(activity, options)?;
+    entries.insert("track".to_owned(), lines_to_track(&activity.lines, options));
+    entries.insert("points".to_owned(), lines_to_points(&activity.lines, options));
+    let mut document: serde_json::Value = entries.into();
+    document.sort_all_objects();
+    Ok(document)
+}
+
+
+/// Calculates the members of the document for the given activity except for the two large GeoJSON
+/// collections, `track` and `points`.
+fn activity_entries(activity: &Activity, options: &DocumentOptions) -> Result<serde_json::Map<String, serde_json::Value>, WalkingError> {
+    let mut document_object = document_entries(&activity.lines, options)?;
+
+    let laps: Vec<serde_json::Value> = activity.laps.iter()
+        .map(|l| summary_to_json(l, options).into())
+        .collect();
+    document_object.insert("laps".to_owned(), laps.into());
+
+    let session = activity.sessions.first()
+        .map(|s| summary_to_json(s, options).into())
+        .unwrap_or(serde_json::Value::Null);
+    document_object.insert("session".to_owned(), session);
+
+    if let Some((calories_kcal, source)) = activity_calories(activity, options) {
+        if let Some(serde_json::Value::Object(stats)) = document_object.get_mut("stats") {
+            stats.insert("calories".to_owned(), calories_kcal.into());
+            stats.insert("calories_source".to_owned(), source.into());
+        }
+    }
+
+    if options.swim {
+        if let Some(serde_json::Value::Object(stats)) = document_object.get_mut("stats") {
+            stats.insert("stroke_times".to_owned(), serde_json::json!(stroke_times_s(&activity.laps)));
+        }
+    }
+
+    document_object.insert("metadata".to_owned(), metadata_to_json(activity, options).into());
+
+    let pauses: Vec<serde_json::Value> = visible_pauses(&activity.lines, &activity.pauses).into_iter()
+        .map(|(pause, point)| pause_to_json(pause, point, options).into())
+        .collect();
+    document_object.insert("pauses".to_owned(), pauses.into());
+
+    if let Some(legs) = split_legs(activity) {
+        document_object.insert("legs".to_owned(), legs_to_json(&legs, options).into());
+        document_object.insert("transitions".to_owned(), transitions_to_json(&legs, options).into());
+    }
+
+    if options.lap_markers {
+        document_object.insert("lap_markers".to_owned(), laps_to_markers(&activity.lines, &activity.laps, options));
+    }
+    if options.pause_markers {
+        document_object.insert("pause_markers".to_owned(), pauses_to_markers(&activity.lines, &activity.pauses, options));
+    }
+    if let Some(min_duration_s) = options.stop_min_duration_s {
+        let stops = detect_stops(&activity.lines, &activity.pauses, options.stop_radius_m, min_duration_s, options.distance_algorithm);
+        document_object.insert("stops".to_owned(), stops_to_markers(&stops, options));
+    }
+    if options.splits {
+        document_object.insert("splits".to_owned(), lines_to_splits(&activity.lines, options));
+    }
+    if options.best_effort_markers && !options.best_effort_windows.is_empty() {
+        let efforts = best_efforts(&activity.lines, &options.best_effort_windows, options.distance_algorithm);
+        document_object.insert("best_efforts".to_owned(), best_efforts_to_features(&efforts, options));
+    }
+    if let Some(interval_m) = options.direction_arrows_m {
+        document_object.insert("direction_arrows".to_owned(), lines_to_direction_arrows(&activity.lines, interval_m, options));
+    }
+    if let Some(route) = &options.route {
+        document_object.insert("route_match".to_owned(), route_match_to_json(&activity.lines, route, options).into());
+    }
+
+    for value in document_object.values_mut() {
+        value.sort_all_objects();
+    }
+    Ok(document_object)
+}
+
+
+/// A document whose `track` and `points` collections are generated while it is being serialized.
+/// Members are output sorted by key, like all JSON objects output by this crate, so that the output
+/// does not depend on whether serde_json preserves insertion order.
+struct StreamedDocument<'a, T, P> {
+    entries: &'a serde_json::Map<String, serde_json::Value>,
+    track: T,
+    points: P,
+}
+impl<T: Serialize, P: Serialize> Serialize for StreamedDocument<'_, T, P> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut keys: Vec<&str> = self.entries.keys()
+            .map(|k| k.as_str())
+            .chain(["track", "points"])
+            .collect();
+        keys.sort_unstable();
+
+        let mut map = serializer.serialize_map(Some(keys.len()))?;
+        for key in keys {
+            match key {
+                "track" => map.serialize_entry(key, &self.track)?,
+                "points" => map.serialize_entry(key, &self.points)?,
+                _ => map.serialize_entry(key, &self.entries[key])?,
+            }
+        }
+        map.end()
+    }
+}
+
+
+pub(crate) fn write_json<W: Write, F: serde_json::ser::Formatter, T: Serialize>(value: &T, writer: W, formatter: F) -> Result<(), WalkingError> {
+    let mut serializer = serde_json::Serializer::with_formatter(writer, formatter);
+    value.serialize(&mut serializer)
+        .map_err(|e| if e.is_io() { WalkingError::Io(e.into()) } else { WalkingError::Json(e) })?;
+    serializer.into_inner().flush()?;
+    Ok(())
+}
+
+
+/// Writes the same document as [`activity_to_document`] as JSON (pretty-printed unless
+/// `options.compact` is set), generating the GeoJSON features while writing instead of building
+/// the whole document in memory first.
+pub fn write_activity_document<W: Write>(writer: W, activity: &Activity, options: &DocumentOptions) -> Result<(), WalkingError> {
+    let entries = activity_entries(activity, options)?;
+    let running_distances = feature_running_distances(&activity.lines, options);
+    let document = StreamedDocument {
+        entries: &entries,
+        track: track_collection(&activity.lines, options),
+        points: points_collection(&activity.lines, running_distances.as_deref(), options),
+    };
+
+    if options.compact {
+        write_json(&document, writer, serde_json::ser::CompactFormatter)
+    } else {
+        write_json(&document, writer, serde_json::ser::PrettyFormatter::new())
+    }
+}
+
+
+/// Writes the features of the `track` and `points` collections of the document as newline-delimited
+/// GeoJSON (one compact Feature per line), which streaming tools ingest more easily than a single
+/// large document. Each feature is given a `layer` property naming the collection it belongs to;
+/// the other entries of the document are not output.
+pub fn write_feature_lines<W: Write>(mut writer: W, activity: &Activity, options: &DocumentOptions) -> Result<(), WalkingError> {
+    let running_distances = feature_running_distances(&activity.lines, options);
+    let track = track_collection(&activity.lines, options);
+    let points = points_collection(&activity.lines, running_distances.as_deref(), options);
+    let layers: [(&str, FeatureIter); 2] = [
+        ("track", (track.make_features)()),
+        ("points", (points.make_features)()),
+    ];
+    for (layer, features) in layers {
+        for mut feature in features {
+            if let Some(properties) = feature["properties"].as_object_mut() {
+                properties.insert("layer".to_owned(), serde_json::Value::from(layer));
+            }
+            feature.sort_all_objects();
+            write_json(&feature, &mut writer, serde_json::ser::CompactFormatter)?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
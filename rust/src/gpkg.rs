@@ -0,0 +1,253 @@
+//! Export of activities as GeoPackage files, which GIS applications such as QGIS and ArcGIS open
+//! directly.
+//!
+//! The GeoPackage contains a `track` layer with one LineString per line and a `points` layer with
+//! one Point per point, both with their values as attribute columns named like those of the CSV
+//! output. Since a GeoPackage is an SQLite database, it is built using the `sqlite3` command-line
+//! tool in a temporary file, which is then copied to the output.
+
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::point::{DistanceAlgorithm, GeoPoint, Point};
+use crate::sql::{
+    ROWS_PER_STATEMENT, sql_f64, sql_optional_f64, sql_optional_integer, sql_string, sql_timestamp,
+};
+use crate::sqlite::execute_sql;
+
+
+/// The application ID identifying SQLite databases as GeoPackages ("GPKG").
+const GPKG_APPLICATION_ID: u32 = 0x4750_4B47;
+
+/// The version of the GeoPackage specification (1.3.0), stored as the user version.
+const GPKG_USER_VERSION: u32 = 10300;
+
+/// The ID of the WGS 84 spatial reference system.
+const SRS_ID_WGS84: i32 = 4326;
+
+/// WKB geometry type of a Point.
+const WKB_POINT: u32 = 1;
+
+/// WKB geometry type of a LineString.
+const WKB_LINE_STRING: u32 = 2;
+
+/// The tables required by the GeoPackage specification along with the feature tables.
+const SCHEMA: &str = "\
+CREATE TABLE gpkg_spatial_ref_sys (
+    srs_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL PRIMARY KEY,
+    organization TEXT NOT NULL,
+    organization_coordsys_id INTEGER NOT NULL,
+    definition TEXT NOT NULL,
+    description TEXT
+);
+INSERT INTO gpkg_spatial_ref_sys VALUES
+    ('Undefined cartesian SRS', -1, 'NONE', -1, 'undefined', 'undefined cartesian coordinate reference system'),
+    ('Undefined geographic SRS', 0, 'NONE', 0, 'undefined', 'undefined geographic coordinate reference system'),
+    ('WGS 84 geodetic', 4326, 'EPSG', 4326, 'GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563,AUTHORITY[\"EPSG\",\"7030\"]],AUTHORITY[\"EPSG\",\"6326\"]],PRIMEM[\"Greenwich\",0,AUTHORITY[\"EPSG\",\"8901\"]],UNIT[\"degree\",0.0174532925199433,AUTHORITY[\"EPSG\",\"9122\"]],AUTHORITY[\"EPSG\",\"4326\"]]', 'longitude/latitude coordinates in decimal degrees on the WGS 84 spheroid');
+CREATE TABLE gpkg_contents (
+    table_name TEXT NOT NULL PRIMARY KEY,
+    data_type TEXT NOT NULL,
+    identifier TEXT UNIQUE,
+    description TEXT DEFAULT '',
+    last_change DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    min_x DOUBLE,
+    min_y DOUBLE,
+    max_x DOUBLE,
+    max_y DOUBLE,
+    srs_id INTEGER,
+    CONSTRAINT fk_gc_r_srs_id FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+CREATE TABLE gpkg_geometry_columns (
+    table_name TEXT NOT NULL,
+    column_name TEXT NOT NULL,
+    geometry_type_name TEXT NOT NULL,
+    srs_id INTEGER NOT NULL,
+    z TINYINT NOT NULL,
+    m TINYINT NOT NULL,
+    CONSTRAINT pk_geom_cols PRIMARY KEY (table_name, column_name),
+    CONSTRAINT fk_gc_tn FOREIGN KEY (table_name) REFERENCES gpkg_contents (table_name),
+    CONSTRAINT fk_gc_srs FOREIGN KEY (srs_id) REFERENCES gpkg_spatial_ref_sys (srs_id)
+);
+CREATE TABLE track (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT,
+    geom LINESTRING,
+    line INTEGER,
+    start_time DATETIME,
+    end_time DATETIME,
+    point_count INTEGER,
+    distance_m DOUBLE
+);
+CREATE TABLE points (
+    fid INTEGER PRIMARY KEY AUTOINCREMENT,
+    geom POINT,
+    line INTEGER,
+    timestamp DATETIME,
+    elevation_m DOUBLE,
+    heart_rate_bpm INTEGER,
+    speed_km_per_h DOUBLE,
+    cadence_rpm INTEGER,
+    temperature_degc INTEGER,
+    power_w INTEGER,
+    distance_m DOUBLE
+);
+INSERT INTO gpkg_geometry_columns VALUES
+    ('track', 'geom', 'LINESTRING', 4326, 0, 0),
+    ('points', 'geom', 'POINT', 4326, 0, 0);
+";
+
+
+/// Distinguishes the temporary files of concurrent conversions.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Options influencing the generated GeoPackage.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GpkgOptions {
+    /// The method by which distances between points are calculated.
+    pub distance_algorithm: DistanceAlgorithm,
+}
+
+
+/// Encodes the points as a GeoPackage geometry blob (a header followed by the WKB geometry) in
+/// SQL hexadecimal notation: a Point if there is one point, a LineString otherwise.
+fn geometry_blob(points: &[&Point]) -> String {
+    let mut blob = Vec::new();
+    blob.extend_from_slice(b"GP");
+    blob.push(0); // version 1
+    if points.len() == 1 {
+        blob.push(0b0000_0001); // little endian, no envelope
+        blob.extend_from_slice(&SRS_ID_WGS84.to_le_bytes());
+    } else {
+        blob.push(0b0000_0011); // little endian, envelope [min_x, max_x, min_y, max_y]
+        blob.extend_from_slice(&SRS_ID_WGS84.to_le_bytes());
+        let (min_x, min_y, max_x, max_y) = bounds(points.iter().copied());
+        for value in [min_x, max_x, min_y, max_y] {
+            blob.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    blob.push(1); // little endian
+    if points.len() == 1 {
+        blob.extend_from_slice(&WKB_POINT.to_le_bytes());
+    } else {
+        blob.extend_from_slice(&WKB_LINE_STRING.to_le_bytes());
+        blob.extend_from_slice(&(points.len() as u32).to_le_bytes());
+    }
+    for point in points {
+        blob.extend_from_slice(&point.longitude().to_le_bytes());
+        blob.extend_from_slice(&point.latitude().to_le_bytes());
+    }
+
+    let hex: String = blob.iter()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    format!("X'{}'", hex)
+}
+
+
+/// Calculates the bounds (minimum longitude and latitude, maximum longitude and latitude) of the
+/// points.
+fn bounds<'a, I: IntoIterator<Item = &'a Point>>(points: I) -> (f64, f64, f64, f64) {
+    points.into_iter()
+        .fold(
+            (f64::INFINITY, f64::INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            |(min_x, min_y, max_x, max_y), p| (
+                min_x.min(p.longitude()), min_y.min(p.latitude()),
+                max_x.max(p.longitude()), max_y.max(p.latitude()),
+            ),
+        )
+}
+
+
+/// Writes the SQL statements creating the GeoPackage tables and filling them with the activity.
+fn write_gpkg_sql<W: Write>(mut writer: W, activity: &Activity, name: &str, options: &GpkgOptions) -> Result<(), WalkingError> {
+    writeln!(writer, "PRAGMA application_id = {};", GPKG_APPLICATION_ID)?;
+    writeln!(writer, "PRAGMA user_version = {};", GPKG_USER_VERSION)?;
+    writeln!(writer, "BEGIN;")?;
+    write!(writer, "{}", SCHEMA)?;
+
+    let (min_x, min_y, max_x, max_y) = bounds(activity.lines.iter().flatten());
+    for table in ["track", "points"] {
+        writeln!(
+            writer,
+            "INSERT INTO gpkg_contents (table_name, data_type, identifier, min_x, min_y, max_x, max_y, srs_id) VALUES ('{}', 'features', {}, {}, {}, {}, {}, {});",
+            table, sql_string(&format!("{} of {}", table, name)),
+            sql_f64(min_x), sql_f64(min_y), sql_f64(max_x), sql_f64(max_y), SRS_ID_WGS84,
+        )?;
+    }
+
+    // as in the CSV output, the distance of a point is counted along all lines, ignoring the gaps
+    let mut point_rows = Vec::new();
+    let mut total_distance_m = 0.0;
+    for (line_index, line) in activity.lines.iter().enumerate() {
+        let line_start_distance_m = total_distance_m;
+        for (point_index, point) in line.iter().enumerate() {
+            if point_index > 0 {
+                total_distance_m += line[point_index - 1].distance_m(point, options.distance_algorithm);
+            }
+            point_rows.push(format!(
+                "({}, {}, {}, {}, {}, {}, {}, {}, {}, {})",
+                geometry_blob(&[point]),
+                line_index,
+                sql_timestamp(point.timestamp.as_ref()),
+                sql_optional_f64(point.elevation_m),
+                sql_optional_integer(point.heart_rate_bpm),
+                sql_optional_f64(point.speed_km_per_h),
+                sql_optional_integer(point.cadence_rpm),
+                sql_optional_integer(point.temperature_degc),
+                sql_optional_integer(point.power_w),
+                sql_f64(total_distance_m),
+            ));
+        }
+
+        // a LineString needs at least two points
+        if line.len() >= 2 {
+            let line_points: Vec<&Point> = line.iter().collect();
+            writeln!(
+                writer,
+                "INSERT INTO track (geom, line, start_time, end_time, point_count, distance_m) VALUES ({}, {}, {}, {}, {}, {});",
+                geometry_blob(&line_points),
+                line_index,
+                sql_timestamp(line.iter().find_map(|p| p.timestamp).as_ref()),
+                sql_timestamp(line.iter().rev().find_map(|p| p.timestamp).as_ref()),
+                line.len(),
+                sql_f64(total_distance_m - line_start_distance_m),
+            )?;
+        }
+    }
+    for chunk in point_rows.chunks(ROWS_PER_STATEMENT) {
+        writeln!(
+            writer,
+            "INSERT INTO points (geom, line, timestamp, elevation_m, heart_rate_bpm, speed_km_per_h, cadence_rpm, temperature_degc, power_w, distance_m) VALUES {};",
+            chunk.join(", "),
+        )?;
+    }
+
+    writeln!(writer, "COMMIT;")?;
+    Ok(())
+}
+
+
+/// Writes the activity as a GeoPackage with the layers `track` and `points`. The name of the
+/// activity is used in the descriptions of the layers.
+pub fn write_gpkg<W: Write>(mut writer: W, activity: &Activity, name: &str, options: &GpkgOptions) -> Result<(), WalkingError> {
+    let mut sql = Vec::new();
+    write_gpkg_sql(&mut sql, activity, name, options)?;
+
+    let temp_path: PathBuf = std::env::temp_dir().join(format!(
+        "fit2walking-{}-{}.gpkg",
+        std::process::id(),
+        TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed),
+    ));
+    let result = execute_sql(&temp_path, &sql)
+        .and_then(|_| Ok(fs::read(&temp_path)?));
+    let _ = fs::remove_file(&temp_path);
+    writer.write_all(&result?)?;
+    Ok(())
+}
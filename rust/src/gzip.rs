@@ -0,0 +1,156 @@
+//! Reading and writing of gzip files (RFC 1952).
+
+
+use std::io::{self, Write};
+
+use crate::deflate::{Deflater, inflate};
+use crate::zip::{crc32, crc32_continue};
+
+
+/// The first two bytes of every gzip member.
+pub const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+const FLAG_HEADER_CRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+
+/// The operating system field of written headers: unknown.
+const OS_UNKNOWN: u8 = 255;
+
+/// How much data is collected before it is compressed into a block.
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+
+fn skip_zero_terminated(data: &[u8], pos: usize) -> Result<usize, String> {
+    let terminator_index = data[pos..].iter()
+        .position(|b| *b == 0)
+        .ok_or_else(|| "unterminated string in gzip header".to_owned())?;
+    Ok(pos + terminator_index + 1)
+}
+
+fn truncated() -> String {
+    "truncated gzip data".to_owned()
+}
+
+
+/// Decompresses gzip data. Multiple concatenated members are decompressed one after the other.
+pub fn gunzip(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut output = Vec::new();
+    let mut pos = 0;
+
+    while pos < data.len() {
+        let header = data.get(pos..pos+10).ok_or_else(truncated)?;
+        if header[0..2] != GZIP_MAGIC {
+            return Err("not gzip data".to_owned());
+        }
+        if header[2] != 8 {
+            return Err(format!("unsupported gzip compression method {}", header[2]));
+        }
+        let flags = header[3];
+        pos += 10;
+
+        if flags & FLAG_EXTRA != 0 {
+            let length_bytes = data.get(pos..pos+2).ok_or_else(truncated)?;
+            pos += 2 + usize::from(u16::from_le_bytes([length_bytes[0], length_bytes[1]]));
+        }
+        if flags & FLAG_NAME != 0 {
+            pos = skip_zero_terminated(data, pos.min(data.len()))?;
+        }
+        if flags & FLAG_COMMENT != 0 {
+            pos = skip_zero_terminated(data, pos.min(data.len()))?;
+        }
+        if flags & FLAG_HEADER_CRC != 0 {
+            pos += 2;
+        }
+
+        let (member, consumed) = inflate(data.get(pos..).ok_or_else(truncated)?)?;
+        pos += consumed;
+
+        let trailer = data.get(pos..pos+8).ok_or_else(truncated)?;
+        let expected_crc = u32::from_le_bytes(trailer[0..4].try_into().unwrap());
+        let expected_size = u32::from_le_bytes(trailer[4..8].try_into().unwrap());
+        if crc32(&member) != expected_crc {
+            return Err("gzip CRC mismatch".to_owned());
+        }
+        if member.len() as u32 != expected_size {
+            return Err("gzip size mismatch".to_owned());
+        }
+        pos += 8;
+
+        output.extend_from_slice(&member);
+    }
+
+    Ok(output)
+}
+
+
+/// Compresses everything written to it into a gzip stream. The data is compressed in blocks, so
+/// only one block needs to be kept in memory.
+///
+/// [`GzipWriter::finish`] must be called to complete the stream.
+pub struct GzipWriter<W: Write> {
+    inner: W,
+    deflater: Deflater,
+    buffer: Vec<u8>,
+    crc: u32,
+    size: u32,
+    header_written: bool,
+}
+impl<W: Write> GzipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            deflater: Deflater::new(),
+            buffer: Vec::new(),
+            crc: 0,
+            size: 0,
+            header_written: false,
+        }
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            // no flags, no modification time, no extra flags
+            self.inner.write_all(&[GZIP_MAGIC[0], GZIP_MAGIC[1], 8, 0, 0, 0, 0, 0, 0, OS_UNKNOWN])?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn compress_buffer(&mut self, is_final: bool) -> io::Result<()> {
+        self.write_header()?;
+        self.deflater.compress_block(&self.buffer, is_final);
+        self.buffer.clear();
+        let compressed = self.deflater.take_output();
+        self.inner.write_all(&compressed)
+    }
+
+    /// Compresses the remaining data, writes the gzip trailer and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.compress_buffer(true)?;
+        let deflater = std::mem::take(&mut self.deflater);
+        self.inner.write_all(&deflater.finish())?;
+        self.inner.write_all(&self.crc.to_le_bytes())?;
+        self.inner.write_all(&self.size.to_le_bytes())?;
+        Ok(self.inner)
+    }
+}
+impl<W: Write> Write for GzipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = buf.len().min(BLOCK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..count]);
+        self.crc = crc32_continue(self.crc, &buf[..count]);
+        // the size is stored modulo 2**32
+        self.size = self.size.wrapping_add(count as u32);
+        if self.buffer.len() == BLOCK_SIZE {
+            self.compress_buffer(false)?;
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // incomplete blocks cannot be flushed
+        self.inner.flush()
+    }
+}
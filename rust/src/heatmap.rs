@@ -0,0 +1,218 @@
+//! Aggregation of many activities into a grid of visit frequencies, for personal heatmaps.
+
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde_json::{json, Map, Value};
+
+use crate::error::WalkingError;
+use crate::geojson::write_json;
+use crate::point::{GeoPoint, Point};
+
+
+/// The default edge length of a grid cell, in meters.
+pub const DEFAULT_HEATMAP_CELL_SIZE_M: f64 = 100.0;
+
+/// The approximate length of a degree of latitude, in meters.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+
+/// The format in which the heatmap is output.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum HeatmapFormat {
+    /// A GeoJSON FeatureCollection with one rectangular polygon per visited cell.
+    #[default]
+    Geojson,
+
+    /// CSV with one row per visited cell, located by the coordinates of its center.
+    Csv,
+}
+
+
+/// The number of visits of a grid cell.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CellCount {
+    /// How often a track entered the cell.
+    pub visits: u64,
+
+    /// How many activities passed through the cell.
+    pub activities: u64,
+}
+
+
+/// A grid of cells counting how often they have been visited.
+///
+/// The cells are approximately square at the latitude of the first point added; their width in
+/// degrees of longitude is the same everywhere, so cells far to the north or south of that point
+/// are narrower or wider than the nominal cell size.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    cell_size_m: f64,
+    cell_height_deg: f64,
+    cell_width_deg: Option<f64>,
+    cells: BTreeMap<(i64, i64), CellCount>,
+}
+impl Heatmap {
+    /// Creates an empty heatmap with cells of the given edge length, in meters.
+    pub fn new(cell_size_m: f64) -> Self {
+        Self {
+            cell_size_m,
+            cell_height_deg: cell_size_m / METERS_PER_DEGREE,
+            cell_width_deg: None,
+            cells: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the visited cells, keyed by their (row, column) index.
+    pub fn cells(&self) -> &BTreeMap<(i64, i64), CellCount> {
+        &self.cells
+    }
+
+    /// Returns the index of the cell containing the given coordinates, in degrees.
+    fn cell_index(&self, cell_width_deg: f64, lat_deg: f64, lon_deg: f64) -> (i64, i64) {
+        (
+            (lat_deg / self.cell_height_deg).floor() as i64,
+            (lon_deg / cell_width_deg).floor() as i64,
+        )
+    }
+
+    /// Returns the bounds of the cell with the given index as (min_lat, min_lon, max_lat, max_lon)
+    /// in degrees.
+    pub fn cell_bounds(&self, (row, column): (i64, i64)) -> (f64, f64, f64, f64) {
+        let cell_width_deg = self.cell_width_deg.unwrap_or(self.cell_height_deg);
+        (
+            row as f64 * self.cell_height_deg,
+            column as f64 * cell_width_deg,
+            (row + 1) as f64 * self.cell_height_deg,
+            (column + 1) as f64 * cell_width_deg,
+        )
+    }
+
+    /// Adds the lines of one activity to the heatmap.
+    ///
+    /// Segments are sampled at intervals of half a cell so that cells crossed between two distant
+    /// points are counted too. A cell counts as visited once for every time a line enters it, and
+    /// the activity counts once for every cell visited at all.
+    pub fn add_activity(&mut self, lines: &[Vec<Point>]) {
+        let cell_width_deg = match self.cell_width_deg {
+            Some(w) => w,
+            None => {
+                let first_point = match lines.iter().flatten().next() {
+                    Some(p) => p,
+                    None => return,
+                };
+                let w = self.cell_height_deg / first_point.latitude().to_radians().cos().max(0.01);
+                self.cell_width_deg = Some(w);
+                w
+            },
+        };
+
+        let mut visits: Vec<(i64, i64)> = Vec::new();
+        let mut activity_cells = BTreeSet::new();
+        for line in lines {
+            let mut current_cell = None;
+            let mut visit = |index: (i64, i64)| {
+                if current_cell != Some(index) {
+                    visits.push(index);
+                    activity_cells.insert(index);
+                    current_cell = Some(index);
+                }
+            };
+            for (i, point) in line.iter().enumerate() {
+                if i > 0 {
+                    let previous = &line[i - 1];
+                    let delta_lat = point.latitude() - previous.latitude();
+                    let delta_lon = point.longitude() - previous.longitude();
+                    let steps = (delta_lat.abs() / (self.cell_height_deg / 2.0))
+                        .max(delta_lon.abs() / (cell_width_deg / 2.0))
+                        .ceil() as usize;
+                    for step in 1..steps {
+                        let fraction = step as f64 / steps as f64;
+                        visit(self.cell_index(
+                            cell_width_deg,
+                            previous.latitude() + fraction * delta_lat,
+                            previous.longitude() + fraction * delta_lon,
+                        ));
+                    }
+                }
+                visit(self.cell_index(cell_width_deg, point.latitude(), point.longitude()));
+            }
+        }
+
+        for index in visits {
+            self.cells.entry(index).or_default().visits += 1;
+        }
+        for index in activity_cells {
+            self.cells.entry(index).or_default().activities += 1;
+        }
+    }
+
+    /// Converts the heatmap into a GeoJSON FeatureCollection. Each feature is a visited cell with
+    /// the properties `visits` and `activities`; the collection has the property `cell_size`.
+    pub fn to_geojson(&self) -> Value {
+        let features: Vec<Value> = self.cells.iter()
+            .map(|(index, count)| {
+                let (min_lat, min_lon, max_lat, max_lon) = self.cell_bounds(*index);
+                json!({
+                    "type": "Feature",
+                    "geometry": {
+                        "type": "Polygon",
+                        "coordinates": [[
+                            [min_lon, min_lat],
+                            [max_lon, min_lat],
+                            [max_lon, max_lat],
+                            [min_lon, max_lat],
+                            [min_lon, min_lat],
+                        ]],
+                    },
+                    "properties": {
+                        "visits": count.visits,
+                        "activities": count.activities,
+                    },
+                })
+            })
+            .collect();
+
+        let mut properties = Map::new();
+        properties.insert("cell_size".to_owned(), self.cell_size_m.into());
+        let mut document = json!({
+            "type": "FeatureCollection",
+            "features": features,
+            "properties": properties,
+        });
+        document.sort_all_objects();
+        document
+    }
+}
+
+
+/// Writes the heatmap as GeoJSON (pretty-printed unless `compact` is set).
+pub fn write_heatmap_geojson<W: Write>(writer: W, heatmap: &Heatmap, compact: bool) -> Result<(), WalkingError> {
+    let document = heatmap.to_geojson();
+    if compact {
+        write_json(&document, writer, serde_json::ser::CompactFormatter)
+    } else {
+        write_json(&document, writer, serde_json::ser::PrettyFormatter::new())
+    }
+}
+
+
+/// Writes the heatmap as CSV with one row per visited cell, located by its center.
+pub fn write_heatmap_csv<W: Write>(mut writer: W, heatmap: &Heatmap) -> Result<(), WalkingError> {
+    writeln!(writer, "latitude,longitude,visits,activities")?;
+    for (index, count) in heatmap.cells() {
+        let (min_lat, min_lon, max_lat, max_lon) = heatmap.cell_bounds(*index);
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            (min_lat + max_lat) / 2.0,
+            (min_lon + max_lon) / 2.0,
+            count.visits,
+            count.activities,
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,80 @@
+//! Export of a standalone HTML page that embeds the map viewer document and a minimal Leaflet
+//! viewer, so that an activity can be viewed without deploying the separate frontend.
+
+
+use std::io::{self, Write};
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::geojson::{DocumentOptions, write_activity_document};
+
+pub use crate::color::ColorBy;
+
+
+/// The HTML page; `@TITLE@`, `@COLOR_BY@` and `@DATA@` are replaced by the page title, the name of
+/// the initially shown layer and the document, respectively.
+const VIEWER_TEMPLATE: &str = include_str!("html_viewer.html");
+
+
+/// Options influencing the generated page.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HtmlOptions {
+    /// The options of the embedded document.
+    pub document: DocumentOptions,
+
+    /// The property by which the track is colored when the page is opened. The other properties can
+    /// still be selected in the layer control of the page.
+    pub color_by: ColorBy,
+}
+
+
+/// Escapes text for inclusion in HTML element content.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+
+/// Passes JSON through to the inner writer, escaping `<` so that strings within the document
+/// cannot close the enclosing `<script>` element. `<` only occurs within strings in JSON, where
+/// `\u003c` is an equivalent escape.
+struct ScriptEscapingWriter<W: Write> {
+    inner: W,
+}
+impl<W: Write> Write for ScriptEscapingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|b| *b == b'<') {
+            match chunk.split_last() {
+                Some((b'<', rest)) => {
+                    self.inner.write_all(rest)?;
+                    self.inner.write_all(b"\\u003c")?;
+                },
+                _ => self.inner.write_all(chunk)?,
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+
+/// Writes a standalone HTML page showing the activity on a map. The page loads Leaflet and the map
+/// tiles from the internet but needs no other files.
+pub fn write_html<W: Write>(mut writer: W, activity: &Activity, name: &str, options: &HtmlOptions) -> Result<(), WalkingError> {
+    let page = VIEWER_TEMPLATE
+        .replace("@TITLE@", &escape_html(name))
+        .replace("@COLOR_BY@", options.color_by.layer_name());
+    let (before_data, after_data) = page.split_once("@DATA@")
+        .expect("viewer template has no data placeholder");
+
+    writer.write_all(before_data.as_bytes())?;
+    write_activity_document(ScriptEscapingWriter { inner: &mut writer }, activity, &options.document)?;
+    writer.write_all(after_data.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,232 @@
+//! A minimal HTTP/1.1 server side: reading the head of a request and writing a response. Enough for
+//! the WebSocket handshake and for serving documents locally, not for exposure to the internet.
+
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::error::WalkingError;
+
+
+/// The maximum length of the head (request line and headers) of a request, in bytes.
+const MAX_HEAD_LENGTH: usize = 16 * 1024;
+
+/// The time after which reading a request or writing a response is given up.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default number of connections answered at the same time.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
+
+/// The head of an HTTP request. The body, if any, is not read.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Request {
+    pub method: String,
+
+    /// The request target, e.g. `/live`, including the query string.
+    pub path: String,
+
+    /// The headers, with their names as sent.
+    pub headers: Vec<(String, String)>,
+}
+impl Request {
+    /// Reads the head of a request, up to and including the empty line terminating the headers.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Self, WalkingError> {
+        let mut lines = Vec::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                return Err(WalkingError::Http("connection closed before the end of the headers".to_owned()));
+            }
+            length += read;
+            if length > MAX_HEAD_LENGTH {
+                return Err(WalkingError::Http("headers too long".to_owned()));
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line.to_owned());
+        }
+
+        let request_line = lines.first()
+            .ok_or_else(|| WalkingError::Http("missing request line".to_owned()))?;
+        let mut parts = request_line.split(' ');
+        let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(m), Some(p), Some(v)) if v.starts_with("HTTP/") => (m.to_owned(), p.to_owned()),
+            _ => return Err(WalkingError::Http(format!("invalid request line {:?}", request_line))),
+        };
+
+        let mut headers = Vec::with_capacity(lines.len() - 1);
+        for line in &lines[1..] {
+            let (name, value) = line.split_once(':')
+                .ok_or_else(|| WalkingError::Http(format!("invalid header {:?}", line)))?;
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+        Ok(Self { method, path, headers })
+    }
+
+    /// Returns the value of the first header with the given name, which is compared
+    /// case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Returns whether the comma-separated values of the header with the given name contain the
+    /// token, which is compared case-insensitively (e.g. `Connection: keep-alive, Upgrade`).
+    pub fn header_contains(&self, name: &str, token: &str) -> bool {
+        self.header(name)
+            .map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+            .unwrap_or(false)
+    }
+}
+
+
+/// Writes a response with the given status (e.g. `404 Not Found`), headers and body. A
+/// `Content-Length` header is added unless the status is `101 Switching Protocols`.
+pub fn write_response<W: Write>(mut writer: W, status: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<(), WalkingError> {
+    write!(writer, "HTTP/1.1 {}\r\n", status)?;
+    for (name, value) in headers {
+        write!(writer, "{}: {}\r\n", name, value)?;
+    }
+    if !status.starts_with("101 ") {
+        write!(writer, "Content-Length: {}\r\n", body.len())?;
+    }
+    write!(writer, "\r\n")?;
+    writer.write_all(body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+
+/// A complete response, sent with `Connection: close`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Response {
+    /// The status code and reason, e.g. `404 Not Found`.
+    pub status: &'static str,
+
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+impl Response {
+    /// A response with the value as its (compact) JSON body.
+    pub fn json(status: &'static str, value: &serde_json::Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    /// A response with the status and a JSON body of the form `{"error": message}`.
+    pub fn error(status: &'static str, message: &str) -> Self {
+        Self::json(status, &serde_json::json!({"error": message}))
+    }
+
+    /// Writes the response. If an origin is given, pages from that origin (or from any origin if
+    /// it is `*`) may read the response too; otherwise browsers only allow pages from the same
+    /// origin to read it.
+    pub fn write_to<W: Write>(&self, writer: W, cors_origin: Option<&str>) -> Result<(), WalkingError> {
+        let mut headers = vec![("Content-Type", self.content_type)];
+        if let Some(origin) = cors_origin {
+            headers.push(("Access-Control-Allow-Origin", origin));
+        }
+        headers.push(("Connection", "close"));
+        write_response(writer, self.status, &headers, &self.body)
+    }
+}
+
+
+/// Options of the server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ServerOptions {
+    /// The origin whose pages may read the responses (e.g. `http://localhost:3000`, or `*` for any
+    /// origin), or `None` if only pages served by the server itself may read them.
+    pub cors_origin: Option<String>,
+
+    /// The number of connections answered at the same time. Further connections are only accepted
+    /// once one of them has been answered.
+    pub max_connections: usize,
+}
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            cors_origin: None,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
+
+/// The number of connections being answered.
+#[derive(Debug, Default)]
+struct ConnectionCount {
+    count: Mutex<usize>,
+    decreased: Condvar,
+}
+impl ConnectionCount {
+    /// Waits until fewer than `max` connections are being answered, then counts one more until the
+    /// returned slot is dropped.
+    fn acquire(self: &Arc<Self>, max: usize) -> ConnectionSlot {
+        let mut count = self.count.lock().unwrap();
+        while *count >= max.max(1) {
+            count = self.decreased.wait(count).unwrap();
+        }
+        *count += 1;
+        ConnectionSlot(Arc::clone(self))
+    }
+}
+
+/// A connection counted by a [`ConnectionCount`].
+#[derive(Debug)]
+struct ConnectionSlot(Arc<ConnectionCount>);
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap() -= 1;
+        self.0.decreased.notify_one();
+    }
+}
+
+
+/// Answers the connections accepted by the listener, each in its own thread, by reading a single
+/// request and writing the response returned by the handler. Runs until accepting fails.
+pub fn serve<F>(listener: TcpListener, options: ServerOptions, handler: F) -> Result<(), WalkingError>
+    where F: Fn(&Request) -> Response + Send + Sync + 'static
+{
+    let handler = Arc::new(handler);
+    let options = Arc::new(options);
+    let connection_count: Arc<ConnectionCount> = Arc::default();
+    loop {
+        // waiting connections are queued by the operating system in the meantime
+        let slot = connection_count.acquire(options.max_connections);
+        let (stream, _) = listener.accept()?;
+        let connection_handler = Arc::clone(&handler);
+        let connection_options = Arc::clone(&options);
+        std::thread::spawn(move || {
+            if let Err(e) = answer(&stream, &connection_options, connection_handler.as_ref()) {
+                log::info!("{}: {}", stream.peer_addr().map(|a| a.to_string()).unwrap_or_default(), e);
+            }
+            drop(slot);
+        });
+    }
+}
+
+fn answer<F: Fn(&Request) -> Response>(stream: &TcpStream, options: &ServerOptions, handler: &F) -> Result<(), WalkingError> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let response = match Request::read(&mut BufReader::new(stream)) {
+        Ok(request) => {
+            log::debug!("{} {}", request.method, request.path);
+            handler(&request)
+        },
+        Err(WalkingError::Http(message)) => Response::error("400 Bad Request", &message),
+        Err(e) => return Err(e),
+    };
+    response.write_to(stream, options.cors_origin.as_deref())
+}
@@ -0,0 +1,142 @@
+//! Importing the activities recorded by a Garmin device that is mounted as USB mass storage.
+//!
+//! The FIT files in the activity folder of the device are copied into an archive directory, named
+//! after the time at which recording started: `<archive>/<year>/<year>-<month>-<day>-<hhmmss>.fit`.
+//! Files whose start time cannot be determined are copied into `<archive>/undated` under their
+//! original names. A file counts as imported if the archive already contains a file of the same
+//! name and contents.
+
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::input::fit::read_fit;
+use crate::input::{list_fit_files, ReadOptions};
+use crate::timezone::Timezone;
+
+
+/// The directories in which removable devices are mounted, each containing one directory per
+/// device. `$USER` is replaced by the name of the user.
+const MOUNT_ROOTS: [&str; 5] = ["/media/$USER", "/run/media/$USER", "/media", "/mnt", "/Volumes"];
+
+/// The names of the folder of a Garmin device containing recorded activities, depending on the
+/// model. They are compared case-insensitively.
+const ACTIVITY_FOLDER_NAMES: [&str; 2] = ["Activity", "Activities"];
+
+
+/// Returns the entry of the directory whose name matches the given name case-insensitively (FAT
+/// file systems mounted on case-sensitive systems keep the case the device has chosen).
+fn child_ignoring_case(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir).ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .find(|p| p.is_dir() && p.file_name().map(|n| n.to_string_lossy().eq_ignore_ascii_case(name)).unwrap_or(false))
+}
+
+
+/// Returns the activity folder (`GARMIN/Activity`) of the Garmin device mounted at the given path,
+/// if it is one.
+pub fn activity_folder(mount_point: &Path) -> Option<PathBuf> {
+    let garmin = child_ignoring_case(mount_point, "GARMIN")?;
+    ACTIVITY_FOLDER_NAMES.iter()
+        .find_map(|name| child_ignoring_case(&garmin, name))
+}
+
+
+/// Returns the activity folders of the Garmin devices that are currently mounted.
+pub fn detect_activity_folders() -> Vec<PathBuf> {
+    let user = env::var("USER").unwrap_or_default();
+    let mut ret = Vec::new();
+    for root in MOUNT_ROOTS {
+        if root.contains("$USER") && user.is_empty() {
+            continue;
+        }
+        let root = PathBuf::from(root.replace("$USER", &user));
+        let mount_points = match fs::read_dir(&root) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()),
+            Err(_) => continue,
+        };
+        for mount_point in mount_points {
+            if let Some(folder) = activity_folder(&mount_point) {
+                if !ret.contains(&folder) {
+                    ret.push(folder);
+                }
+            }
+        }
+    }
+    ret
+}
+
+
+/// Returns the path relative to the archive directory under which the FIT file is archived.
+pub fn archive_path(data: &[u8], original_path: &Path, timezone: &Timezone) -> PathBuf {
+    let original_name = original_path.file_name()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("activity.fit"));
+    match fit_start(data) {
+        Some((t, activity)) => {
+            let local = timezone.for_lines(&activity.lines).convert(&t);
+            PathBuf::from(local.format("%Y").to_string())
+                .join(local.format("%Y-%m-%d-%H%M%S.fit").to_string())
+        },
+        None => PathBuf::from("undated").join(original_name),
+    }
+}
+
+/// Returns the time at which recording of the first activity in the FIT file started, along with
+/// that activity.
+fn fit_start(data: &[u8]) -> Option<(DateTime<Local>, Activity)> {
+    let mut reader = data;
+    read_fit(&mut reader, &ReadOptions::default()).ok()?
+        .into_iter()
+        .filter_map(|a| a.recording_start_time().map(|t| (t, a)))
+        .min_by_key(|(t, _)| *t)
+}
+
+
+/// Copies the FIT files in the activity folder that have not been imported before into the archive
+/// directory. Returns the paths of the copied files within the archive.
+///
+/// If the archive already contains a different file of the same name (e.g. two activities started
+/// within the same second), a number is appended to the name of the new file.
+pub fn import_activities(activity_folder: &Path, archive: &Path, timezone: &Timezone) -> Result<Vec<PathBuf>, WalkingError> {
+    let mut ret = Vec::new();
+    for path in list_fit_files(activity_folder)? {
+        let data = fs::read(&path)?;
+        let relative_path = archive_path(&data, &path, timezone);
+        let mut target = archive.join(&relative_path);
+        let mut number = 1;
+        let imported = loop {
+            match fs::read(&target) {
+                Ok(existing) if existing == data => break true,
+                Ok(_) => {
+                    number += 1;
+                    let stem = relative_path.file_stem().unwrap_or_default().to_string_lossy();
+                    target = archive.join(relative_path.with_file_name(format!("{}-{}.fit", stem, number)));
+                },
+                Err(_) => break false,
+            }
+        };
+        if imported {
+            log::debug!("{}: already imported as {}", path.display(), target.display());
+            continue;
+        }
+
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // written under a temporary name first, so that an interrupted copy is not taken for an
+        // imported file
+        let partial_target = target.with_extension("fit.part");
+        fs::write(&partial_target, &data)?;
+        fs::rename(&partial_target, &target)?;
+        log::info!("{}: imported as {}", path.display(), target.display());
+        ret.push(target);
+    }
+    Ok(ret)
+}
@@ -0,0 +1,284 @@
+//! Indoor activities (e.g. on trainers and treadmills), which are recorded without positions.
+//!
+//! Instead of a map, the document of an indoor activity contains a series of the measurements,
+//! indexed by the time since the start and by a virtual distance, from which charts can be drawn.
+
+
+use std::cmp::Ordering;
+use std::io::Write;
+
+use chrono::{DateTime, Local};
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::geojson::{DocumentOptions, format_timestamp, metadata_entries, summary_entries, write_json};
+use crate::point::Point;
+use crate::schema::{FORMAT_VERSION, IndoorDocument, to_json};
+use crate::stats::Statistics;
+
+
+/// The measurements of a record without a position.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sample {
+    pub timestamp: Option<DateTime<Local>>,
+    pub unix_timestamp: Option<f64>,
+
+    /// The cumulative distance recorded by the device (e.g. by the treadmill or a wheel sensor), in
+    /// meters.
+    pub device_distance_m: Option<f64>,
+
+    pub speed_km_per_h: Option<f64>,
+    pub heart_rate_bpm: Option<u64>,
+    pub cadence_rpm: Option<u64>,
+    pub power_w: Option<u64>,
+}
+impl From<&Point> for Sample {
+    fn from(point: &Point) -> Self {
+        Self {
+            timestamp: point.timestamp,
+            unix_timestamp: point.unix_timestamp,
+            device_distance_m: point.device_distance_m,
+            speed_km_per_h: point.speed_km_per_h,
+            heart_rate_bpm: point.heart_rate_bpm,
+            cadence_rpm: point.cadence_rpm,
+            power_w: point.power_w,
+        }
+    }
+}
+
+
+/// Returns whether the activity was recorded indoors, i.e. whether it has samples but no points.
+pub fn is_indoor(activity: &Activity) -> bool {
+    activity.point_count() == 0 && !activity.samples.is_empty()
+}
+
+
+/// Returns the samples of the activity together with the measurements of its points, ordered by
+/// time. Samples without timestamps are placed at the end.
+pub fn all_samples(activity: &Activity) -> Vec<Sample> {
+    let mut samples: Vec<Sample> = activity.lines.iter()
+        .flatten()
+        .map(Sample::from)
+        .chain(activity.samples.iter().cloned())
+        .collect();
+    samples.sort_by(|a, b| match (a.unix_timestamp, b.unix_timestamp) {
+        (Some(ta), Some(tb)) => ta.total_cmp(&tb),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    samples
+}
+
+
+/// Calculates the virtual distance covered up to each sample, in meters. Between two samples, the
+/// increase of the distance recorded by the device is used if both have one; otherwise, the
+/// distance covered at the average of their speeds in the time between them.
+pub fn virtual_distances_m(samples: &[Sample]) -> Vec<f64> {
+    let mut distance_m = 0.0;
+    let mut ret = Vec::with_capacity(samples.len());
+    for (i, sample) in samples.iter().enumerate() {
+        if let Some(previous) = i.checked_sub(1).map(|j| &samples[j]) {
+            distance_m += distance_between_m(previous, sample);
+        }
+        ret.push(distance_m);
+    }
+    ret
+}
+
+fn distance_between_m(previous: &Sample, sample: &Sample) -> f64 {
+    if let (Some(d1), Some(d2)) = (previous.device_distance_m, sample.device_distance_m) {
+        return (d2 - d1).max(0.0);
+    }
+    let duration_s = match (previous.unix_timestamp, sample.unix_timestamp) {
+        (Some(t1), Some(t2)) if t2 > t1 => t2 - t1,
+        _ => return 0.0,
+    };
+    let speed_km_per_h = match (previous.speed_km_per_h, sample.speed_km_per_h) {
+        (Some(s1), Some(s2)) => (s1 + s2) / 2.0,
+        (Some(s), None)|(None, Some(s)) => s,
+        (None, None) => return 0.0,
+    };
+    speed_km_per_h / 3.6 * duration_s
+}
+
+
+/// Calculates statistics over the time between consecutive samples, in the way
+/// [`compute_statistics`](crate::stats::compute_statistics) does over segments, using the virtual
+/// distance between the samples.
+pub fn sample_statistics(samples: &[Sample], moving_threshold_km_per_h: f64) -> Statistics {
+    let mut stats = Statistics::default();
+    let mut moving_distance_m = 0.0;
+    for pair in samples.windows(2) {
+        let distance_m = distance_between_m(&pair[0], &pair[1]);
+        stats.total_distance_m += distance_m;
+
+        let duration_s = match (pair[0].unix_timestamp, pair[1].unix_timestamp) {
+            (Some(t1), Some(t2)) if t2 > t1 => t2 - t1,
+            _ => continue,
+        };
+        if distance_m / duration_s * 3.6 >= moving_threshold_km_per_h {
+            stats.moving_time_s += duration_s;
+            moving_distance_m += distance_m;
+        }
+    }
+
+    let first_timestamp = samples.iter().find_map(|s| s.unix_timestamp);
+    let last_timestamp = samples.iter().rev().find_map(|s| s.unix_timestamp);
+    if let (Some(first), Some(last)) = (first_timestamp, last_timestamp) {
+        stats.elapsed_time_s = last - first;
+    }
+    if stats.moving_time_s > 0.0 {
+        stats.avg_moving_speed_km_per_h = Some(moving_distance_m / stats.moving_time_s * 3.6);
+    }
+    stats.max_speed_km_per_h = extrema(samples, |s| s.speed_km_per_h).map(|(_, max)| max);
+    stats
+}
+
+
+/// Returns the minimum and maximum of the values of the samples, if any sample has a value.
+fn extrema<F: Fn(&Sample) -> Option<f64>>(samples: &[Sample], value: F) -> Option<(f64, f64)> {
+    samples.iter()
+        .filter_map(value)
+        .fold(None, |acc, v| match acc {
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+            None => Some((v, v)),
+        })
+}
+
+/// Returns the mean of the values of the samples, if any sample has a value.
+fn mean<F: Fn(&Sample) -> Option<f64>>(samples: &[Sample], value: F) -> Option<f64> {
+    let values: Vec<f64> = samples.iter()
+        .filter_map(value)
+        .collect();
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+
+/// Converts a sample into a JSON object, with its time since `start_unix_timestamp` in seconds and
+/// its virtual distance. Values that have not been recorded are omitted.
+fn sample_to_json(sample: &Sample, distance_m: f64, start_unix_timestamp: Option<f64>, options: &DocumentOptions) -> serde_json::Value {
+    let units = options.units;
+    let mut ret = serde_json::Map::new();
+    if let (Some(start), Some(t)) = (start_unix_timestamp, sample.unix_timestamp) {
+        ret.insert("time".to_owned(), (t - start).into());
+    }
+    if let Some(timestamp) = &sample.timestamp {
+        ret.insert("timestamp".to_owned(), format_timestamp(timestamp, &options.timezone));
+    }
+    ret.insert("distance".to_owned(), units.distance(distance_m).into());
+    if let Some(speed) = sample.speed_km_per_h {
+        ret.insert("speed".to_owned(), units.speed(speed).into());
+        if let Some(pace) = units.pace_min_per_unit(speed) {
+            ret.insert("pace".to_owned(), pace.into());
+        }
+    }
+    if let Some(heart_rate) = sample.heart_rate_bpm {
+        ret.insert("heart_rate".to_owned(), heart_rate.into());
+    }
+    if let Some(cadence) = sample.cadence_rpm {
+        ret.insert("cadence".to_owned(), cadence.into());
+    }
+    if let Some(power) = sample.power_w {
+        ret.insert("power".to_owned(), power.into());
+    }
+    ret.into()
+}
+
+
+/// Converts the activity into a document without a map: the `samples` series of the measurements
+/// (of both the samples and the points of the activity), their ranges, statistics, the lap and
+/// session totals and the metadata. The document is marked with `"indoor": true`.
+pub fn indoor_document(activity: &Activity, options: &DocumentOptions) -> Result<serde_json::Value, WalkingError> {
+    let samples = all_samples(activity);
+    if samples.is_empty() {
+        return Err(WalkingError::NoPoints);
+    }
+    let units = options.units;
+    let distances_m = virtual_distances_m(&samples);
+    let total_distance_m = distances_m.last().copied().unwrap_or(0.0);
+
+    let start_unix_timestamp = samples.iter().find_map(|s| s.unix_timestamp);
+    let end_unix_timestamp = samples.iter().rev().find_map(|s| s.unix_timestamp);
+    let series: Vec<serde_json::Value> = samples.iter()
+        .zip(&distances_m)
+        .map(|(s, d)| sample_to_json(s, *d, start_unix_timestamp, options))
+        .collect();
+
+    let speed = |s: &Sample| s.speed_km_per_h;
+    let heart_rate = |s: &Sample| s.heart_rate_bpm.map(|hr| hr as f64);
+    let cadence = |s: &Sample| s.cadence_rpm.map(|c| c as f64);
+    let power = |s: &Sample| s.power_w.map(|p| p as f64);
+    let (min_speed, max_speed) = extrema(&samples, speed)
+        .unwrap_or((0.0, 10.0));
+    let (min_pace, max_pace) = extrema(&samples, |s| s.speed_km_per_h.and_then(|v| units.pace_min_per_unit(v)))
+        .unwrap_or((
+            units.pace_min_per_unit(10.0).unwrap(),
+            units.pace_min_per_unit(2.0).unwrap(),
+        ));
+    let (min_hr, max_hr) = extrema(&samples, heart_rate)
+        .unwrap_or((80.0, 160.0));
+    let (min_cad, max_cad) = extrema(&samples, cadence)
+        .unwrap_or((0.0, 120.0));
+    let (min_power, max_power) = extrema(&samples, power)
+        .unwrap_or((0.0, 300.0));
+
+    let mut stats = serde_json::Map::new();
+    stats.insert("distance".to_owned(), units.distance(total_distance_m).into());
+    if let (Some(start), Some(end)) = (start_unix_timestamp, end_unix_timestamp) {
+        let elapsed_time_s = end - start;
+        stats.insert("elapsed_time".to_owned(), elapsed_time_s.into());
+        if elapsed_time_s > 0.0 {
+            stats.insert("avg_speed".to_owned(), units.speed(total_distance_m / elapsed_time_s * 3.6).into());
+        }
+    }
+    if let Some((_, max)) = extrema(&samples, speed) {
+        stats.insert("max_speed".to_owned(), units.speed(max).into());
+    }
+    if let Some(avg) = mean(&samples, heart_rate) {
+        stats.insert("avg_heart_rate".to_owned(), avg.into());
+    }
+    if let Some((_, max)) = extrema(&samples, heart_rate) {
+        stats.insert("max_heart_rate".to_owned(), max.into());
+    }
+    if let Some(avg) = mean(&samples, cadence) {
+        stats.insert("avg_cadence".to_owned(), avg.into());
+    }
+    if let Some(avg) = mean(&samples, power) {
+        stats.insert("avg_power".to_owned(), avg.into());
+    }
+    if let Some((_, max)) = extrema(&samples, power) {
+        stats.insert("max_power".to_owned(), max.into());
+    }
+
+    Ok(to_json(&IndoorDocument {
+        format_version: FORMAT_VERSION,
+        indoor: true,
+        samples: series,
+        speed_range: [units.speed(min_speed), units.speed(max_speed)],
+        pace_range: [min_pace, max_pace],
+        heart_rate_range: [min_hr, max_hr],
+        cadence_range: [min_cad, max_cad],
+        power_range: [min_power, max_power],
+        stats,
+        laps: activity.laps.iter()
+            .map(|l| summary_entries(l, options))
+            .collect(),
+        session: activity.sessions.first()
+            .map(|s| summary_entries(s, options)),
+        metadata: metadata_entries(activity, options),
+        units: units.names_to_json(),
+    }))
+}
+
+
+/// Writes the document of [`indoor_document`] as JSON (pretty-printed unless `options.compact` is
+/// set).
+pub fn write_indoor_document<W: Write>(writer: W, activity: &Activity, options: &DocumentOptions) -> Result<(), WalkingError> {
+    let document = indoor_document(activity, options)?;
+    if options.compact {
+        write_json(&document, writer, serde_json::ser::CompactFormatter)
+    } else {
+        write_json(&document, writer, serde_json::ser::PrettyFormatter::new())
+    }
+}
@@ -0,0 +1,447 @@
+use std::collections::HashSet;
+use std::io::Read;
+
+use chrono::{DateTime, Local};
+
+use fitparser::profile::MesgNum;
+
+use crate::activity::{Activity, Pause, Summary};
+use crate::console;
+use crate::diagnostics::{Diagnostics, DropReason};
+use crate::error::WalkingError;
+use crate::indoor::Sample;
+use crate::input::ReadOptions;
+use crate::point::Point;
+
+
+/// The number of records after which the progress is updated.
+const PROGRESS_RECORD_CHUNK: usize = 1000;
+
+
+#[inline]
+fn semicircles_to_degrees(sc: f64) -> f64 {
+    sc * 180.0 / 2.0_f64.powi(31)
+}
+
+fn semicircle_value_to_degrees(sc_val: &fitparser::Value) -> Result<f64, String> {
+    if let fitparser::Value::SInt32(sc) = sc_val {
+        Ok(semicircles_to_degrees((*sc).into()))
+    } else {
+        Err(format!("unexpected position value {:?}", sc_val))
+    }
+}
+
+
+/// Adds a warning about a field whose value is not of the expected type.
+fn unexpected_value(diagnostics: &mut Diagnostics, record_index: usize, field: &fitparser::FitDataField) {
+    diagnostics.ignore_field(record_index, field.name(), format!("unexpected value {:?}", field.value()));
+}
+
+/// Logs all fields of a message. Record messages are logged at trace level, all others at debug
+/// level.
+fn log_message(record_index: usize, record: &fitparser::FitDataRecord) {
+    let level = if record.kind() == MesgNum::Record {
+        log::Level::Trace
+    } else {
+        log::Level::Debug
+    };
+    if !log::log_enabled!(level) {
+        return;
+    }
+    let fields: Vec<String> = record.fields().iter()
+        .map(|field| if field.units().is_empty() {
+            format!("{}[{}] = {:?}", field.name(), field.number(), field.value())
+        } else {
+            format!("{}[{}] = {:?} {}", field.name(), field.number(), field.value(), field.units())
+        })
+        .collect();
+    log::log!(level, "message {}: {:?} {{ {} }}", record_index, record.kind(), fields.join(", "));
+}
+
+
+fn field_value<'a>(record: &'a fitparser::FitDataRecord, name: &str) -> Option<&'a fitparser::Value> {
+    record.fields().iter()
+        .find(|df| df.name() == name)
+        .map(|df| df.value())
+}
+
+fn field_f64(record: &fitparser::FitDataRecord, name: &str) -> Option<f64> {
+    field_value(record, name)
+        .and_then(|v| v.clone().try_into().ok())
+}
+
+fn field_u64(record: &fitparser::FitDataRecord, name: &str) -> Option<u64> {
+    field_value(record, name)
+        .and_then(|v| TryInto::<i64>::try_into(v).ok())
+        .and_then(|v| v.try_into().ok())
+}
+
+/// Returns the value of a field as a string; numeric values are formatted.
+fn field_string(record: &fitparser::FitDataRecord, name: &str) -> Option<String> {
+    match field_value(record, name)? {
+        fitparser::Value::String(s) => Some(s.clone()),
+        other => TryInto::<i64>::try_into(other).ok()
+            .map(|n| n.to_string()),
+    }
+}
+
+fn field_timestamp(record: &fitparser::FitDataRecord, name: &str) -> Option<DateTime<Local>> {
+    match field_value(record, name) {
+        Some(fitparser::Value::Timestamp(ts)) => Some(*ts),
+        _ => None,
+    }
+}
+
+/// Reads the measurements of a record without a position.
+fn record_to_sample(record: &fitparser::FitDataRecord) -> Sample {
+    let timestamp = field_timestamp(record, "timestamp");
+    Sample {
+        timestamp,
+        unix_timestamp: timestamp.map(|ts| ts.timestamp_millis() as f64 / 1000.0),
+        device_distance_m: field_f64(record, "distance"),
+        speed_km_per_h: field_f64(record, "enhanced_speed").map(|s| s * 3.6),
+        heart_rate_bpm: field_u64(record, "heart_rate"),
+        cadence_rpm: field_u64(record, "cadence"),
+        power_w: field_u64(record, "power"),
+    }
+}
+
+/// Reads the share of the power contributed by the left leg from the `left_right_balance` field of a
+/// Record message. The field contains the share of the right leg if its highest bit is set;
+/// otherwise, the leg to which it refers is unknown and `None` is returned.
+fn left_balance_percent(record: &fitparser::FitDataRecord) -> Option<f64> {
+    const RIGHT_FLAG: u64 = 0x80;
+    const PERCENT_MASK: u64 = 0x7F;
+
+    // the decoder names the values that coincide with the flag and the mask
+    let balance = match field_value(record, "left_right_balance")? {
+        fitparser::Value::String(s) if s == "right" => RIGHT_FLAG,
+        other => TryInto::<i64>::try_into(other).ok()
+            .and_then(|v| u64::try_from(v).ok())?,
+    };
+    let right_percent = balance & PERCENT_MASK;
+    if balance & RIGHT_FLAG == 0 || right_percent > 100 {
+        return None;
+    }
+    Some(100.0 - right_percent as f64)
+}
+
+/// Reads the totals from a Lap or Session message.
+fn record_to_summary(record: &fitparser::FitDataRecord) -> Summary {
+    Summary {
+        sport: field_string(record, "sport"),
+        sub_sport: field_string(record, "sub_sport"),
+        swim_stroke: field_string(record, "swim_stroke"),
+        start_time: field_timestamp(record, "start_time"),
+        end_time: field_timestamp(record, "timestamp"),
+        total_elapsed_time_s: field_f64(record, "total_elapsed_time"),
+        total_timer_time_s: field_f64(record, "total_timer_time"),
+        total_distance_m: field_f64(record, "total_distance"),
+        total_calories_kcal: field_u64(record, "total_calories"),
+        avg_heart_rate_bpm: field_u64(record, "avg_heart_rate"),
+        max_heart_rate_bpm: field_u64(record, "max_heart_rate"),
+    }
+}
+
+
+/// Reads the records of a FIT file into activities. Chained FIT files (multiple FIT files stored
+/// one after the other) produce one activity per contained file.
+///
+/// All messages are logged at debug level (Record messages at trace level).
+pub fn read_fit<R: Read>(reader: &mut R, options: &ReadOptions) -> Result<Vec<Activity>, WalkingError> {
+    let records = fitparser::from_reader(reader)?;
+
+    // every FIT file starts with a FileId message
+    let mut activities = Vec::new();
+    let mut file_records = Vec::new();
+    for record in records {
+        if record.kind() == MesgNum::FileId && !file_records.is_empty() {
+            activities.push(records_to_activity(std::mem::take(&mut file_records), options));
+        }
+        file_records.push(record);
+    }
+    if !file_records.is_empty() || activities.is_empty() {
+        activities.push(records_to_activity(file_records, options));
+    }
+    Ok(activities)
+}
+
+
+/// Converts the records of a single FIT file into an activity.
+fn records_to_activity(records: Vec<fitparser::FitDataRecord>, options: &ReadOptions) -> Activity {
+    let mut activity = Activity::default();
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+
+    // developer fields are identified by their (field definition number, name) pairs, as announced
+    // by FieldDescription messages
+    let mut developer_fields: HashSet<(u8, String)> = HashSet::new();
+
+    // SpO2 is measured far less often than the other values and stored in separate messages; the
+    // most recent reading applies to all following records
+    let mut current_spo2_percent: Option<u64> = None;
+
+    // the time and position at which the timer was last stopped, until it is started again
+    let mut pause_start: Option<(DateTime<Local>, geo::Point<f64>)> = None;
+
+    let record_count = records.len();
+    for (record_index, record) in records.into_iter().enumerate() {
+        log_message(record_index, &record);
+        if (record_index + 1) % PROGRESS_RECORD_CHUNK == 0 {
+            console::add_records(PROGRESS_RECORD_CHUNK);
+        }
+
+        if record.kind() == MesgNum::Event {
+            let event_category_opt = record.fields().iter()
+                .filter(|f| f.number() == 0)
+                .map(|f| f.value())
+                .next();
+            let event_type_opt = record.fields().iter()
+                .filter(|f| f.number() == 1)
+                .map(|f| f.value())
+                .next();
+
+            if let Some(fitparser::Value::String(ec)) = event_category_opt {
+                if ec == "timer" {
+                    if let Some(fitparser::Value::String(et)) = event_type_opt {
+                        let timestamp_opt = match field_value(&record, "timestamp") {
+                            Some(fitparser::Value::Timestamp(ts)) => Some(*ts),
+                            _ => None,
+                        };
+                        if et == "stop_all" {
+                            let last_point = line.last()
+                                .or_else(|| lines.last().and_then(|l: &Vec<Point>| l.last()));
+                            if let (Some(ts), Some(point)) = (timestamp_opt, last_point) {
+                                pause_start = Some((ts, point.coordinates_deg));
+                            }
+
+                            // timer stopped; show this as a discontinuity in the line
+                            if !line.is_empty() {
+                                lines.push(line);
+                            }
+                            line = Vec::new();
+                        } else if et == "start" {
+                            if let (Some((start_time, position_deg)), Some(end_time)) = (pause_start.take(), timestamp_opt) {
+                                activity.pauses.push(Pause {
+                                    start_time,
+                                    end_time,
+                                    position_deg,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        if record.kind() == MesgNum::FieldDescription {
+            let number_opt = field_u64(&record, "field_definition_number")
+                .and_then(|n| u8::try_from(n).ok());
+            let name_opt = match field_value(&record, "field_name") {
+                Some(fitparser::Value::String(n)) => Some(n.clone()),
+                _ => None,
+            };
+            if let (Some(number), Some(name)) = (number_opt, name_opt) {
+                developer_fields.insert((number, name));
+            }
+        }
+
+        if record.kind() == MesgNum::Spo2Data {
+            if let Some(spo2) = field_u64(&record, "reading_spo2") {
+                current_spo2_percent = Some(spo2);
+            }
+        }
+
+        if record.kind() == MesgNum::FileId {
+            let metadata = &mut activity.metadata;
+            metadata.manufacturer = field_string(&record, "manufacturer");
+            // the product is expanded into a manufacturer-specific field if known
+            metadata.product = field_string(&record, "garmin_product")
+                .or_else(|| field_string(&record, "product"));
+            metadata.serial_number = field_u64(&record, "serial_number");
+        }
+
+        if record.kind() == MesgNum::DeviceInfo {
+            // the device that created the file has the index 0 ("creator")
+            let is_creator = match field_value(&record, "device_index") {
+                Some(fitparser::Value::String(di)) => di == "creator",
+                Some(di) => TryInto::<i64>::try_into(di).ok() == Some(0),
+                None => false,
+            };
+            if is_creator && activity.metadata.software_version.is_none() {
+                activity.metadata.software_version = field_f64(&record, "software_version")
+                    .map(|v| v.to_string());
+            }
+        }
+
+        if record.kind() == MesgNum::Sport || record.kind() == MesgNum::Session {
+            // prefer the Sport message, which precedes the Session message
+            if activity.metadata.sport.is_none() {
+                activity.metadata.sport = field_string(&record, "sport");
+                activity.metadata.sub_sport = field_string(&record, "sub_sport");
+            }
+        }
+
+        if record.kind() == MesgNum::Lap {
+            activity.laps.push(record_to_summary(&record));
+        } else if record.kind() == MesgNum::Session {
+            activity.sessions.push(record_to_summary(&record));
+        }
+
+        if record.kind() != MesgNum::Record {
+            continue;
+        }
+
+        let lat_semicirc_opt = record.fields().iter()
+            .find(|df| df.name() == "position_lat");
+        let lon_semicirc_opt = record.fields().iter()
+            .find(|df| df.name() == "position_long");
+
+        let (lat_semicirc, lon_semicirc) = match (lat_semicirc_opt, lon_semicirc_opt) {
+            (Some(lat), Some(lon)) => (lat, lon),
+            _ => {
+                // position recording paused (probably went indoors); keep the measurements as a
+                // sample, store the current line and try the next point
+                activity.samples.push(record_to_sample(&record));
+                activity.diagnostics.drop_points(DropReason::NoPosition, 1);
+                if !line.is_empty() {
+                    lines.push(line);
+                    line = Vec::new();
+                }
+                continue;
+            },
+        };
+
+        let lat_lon_res = semicircle_value_to_degrees(lat_semicirc.value())
+            .and_then(|lat| Ok((lat, semicircle_value_to_degrees(lon_semicirc.value())?)));
+        let (lat_deg, lon_deg) = match lat_lon_res {
+            Ok(ll) => ll,
+            Err(message) => {
+                activity.diagnostics.skip_record(record_index, message);
+                continue;
+            },
+        };
+        let mut point = Point::new(geo::Point::from((lon_deg, lat_deg)));
+
+        let timestamp_field_opt = record.fields().iter()
+            .find(|df| df.name() == "timestamp");
+        if let Some(tsfield) = timestamp_field_opt {
+            if let fitparser::Value::Timestamp(ts) = tsfield.value() {
+                let ts_ms = ts.timestamp_millis();
+                let ts_f64 = (ts_ms as f64) / 1000.0;
+                point.unix_timestamp = Some(ts_f64);
+                point.timestamp = Some(*ts);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, tsfield);
+            }
+        }
+
+        let hr_field_opt = record.fields().iter()
+            .find(|df| df.name() == "heart_rate");
+        if let Some(hr_field) = hr_field_opt {
+            if let fitparser::Value::UInt8(hr) = hr_field.value() {
+                point.heart_rate_bpm = Some((*hr) as u64);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, hr_field);
+            }
+        }
+
+        let alt_field_opt = record.fields().iter()
+            .find(|df| df.name() == "enhanced_altitude");
+        if let Some(alt_field) = alt_field_opt {
+            if let fitparser::Value::Float64(alt) = alt_field.value() {
+                point.elevation_m = Some(*alt);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, alt_field);
+            }
+        }
+
+        let speed_field_opt = record.fields().iter()
+            .find(|df| df.name() == "enhanced_speed");
+        if let Some(speed_field) = speed_field_opt {
+            if let fitparser::Value::Float64(speed_mpers) = speed_field.value() {
+                let speed_km_per_h = (*speed_mpers) * 3.6;
+                point.speed_km_per_h = Some(speed_km_per_h);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, speed_field);
+            }
+        }
+
+        let distance_field_opt = record.fields().iter()
+            .find(|df| df.name() == "distance");
+        if let Some(distance_field) = distance_field_opt {
+            if let fitparser::Value::Float64(distance_m) = distance_field.value() {
+                point.device_distance_m = Some(*distance_m);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, distance_field);
+            }
+        }
+
+        let cadence_field_opt = record.fields().iter()
+            .find(|df| df.name() == "cadence");
+        if let Some(cadence_field) = cadence_field_opt {
+            if let fitparser::Value::UInt8(cad) = cadence_field.value() {
+                point.cadence_rpm = Some((*cad) as u64);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, cadence_field);
+            }
+        }
+
+        let temperature_field_opt = record.fields().iter()
+            .find(|df| df.name() == "temperature");
+        if let Some(temperature_field) = temperature_field_opt {
+            if let fitparser::Value::SInt8(temp) = temperature_field.value() {
+                point.temperature_degc = Some((*temp) as i64);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, temperature_field);
+            }
+        }
+
+        let power_field_opt = record.fields().iter()
+            .find(|df| df.name() == "power");
+        if let Some(power_field) = power_field_opt {
+            if let fitparser::Value::UInt16(pwr) = power_field.value() {
+                point.power_w = Some((*pwr) as u64);
+            } else {
+                unexpected_value(&mut activity.diagnostics, record_index, power_field);
+            }
+        }
+
+        // respiration_rate is expanded into enhanced_respiration_rate by the decoder
+        point.respiration_rate_per_min = field_f64(&record, "enhanced_respiration_rate");
+        point.spo2_percent = current_spo2_percent;
+
+        point.left_balance_percent = left_balance_percent(&record);
+        point.left_torque_effectiveness_percent = field_f64(&record, "left_torque_effectiveness");
+        point.right_torque_effectiveness_percent = field_f64(&record, "right_torque_effectiveness");
+        point.left_pedal_smoothness_percent = field_f64(&record, "left_pedal_smoothness");
+        point.right_pedal_smoothness_percent = field_f64(&record, "right_pedal_smoothness");
+        point.combined_pedal_smoothness_percent = field_f64(&record, "combined_pedal_smoothness");
+
+        point.stance_time_ms = field_f64(&record, "stance_time");
+        point.vertical_oscillation_mm = field_f64(&record, "vertical_oscillation");
+        point.step_length_mm = field_f64(&record, "step_length");
+
+        if options.developer_fields {
+            for field in record.fields() {
+                if !developer_fields.contains(&(field.number(), field.name().to_owned())) {
+                    continue;
+                }
+                if let Ok(value) = TryInto::<f64>::try_into(field.value().clone()) {
+                    point.developer_fields.insert(field.name().to_owned(), value);
+                }
+            }
+        }
+
+        line.push(point);
+    }
+
+    // store final line
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    console::add_records(record_count % PROGRESS_RECORD_CHUNK);
+
+    activity.lines = lines;
+    activity
+}
@@ -0,0 +1,227 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use serde_json::{Map, Value};
+
+use crate::activity::Activity;
+use crate::censor::geojson_position;
+use crate::diagnostics::Diagnostics;
+use crate::error::WalkingError;
+use crate::input::{optional_field, parse_timestamp};
+use crate::point::Point;
+
+
+/// The property names recognized for each value of a point, including those used by other tools
+/// (e.g. the `coordTimes` and `heartRates` of togeojson).
+const TIMESTAMP_NAMES: [&str; 4] = ["timestamp", "time", "times", "coordTimes"];
+const ELEVATION_NAMES: [&str; 2] = ["elevation", "ele"];
+const HEART_RATE_NAMES: [&str; 4] = ["heart_rate", "heart", "heartRates", "hr"];
+const SPEED_NAMES: [&str; 1] = ["speed"];
+const CADENCE_NAMES: [&str; 2] = ["cadence", "cad"];
+const TEMPERATURE_NAMES: [&str; 2] = ["temperature", "atemp"];
+const POWER_NAMES: [&str; 1] = ["power"];
+const RESPIRATION_RATE_NAMES: [&str; 1] = ["respiration_rate"];
+const SPO2_NAMES: [&str; 1] = ["spo2"];
+
+
+/// Finds the value of a point within the properties of a feature.
+///
+/// For a Point feature, the value is a property with one of the given names. For the coordinates of
+/// a LineString, it is the element at `index` of a property that is an array (looked up in the
+/// `coordinateProperties` object first, then in the properties themselves); for those of a
+/// MultiLineString, `part` additionally selects the inner array.
+fn point_value<'a>(properties: &'a Map<String, Value>, names: &[&str], part: Option<usize>, index: Option<usize>) -> Option<(&'a str, &'a Value)> {
+    let coordinate_properties = properties.get("coordinateProperties")
+        .and_then(|c| c.as_object());
+    for name in names {
+        let value = coordinate_properties
+            .and_then(|c| c.get_key_value(*name))
+            .or_else(|| properties.get_key_value(*name));
+        let (key, value) = match value {
+            Some(kv) => kv,
+            None => continue,
+        };
+        let value = match (part, index) {
+            (_, None) => Some(value),
+            (None, Some(i)) => value.as_array().and_then(|a| a.get(i)),
+            (Some(p), Some(i)) => value.as_array()
+                .and_then(|a| a.get(p))
+                .and_then(|a| a.as_array())
+                .and_then(|a| a.get(i)),
+        };
+        if let Some(v) = value.filter(|v| !v.is_null()) {
+            return Some((key.as_str(), v));
+        }
+    }
+    None
+}
+
+fn json_number(value: &Value, what: &str) -> Result<f64, String> {
+    value.as_f64()
+        .ok_or_else(|| format!("{} is not {}", value, what))
+}
+
+fn json_unsigned(value: &Value, what: &str) -> Result<u64, String> {
+    json_number(value, what)
+        .and_then(|n| if n >= 0.0 { Ok(n.round() as u64) } else { Err(format!("{} is not {}", value, what)) })
+}
+
+/// Parses a timestamp as output by other tools (RFC 3339) or by ourselves (local date and time
+/// without an offset).
+fn json_timestamp(value: &Value) -> Result<DateTime<Local>, String> {
+    let text = value.as_str()
+        .ok_or_else(|| format!("{} is not a timestamp", value))?;
+    if let Ok(ts) = parse_timestamp(text) {
+        return Ok(ts);
+    }
+    NaiveDateTime::parse_from_str(text.trim(), "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|t| Local.from_local_datetime(&t).earliest())
+        .ok_or_else(|| format!("failed to parse {:?} as a timestamp", text))
+}
+
+/// Converts a GeoJSON position along with the recognized values of the feature's properties into a
+/// point. A third coordinate is taken as the elevation unless an elevation property exists.
+fn position_to_point(position: &Value, properties: &Map<String, Value>, part: Option<usize>, index: Option<usize>, record_index: usize, diagnostics: &mut Diagnostics) -> Result<Point, WalkingError> {
+    let coord = geojson_position(position)?;
+    let mut point = Point::new(geo::Point::from(coord));
+
+    let value = |names: &[&str]| point_value(properties, names, part, index);
+
+    point.elevation_m = match value(&ELEVATION_NAMES) {
+        Some((key, v)) => optional_field(json_number(v, "an elevation"), record_index, key, diagnostics),
+        None => position.get(2).and_then(|e| e.as_f64()),
+    };
+    if let Some((key, v)) = value(&TIMESTAMP_NAMES) {
+        if let Some(ts) = optional_field(json_timestamp(v), record_index, key, diagnostics) {
+            point.unix_timestamp = Some((ts.timestamp_millis() as f64) / 1000.0);
+            point.timestamp = Some(ts);
+        }
+    }
+    if let Some((key, v)) = value(&HEART_RATE_NAMES) {
+        point.heart_rate_bpm = optional_field(json_unsigned(v, "a heart rate"), record_index, key, diagnostics);
+    }
+    if let Some((key, v)) = value(&SPEED_NAMES) {
+        point.speed_km_per_h = optional_field(json_number(v, "a speed"), record_index, key, diagnostics);
+    }
+    if let Some((key, v)) = value(&CADENCE_NAMES) {
+        point.cadence_rpm = optional_field(json_unsigned(v, "a cadence"), record_index, key, diagnostics);
+    }
+    if let Some((key, v)) = value(&TEMPERATURE_NAMES) {
+        let temp_opt = optional_field(json_number(v, "a temperature"), record_index, key, diagnostics);
+        point.temperature_degc = temp_opt.map(|t| t.round() as i64);
+    }
+    if let Some((key, v)) = value(&POWER_NAMES) {
+        point.power_w = optional_field(json_unsigned(v, "a power"), record_index, key, diagnostics);
+    }
+    if let Some((key, v)) = value(&RESPIRATION_RATE_NAMES) {
+        point.respiration_rate_per_min = optional_field(json_number(v, "a respiration rate"), record_index, key, diagnostics);
+    }
+    if let Some((key, v)) = value(&SPO2_NAMES) {
+        point.spo2_percent = optional_field(json_unsigned(v, "a blood oxygen saturation"), record_index, key, diagnostics);
+    }
+    Ok(point)
+}
+
+/// Converts the positions of a LineString into a line, skipping invalid positions.
+fn positions_to_line(positions: &Value, properties: &Map<String, Value>, part: Option<usize>, record_index: &mut usize, diagnostics: &mut Diagnostics) -> Result<Vec<Point>, WalkingError> {
+    let positions = positions.as_array()
+        .ok_or_else(|| WalkingError::InvalidGeoJson("LineString coordinates are not an array".to_owned()))?;
+    let mut line = Vec::with_capacity(positions.len());
+    for (i, position) in positions.iter().enumerate() {
+        match position_to_point(position, properties, part, Some(i), *record_index, diagnostics) {
+            Ok(point) => line.push(point),
+            Err(e) => diagnostics.skip_record(*record_index, e.to_string()),
+        }
+        *record_index += 1;
+    }
+    Ok(line)
+}
+
+/// Collects the features of a GeoJSON object (a FeatureCollection, a Feature or a bare geometry) as
+/// pairs of geometry and properties.
+fn collect_features<'a>(object: &'a Value, empty_properties: &'a Map<String, Value>, features: &mut Vec<(&'a Value, &'a Map<String, Value>)>) -> Result<(), WalkingError> {
+    match object.get("type").and_then(|t| t.as_str()) {
+        Some("FeatureCollection") => {
+            let members = object.get("features")
+                .and_then(|f| f.as_array())
+                .ok_or_else(|| WalkingError::InvalidGeoJson("FeatureCollection without features array".to_owned()))?;
+            for member in members {
+                collect_features(member, empty_properties, features)?;
+            }
+        },
+        Some("Feature") => {
+            let properties = object.get("properties")
+                .and_then(|p| p.as_object())
+                .unwrap_or(empty_properties);
+            if let Some(geometry) = object.get("geometry").filter(|g| !g.is_null()) {
+                features.push((geometry, properties));
+            }
+        },
+        Some(_) => features.push((object, empty_properties)),
+        None => return Err(WalkingError::InvalidGeoJson("object without type".to_owned())),
+    }
+    Ok(())
+}
+
+
+/// Reads a GeoJSON document (a FeatureCollection, a single Feature or a bare geometry) into an
+/// activity.
+///
+/// Each LineString and each part of a MultiLineString becomes its own line; runs of consecutive
+/// Point features (such as our own output with `--points-as point`) are joined into one line.
+/// Timestamps, heart rates and other values are taken from the properties of Point features, or
+/// from per-coordinate arrays in the properties of line features (e.g. `coordTimes` or
+/// `coordinateProperties.times`). Other geometries are ignored.
+pub fn read_geojson(text: &str) -> Result<Activity, WalkingError> {
+    let document: Value = serde_json::from_str(text)?;
+    let empty_properties = Map::new();
+    let mut features = Vec::new();
+    collect_features(&document, &empty_properties, &mut features)?;
+
+    let mut activity = Activity::default();
+    activity.metadata.sport = features.iter()
+        .find_map(|(_, properties)| properties.get("sport").and_then(|s| s.as_str()))
+        .map(|s| s.trim().to_lowercase());
+
+    let mut record_index = 0;
+    let mut point_line = Vec::new();
+    for (geometry, properties) in features {
+        let coordinates = geometry.get("coordinates").unwrap_or(&Value::Null);
+        match geometry.get("type").and_then(|t| t.as_str()) {
+            Some("Point") => {
+                match position_to_point(coordinates, properties, None, None, record_index, &mut activity.diagnostics) {
+                    Ok(point) => point_line.push(point),
+                    Err(e) => activity.diagnostics.skip_record(record_index, e.to_string()),
+                }
+                record_index += 1;
+                continue;
+            },
+            Some(_) if !point_line.is_empty() => {
+                // any other feature ends the current run of points
+                activity.lines.push(std::mem::take(&mut point_line));
+            },
+            _ => {},
+        }
+
+        match geometry.get("type").and_then(|t| t.as_str()) {
+            Some("LineString") => {
+                let line = positions_to_line(coordinates, properties, None, &mut record_index, &mut activity.diagnostics)?;
+                activity.lines.push(line);
+            },
+            Some("MultiLineString") => {
+                let parts = coordinates.as_array()
+                    .ok_or_else(|| WalkingError::InvalidGeoJson("MultiLineString coordinates are not an array".to_owned()))?;
+                for (p, part) in parts.iter().enumerate() {
+                    let line = positions_to_line(part, properties, Some(p), &mut record_index, &mut activity.diagnostics)?;
+                    activity.lines.push(line);
+                }
+            },
+            Some(other) => log::debug!("ignoring {} geometry", other),
+            None => return Err(WalkingError::InvalidGeoJson("geometry without type".to_owned())),
+        }
+    }
+    if !point_line.is_empty() {
+        activity.lines.push(point_line);
+    }
+    activity.lines.retain(|l| !l.is_empty());
+    Ok(activity)
+}
@@ -0,0 +1,89 @@
+use crate::activity::Activity;
+use crate::diagnostics::Diagnostics;
+use crate::error::WalkingError;
+use crate::input::{check_root, optional_field, parse_number, parse_timestamp, xml};
+use crate::point::Point;
+
+
+/// Converts a GPX trackpoint into a point. Values that cannot be parsed are ignored with a warning;
+/// an error is only returned if the position is missing or invalid.
+fn trackpoint_to_point(trkpt: &xml::Element, record_index: usize, diagnostics: &mut Diagnostics) -> Result<Point, String> {
+    let lat: f64 = match trkpt.attribute("lat") {
+        Some(l) => parse_number(l, "a latitude")?,
+        None => return Err("trackpoint without latitude".to_owned()),
+    };
+    let lon: f64 = match trkpt.attribute("lon") {
+        Some(l) => parse_number(l, "a longitude")?,
+        None => return Err("trackpoint without longitude".to_owned()),
+    };
+    let mut point = Point::new(geo::Point::from((lon, lat)));
+
+    if let Some(ele) = trkpt.child_text("ele") {
+        point.elevation_m = optional_field(parse_number(&ele, "an elevation"), record_index, "ele", diagnostics);
+    }
+
+    if let Some(time) = trkpt.child_text("time") {
+        if let Some(ts) = optional_field(parse_timestamp(&time), record_index, "time", diagnostics) {
+            point.unix_timestamp = Some((ts.timestamp_millis() as f64) / 1000.0);
+            point.timestamp = Some(ts);
+        }
+    }
+
+    // Garmin TrackPointExtension (v1 or v2)
+    if let Some(extensions) = trkpt.child("extensions") {
+        if let Some(hr) = extensions.descendant("hr") {
+            point.heart_rate_bpm = optional_field(parse_number(&hr.text(), "a heart rate"), record_index, "hr", diagnostics);
+        }
+        if let Some(cad) = extensions.descendant("cad") {
+            point.cadence_rpm = optional_field(parse_number(&cad.text(), "a cadence"), record_index, "cad", diagnostics);
+        }
+        if let Some(atemp) = extensions.descendant("atemp") {
+            let temp_opt: Option<f64> = optional_field(parse_number(&atemp.text(), "a temperature"), record_index, "atemp", diagnostics);
+            point.temperature_degc = temp_opt.map(|t| t.round() as i64);
+        }
+        if let Some(speed) = extensions.descendant("speed") {
+            let speed_opt: Option<f64> = optional_field(parse_number(&speed.text(), "a speed"), record_index, "speed", diagnostics);
+            point.speed_km_per_h = speed_opt.map(|s| s * 3.6);
+        }
+        // "power" (e.g. Strava) or "PowerInWatts" (Garmin PowerExtension)
+        if let Some(power) = extensions.descendant("power").or_else(|| extensions.descendant("PowerInWatts")) {
+            point.power_w = optional_field(parse_number(&power.text(), "a power"), record_index, &power.name, diagnostics);
+        }
+    }
+
+    Ok(point)
+}
+
+
+/// Reads the tracks of a GPX document into an activity. Each track segment becomes its own line;
+/// trackpoints without a valid position are skipped.
+pub fn read_gpx(text: &str) -> Result<Activity, WalkingError> {
+    let root = xml::parse(text)?;
+    check_root(&root, "gpx")?;
+
+    let mut activity = Activity::default();
+    // the type of the track is free text, but commonly the name of the sport
+    activity.metadata.sport = root.children_named("trk")
+        .find_map(|trk| trk.child_text("type"))
+        .map(|t| t.trim().to_lowercase());
+    activity.metadata.product = root.attribute("creator")
+        .map(|c| c.to_owned());
+
+    let mut record_index = 0;
+    for trk in root.children_named("trk") {
+        for trkseg in trk.children_named("trkseg") {
+            let mut line = Vec::new();
+            for trkpt in trkseg.children_named("trkpt") {
+                match trackpoint_to_point(trkpt, record_index, &mut activity.diagnostics) {
+                    Ok(point) => line.push(point),
+                    Err(message) => activity.diagnostics.skip_record(record_index, message),
+                }
+                record_index += 1;
+            }
+            if !line.is_empty() {
+                activity.lines.push(line);
+            }
+        }
+    }
+    Ok(activity)
+}
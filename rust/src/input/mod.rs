@@ -0,0 +1,282 @@
+pub mod fit;
+pub mod geojson;
+pub mod gpx;
+pub mod tcx;
+pub mod xml;
+
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local};
+
+use crate::activity::Activity;
+use crate::diagnostics::Diagnostics;
+use crate::error::WalkingError;
+use crate::gzip::{gunzip, GZIP_MAGIC};
+use crate::zip::{read_zip_entries, ZIP_MAGIC};
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum InputFormat {
+    Fit,
+    Geojson,
+    Gpx,
+    Tcx,
+}
+impl InputFormat {
+    /// Determines the input format from the extension of the given path. Files with unknown
+    /// extensions are assumed to be FIT files.
+    pub fn from_path(path: &Path) -> Self {
+        Self::from_extension(path).unwrap_or(Self::Fit)
+    }
+
+    /// Determines the input format from the file contents. Returns `None` if the contents are not
+    /// recognized.
+    pub fn from_content(data: &[u8]) -> Option<Self> {
+        if data.get(8..12) == Some(b".FIT") {
+            return Some(Self::Fit);
+        }
+
+        // look at the start of text documents only
+        let head = String::from_utf8_lossy(&data[..data.len().min(1024)]);
+        if head.contains("<gpx") {
+            Some(Self::Gpx)
+        } else if head.contains("<TrainingCenterDatabase") {
+            Some(Self::Tcx)
+        } else if head.trim_start().starts_with('{') && head.contains("\"type\"") {
+            Some(Self::Geojson)
+        } else {
+            None
+        }
+    }
+
+    /// Determines the input format from the extension of the given path or, if the extension is
+    /// unknown, from the file contents. Falls back to FIT.
+    pub fn detect(path: &Path, data: &[u8]) -> Self {
+        Self::from_extension(path)
+            .or_else(|| Self::from_content(data))
+            .unwrap_or(Self::Fit)
+    }
+
+    /// Determines the input format from the extension of the given path. Returns `None` for
+    /// unknown extensions.
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match extension.as_deref() {
+            Some("fit") => Some(Self::Fit),
+            Some("geojson") | Some("json") => Some(Self::Geojson),
+            Some("gpx") => Some(Self::Gpx),
+            Some("tcx") => Some(Self::Tcx),
+            _ => None,
+        }
+    }
+}
+
+
+/// Options influencing how input files are read.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ReadOptions {
+    /// Read the numeric developer fields of FIT Record messages into the points.
+    pub developer_fields: bool,
+}
+
+
+/// The file name that stands for standard input.
+pub const STDIN_FILENAME: &str = "-";
+
+
+/// Returns whether the file name refers to standard input instead of a file.
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN_FILENAME
+}
+
+
+/// Returns the FIT files in the given directory, sorted by name, not descending into
+/// subdirectories.
+pub fn list_fit_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut fit_paths = Vec::new();
+    for entry_res in fs::read_dir(dir)? {
+        let entry_path = entry_res?.path();
+        let is_fit = entry_path.extension()
+            .map(|e| e.eq_ignore_ascii_case("fit"))
+            .unwrap_or(false);
+        if is_fit && entry_path.is_file() {
+            fit_paths.push(entry_path);
+        }
+    }
+    fit_paths.sort_unstable();
+    Ok(fit_paths)
+}
+
+
+/// Replaces each directory in the list of input paths by the FIT files it contains (see
+/// [`list_fit_files`]). Other paths are kept as they are.
+pub fn expand_input_paths(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut expanded = Vec::with_capacity(paths.len());
+    for path in paths {
+        if is_stdin(path) || !path.is_dir() {
+            expanded.push(path.clone());
+            continue;
+        }
+
+        let fit_paths = list_fit_files(path)?;
+        if fit_paths.is_empty() {
+            log::warn!("{}: directory contains no FIT files", path.display());
+        }
+        expanded.extend(fit_paths);
+    }
+    Ok(expanded)
+}
+
+
+/// Reads the file at the given path (or standard input if the path is `-`) into an activity,
+/// choosing the parser according to the file extension or, failing that, the file contents.
+///
+/// Gzipped files (e.g. `.fit.gz`) are decompressed transparently; the format is then chosen
+/// according to the extension preceding `.gz`. ZIP archives are searched for entries with known
+/// extensions. If the file contains multiple activities (chained FIT files or ZIP archives), they
+/// are merged.
+pub fn read_activity(path: &Path, options: &ReadOptions) -> Result<Activity, WalkingError> {
+    read_activities(path, options)
+        .map(Activity::merge)
+}
+
+
+/// Reads the file at the given path like [`read_activity`], but returns each activity of a chained
+/// FIT file separately. The activities within a ZIP archive are still merged into one.
+pub fn read_activities(path: &Path, options: &ReadOptions) -> Result<Vec<Activity>, WalkingError> {
+    let data = read_input_data(path)?;
+
+    if data.starts_with(&GZIP_MAGIC) {
+        let decompressed = gunzip(&data)
+            .map_err(WalkingError::Decompression)?;
+        let inner_path = match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("gz") => path.with_extension(""),
+            _ => path.to_owned(),
+        };
+        let format = InputFormat::detect(&inner_path, &decompressed);
+        return read_activity_data(&decompressed, format, options);
+    }
+
+    if data.starts_with(&ZIP_MAGIC) {
+        let mut activities = Vec::new();
+        for entry in read_zip_entries(&data).map_err(WalkingError::Decompression)? {
+            if entry.is_directory() {
+                continue;
+            }
+            let format = match InputFormat::from_extension(Path::new(&entry.name)) {
+                Some(f) => f,
+                None => continue,
+            };
+            let entry_data = entry.extract(&data)
+                .map_err(WalkingError::Decompression)?;
+            activities.extend(read_activity_data(&entry_data, format, options)?);
+        }
+        if activities.is_empty() {
+            return Err(WalkingError::Decompression("ZIP archive contains no activity files".to_owned()));
+        }
+        return Ok(vec![Activity::merge(activities)]);
+    }
+
+    read_activity_data(&data, InputFormat::detect(path, &data), options)
+}
+
+
+/// Reads the whole file at the given path, or standard input if the path is `-`.
+fn read_input_data(path: &Path) -> Result<Vec<u8>, WalkingError> {
+    if is_stdin(path) {
+        let mut buf = Vec::new();
+        std::io::stdin().lock().read_to_end(&mut buf)?;
+        Ok(buf)
+    } else {
+        Ok(std::fs::read(path)?)
+    }
+}
+
+
+/// Reads the contents of the FIT file at the given path (or standard input if the path is `-`),
+/// decompressing it first if it is gzipped.
+pub fn read_fit_data(path: &Path) -> Result<Vec<u8>, WalkingError> {
+    let data = read_input_data(path)?;
+    if data.starts_with(&GZIP_MAGIC) {
+        gunzip(&data)
+            .map_err(WalkingError::Decompression)
+    } else {
+        Ok(data)
+    }
+}
+
+
+/// Reads the raw messages of the FIT file at the given path (or standard input if the path is
+/// `-`), decompressing it first if it is gzipped.
+pub fn read_fit_records(path: &Path) -> Result<Vec<fitparser::FitDataRecord>, WalkingError> {
+    let data = read_fit_data(path)?;
+    Ok(fitparser::from_bytes(&data)?)
+}
+
+
+/// Reads the activities in the given format from the (uncompressed) file contents. Only chained FIT
+/// files contain more than one activity.
+fn read_activity_data(data: &[u8], format: InputFormat, options: &ReadOptions) -> Result<Vec<Activity>, WalkingError> {
+    if format == InputFormat::Fit {
+        let mut reader = data;
+        return fit::read_fit(&mut reader, options);
+    }
+
+    // the rest are text-based
+    let text = std::str::from_utf8(data)
+        .map_err(|e| WalkingError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+    match format {
+        InputFormat::Fit => unreachable!(),
+        InputFormat::Geojson => geojson::read_geojson(text).map(|a| vec![a]),
+        InputFormat::Gpx => gpx::read_gpx(text).map(|a| vec![a]),
+        InputFormat::Tcx => tcx::read_tcx(text).map(|a| vec![a]),
+    }
+}
+
+
+/// Parses a number, rejecting `NaN` and infinities (which Rust's floating-point parsing accepts).
+fn parse_number<T: std::str::FromStr>(text: &str, what: &str) -> Result<T, String>
+    where
+        T::Err: std::fmt::Display,
+{
+    if text.trim().parse::<f64>().is_ok_and(|v| !v.is_finite()) {
+        return Err(format!("failed to parse {:?} as {}: not a finite number", text, what));
+    }
+    text.trim().parse()
+        .map_err(|e| format!("failed to parse {:?} as {}: {}", text, what, e))
+}
+
+fn parse_timestamp(text: &str) -> Result<DateTime<Local>, String> {
+    DateTime::parse_from_rfc3339(text.trim())
+        .map(|t| t.with_timezone(&Local))
+        .map_err(|e| format!("failed to parse {:?} as a timestamp: {}", text, e))
+}
+
+/// Returns the parsed value of an optional field of a record. If the value could not be parsed, a
+/// warning is added to the diagnostics and `None` is returned.
+fn optional_field<T>(result: Result<T, String>, record_index: usize, field: &str, diagnostics: &mut Diagnostics) -> Option<T> {
+    match result {
+        Ok(value) => Some(value),
+        Err(message) => {
+            diagnostics.ignore_field(record_index, field, message);
+            None
+        },
+    }
+}
+
+/// Verifies that the root element of an XML document has the expected name.
+fn check_root(root: &xml::Element, expected_name: &str) -> Result<(), WalkingError> {
+    if root.name == expected_name {
+        Ok(())
+    } else {
+        Err(WalkingError::Xml(xml::XmlError {
+            position: 0,
+            message: format!("root element is {:?}, not {:?}", root.name, expected_name),
+        }))
+    }
+}
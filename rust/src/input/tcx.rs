@@ -0,0 +1,201 @@
+use crate::activity::{Activity, Metadata, Summary};
+use crate::diagnostics::{Diagnostics, DropReason};
+use crate::error::WalkingError;
+use crate::indoor::Sample;
+use crate::input::{check_root, optional_field, parse_number, parse_timestamp, xml};
+use crate::point::Point;
+
+
+/// A TCX trackpoint, which only becomes a point of the track if it has a position.
+enum Trackpoint {
+    Positioned(Box<Point>),
+    Unpositioned(Sample),
+}
+
+
+/// Converts a TCX trackpoint into a point or, if it has no position, into a sample. Values that
+/// cannot be parsed are ignored with a warning; an error is only returned if the position is
+/// invalid.
+fn trackpoint_to_point(trackpoint: &xml::Element, record_index: usize, diagnostics: &mut Diagnostics) -> Result<Trackpoint, String> {
+    let coordinates_opt = match trackpoint.child("Position") {
+        Some(position) => {
+            let lat: f64 = match position.child_text("LatitudeDegrees") {
+                Some(l) => parse_number(&l, "a latitude")?,
+                None => return Err("position without latitude".to_owned()),
+            };
+            let lon: f64 = match position.child_text("LongitudeDegrees") {
+                Some(l) => parse_number(&l, "a longitude")?,
+                None => return Err("position without longitude".to_owned()),
+            };
+            Some(geo::Point::from((lon, lat)))
+        },
+        None => None,
+    };
+    let mut point = Point::new(coordinates_opt.unwrap_or_else(|| geo::Point::new(0.0, 0.0)));
+
+    if let Some(time) = trackpoint.child_text("Time") {
+        if let Some(ts) = optional_field(parse_timestamp(&time), record_index, "Time", diagnostics) {
+            point.unix_timestamp = Some((ts.timestamp_millis() as f64) / 1000.0);
+            point.timestamp = Some(ts);
+        }
+    }
+
+    if let Some(alt) = trackpoint.child_text("AltitudeMeters") {
+        point.elevation_m = optional_field(parse_number(&alt, "an altitude"), record_index, "AltitudeMeters", diagnostics);
+    }
+
+    if let Some(dist) = trackpoint.child_text("DistanceMeters") {
+        point.device_distance_m = optional_field(parse_number(&dist, "a distance"), record_index, "DistanceMeters", diagnostics);
+    }
+
+    if let Some(hr) = trackpoint.child("HeartRateBpm").and_then(|h| h.child_text("Value")) {
+        point.heart_rate_bpm = optional_field(parse_number(&hr, "a heart rate"), record_index, "HeartRateBpm", diagnostics);
+    }
+
+    if let Some(cad) = trackpoint.child_text("Cadence") {
+        point.cadence_rpm = optional_field(parse_number(&cad, "a cadence"), record_index, "Cadence", diagnostics);
+    }
+
+    // ActivityExtension (TPX)
+    if let Some(extensions) = trackpoint.child("Extensions") {
+        if let Some(speed) = extensions.descendant("Speed") {
+            let speed_opt: Option<f64> = optional_field(parse_number(&speed.text(), "a speed"), record_index, "Speed", diagnostics);
+            point.speed_km_per_h = speed_opt.map(|s| s * 3.6);
+        }
+        if let Some(watts) = extensions.descendant("Watts") {
+            point.power_w = optional_field(parse_number(&watts.text(), "a power"), record_index, "Watts", diagnostics);
+        }
+        if point.cadence_rpm.is_none() {
+            if let Some(run_cad) = extensions.descendant("RunCadence") {
+                point.cadence_rpm = optional_field(parse_number(&run_cad.text(), "a cadence"), record_index, "RunCadence", diagnostics);
+            }
+        }
+    }
+
+    Ok(match coordinates_opt {
+        Some(_) => Trackpoint::Positioned(Box::new(point)),
+        None => Trackpoint::Unpositioned(Sample::from(&point)),
+    })
+}
+
+
+/// Reads the values of a TCX lap, except for the end time, which is not stored explicitly.
+fn lap_to_summary(lap: &xml::Element) -> Result<Summary, String> {
+    let mut summary = Summary::default();
+    if let Some(start_time) = lap.attribute("StartTime") {
+        summary.start_time = Some(parse_timestamp(start_time)?);
+    }
+    if let Some(total_time) = lap.child_text("TotalTimeSeconds") {
+        summary.total_timer_time_s = Some(parse_number(&total_time, "a duration")?);
+    }
+    if let Some(distance) = lap.child_text("DistanceMeters") {
+        summary.total_distance_m = Some(parse_number(&distance, "a distance")?);
+    }
+    if let Some(calories) = lap.child_text("Calories") {
+        summary.total_calories_kcal = Some(parse_number(&calories, "a calorie count")?);
+    }
+    if let Some(avg_hr) = lap.child("AverageHeartRateBpm").and_then(|h| h.child_text("Value")) {
+        summary.avg_heart_rate_bpm = Some(parse_number(&avg_hr, "a heart rate")?);
+    }
+    if let Some(max_hr) = lap.child("MaximumHeartRateBpm").and_then(|h| h.child_text("Value")) {
+        summary.max_heart_rate_bpm = Some(parse_number(&max_hr, "a heart rate")?);
+    }
+    Ok(summary)
+}
+
+
+/// Reads the sport and the device information of a TCX activity. Values that cannot be parsed are
+/// ignored.
+fn activity_to_metadata(activity: &xml::Element) -> Metadata {
+    let mut metadata = Metadata {
+        sport: activity.attribute("Sport").map(|s| s.to_lowercase()),
+        ..Metadata::default()
+    };
+    if let Some(creator) = activity.child("Creator") {
+        metadata.product = creator.child_text("Name");
+        metadata.serial_number = creator.child_text("UnitId")
+            .and_then(|u| u.trim().parse().ok());
+        if let Some(version) = creator.child("Version") {
+            let major = version.child_text("VersionMajor");
+            let minor = version.child_text("VersionMinor");
+            if let (Some(major), Some(minor)) = (major, minor) {
+                metadata.software_version = Some(format!("{}.{}", major.trim(), minor.trim()));
+            }
+        }
+    }
+    metadata
+}
+
+
+/// Reads the activities of a TCX document. Each track becomes its own line; trackpoints without a
+/// position also break the line.
+pub fn read_tcx(text: &str) -> Result<Activity, WalkingError> {
+    let root = xml::parse(text)?;
+    check_root(&root, "TrainingCenterDatabase")?;
+
+    let mut lines = Vec::new();
+    let mut samples = Vec::new();
+    let mut laps = Vec::new();
+    let mut diagnostics = Diagnostics::default();
+    let mut metadata_opt = None;
+    let mut record_index = 0;
+    let activities = root.children_named("Activities")
+        .flat_map(|a| a.children_named("Activity"));
+    for activity in activities {
+        if metadata_opt.is_none() {
+            metadata_opt = Some(activity_to_metadata(activity));
+        }
+
+        for lap in activity.children_named("Lap") {
+            let mut summary = lap_to_summary(lap)
+                .map_err(|message| WalkingError::InvalidRecord { record_index, message })?;
+
+            for track in lap.children_named("Track") {
+                let mut line = Vec::new();
+                for trackpoint in track.children_named("Trackpoint") {
+                    let converted = match trackpoint_to_point(trackpoint, record_index, &mut diagnostics) {
+                        Ok(c) => c,
+                        Err(message) => {
+                            diagnostics.skip_record(record_index, message);
+                            record_index += 1;
+                            continue;
+                        },
+                    };
+                    record_index += 1;
+                    match converted {
+                        Trackpoint::Positioned(point) => {
+                            if let Some(ts) = point.timestamp {
+                                summary.end_time = Some(ts);
+                            }
+                            line.push(*point);
+                        },
+                        Trackpoint::Unpositioned(sample) => {
+                            // position recording paused; keep the measurements as a sample and
+                            // store the current line
+                            diagnostics.drop_points(DropReason::NoPosition, 1);
+                            samples.push(sample);
+                            if !line.is_empty() {
+                                lines.push(line);
+                                line = Vec::new();
+                            }
+                        },
+                    }
+                }
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+            }
+
+            laps.push(summary);
+        }
+    }
+    Ok(Activity {
+        lines,
+        samples,
+        laps,
+        sessions: Vec::new(),
+        pauses: Vec::new(),
+        metadata: metadata_opt.unwrap_or_default(),
+        diagnostics,
+    })
+}
@@ -0,0 +1,308 @@
+//! A minimal XML parser, just capable enough to read GPX and similar files.
+//!
+//! Namespace prefixes are stripped from element and attribute names; DTDs, comments and processing
+//! instructions are skipped.
+
+
+use std::fmt;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+impl Element {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .filter(|(n, _v)| n == name)
+            .map(|(_n, v)| v.as_str())
+            .next()
+    }
+
+    pub fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter()
+            .filter_map(|c| match c {
+                Node::Element(e) => Some(e),
+                Node::Text(_) => None,
+            })
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.child_elements()
+            .filter(move |e| e.name == name)
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.child_elements()
+            .find(|e| e.name == name)
+    }
+
+    /// Returns the first element with the given name among all the descendants of this element.
+    pub fn descendant(&self, name: &str) -> Option<&Element> {
+        for child in self.child_elements() {
+            if child.name == name {
+                return Some(child);
+            }
+            if let Some(desc) = child.descendant(name) {
+                return Some(desc);
+            }
+        }
+        None
+    }
+
+    /// Returns the concatenated text directly contained within this element, trimmed of
+    /// surrounding whitespace.
+    pub fn text(&self) -> String {
+        let mut ret = String::new();
+        for child in &self.children {
+            if let Node::Text(t) = child {
+                ret.push_str(t);
+            }
+        }
+        ret.trim().to_owned()
+    }
+
+    /// Returns the text of the first child element with the given name.
+    pub fn child_text(&self, name: &str) -> Option<String> {
+        self.child(name)
+            .map(|c| c.text())
+    }
+}
+
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct XmlError {
+    pub position: usize,
+    pub message: String,
+}
+impl XmlError {
+    fn new<M: Into<String>>(position: usize, message: M) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.position, self.message)
+    }
+}
+impl std::error::Error for XmlError {
+}
+
+
+fn local_name(qualified_name: &str) -> &str {
+    match qualified_name.find(':') {
+        Some(colon_index) => &qualified_name[colon_index+1..],
+        None => qualified_name,
+    }
+}
+
+fn decode_entities(text: &str, position: usize) -> Result<String, XmlError> {
+    let mut ret = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp_index) = rest.find('&') {
+        ret.push_str(&rest[..amp_index]);
+        rest = &rest[amp_index..];
+        let semicolon_index = rest.find(';')
+            .ok_or_else(|| XmlError::new(position, "unterminated entity reference"))?;
+        let entity = &rest[1..semicolon_index];
+        match entity {
+            "amp" => ret.push('&'),
+            "lt" => ret.push('<'),
+            "gt" => ret.push('>'),
+            "quot" => ret.push('"'),
+            "apos" => ret.push('\''),
+            _ => {
+                let code_point = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = entity.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                let c = code_point
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| XmlError::new(position, format!("unknown entity {:?}", entity)))?;
+                ret.push(c);
+            },
+        }
+        rest = &rest[semicolon_index+1..];
+    }
+    ret.push_str(rest);
+    Ok(ret)
+}
+
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_past(&mut self, terminator: &str) -> Result<&'a str, XmlError> {
+        match self.rest().find(terminator) {
+            Some(index) => {
+                let skipped = &self.rest()[..index];
+                self.pos += index + terminator.len();
+                Ok(skipped)
+            },
+            None => Err(XmlError::new(self.pos, format!("missing {:?}", terminator))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn read_name(&mut self) -> Result<&'a str, XmlError> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '/' || c == '>' || c == '=')
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(XmlError::new(self.pos, "expected a name"));
+        }
+        self.pos += end;
+        Ok(&rest[..end])
+    }
+
+    fn skip_doctype(&mut self) -> Result<(), XmlError> {
+        // may contain an internal subset in square brackets
+        let mut bracket_depth = 0usize;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '[' => bracket_depth += 1,
+                ']' => bracket_depth = bracket_depth.saturating_sub(1),
+                '>' if bracket_depth == 0 => {
+                    self.pos += i + 1;
+                    return Ok(());
+                },
+                _ => {},
+            }
+        }
+        Err(XmlError::new(self.pos, "unterminated DOCTYPE"))
+    }
+
+    /// Reads a start tag, assuming the opening `<` has already been consumed. Returns the
+    /// qualified name, the element, and whether the tag was self-closing.
+    fn read_start_tag(&mut self) -> Result<(&'a str, Element, bool), XmlError> {
+        let qualified_name = self.read_name()?;
+        let mut element = Element {
+            name: local_name(qualified_name).to_owned(),
+            ..Element::default()
+        };
+        loop {
+            self.skip_whitespace();
+            if let Some(rest) = self.rest().strip_prefix("/>") {
+                self.pos = self.text.len() - rest.len();
+                return Ok((qualified_name, element, true));
+            }
+            if let Some(rest) = self.rest().strip_prefix('>') {
+                self.pos = self.text.len() - rest.len();
+                return Ok((qualified_name, element, false));
+            }
+
+            let attribute_name = self.read_name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=') {
+                return Err(XmlError::new(self.pos, "expected '=' after attribute name"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(q) if q == '"' || q == '\'' => q,
+                _ => return Err(XmlError::new(self.pos, "expected quoted attribute value")),
+            };
+            self.pos += 1;
+            let value_pos = self.pos;
+            let raw_value = self.skip_past(if quote == '"' { "\"" } else { "'" })?;
+            let value = decode_entities(raw_value, value_pos)?;
+            element.attributes.push((local_name(attribute_name).to_owned(), value));
+        }
+    }
+}
+
+
+fn push_text(element: &mut Element, text: String) {
+    if let Some(Node::Text(previous)) = element.children.last_mut() {
+        previous.push_str(&text);
+    } else {
+        element.children.push(Node::Text(text));
+    }
+}
+
+
+/// Parses an XML document, returning its root element.
+pub fn parse(text: &str) -> Result<Element, XmlError> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut stack: Vec<(&str, Element)> = Vec::new();
+
+    while parser.pos < text.len() {
+        let rest = parser.rest();
+        if rest.starts_with("<?") {
+            parser.skip_past("?>")?;
+        } else if rest.starts_with("<!--") {
+            parser.skip_past("-->")?;
+        } else if rest.starts_with("<![CDATA[") {
+            parser.pos += "<![CDATA[".len();
+            let cdata = parser.skip_past("]]>")?;
+            if let Some((_, top)) = stack.last_mut() {
+                push_text(top, cdata.to_owned());
+            }
+        } else if rest.starts_with("<!") {
+            parser.skip_doctype()?;
+        } else if rest.starts_with("</") {
+            let tag_pos = parser.pos;
+            parser.pos += 2;
+            let closing_name = parser.skip_past(">")?.trim();
+            let (opening_name, element) = stack.pop()
+                .ok_or_else(|| XmlError::new(tag_pos, format!("unexpected closing tag {:?}", closing_name)))?;
+            if opening_name != closing_name {
+                return Err(XmlError::new(
+                    tag_pos,
+                    format!("closing tag {:?} does not match opening tag {:?}", closing_name, opening_name),
+                ));
+            }
+            match stack.last_mut() {
+                Some((_, parent)) => parent.children.push(Node::Element(element)),
+                None => return Ok(element),
+            }
+        } else if rest.starts_with('<') {
+            parser.pos += 1;
+            let (qualified_name, element, self_closing) = parser.read_start_tag()?;
+            if self_closing {
+                match stack.last_mut() {
+                    Some((_, parent)) => parent.children.push(Node::Element(element)),
+                    None => return Ok(element),
+                }
+            } else {
+                stack.push((qualified_name, element));
+            }
+        } else {
+            let text_pos = parser.pos;
+            let end = rest.find('<').unwrap_or(rest.len());
+            parser.pos += end;
+            if let Some((_, top)) = stack.last_mut() {
+                let decoded = decode_entities(&rest[..end], text_pos)?;
+                push_text(top, decoded);
+            }
+        }
+    }
+
+    Err(XmlError::new(text.len(), "unexpected end of document"))
+}
@@ -0,0 +1,170 @@
+//! Export of activities as KML documents (and zipped KMZ files) for Google Earth and similar
+//! applications.
+
+
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use clap::ValueEnum;
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::point::{GeoPoint, Point};
+use crate::timezone::Timezone;
+use crate::units::Units;
+use crate::zip::write_stored_zip;
+
+
+/// The name of the KML document within a KMZ archive.
+const KMZ_DOCUMENT_NAME: &str = "doc.kml";
+
+
+/// How the elevations of the points are interpreted by the viewer.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum AltitudeMode {
+    /// The elevations are ignored and the track is draped over the terrain.
+    #[default]
+    ClampToGround,
+
+    /// The elevations are taken as heights above the terrain.
+    RelativeToGround,
+
+    /// The elevations are taken as heights above sea level.
+    Absolute,
+}
+impl AltitudeMode {
+    /// The value of the KML `altitudeMode` element.
+    pub fn as_kml(&self) -> &'static str {
+        match self {
+            Self::ClampToGround => "clampToGround",
+            Self::RelativeToGround => "relativeToGround",
+            Self::Absolute => "absolute",
+        }
+    }
+}
+
+
+/// Options influencing the generated KML document.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct KmlOptions {
+    pub altitude_mode: AltitudeMode,
+    pub units: Units,
+    pub timezone: Timezone,
+}
+
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => ret.push_str("&amp;"),
+            '<' => ret.push_str("&lt;"),
+            '>' => ret.push_str("&gt;"),
+            '"' => ret.push_str("&quot;"),
+            '\'' => ret.push_str("&apos;"),
+            other => ret.push(other),
+        }
+    }
+    ret
+}
+
+fn kml_coordinates(point: &Point) -> String {
+    match point.elevation_m {
+        Some(ele) => format!("{},{},{}", point.longitude(), point.latitude(), ele),
+        None => format!("{},{}", point.longitude(), point.latitude()),
+    }
+}
+
+/// Assembles the text of the description balloon of a point.
+fn point_description(point: &Point, options: &KmlOptions) -> String {
+    let units = options.units;
+    let mut lines = Vec::new();
+    if let Some(timestamp) = &point.timestamp {
+        lines.push(format!("Time: {}", options.timezone.convert(timestamp).format("%Y-%m-%d %H:%M:%S")));
+    }
+    let unit_names: BTreeMap<&str, &str> = units.names().into_iter().collect();
+    if let Some(ele) = point.elevation_m {
+        lines.push(format!("Elevation: {:.1} {}", units.elevation(ele), unit_names["elevation"]));
+    }
+    if let Some(hr) = point.heart_rate_bpm {
+        lines.push(format!("Heart rate: {} bpm", hr));
+    }
+    if let Some(speed) = point.speed_km_per_h {
+        lines.push(format!("Speed: {:.1} {}", units.speed(speed), unit_names["speed"]));
+    }
+    if let Some(temp) = point.temperature_degc {
+        lines.push(format!("Temperature: {:.0} {}", units.temperature(temp as f64), unit_names["temperature"]));
+    }
+    lines.join("<br/>")
+}
+
+
+/// Writes the activity as a KML document. The track is output as a placemark with one LineString
+/// per line of at least two points; each point is additionally output as a placemark whose description balloon shows the
+/// measurements at that point.
+pub fn write_kml<W: Write>(mut writer: W, activity: &Activity, name: &str, options: &KmlOptions) -> Result<(), WalkingError> {
+    let altitude_mode = options.altitude_mode.as_kml();
+    let tessellate = if options.altitude_mode == AltitudeMode::ClampToGround { 1 } else { 0 };
+
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<kml xmlns="http://www.opengis.net/kml/2.2">"#)?;
+    writeln!(writer, "<Document>")?;
+    writeln!(writer, "<name>{}</name>", escape_xml(name))?;
+    writeln!(writer, r#"<Style id="track"><LineStyle><color>ff0000ff</color><width>3</width></LineStyle></Style>"#)?;
+    writeln!(writer, concat!(
+        r#"<Style id="point"><IconStyle><scale>0.4</scale><Icon>"#,
+        "<href>http://maps.google.com/mapfiles/kml/shapes/shaded_dot.png</href>",
+        "</Icon></IconStyle><LabelStyle><scale>0</scale></LabelStyle></Style>",
+    ))?;
+
+    writeln!(writer, "<Placemark>")?;
+    writeln!(writer, "<name>Track</name>")?;
+    writeln!(writer, "<styleUrl>#track</styleUrl>")?;
+    writeln!(writer, "<MultiGeometry>")?;
+    for line in activity.lines.iter().filter(|l| l.len() > 1) {
+        writeln!(writer, "<LineString>")?;
+        writeln!(writer, "<tessellate>{}</tessellate>", tessellate)?;
+        writeln!(writer, "<altitudeMode>{}</altitudeMode>", altitude_mode)?;
+        writeln!(writer, "<coordinates>")?;
+        for point in line {
+            writeln!(writer, "{}", kml_coordinates(point))?;
+        }
+        writeln!(writer, "</coordinates>")?;
+        writeln!(writer, "</LineString>")?;
+    }
+    writeln!(writer, "</MultiGeometry>")?;
+    writeln!(writer, "</Placemark>")?;
+
+    writeln!(writer, "<Folder>")?;
+    writeln!(writer, "<name>Points</name>")?;
+    for point in activity.lines.iter().flatten() {
+        writeln!(writer, "<Placemark>")?;
+        if let Some(timestamp) = &point.timestamp {
+            writeln!(writer, "<TimeStamp><when>{}</when></TimeStamp>", options.timezone.convert(timestamp).to_rfc3339())?;
+        }
+        writeln!(writer, "<styleUrl>#point</styleUrl>")?;
+        writeln!(writer, "<description>{}</description>", escape_xml(&point_description(point, options)))?;
+        writeln!(writer, "<Point>")?;
+        writeln!(writer, "<altitudeMode>{}</altitudeMode>", altitude_mode)?;
+        writeln!(writer, "<coordinates>{}</coordinates>", kml_coordinates(point))?;
+        writeln!(writer, "</Point>")?;
+        writeln!(writer, "</Placemark>")?;
+    }
+    writeln!(writer, "</Folder>")?;
+
+    writeln!(writer, "</Document>")?;
+    writeln!(writer, "</kml>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+
+/// Writes the activity as a KMZ file, i.e. a ZIP archive containing the KML document produced by
+/// [`write_kml`].
+pub fn write_kmz<W: Write>(mut writer: W, activity: &Activity, name: &str, options: &KmlOptions) -> Result<(), WalkingError> {
+    let mut kml = Vec::new();
+    write_kml(&mut kml, activity, name, options)?;
+    write_stored_zip(&mut writer, &[(KMZ_DOCUMENT_NAME, &kml)])?;
+    writer.flush()?;
+    Ok(())
+}
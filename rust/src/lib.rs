@@ -0,0 +1,70 @@
+//! Conversion of recorded activities (FIT, GPX, TCX) into GeoJSON documents for the walking map
+//! viewer (or standalone HTML pages, KML, CSV, encoded polylines and Strava-style stream documents for
+//! other applications), with optional
+//! censoring of private areas.
+
+
+pub mod activity;
+pub mod censor;
+pub mod color;
+pub mod compare;
+pub mod config;
+pub mod console;
+pub mod csv;
+pub mod deflate;
+pub mod dem;
+pub mod diagnostics;
+pub mod dump;
+pub mod efforts;
+pub mod elevation;
+pub mod error;
+#[cfg(feature = "fetch")]
+pub mod fetch;
+pub mod filter;
+pub mod fitcensor;
+pub mod geocode;
+pub mod geojson;
+pub mod gpkg;
+pub mod gzip;
+pub mod heatmap;
+pub mod html;
+pub mod http;
+pub mod import;
+pub mod indoor;
+pub mod input;
+pub mod kml;
+pub mod live;
+pub mod multisport;
+pub mod mvt;
+pub mod point;
+pub mod polyline;
+pub mod postgis;
+pub mod route;
+pub mod schema;
+pub mod series;
+pub mod serve;
+pub mod sha1;
+pub mod sha256;
+pub mod smoothing;
+pub mod sql;
+pub mod sqlite;
+pub mod state;
+pub mod stats;
+pub mod stops;
+pub mod strava;
+pub mod summary;
+pub mod swim;
+pub mod timeshift;
+pub mod timezone;
+pub mod toml;
+pub mod trip;
+pub mod units;
+pub mod viewport;
+pub mod websocket;
+pub mod zip;
+pub mod zones;
+
+
+pub use crate::activity::Activity;
+pub use crate::error::WalkingError;
+pub use crate::point::{GeoPoint, Point};
@@ -0,0 +1,177 @@
+//! Live tracking: following a FIT file while it is being recorded and pushing the features of the
+//! newly recorded part of the track to WebSocket clients, which can draw the track as it grows.
+//!
+//! Each WebSocket message is a single compact GeoJSON Feature of the `points` collection of the
+//! document (see [`crate::geojson::lines_to_points`]). Clients connecting later first receive all
+//! features sent so far.
+
+
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::fitcensor::{complete_messages_length, fit_crc};
+use crate::geojson::{DocumentOptions, lines_to_points};
+use crate::websocket::WebSocket;
+
+
+/// Returns a valid FIT file made of the complete messages at the start of `data`, which may be a
+/// FIT file that is still being written (with a data size of 0 in its header and a truncated last
+/// message). Complete files are returned as they are. Returns `None` if not even the header is
+/// complete.
+pub fn complete_fit_prefix(data: &[u8]) -> Option<Vec<u8>> {
+    let header_size = *data.first()? as usize;
+    if header_size < 12 || data.get(8..12) != Some(b".FIT") {
+        return None;
+    }
+    let header = data.get(..header_size)?;
+    let data_size = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    if data_size > 0 {
+        if let Some(file) = data.get(..header_size + data_size + 2) {
+            return Some(file.to_vec());
+        }
+    }
+
+    let messages = &data[header_size..];
+    let length = complete_messages_length(messages);
+    let mut output = header.to_vec();
+    output[4..8].copy_from_slice(&(length as u32).to_le_bytes());
+    if header_size >= 14 {
+        let header_crc = fit_crc(&output[..12]);
+        output[12..14].copy_from_slice(&header_crc.to_le_bytes());
+    }
+    output.extend_from_slice(&messages[..length]);
+    let crc = fit_crc(&output);
+    output.extend_from_slice(&crc.to_le_bytes());
+    Some(output)
+}
+
+
+/// Converts the lines of the activity into the messages pushed to clients: one compact GeoJSON
+/// Feature per segment (or point) of the `points` collection.
+pub fn feature_messages(activity: &Activity, options: &DocumentOptions) -> Vec<String> {
+    let mut points = lines_to_points(&activity.lines, options);
+    points.sort_all_objects();
+    match points.get("features").and_then(|f| f.as_array()) {
+        Some(features) => features.iter()
+            .map(|f| f.to_string())
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+
+#[derive(Debug, Default)]
+struct LiveState {
+    clients: Vec<WebSocket>,
+
+    /// The messages sent so far, which are sent to clients when they connect.
+    messages: Vec<String>,
+}
+impl LiveState {
+    /// Sends the messages to all clients, disconnecting those to which they cannot be sent, and
+    /// keeps them for the clients connecting later.
+    fn send(&mut self, messages: &[String]) {
+        self.clients.retain_mut(|client| {
+            for message in messages {
+                if let Err(e) = client.send_text(message) {
+                    log::info!("{}: disconnected: {}", peer_name(client), e);
+                    return false;
+                }
+            }
+            true
+        });
+        self.messages.extend_from_slice(messages);
+    }
+}
+
+
+/// A WebSocket server pushing messages to all connected clients.
+#[derive(Debug)]
+pub struct LiveServer {
+    state: Arc<Mutex<LiveState>>,
+    local_addr: SocketAddr,
+}
+impl LiveServer {
+    /// Starts listening on the address, accepting clients in the background.
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<Self, WalkingError> {
+        let listener = TcpListener::bind(address)?;
+        let local_addr = listener.local_addr()?;
+        let state: Arc<Mutex<LiveState>> = Arc::default();
+
+        let accepting_state = Arc::clone(&state);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(s) => s,
+                    Err(e) => {
+                        log::warn!("failed to accept connection: {}", e);
+                        continue;
+                    },
+                };
+                // a client taking its time with the handshake must not hold up the others
+                let client_state = Arc::clone(&accepting_state);
+                std::thread::spawn(move || add_client(stream, &client_state));
+            }
+        });
+        Ok(Self { state, local_addr })
+    }
+
+    /// The address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The number of messages sent so far.
+    pub fn message_count(&self) -> usize {
+        self.state.lock().unwrap().messages.len()
+    }
+
+    /// Sends the messages of the current state of the track that have not been sent yet, provided
+    /// that those sent before are unchanged. Returns the number of messages sent, or the index of
+    /// the first message that has changed since it was sent, in which case nothing is sent.
+    pub fn publish_new(&self, messages: &[String]) -> Result<usize, usize> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(changed) = state.messages.iter().zip(messages).position(|(sent, current)| sent != current) {
+            return Err(changed);
+        }
+        let new_messages = messages.get(state.messages.len()..).unwrap_or_default();
+        state.send(new_messages);
+        Ok(new_messages.len())
+    }
+
+    /// Sends the messages to all clients. Clients to which they cannot be sent are disconnected.
+    pub fn publish(&self, messages: &[String]) {
+        self.state.lock().unwrap().send(messages);
+    }
+}
+
+
+/// Performs the handshake with a new client, sends it the messages sent so far and adds it to the
+/// clients receiving further messages.
+fn add_client(stream: std::net::TcpStream, state: &Mutex<LiveState>) {
+    let mut client = match WebSocket::accept(stream) {
+        Ok(c) => c,
+        Err(e) => {
+            log::warn!("rejected connection: {}", e);
+            return;
+        },
+    };
+    let mut state = state.lock().unwrap();
+    for message in &state.messages {
+        if let Err(e) = client.send_text(message) {
+            log::info!("{}: disconnected: {}", peer_name(&client), e);
+            return;
+        }
+    }
+    log::info!("{}: connected", peer_name(&client));
+    state.clients.push(client);
+}
+
+
+fn peer_name(client: &WebSocket) -> String {
+    client.peer()
+        .map(|p| p.to_string())
+        .unwrap_or_else(|| "client".to_owned())
+}
@@ -1,494 +1,2754 @@
-use std::fs::File;
-use std::io::Read;
-use std::path::{Path, PathBuf};
-
-use chrono::{DateTime, Local, TimeZone};
-use clap::Parser;
-use fitparser;
-use fitparser::profile::MesgNum;
-use geo;
-use geo::prelude::Contains;
-use geo::vincenty_distance::VincentyDistance;
-use serde_json;
-
-
-trait GeoPoint {
-    fn latitude(&self) -> f64;
-    fn longitude(&self) -> f64;
-    fn as_lonlat_list(&self) -> serde_json::Value {
-        serde_json::json!([
-            self.longitude(),
-            self.latitude(),
-        ])
-    }
-}
-
-
-#[derive(Clone, Copy, Debug, PartialEq)]
-struct Point {
-    pub coordinates_deg: geo::Point<f64>,
-    pub elevation_m: Option<f64>,
-    pub unix_timestamp: Option<f64>,
-    pub heart_rate_bpm: Option<u64>,
-    pub speed_km_per_h: Option<f64>,
-    pub cadence_rpm: Option<u64>,
-    pub temperature_degc: Option<i64>,
-    pub timestamp: Option<DateTime<Local>>,
-}
-impl Point {
-    pub fn new(
-        coordinates_deg: geo::Point<f64>,
-        elevation_m: Option<f64>,
-        unix_timestamp: Option<f64>,
-        heart_rate_bpm: Option<u64>,
-        speed_km_per_h: Option<f64>,
-        cadence_rpm: Option<u64>,
-        temperature_degc: Option<i64>,
-        timestamp: Option<DateTime<Local>>,
-    ) -> Self {
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::net::TcpListener;
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, TimeDelta};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use log::LevelFilter;
+use rayon::prelude::*;
+use serde_json::{json, Value};
+use fit2walking::{Activity, WalkingError};
+use fit2walking::censor::{
+    CensorArea, CensorCircle, CensorMode, censor_lines, load_censor_file, load_censor_geojson, trim_lines,
+};
+use fit2walking::color::{ColorBy, ColorScale};
+use fit2walking::compare::{
+    CompareFormat, CompareOptions, compare_tracks, DEFAULT_COMPARE_INTERVAL_M, DEFAULT_COMPARE_TOLERANCE_M,
+    write_comparison_csv, write_comparison_json,
+};
+use fit2walking::config::{CensorProfile, Config, default_config_path};
+use fit2walking::console::{self, Verbosity};
+use fit2walking::csv::write_csv;
+use fit2walking::dem::{Dem, DemMode};
+use fit2walking::dump::{DumpFilter, DumpFormat, write_records_json_lines, write_records_table};
+use fit2walking::diagnostics::{Diagnostics, DropReason};
+use fit2walking::efforts::EffortWindow;
+use fit2walking::elevation::DEFAULT_ELEVATION_THRESHOLD_M;
+#[cfg(feature = "fetch")]
+use fit2walking::fetch::{default_token_path, fetch_activities, FetchService, TokenFile, valid_token};
+use fit2walking::filter::{
+    BoundingBox, clip_time_range, clip_to_bbox, remove_speed_outliers, split_at_jumps, split_at_time_gaps, TimeBound,
+};
+use fit2walking::fitcensor::{censor_fit, FitCensorMode};
+use fit2walking::geocode::{geocode_activity, Geocoder};
+use fit2walking::gpkg::{GpkgOptions, write_gpkg};
+use fit2walking::gzip::GzipWriter;
+use fit2walking::geojson::{
+    DistanceSource, DocumentOptions, PointsGeometry, Property, write_activity_document, write_feature_lines,
+};
+use fit2walking::heatmap::{DEFAULT_HEATMAP_CELL_SIZE_M, Heatmap, HeatmapFormat, write_heatmap_csv, write_heatmap_geojson};
+use fit2walking::html::{HtmlOptions, write_html};
+use fit2walking::indoor::{is_indoor, write_indoor_document};
+use fit2walking::import::{activity_folder, detect_activity_folders, import_activities};
+use fit2walking::input::fit::read_fit;
+use fit2walking::input::{
+    expand_input_paths, is_stdin, list_fit_files, read_activities, read_activity, read_fit_data, read_fit_records,
+    ReadOptions,
+};
+use fit2walking::http::{self, DEFAULT_MAX_CONNECTIONS, ServerOptions};
+use fit2walking::live::{complete_fit_prefix, feature_messages, LiveServer};
+use fit2walking::mvt::{DEFAULT_MAX_ZOOM, DEFAULT_MIN_ZOOM, MAX_SUPPORTED_ZOOM, TileOptions, TileSet};
+use fit2walking::point::{DistanceAlgorithm, Point};
+use fit2walking::route::{DEFAULT_MATCH_TOLERANCE_M, load_route_geojson, Route};
+use fit2walking::postgis::write_postgis;
+use fit2walking::polyline::{DEFAULT_POLYLINE_PRECISION, PolylineOptions, write_polyline, write_polyline_statistics};
+use fit2walking::serve::{Catalog, Converted};
+use fit2walking::sha256::sha256_hex;
+use fit2walking::smoothing::{derive_speed, smooth_heart_rate, smooth_speed, SmoothingMethod};
+use fit2walking::kml::{AltitudeMode, KmlOptions, write_kml, write_kmz};
+use fit2walking::sqlite::{ActivitySource, insert_activity, SQLITE_OUTPUT_PREFIX, SqliteOptions};
+use fit2walking::state::{FileState, StateFile};
+use fit2walking::stats::{DEFAULT_AGE_YEARS, DEFAULT_MOVING_THRESHOLD_KM_PER_H};
+use fit2walking::stops::DEFAULT_STOP_RADIUS_M;
+use fit2walking::strava::{StreamsOptions, write_streams};
+use fit2walking::summary::{
+    ActivitySummary, combine_summaries, summarize_activity, summary_to_json, SummaryFormat, SummaryOptions,
+    write_summary_json, write_summary_table,
+};
+use fit2walking::swim::smooth_swim;
+use fit2walking::timeshift::{random_time_offset, TimeOffset};
+use fit2walking::timezone::Timezone;
+use fit2walking::trip::{TripOptions, write_playback, write_trips};
+use fit2walking::units::Units;
+use fit2walking::viewport::Viewport;
+use fit2walking::zones::HeartRateZones;
+
+
+/// The format of the output documents.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// GeoJSON-based document for the walking map viewer.
+    #[default]
+    Geojson,
+
+    /// KML document.
+    Kml,
+
+    /// Zipped KML document.
+    Kmz,
+
+    /// CSV table with one row per point.
+    Csv,
+
+    /// JSON document in the format of the Strava API streams.
+    StravaStreams,
+
+    /// Standalone HTML page showing the track on a map.
+    Html,
+
+    /// Google Encoded Polyline of the track. When writing to files, the summary statistics are
+    /// written to an accompanying `.stats.json` file.
+    Polyline,
+
+    /// The input FIT file itself, with the positions within censor areas removed (see
+    /// `--fit-censor-mode`) and all other messages preserved. No other processing is applied.
+    Fit,
+
+    /// SQL statements inserting the track and points into PostGIS tables.
+    Postgis,
+
+    /// GeoPackage with a track and a points layer (requires the `sqlite3` command-line tool).
+    Gpkg,
+
+    /// Newline-delimited GeoJSON with one feature of the track or points collection per line.
+    Geojsonl,
+
+    /// GeoJSON for the trip layer of kepler.gl: positions with elevations and Unix timestamps, for
+    /// an animated replay.
+    Trip,
+
+    /// GeoJSON for Leaflet.Playback: a MultiPoint with the timestamp of each position, for an
+    /// animated replay.
+    TimestampedGeojson,
+}
+impl OutputFormat {
+    /// The extension of the files generated with the `-O` option.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Geojson => "json",
+            Self::Kml => "kml",
+            Self::Kmz => "kmz",
+            Self::Csv => "csv",
+            Self::StravaStreams => "json",
+            Self::Html => "html",
+            Self::Polyline => "polyline",
+
+            // never overwrite the input file
+            Self::Fit => "censored.fit",
+            Self::Postgis => "sql",
+            Self::Gpkg => "gpkg",
+            Self::Geojsonl => "geojsonl",
+            Self::Trip => "trip.json",
+            Self::TimestampedGeojson => "timestamped.json",
+        }
+    }
+
+    /// Whether the format requires at least one point, e.g. to center the map on. The other
+    /// formats produce valid empty documents for activities without points.
+    pub fn requires_points(&self) -> bool {
+        matches!(self, Self::Geojson | Self::Html)
+    }
+}
+
+
+/// The most detailed level of messages that are logged.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+enum LogLevel {
+    Off,
+    Error,
+    #[default]
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+impl LogLevel {
+    pub fn to_level_filter(self) -> LevelFilter {
+        match self {
+            Self::Off => LevelFilter::Off,
+            Self::Error => LevelFilter::Error,
+            Self::Warn => LevelFilter::Warn,
+            Self::Info => LevelFilter::Info,
+            Self::Debug => LevelFilter::Debug,
+            Self::Trace => LevelFilter::Trace,
+        }
+    }
+}
+
+
+/// Converts recorded activities into documents for the walking map viewer and other applications.
+///
+/// If the first argument is not a subcommand, `convert` is assumed.
+///
+/// The exit status is 0 on success, 1 if an error prevented processing, 2 if the arguments are
+/// invalid, 3 if some of the input files could not be processed and 4 if none of them could.
+#[derive(Clone, Debug, Parser, PartialEq)]
+struct Cli {
+    #[command(subcommand)] pub command: Command,
+}
+
+
+#[derive(Clone, Debug, PartialEq, Subcommand)]
+enum Command {
+    // options overriding themselves allows the command line to override the configuration
+
+    /// Convert each input file into a document.
+    #[command(args_override_self = true)]
+    Convert(Opts),
+
+    /// Output summary statistics of the input files instead of documents.
+    #[command(args_override_self = true)]
+    Stats(Opts),
+
+    /// Convert the input files like `convert`, but refuse to do so without censor areas.
+    #[command(args_override_self = true)]
+    Censor(Opts),
+
+    /// Convert all input files into a single document.
+    #[command(args_override_self = true)]
+    Merge(Opts),
+
+    /// Output the raw messages of FIT files.
+    Dump(DumpOpts),
+
+    /// Compare two recordings of the same route along the first one.
+    Compare(CompareOpts),
+
+    /// Watch directories and convert FIT files appearing in them.
+    #[command(args_override_self = true)]
+    Watch(WatchOpts),
+
+    /// Slice the input files into a directory of vector tiles.
+    #[command(args_override_self = true)]
+    Tiles(TilesOpts),
+
+    /// Follow a FIT file while it is being recorded and push new features to WebSocket clients.
+    #[command(args_override_self = true)]
+    Live(LiveOpts),
+
+    /// Serve the activities in directories over HTTP, converting them on demand.
+    #[command(args_override_self = true)]
+    Serve(ServeOpts),
+
+    /// Download recent activities from Garmin Connect or Strava into a directory and convert them.
+    #[cfg(feature = "fetch")]
+    #[command(args_override_self = true)]
+    Fetch(FetchOpts),
+
+    /// Copy new activities from a mounted Garmin device into an archive directory and convert them.
+    #[command(args_override_self = true)]
+    Import(ImportOpts),
+}
+impl Command {
+    /// Returns the conversion options of the subcommands that convert files.
+    fn opts(&self) -> Option<&Opts> {
+        match self {
+            Self::Convert(opts)|Self::Stats(opts)|Self::Censor(opts)|Self::Merge(opts) => Some(opts),
+            Self::Watch(watch_opts) => Some(&watch_opts.convert),
+            Self::Tiles(tiles_opts) => Some(&tiles_opts.convert),
+            Self::Live(live_opts) => Some(&live_opts.convert),
+            Self::Serve(serve_opts) => Some(&serve_opts.convert),
+            #[cfg(feature = "fetch")]
+            Self::Fetch(fetch_opts) => Some(&fetch_opts.convert),
+            Self::Import(import_opts) => Some(&import_opts.convert),
+            Self::Dump(_)|Self::Compare(_) => None,
+        }
+    }
+
+    /// Returns the conversion options of the subcommands that convert files, for modification.
+    fn opts_mut(&mut self) -> Option<&mut Opts> {
+        match self {
+            Self::Convert(opts)|Self::Stats(opts)|Self::Censor(opts)|Self::Merge(opts) => Some(opts),
+            Self::Watch(watch_opts) => Some(&mut watch_opts.convert),
+            Self::Tiles(tiles_opts) => Some(&mut tiles_opts.convert),
+            Self::Live(live_opts) => Some(&mut live_opts.convert),
+            Self::Serve(serve_opts) => Some(&mut serve_opts.convert),
+            #[cfg(feature = "fetch")]
+            Self::Fetch(fetch_opts) => Some(&mut fetch_opts.convert),
+            Self::Import(import_opts) => Some(&mut import_opts.convert),
+            Self::Dump(_)|Self::Compare(_) => None,
+        }
+    }
+
+    /// The names of the subcommands, including the one generated by clap. `fetch` is included even
+    /// if it is not available, so that clap reports it as such instead of converting a file named
+    /// `fetch`.
+    pub const NAMES: [&'static str; 13] = ["convert", "stats", "censor", "merge", "dump", "compare", "watch", "tiles", "live", "serve", "fetch", "import", "help"];
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct Opts {
+    #[arg(long, value_enum, default_value_t)] pub log_level: LogLevel,
+    #[arg(short, long, conflicts_with = "verbose")] pub quiet: bool,
+    #[arg(short, long)] pub verbose: bool,
+    #[arg(long)] pub report: Option<PathBuf>,
+    #[arg(long)] pub error_report: Option<PathBuf>,
+    #[arg(long)] pub include_developer_fields: bool,
+    #[arg(short = 'O', long, overrides_with = "output")] pub output_as_files: bool,
+    #[arg(long)] pub output_dir: Option<PathBuf>,
+    #[arg(short, long)] pub output: Option<String>,
+    #[arg(short, long = "censor-polygon")] pub censor_polygons: Vec<PathBuf>,
+    #[arg(long = "censor-geojson")] pub censor_geojsons: Vec<PathBuf>,
+    #[arg(long = "censor-circle")] pub censor_circles: Vec<CensorCircle>,
+    #[arg(long = "censor-profile", value_delimiter = ',')] pub censor_profiles: Vec<String>,
+    #[arg(skip)] pub censor_profile_definitions: BTreeMap<String, CensorProfile>,
+    #[arg(long, value_enum, default_value_t)] pub censor_mode: CensorMode,
+    #[arg(long, value_enum, default_value_t)] pub fit_censor_mode: FitCensorMode,
+    #[arg(long, value_enum, default_value_t)] pub distance_algo: DistanceAlgorithm,
+    #[arg(long)] pub max_speed: Option<f64>,
+    #[arg(long)] pub gap_threshold: Option<f64>,
+    #[arg(long)] pub jump_threshold: Option<f64>,
+    #[arg(long)] pub start_time: Option<TimeBound>,
+    #[arg(long)] pub end_time: Option<TimeBound>,
+    #[arg(long)] pub clip_bbox: Option<BoundingBox>,
+    #[arg(long, conflicts_with_all = ["shift_times", "randomize_start"])] pub strip_times: bool,
+    #[arg(long, allow_hyphen_values = true, conflicts_with = "randomize_start")] pub shift_times: Option<TimeOffset>,
+    #[arg(long, conflicts_with = "stable")] pub randomize_start: bool,
+    #[arg(long)] pub dem_dir: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t)] pub dem_mode: DemMode,
+    #[arg(long)] pub smooth_speed: Option<usize>,
+    #[arg(long)] pub derive_speed: bool,
+    #[arg(long)] pub smooth_hr: Option<usize>,
+    #[arg(long, value_enum, default_value_t)] pub smoothing: SmoothingMethod,
+    #[arg(long)] pub raw_values: bool,
+    #[arg(long, default_value_t = 0.0)] pub trim_start: f64,
+    #[arg(long, default_value_t = 0.0)] pub trim_end: f64,
+    #[arg(long, default_value_t)] pub viewport: Viewport,
+    #[arg(long, value_enum, default_value_t)] pub units: Units,
+    #[arg(long, default_value_t)] pub timezone: Timezone,
+    #[arg(long)] pub lap_markers: bool,
+    #[arg(long)] pub pause_markers: bool,
+    #[arg(long)] pub derived_temps: bool,
+    #[arg(long)] pub cycling_dynamics: bool,
+    #[arg(long)] pub running_dynamics: bool,
+    #[arg(long)] pub swim: bool,
+    #[arg(long)] pub indoor: bool,
+    #[arg(long, value_parser = parse_positive_f64)] pub weight_kg: Option<f64>,
+    #[arg(long, default_value_t = DEFAULT_AGE_YEARS, value_parser = parse_positive_f64)] pub age: f64,
+    #[arg(long)] pub splits: bool,
+    #[arg(long, value_parser = clap::value_parser!(u64).range(3..))] pub series: Option<u64>,
+    #[arg(long, value_parser = parse_positive_f64)] pub direction_arrows: Option<f64>,
+    #[arg(long, value_parser = parse_positive_f64)] pub stops: Option<f64>,
+    #[arg(long, default_value_t = DEFAULT_STOP_RADIUS_M, value_parser = parse_positive_f64)] pub stop_radius: f64,
+    #[arg(long, default_value_t = DEFAULT_ELEVATION_THRESHOLD_M)] pub elevation_threshold: f64,
+    #[arg(long, default_value_t = DEFAULT_MOVING_THRESHOLD_KM_PER_H)] pub moving_threshold: f64,
+    #[arg(long, overrides_with = "hr_zones")] pub max_hr: Option<u64>,
+    #[arg(long)] pub hr_zones: Option<HeartRateZones>,
+    #[arg(long, requires = "max_hr")] pub rest_hr: Option<u64>,
+    #[arg(long, value_parser = parse_positive_f64)] pub ftp: Option<f64>,
+    #[arg(long, value_delimiter = ',')] pub best_efforts: Vec<EffortWindow>,
+    #[arg(long, requires = "best_efforts")] pub best_effort_markers: bool,
+    #[arg(short, long, default_value_t = 1)] pub jobs: usize,
+    #[arg(long)] pub config: Option<PathBuf>,
+    #[arg(long, conflicts_with = "config")] pub no_config: bool,
+    #[arg(long)] pub state_file: Option<PathBuf>,
+    #[arg(short, long)] pub merge: bool,
+    #[arg(long, value_enum, default_value_t)] pub output_format: OutputFormat,
+    #[arg(long, value_enum, default_value_t)] pub points_as: PointsGeometry,
+    #[arg(long, value_enum, default_value_t)] pub html_color_by: ColorBy,
+    #[arg(long, value_enum)] pub color_by: Option<ColorBy>,
+    #[arg(long, value_enum, default_value_t)] pub color_scale: ColorScale,
+    #[arg(long, default_value_t = DEFAULT_POLYLINE_PRECISION, value_parser = clap::value_parser!(u32).range(5..=6))] pub polyline_precision: u32,
+    #[arg(long)] pub feature_bbox: bool,
+    #[arg(long)] pub coords_3d: bool,
+    #[arg(long)] pub null_missing: bool,
+    #[arg(long, value_enum, value_delimiter = ',', action = clap::ArgAction::Set)] pub properties: Option<Vec<Property>>,
+    #[arg(long, value_enum, default_value_t)] pub distance_source: DistanceSource,
+    #[arg(long)] pub compact: bool,
+    #[arg(long)] pub stable: bool,
+    #[arg(long)] pub match_route: Option<PathBuf>,
+    #[arg(long, default_value_t = DEFAULT_MATCH_TOLERANCE_M)] pub match_tolerance: f64,
+
+    /// The route loaded from `match_route` in `main()`.
+    #[arg(skip)] pub route: Option<Arc<Route>>,
+    #[arg(long)] pub geocode: Option<String>,
+
+    /// The geocoder opened from `geocode` in `main()`.
+    #[arg(skip)] pub geocoder: Option<Arc<Geocoder>>,
+    #[arg(long, overrides_with_all = ["output", "output_as_files", "report"])] pub summary_only: bool,
+    #[arg(long, value_enum, default_value_t)] pub summary_format: SummaryFormat,
+    #[arg(long, overrides_with_all = ["output", "output_as_files", "report", "summary_only"])] pub heatmap: bool,
+    #[arg(long, default_value_t = DEFAULT_HEATMAP_CELL_SIZE_M, value_parser = parse_positive_f64)] pub heatmap_cell_size: f64,
+    #[arg(long, value_enum, default_value_t)] pub heatmap_format: HeatmapFormat,
+    #[arg(long)] pub gzip: bool,
+    #[arg(long)] pub precision: Option<u32>,
+    #[arg(long, requires = "precision")] pub round_properties: bool,
+    #[arg(long, value_enum, default_value_t)] pub altitude_mode: AltitudeMode,
+    #[arg(required = true)] pub filenames: Vec<PathBuf>,
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct DumpOpts {
+    #[arg(long, value_enum, default_value_t)] pub log_level: LogLevel,
+    #[arg(short, long, conflicts_with = "verbose")] pub quiet: bool,
+    #[arg(short, long)] pub verbose: bool,
+    #[arg(long = "kind", value_delimiter = ',')] pub kinds: Vec<String>,
+    #[arg(long = "field", value_delimiter = ',')] pub fields: Vec<String>,
+    #[arg(long, value_enum, default_value_t)] pub format: DumpFormat,
+    #[arg(short, long)] pub output: Option<PathBuf>,
+    #[arg(required = true)] pub filenames: Vec<PathBuf>,
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct CompareOpts {
+    #[arg(long, value_enum, default_value_t)] pub log_level: LogLevel,
+    #[arg(short, long, conflicts_with = "verbose")] pub quiet: bool,
+    #[arg(short, long)] pub verbose: bool,
+    #[arg(long, default_value_t = DEFAULT_COMPARE_INTERVAL_M, value_parser = parse_positive_f64)] pub interval: f64,
+    #[arg(long, default_value_t = DEFAULT_COMPARE_TOLERANCE_M, value_parser = parse_positive_f64)] pub tolerance: f64,
+    #[arg(long, value_enum, default_value_t)] pub distance_algo: DistanceAlgorithm,
+    #[arg(long, value_enum, default_value_t)] pub units: Units,
+    #[arg(long, value_enum, default_value_t)] pub format: CompareFormat,
+    #[arg(long)] pub compact: bool,
+    #[arg(short, long)] pub output: Option<PathBuf>,
+    pub first: PathBuf,
+    pub second: PathBuf,
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct WatchOpts {
+    #[arg(long, default_value_t = DEFAULT_POLL_INTERVAL_S, value_parser = parse_positive_f64)] pub poll_interval: f64,
+    #[arg(long)] pub profile: Option<PathBuf>,
+    #[command(flatten)] pub convert: Opts,
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct LiveOpts {
+    #[arg(long, default_value = DEFAULT_LIVE_ADDRESS)] pub listen: String,
+    #[arg(long, default_value_t = DEFAULT_POLL_INTERVAL_S, value_parser = parse_positive_f64)] pub poll_interval: f64,
+    #[command(flatten)] pub convert: Opts,
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct ServeOpts {
+    #[arg(long, default_value = DEFAULT_SERVE_ADDRESS)] pub listen: String,
+    #[arg(long, value_parser = parse_cors_origin)] pub cors_origin: Option<String>,
+    #[arg(long, default_value_t = DEFAULT_MAX_CONNECTIONS, value_parser = parse_positive_usize)] pub max_connections: usize,
+    #[command(flatten)] pub convert: Opts,
+}
+
+
+#[cfg(feature = "fetch")]
+#[derive(Clone, Debug, Args, PartialEq)]
+struct FetchOpts {
+    #[arg(long, value_enum)] pub service: FetchService,
+    #[arg(long)] pub token_file: Option<PathBuf>,
+    #[arg(long, default_value_t = DEFAULT_FETCH_COUNT)] pub count: usize,
+    #[command(flatten)] pub convert: Opts,
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct ImportOpts {
+    #[arg(long)] pub device: Option<PathBuf>,
+    #[command(flatten)] pub convert: Opts,
+}
+
+
+#[derive(Clone, Debug, Args, PartialEq)]
+struct TilesOpts {
+    #[arg(long, default_value_t = DEFAULT_MIN_ZOOM, value_parser = clap::value_parser!(u8).range(0..=MAX_SUPPORTED_ZOOM as i64))] pub min_zoom: u8,
+    #[arg(long, default_value_t = DEFAULT_MAX_ZOOM, value_parser = clap::value_parser!(u8).range(0..=MAX_SUPPORTED_ZOOM as i64))] pub max_zoom: u8,
+    #[command(flatten)] pub convert: Opts,
+}
+
+
+/// Inserts the `convert` subcommand into the command-line arguments if no subcommand is given, so
+/// that command lines from before the introduction of subcommands keep working.
+fn with_subcommand(mut args: Vec<OsString>) -> Vec<OsString> {
+    let needs_subcommand = match args.get(1).and_then(|a| a.to_str()) {
+        None => false,
+        Some("-h"|"--help"|"-V"|"--version") => false,
+        Some(first) => !Command::NAMES.contains(&first),
+    };
+    if needs_subcommand {
+        args.insert(1, OsString::from("convert"));
+    }
+    args
+}
+
+
+/// The default time between two scans of the watched directories (or two reads of the followed
+/// file), in seconds.
+const DEFAULT_POLL_INTERVAL_S: f64 = 5.0;
+
+/// The default address on which `live` accepts WebSocket connections.
+const DEFAULT_LIVE_ADDRESS: &str = "127.0.0.1:8765";
+
+/// The default address on which `serve` accepts HTTP connections.
+const DEFAULT_SERVE_ADDRESS: &str = "127.0.0.1:8080";
+
+/// The default number of recent activities considered by `fetch`.
+#[cfg(feature = "fetch")]
+const DEFAULT_FETCH_COUNT: usize = 10;
+
+
+/// The number of files after which the state file is saved while converting sequentially, so that
+/// an interrupted conversion can be resumed without rewriting the whole state after every file.
+const STATE_SAVE_INTERVAL: usize = 20;
+
+/// The exit code if some of the input files could not be processed.
+const EXIT_SOME_FILES_FAILED: u8 = 3;
+
+/// The exit code if none of the input files could be processed.
+const EXIT_ALL_FILES_FAILED: u8 = 4;
+
+
+/// Returns the verbosity requested by `--quiet` or `--verbose`.
+fn verbosity(quiet: bool, verbose: bool) -> Verbosity {
+    if quiet {
+        Verbosity::Quiet
+    } else if verbose {
+        Verbosity::Verbose
+    } else {
+        Verbosity::Normal
+    }
+}
+
+
+/// Parses a number that must be greater than zero, such as a distance interval.
+fn parse_positive_f64(text: &str) -> Result<f64, String> {
+    let value: f64 = text.parse()
+        .map_err(|e| format!("failed to parse {:?} as a number: {}", text, e))?;
+    if !(value > 0.0 && value.is_finite()) {
+        return Err(format!("{} is not a positive number", value));
+    }
+    Ok(value)
+}
+
+
+/// Parses a count that must be greater than zero, such as a number of connections.
+fn parse_positive_usize(text: &str) -> Result<usize, String> {
+    let value: usize = text.parse()
+        .map_err(|e| format!("failed to parse {:?} as a count: {}", text, e))?;
+    if value == 0 {
+        return Err("the count must be greater than zero".to_owned());
+    }
+    Ok(value)
+}
+
+
+/// Parses the origin whose pages may read the responses of `serve`: either `*` for any origin or a
+/// scheme, host and optional port, such as `http://localhost:3000`.
+fn parse_cors_origin(text: &str) -> Result<String, String> {
+    let is_origin = match text.split_once("://") {
+        Some((scheme, host)) => !scheme.is_empty() && !host.is_empty()
+            && !host.contains(|c: char| c == '/' || c.is_whitespace() || c.is_control())
+            && !scheme.contains(|c: char| c.is_whitespace() || c.is_control()),
+        None => false,
+    };
+    if text != "*" && !is_origin {
+        return Err(format!("{:?} is neither \"*\" nor an origin such as \"http://localhost:3000\"", text));
+    }
+    Ok(text.to_owned())
+}
+
+
+/// Expands an output filename template for the given input file.
+///
+/// Supported placeholders are `{stem}` (file name without extension), `{name}` (file name with
+/// extension), `{ext}` (extension) and `{dir}` (directory containing the input file). `{{` and
+/// `}}` produce literal braces.
+fn expand_output_template(template: &str, input: &Path) -> Result<PathBuf, String> {
+    let lossy = |s: Option<&std::ffi::OsStr>| s
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = lossy(input.file_stem());
+    let name = lossy(input.file_name());
+    let ext = lossy(input.extension());
+    let dir = match input.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_string_lossy().into_owned(),
+        _ => ".".to_owned(),
+    };
+
+    let mut ret = String::new();
+    let mut rest = template;
+    while let Some(brace_index) = rest.find(['{', '}']) {
+        ret.push_str(&rest[..brace_index]);
+        rest = &rest[brace_index..];
+        if let Some(r) = rest.strip_prefix("{{") {
+            ret.push('{');
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("}}") {
+            ret.push('}');
+            rest = r;
+        } else if rest.starts_with('}') {
+            return Err("unmatched '}' in output template".to_owned());
+        } else {
+            let close_index = rest.find('}')
+                .ok_or_else(|| "unmatched '{' in output template".to_owned())?;
+            let value = match &rest[1..close_index] {
+                "stem" => &stem,
+                "name" => &name,
+                "ext" => &ext,
+                "dir" => &dir,
+                other => return Err(format!("unknown placeholder {{{}}} in output template", other)),
+            };
+            ret.push_str(value);
+            rest = &rest[close_index+1..];
+        }
+    }
+    ret.push_str(rest);
+    Ok(PathBuf::from(ret))
+}
+
+
+/// Loads all the censor areas specified on the command line.
+fn load_censor_areas(opts: &Opts) -> Result<Vec<CensorArea>, String> {
+    let mut censor_circles = opts.censor_circles.clone();
+    let mut censor_polygons = opts.censor_polygons.clone();
+    let mut censor_geojsons = opts.censor_geojsons.clone();
+    for name in &opts.censor_profiles {
+        let profile = opts.censor_profile_definitions.get(name)
+            .ok_or_else(|| format!("censor profile {:?} is not defined in the configuration file", name))?;
+        censor_circles.extend(profile.circles.iter().copied());
+        censor_polygons.extend(profile.polygons.iter().cloned());
+        censor_geojsons.extend(profile.geojsons.iter().cloned());
+    }
+
+    let mut censor_areas: Vec<CensorArea> = censor_circles.iter()
+        .map(|cc| CensorArea::Circle(*cc))
+        .collect();
+    for path in &censor_polygons {
+        let polygons = load_censor_file(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        censor_areas.extend(polygons.into_iter().map(CensorArea::Polygon));
+    }
+    for path in &censor_geojsons {
+        let polygons = load_censor_geojson(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        censor_areas.extend(polygons.into_iter().map(CensorArea::Polygon));
+    }
+    Ok(censor_areas)
+}
+
+
+
+
+/// What happened to the points of an activity during conversion.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Outcome {
+    /// The (one-based) index of the activity within a chained FIT file, if the file contains
+    /// more than one.
+    pub activity_index: Option<usize>,
+
+    pub points_read: usize,
+    pub points_written: usize,
+    pub diagnostics: Diagnostics,
+}
+
+
+/// An error while processing input files, along with the files concerned: the file that failed or,
+/// if the files are merged, all of them.
+#[derive(Debug)]
+struct FileFailure {
+    pub inputs: Vec<PathBuf>,
+    pub error: WalkingError,
+}
+impl FileFailure {
+    fn new(inputs: &[PathBuf], error: WalkingError) -> Self {
+        Self {
+            inputs: inputs.to_vec(),
+            error,
+        }
+    }
+}
+impl fmt::Display for FileFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, input) in self.inputs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", input.display())?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+
+/// The input files that could not be processed, from which the exit code and the error report are
+/// derived.
+#[derive(Debug, Default)]
+struct FailedFiles {
+    /// The number of input files that were to be processed.
+    pub total: usize,
+
+    /// Whether the input files are merged, so that one failure fails all of them.
+    pub merged: bool,
+
+    pub failures: Vec<FileFailure>,
+}
+impl FailedFiles {
+    fn new(total: usize, merged: bool) -> Self {
         Self {
-            coordinates_deg,
-            elevation_m,
-            unix_timestamp,
-            heart_rate_bpm,
-            speed_km_per_h,
-            cadence_rpm,
-            temperature_degc,
-            timestamp,
+            total,
+            merged,
+            failures: Vec::new(),
+        }
+    }
+
+    /// Outputs the error and records the failure.
+    fn add(&mut self, failure: FileFailure) {
+        console::error(&failure);
+        self.failures.push(failure);
+    }
+
+    fn failed_count(&self) -> usize {
+        if self.merged && !self.failures.is_empty() {
+            self.total
+        } else {
+            self.failures.len()
+        }
+    }
+
+    fn exit_code(&self) -> ExitCode {
+        if self.failures.is_empty() {
+            ExitCode::SUCCESS
+        } else if self.failed_count() >= self.total {
+            ExitCode::from(EXIT_ALL_FILES_FAILED)
+        } else {
+            ExitCode::from(EXIT_SOME_FILES_FAILED)
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let failures: Vec<Value> = self.failures.iter()
+            .map(|failure| json!({
+                "inputs": failure.inputs.iter().map(|f| f.display().to_string()).collect::<Vec<_>>(),
+                "error": failure.error.to_string(),
+                "kind": failure.error.kind(),
+                "transient": failure.error.is_transient(),
+            }))
+            .collect();
+        json!({
+            "files_total": self.total,
+            "files_failed": self.failed_count(),
+            "failures": failures,
+        })
+    }
+}
+
+
+/// Writes the error report if one was requested and returns the exit code summarizing the failures.
+/// The report is written even if nothing failed, so that it never belongs to an earlier run.
+fn finish_failures(opts: &Opts, failed: &FailedFiles) -> ExitCode {
+    if failed.total > 1 && !failed.failures.is_empty() && !failed.merged {
+        log::warn!("{} of {} input files could not be processed", failed.failed_count(), failed.total);
+    }
+    if let Some(path) = &opts.error_report {
+        if let Err(e) = write_json_file(path, &failed.to_json(), "error report") {
+            console::error(e);
+            return ExitCode::FAILURE;
+        }
+    }
+    failed.exit_code()
+}
+
+
+/// Runs the processing of an input file, turning a panic into an error so that the other files are
+/// still processed.
+fn isolated<T, F: FnOnce() -> Result<T, WalkingError>>(process: F) -> Result<T, WalkingError> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(process)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "panic".to_owned());
+            Err(WalkingError::Internal(message))
+        },
+    }
+}
+
+
+/// Returns the entries of the report for the given input files, one for each activity read from
+/// them.
+fn file_report_entries(filenames: &[PathBuf], result: Result<&[Outcome], String>) -> Vec<Value> {
+    let inputs: Vec<String> = filenames.iter()
+        .map(|f| f.display().to_string())
+        .collect();
+    match result {
+        Ok(outcomes) => outcomes.iter()
+            .map(|outcome| {
+                let mut entry = json!({
+                    "inputs": inputs,
+                    "points_read": outcome.points_read,
+                    "points_written": outcome.points_written,
+                });
+                if let Some(entry_map) = entry.as_object_mut() {
+                    if let Some(activity_index) = outcome.activity_index {
+                        entry_map.insert("activity".to_owned(), activity_index.into());
+                    }
+                    if let Value::Object(diagnostics_map) = outcome.diagnostics.to_json() {
+                        entry_map.extend(diagnostics_map);
+                    }
+                }
+                entry
+            })
+            .collect(),
+        Err(e) => vec![json!({
+            "inputs": inputs,
+            "error": e,
+        })],
+    }
+}
+
+
+/// Records in the state, if any, that the given input file has been converted. Standard input is not
+/// recorded.
+fn record_converted(state: &mut Option<StateFile>, filename: &Path) {
+    if let (Some(state), false) = (state, is_stdin(filename)) {
+        if let Err(e) = state.mark_converted(filename) {
+            log::warn!("{}: failed to record conversion in state file: {}", filename.display(), e);
+        }
+    }
+}
+
+
+/// Writes the state, if any, to its file. Returns whether this succeeded.
+fn save_state(state: &Option<StateFile>) -> bool {
+    if let Some(state) = state {
+        if let Err(e) = state.save() {
+            console::error(format_args!("failed to write state file: {}", e));
+            return false;
+        }
+    }
+    true
+}
+
+
+/// Writes the report about all converted files if one was requested. Returns whether this
+/// succeeded.
+fn finish_report(opts: &Opts, entries: Vec<Value>) -> bool {
+    if let Some(report_path) = &opts.report {
+        if let Err(e) = write_json_file(report_path, &json!({ "files": entries }), "report") {
+            console::error(e);
+            return false;
+        }
+    }
+    true
+}
+
+
+/// Writes a report (described as `what` in error messages) as a JSON file.
+fn write_json_file(path: &Path, report: &Value, what: &str) -> Result<(), String> {
+    let mut report = report.clone();
+    report.sort_all_objects();
+    let mut writer = BufWriter::new(
+        File::create(path)
+            .map_err(|e| format!("failed to create {} {}: {}", what, path.display(), e))?
+    );
+    serde_json::to_writer_pretty(&mut writer, &report)
+        .map_err(|e| format!("failed to write {} {}: {}", what, path.display(), e))?;
+    writeln!(writer)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("failed to write {} {}: {}", what, path.display(), e))
+}
+
+
+fn read_options(opts: &Opts) -> ReadOptions {
+    ReadOptions {
+        developer_fields: opts.include_developer_fields,
+    }
+}
+
+
+/// Reads the activities of an input file and logs the warnings encountered while doing so.
+fn read_input(opts: &Opts, filename: &Path) -> Result<Vec<Activity>, WalkingError> {
+    let activities = read_activities(filename, &read_options(opts))?;
+    for warning in activities.iter().flat_map(|a| &a.diagnostics.warnings) {
+        log::warn!("{}: {}", filename.display(), warning);
+    }
+    Ok(activities)
+}
+
+
+/// Returns the input path from which output file names are derived; standard input is named
+/// `stdin`.
+fn output_base(filename: &Path) -> &Path {
+    if is_stdin(filename) {
+        Path::new("stdin")
+    } else {
+        filename
+    }
+}
+
+
+/// Returns the input path from which the output file name for the activity with the given
+/// (one-based) index within a chained FIT file is derived, e.g. `ride-2.fit` for the second
+/// activity in `ride.fit`.
+fn activity_input_path(filename: &Path, activity_index: usize) -> PathBuf {
+    let base = output_base(filename);
+    let mut activity_name = base.file_stem()
+        .unwrap_or_default()
+        .to_owned();
+    activity_name.push(format!("-{}", activity_index));
+    if let Some(ext) = base.extension() {
+        activity_name.push(".");
+        activity_name.push(ext);
+    }
+    base.with_file_name(activity_name)
+}
+
+
+/// Returns the path of the file to which the document for the given input file should be written,
+/// or `None` if it should be written to stdout. Compressed documents are given the extension `.gz`,
+/// which is appended to file names from templates unless they already end in it.
+fn output_path(opts: &Opts, filename: &Path) -> Option<PathBuf> {
+    let filename = output_base(filename);
+    if let Some(template) = &opts.output {
+        // template has been validated in main()
+        let mut output_filename = expand_output_template(template, filename).unwrap().into_os_string();
+        if opts.gzip && !output_filename.to_string_lossy().ends_with(".gz") {
+            output_filename.push(".gz");
+        }
+        Some(PathBuf::from(output_filename))
+    } else if opts.output_as_files {
+        let mut output_filename = match &opts.output_dir {
+            Some(dir) => dir.join(filename.file_name().unwrap_or(filename.as_os_str())),
+            None => filename.to_owned(),
+        };
+        if opts.gzip {
+            output_filename.set_extension(format!("{}.gz", opts.output_format.extension()));
+        } else {
+            output_filename.set_extension(opts.output_format.extension());
+        }
+        Some(output_filename)
+    } else {
+        None
+    }
+}
+
+
+/// Returns whether the progress can be displayed during a conversion: not if the documents are
+/// written to a terminal, where the progress would get in between them.
+fn progress_fits(opts: &Opts) -> bool {
+    !io::stdout().is_terminal()
+        || sqlite_database(opts).is_some()
+        || output_path(opts, &opts.filenames[0]).is_some()
+}
+
+
+/// Converts a single input file. The document is written to its own file if requested by the
+/// options, otherwise to `stdout`. Each activity of a chained FIT file is converted into its own
+/// document.
+fn process_file<W: Write>(opts: &Opts, filename: &Path, censor_areas: &[CensorArea], mut stdout: W) -> Result<Vec<Outcome>, WalkingError> {
+    if opts.output_format == OutputFormat::Fit {
+        return Ok(vec![export_fit(opts, filename, censor_areas, stdout)?]);
+    }
+    if let Some(database) = sqlite_database(opts) {
+        return store_activities(opts, database, filename, censor_areas);
+    }
+
+    let activities = read_input(opts, filename)?;
+    if activities.len() == 1 {
+        let activity = activities.into_iter().next().unwrap();
+        return Ok(vec![output_activity(opts, activity, filename, None, censor_areas, &mut stdout)?]);
+    }
+
+    let mut outcomes = Vec::with_capacity(activities.len());
+    for (i, activity) in activities.into_iter().enumerate() {
+        outcomes.push(output_activity(opts, activity, filename, Some(i + 1), censor_areas, &mut stdout)?);
+    }
+    Ok(outcomes)
+}
+
+
+/// Censors the positions of a FIT file and writes the resulting FIT file to the output file derived
+/// from `filename` or, if no output files were requested, to `stdout`. Records with positions count
+/// as points.
+fn export_fit<W: Write>(opts: &Opts, filename: &Path, censor_areas: &[CensorArea], mut stdout: W) -> Result<Outcome, WalkingError> {
+    let data = read_fit_data(filename)?;
+    let (censored, counts) = censor_fit(&data, censor_areas, opts.fit_censor_mode)?;
+    log::info!(
+        "{}: censored {} of {} positions, removed {} messages",
+        filename.display(), counts.records_censored, counts.positions_read, counts.messages_removed,
+    );
+
+    let mut diagnostics = Diagnostics::default();
+    diagnostics.drop_points(DropReason::Censored, counts.records_censored);
+    let outcome = Outcome {
+        activity_index: None,
+        points_read: counts.positions_read,
+        points_written: counts.positions_read - counts.records_censored,
+        diagnostics,
+    };
+
+    let mut writer: Box<dyn Write + '_> = match output_path(opts, filename) {
+        Some(f) => {
+            if !is_stdin(filename) && fs::canonicalize(&f).ok() == fs::canonicalize(filename).ok() {
+                return Err(WalkingError::Io(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("refusing to overwrite the input file {}", filename.display()),
+                )));
+            }
+            Box::new(BufWriter::new(File::create(f)?))
+        },
+        None => Box::new(&mut stdout),
+    };
+    if opts.gzip {
+        let mut gzip_writer = GzipWriter::new(writer);
+        gzip_writer.write_all(&censored)?;
+        gzip_writer.finish()?.flush()?;
+    } else {
+        writer.write_all(&censored)?;
+        writer.flush()?;
+    }
+    Ok(outcome)
+}
+
+
+/// Returns the path of the SQLite database given as `--output sqlite:PATH`, if any.
+fn sqlite_database(opts: &Opts) -> Option<&Path> {
+    opts.output.as_deref()
+        .and_then(|o| o.strip_prefix(SQLITE_OUTPUT_PREFIX))
+        .map(Path::new)
+}
+
+
+/// Processes the activities of an input file and inserts them into the SQLite database, replacing
+/// those inserted from the same file contents before.
+fn store_activities(opts: &Opts, database: &Path, filename: &Path, censor_areas: &[CensorArea]) -> Result<Vec<Outcome>, WalkingError> {
+    let file_hash = sha256_hex(&fs::read(filename)?);
+    let activities = read_input(opts, filename)?;
+    let sqlite_options = SqliteOptions {
+        distance_algorithm: opts.distance_algo,
+        moving_threshold_km_per_h: opts.moving_threshold,
+        elevation_threshold_m: opts.elevation_threshold,
+    };
+    let activity_count = activities.len();
+    let mut outcomes = Vec::with_capacity(activity_count);
+    for (i, mut activity) in activities.into_iter().enumerate() {
+        let points_read = process_activity(opts, &mut activity, filename, censor_areas)?;
+        let source = ActivitySource {
+            path: filename,
+            file_hash: &file_hash,
+            activity_index: i + 1,
+        };
+        insert_activity(database, &activity, &source, &sqlite_options)?;
+        outcomes.push(Outcome {
+            activity_index: (activity_count > 1).then_some(i + 1),
+            points_read,
+            points_written: activity.point_count(),
+            diagnostics: activity.diagnostics,
+        });
+    }
+    Ok(outcomes)
+}
+
+
+/// Reads all the input files and converts them into a single document. The output file name is
+/// derived from the first input file.
+fn process_merged<W: Write>(opts: &Opts, censor_areas: &[CensorArea], stdout: W) -> Result<Outcome, FileFailure> {
+    let mut activities = Vec::with_capacity(opts.filenames.len());
+    for filename in &opts.filenames {
+        let file_activities = isolated(|| read_input(opts, filename))
+            .map_err(|e| FileFailure::new(std::slice::from_ref(filename), e))?;
+        activities.extend(file_activities);
+    }
+    let activity = Activity::merge(activities);
+    isolated(|| output_activity(opts, activity, &opts.filenames[0], None, censor_areas, stdout))
+        .map_err(|e| FileFailure::new(&opts.filenames, e))
+}
+
+
+/// Whether the activity is output as a series of measurements without a map (see
+/// [`write_indoor_document`]): if requested using `--indoor` or if it has been recorded indoors.
+fn indoor_output(opts: &Opts, activity: &Activity) -> bool {
+    opts.output_format == OutputFormat::Geojson && (opts.indoor || is_indoor(activity))
+}
+
+
+/// Writes the document in the requested output format. `source` is the input file from which the
+/// activity has been read. JSON documents written to stdout are terminated by a newline.
+fn write_document<W: Write>(opts: &Opts, mut writer: W, activity: &Activity, name: &str, source: &Path, to_stdout: bool) -> Result<(), WalkingError> {
+    let timezone = output_timezone(opts).for_lines(&activity.lines);
+    match opts.output_format {
+        OutputFormat::Geojson if indoor_output(opts, activity) => {
+            write_indoor_document(&mut writer, activity, &document_options(opts, timezone))?;
+            if to_stdout {
+                writeln!(writer)?;
+            }
+        },
+        OutputFormat::Geojson => {
+            write_activity_document(&mut writer, activity, &document_options(opts, timezone))?;
+            if to_stdout {
+                writeln!(writer)?;
+            }
+        },
+        OutputFormat::Kml|OutputFormat::Kmz => {
+            let kml_options = KmlOptions {
+                altitude_mode: opts.altitude_mode,
+                units: opts.units,
+                timezone,
+            };
+            if opts.output_format == OutputFormat::Kmz {
+                write_kmz(&mut writer, activity, name, &kml_options)?;
+            } else {
+                write_kml(&mut writer, activity, name, &kml_options)?;
+            }
+        },
+        OutputFormat::Csv => write_csv(&mut writer, activity, opts.distance_algo, &timezone)?,
+        OutputFormat::StravaStreams => {
+            let streams_options = StreamsOptions {
+                distance_algorithm: opts.distance_algo,
+                moving_threshold_km_per_h: opts.moving_threshold,
+                compact: opts.compact,
+            };
+            write_streams(&mut writer, activity, &streams_options)?;
+            if to_stdout {
+                writeln!(writer)?;
+            }
+        },
+        OutputFormat::Html => {
+            let html_options = HtmlOptions {
+                document: document_options(opts, timezone),
+                color_by: opts.html_color_by,
+            };
+            write_html(&mut writer, activity, name, &html_options)?;
+        },
+        OutputFormat::Polyline => write_polyline(&mut writer, activity, &polyline_options(opts))?,
+        OutputFormat::Postgis => {
+            // the same path may be written as `walk.fit` or `./walk.fit`
+            let source: PathBuf = source.components()
+                .filter(|c| c != &Component::CurDir)
+                .collect();
+            write_postgis(&mut writer, activity, name, &source.to_string_lossy())?;
+        },
+        OutputFormat::Gpkg => {
+            let gpkg_options = GpkgOptions {
+                distance_algorithm: opts.distance_algo,
+            };
+            write_gpkg(&mut writer, activity, name, &gpkg_options)?;
+        },
+        OutputFormat::Geojsonl => write_feature_lines(&mut writer, activity, &document_options(opts, timezone))?,
+        OutputFormat::Trip|OutputFormat::TimestampedGeojson => {
+            let trip_options = TripOptions {
+                compact: opts.compact,
+            };
+            if opts.output_format == OutputFormat::Trip {
+                write_trips(&mut writer, activity, name, &trip_options)?;
+            } else {
+                write_playback(&mut writer, activity, name, &trip_options)?;
+            }
+            if to_stdout {
+                writeln!(writer)?;
+            }
+        },
+        OutputFormat::Fit => unreachable!("FIT files are censored without being converted into activities"),
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+
+/// Returns the offset by which the timestamps of the activity are moved: with `--strip-times`,
+/// so that recording starts at the Unix epoch (leaving only the times relative to the start);
+/// with `--shift-times`, by the given offset; with `--randomize-start`, by a random offset that
+/// is the same for every activity read from the input file.
+fn time_offset(opts: &Opts, activity: &Activity, input_filename: &Path) -> Option<TimeDelta> {
+    if opts.strip_times {
+        activity.recording_start_time()
+            .map(|start| DateTime::UNIX_EPOCH - start.to_utc())
+    } else if let Some(offset) = opts.shift_times {
+        Some(offset.0)
+    } else {
+        opts.randomize_start.then(|| random_time_offset(input_filename))
+    }
+}
+
+
+/// Returns the time zone in which timestamps are output. With `--stable`, the local time zone is
+/// replaced by UTC so that the output does not depend on the settings of the machine; with
+/// `--strip-times`, timestamps are always output in UTC, so that they start at midnight.
+fn output_timezone(opts: &Opts) -> Timezone {
+    if opts.strip_times || (opts.stable && opts.timezone == Timezone::Local) {
+        Timezone::Utc
+    } else {
+        opts.timezone.clone()
+    }
+}
+
+
+fn document_options(opts: &Opts, timezone: Timezone) -> DocumentOptions {
+    DocumentOptions {
+        viewport: opts.viewport,
+        units: opts.units,
+        lap_markers: opts.lap_markers,
+        pause_markers: opts.pause_markers,
+        splits: opts.splits,
+        series_points: opts.series.map(|n| n as usize),
+        direction_arrows_m: opts.direction_arrows,
+        stop_min_duration_s: opts.stops.map(|m| m * 60.0),
+        stop_radius_m: opts.stop_radius,
+        derived_temps: opts.derived_temps,
+        cycling_dynamics: opts.cycling_dynamics,
+        running_dynamics: opts.running_dynamics,
+        swim: opts.swim,
+        weight_kg: opts.weight_kg,
+        age_years: opts.age,
+        elevation_threshold_m: opts.elevation_threshold,
+        moving_threshold_km_per_h: opts.moving_threshold,
+        distance_algorithm: opts.distance_algo,
+        heart_rate_zones: opts.hr_zones
+            .or_else(|| opts.max_hr.map(HeartRateZones::from_max_heart_rate)),
+        trimp_heart_rates_bpm: trimp_heart_rates_bpm(opts),
+        ftp_w: opts.ftp,
+        best_effort_windows: opts.best_efforts.clone(),
+        best_effort_markers: opts.best_effort_markers,
+        coordinate_precision: opts.precision,
+        round_properties: opts.round_properties,
+        compact: opts.compact,
+        timezone,
+        points_geometry: opts.points_as,
+        feature_bbox: opts.feature_bbox,
+        coords_3d: opts.coords_3d,
+        raw_values: opts.raw_values,
+        null_missing: opts.null_missing,
+        properties: opts.properties.as_ref()
+            .map(|p| p.iter().copied().collect()),
+        color_by: opts.color_by,
+        color_scale: opts.color_scale,
+        distance_source: opts.distance_source,
+        route: opts.route.clone(),
+    }
+}
+
+
+fn summary_options(opts: &Opts) -> SummaryOptions {
+    SummaryOptions {
+        distance_algorithm: opts.distance_algo,
+        moving_threshold_km_per_h: opts.moving_threshold,
+        elevation_threshold_m: opts.elevation_threshold,
+        units: opts.units,
+        timezone: output_timezone(opts),
+        trimp_heart_rates_bpm: trimp_heart_rates_bpm(opts),
+    }
+}
+
+
+/// Returns the resting and maximum heart rates with which the training impulse is calculated, if
+/// both are given.
+fn trimp_heart_rates_bpm(opts: &Opts) -> Option<(u64, u64)> {
+    opts.rest_hr.zip(opts.max_hr)
+}
+
+
+fn polyline_options(opts: &Opts) -> PolylineOptions {
+    PolylineOptions {
+        precision: opts.polyline_precision,
+        distance_algorithm: opts.distance_algo,
+        moving_threshold_km_per_h: opts.moving_threshold,
+        elevation_threshold_m: opts.elevation_threshold,
+        units: opts.units,
+        compact: opts.compact,
+    }
+}
+
+
+/// Returns the path of the statistics file accompanying the polyline written to the given file,
+/// e.g. `walk.stats.json` for `walk.polyline` or `walk.polyline.gz`.
+fn polyline_statistics_path(opts: &Opts, output_filename: &Path) -> PathBuf {
+    let mut statistics_filename = output_filename.to_owned();
+    if opts.gzip {
+        statistics_filename.set_extension("");
+    }
+    statistics_filename.set_extension("stats.json");
+    statistics_filename
+}
+
+
+/// Filters, trims, censors and smooths the activity as requested by the options. Returns the
+/// number of points before processing; the dropped points are counted in the diagnostics of the
+/// activity.
+fn process_activity(opts: &Opts, activity: &mut Activity, input_filename: &Path, censor_areas: &[CensorArea]) -> Result<usize, WalkingError> {
+    let points_read = activity.point_count();
+
+    let mut remaining_points = points_read;
+    // some steps add points (e.g. on the boundary of the clipping box) in addition to dropping some
+    let mut count_dropped = |activity: &mut Activity, reason: DropReason, added: usize| {
+        let point_count = activity.point_count();
+        activity.diagnostics.drop_points(reason, remaining_points + added - point_count);
+        remaining_points = point_count;
+    };
+
+    if let Some(gap_threshold) = opts.gap_threshold {
+        let split_count = split_at_time_gaps(&mut activity.lines, gap_threshold);
+        log::info!("{}: split lines at {} time gaps", input_filename.display(), split_count);
+    }
+    clip_time_range(&mut activity.lines, opts.start_time, opts.end_time);
+    count_dropped(activity, DropReason::OutsideTimeRange, 0);
+    if let Some(max_speed) = opts.max_speed {
+        remove_speed_outliers(&mut activity.lines, max_speed, opts.distance_algo);
+        count_dropped(activity, DropReason::SpeedOutlier, 0);
+    }
+    if let Some(jump_threshold) = opts.jump_threshold {
+        let split_count = split_at_jumps(&mut activity.lines, jump_threshold, opts.distance_algo);
+        log::info!("{}: split lines at {} jumps", input_filename.display(), split_count);
+    }
+    if opts.swim {
+        // before censoring, so that no smoothed position ends up within a censored area
+        let derived_count = smooth_swim(&mut activity.lines, opts.smoothing, opts.distance_algo);
+        log::info!("{}: smoothed swim, derived speed of {} points from their positions", input_filename.display(), derived_count);
+    }
+    if opts.derive_speed {
+        // before trimming and censoring, which would make the speeds at their edges wrong
+        let derived_count = derive_speed(&mut activity.lines, opts.smoothing, opts.distance_algo);
+        log::info!("{}: derived speed of {} points from their positions", input_filename.display(), derived_count);
+    }
+    if let Some(dem_dir) = &opts.dem_dir {
+        Dem::new(dem_dir.clone()).correct_elevations(&mut activity.lines, opts.dem_mode)?;
+    }
+    trim_lines(&mut activity.lines, opts.trim_start, opts.trim_end, opts.distance_algo);
+    count_dropped(activity, DropReason::Trimmed, 0);
+    if let Some(bbox) = &opts.clip_bbox {
+        let boundary_count = clip_to_bbox(&mut activity.lines, bbox);
+        count_dropped(activity, DropReason::OutsideBoundingBox, boundary_count);
+    }
+    censor_lines(&mut activity.lines, censor_areas, opts.censor_mode);
+    count_dropped(activity, DropReason::Censored, 0);
+    if let Some(window) = opts.smooth_speed {
+        smooth_speed(&mut activity.lines, window, opts.smoothing);
+    }
+    if let Some(window) = opts.smooth_hr {
+        smooth_heart_rate(&mut activity.lines, window, opts.smoothing);
+    }
+    if let Some(offset) = time_offset(opts, activity, input_filename) {
+        activity.shift_times(offset);
+    }
+
+    for (reason, count) in &activity.diagnostics.dropped_points {
+        log::info!("{}: dropped {} points ({})", input_filename.display(), count, reason);
+    }
+    Ok(points_read)
+}
+
+
+/// Processes the activity and writes the resulting document to the output file derived from
+/// `input_filename` (and, for an activity of a chained FIT file, its one-based index) or, if no
+/// output files were requested, to `stdout`.
+fn output_activity<W: Write>(opts: &Opts, mut activity: Activity, input_filename: &Path, activity_index: Option<usize>, censor_areas: &[CensorArea], mut stdout: W) -> Result<Outcome, WalkingError> {
+    let points_read = process_activity(opts, &mut activity, input_filename, censor_areas)?;
+    let activity_filename = match activity_index {
+        Some(index) => activity_input_path(input_filename, index),
+        None => input_filename.to_owned(),
+    };
+
+    // fail before creating an output file that would remain empty
+    if activity.point_count() == 0 && opts.output_format.requires_points() && !indoor_output(opts, &activity) {
+        return Err(WalkingError::NoPoints);
+    }
+
+    // after censoring, so that censored places are not named
+    if let Some(geocoder) = &opts.geocoder {
+        if let Err(e) = geocode_activity(&mut activity, geocoder) {
+            log::warn!("{}: {}", activity_filename.display(), e);
         }
     }
+
+    let name = output_base(&activity_filename).file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let output_filename = output_path(opts, &activity_filename);
+    let to_stdout = output_filename.is_none();
+    if opts.output_format == OutputFormat::Polyline {
+        if let Some(f) = &output_filename {
+            let statistics_writer = BufWriter::new(File::create(polyline_statistics_path(opts, f))?);
+            write_polyline_statistics(statistics_writer, &activity, &polyline_options(opts))?;
+        }
+    }
+    let writer: Box<dyn Write + '_> = match output_filename {
+        Some(f) => Box::new(BufWriter::new(File::create(f)?)),
+        None => Box::new(&mut stdout),
+    };
+    if opts.gzip {
+        let mut gzip_writer = GzipWriter::new(writer);
+        write_document(opts, &mut gzip_writer, &activity, &name, output_base(&activity_filename), to_stdout)?;
+        gzip_writer.finish()?.flush()?;
+    } else {
+        write_document(opts, writer, &activity, &name, output_base(&activity_filename), to_stdout)?;
+    }
+    Ok(Outcome {
+        activity_index,
+        points_read,
+        points_written: activity.point_count(),
+        diagnostics: activity.diagnostics,
+    })
+}
+
+
+/// Reads and processes an input file and summarizes each of its activities.
+fn summarize_file(opts: &Opts, filename: &Path, censor_areas: &[CensorArea]) -> Result<Vec<ActivitySummary>, WalkingError> {
+    let activities = read_input(opts, filename)?;
+    let activity_count = activities.len();
+    let mut summaries = Vec::with_capacity(activity_count);
+    for (i, mut activity) in activities.into_iter().enumerate() {
+        let activity_filename = if activity_count == 1 {
+            filename.to_owned()
+        } else {
+            activity_input_path(filename, i + 1)
+        };
+        // processed under the path of the file, so that the activities share a random start offset
+        process_activity(opts, &mut activity, filename, censor_areas)?;
+        summaries.push(summarize_activity(&activity, &activity_filename.display().to_string(), &summary_options(opts)));
+    }
+    Ok(summaries)
+}
+
+
+/// Reads, merges and processes all the input files and summarizes the result.
+fn summarize_merged(opts: &Opts, censor_areas: &[CensorArea]) -> Result<ActivitySummary, FileFailure> {
+    let mut activities = Vec::with_capacity(opts.filenames.len());
+    for filename in &opts.filenames {
+        let file_activities = isolated(|| read_input(opts, filename))
+            .map_err(|e| FileFailure::new(std::slice::from_ref(filename), e))?;
+        activities.extend(file_activities);
+    }
+    let mut activity = Activity::merge(activities);
+    isolated(|| process_activity(opts, &mut activity, &opts.filenames[0], censor_areas))
+        .map_err(|e| FileFailure::new(&opts.filenames, e))?;
+    Ok(summarize_activity(&activity, &opts.filenames[0].display().to_string(), &summary_options(opts)))
+}
+
+
+/// Outputs a summary of each input file instead of the documents. If there is more than one
+/// summary, a combined total is appended.
+fn output_summaries(opts: &Opts, censor_areas: &[CensorArea]) -> ExitCode {
+    let mut summaries = Vec::new();
+    let mut failed = FailedFiles::new(opts.filenames.len(), opts.merge);
+    if opts.merge {
+        match summarize_merged(opts, censor_areas) {
+            Ok(s) => summaries.push(s),
+            Err(failure) => {
+                failed.add(failure);
+                return finish_failures(opts, &failed);
+            },
+        }
+    } else {
+        // 0 jobs = one thread per CPU
+        let pool = match rayon::ThreadPoolBuilder::new().num_threads(opts.jobs).build() {
+            Ok(p) => p,
+            Err(e) => {
+                console::error(format_args!("failed to start worker threads: {}", e));
+                return ExitCode::FAILURE;
+            },
+        };
+        console::start_progress(opts.filenames.len());
+        let results: Vec<_> = pool.install(|| {
+            opts.filenames.par_iter()
+                .map(|filename| {
+                    let result = isolated(|| summarize_file(opts, filename, censor_areas));
+                    console::finish_file(result.is_ok());
+                    result
+                })
+                .collect()
+        });
+        console::finish_progress();
+        for (filename, result) in opts.filenames.iter().zip(results) {
+            match result {
+                Ok(s) => summaries.extend(s),
+                Err(e) => failed.add(FileFailure::new(std::slice::from_ref(filename), e)),
+            }
+        }
+    }
+
+    if summaries.len() > 1 {
+        summaries.push(combine_summaries(&summaries, "total"));
+    }
+
+    let summary_options = summary_options(opts);
+    let stdout = io::stdout().lock();
+    let result = match opts.summary_format {
+        SummaryFormat::Table => write_summary_table(stdout, &summaries, &summary_options),
+        SummaryFormat::Json => write_summary_json(stdout, &summaries, &summary_options, opts.compact),
+    };
+    if let Err(e) = result {
+        console::error(format_args!("failed to write summary: {}", e));
+        return ExitCode::FAILURE;
+    }
+    finish_failures(opts, &failed)
+}
+
+
+/// Reads and processes an input file and returns the lines of each of its activities.
+fn processed_lines(opts: &Opts, filename: &Path, censor_areas: &[CensorArea]) -> Result<Vec<Vec<Vec<Point>>>, WalkingError> {
+    let activities = read_input(opts, filename)?;
+    let mut ret = Vec::with_capacity(activities.len());
+    for mut activity in activities {
+        process_activity(opts, &mut activity, filename, censor_areas)?;
+        ret.push(activity.lines);
+    }
+    Ok(ret)
+}
+
+
+/// Outputs a heatmap aggregated from all input files instead of the documents.
+fn output_heatmap(opts: &Opts, censor_areas: &[CensorArea]) -> ExitCode {
+    // 0 jobs = one thread per CPU
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(opts.jobs).build() {
+        Ok(p) => p,
+        Err(e) => {
+            console::error(format_args!("failed to start worker threads: {}", e));
+            return ExitCode::FAILURE;
+        },
+    };
+    console::start_progress(opts.filenames.len());
+    let results: Vec<_> = pool.install(|| {
+        opts.filenames.par_iter()
+            .map(|filename| {
+                let result = isolated(|| processed_lines(opts, filename, censor_areas));
+                console::finish_file(result.is_ok());
+                result
+            })
+            .collect()
+    });
+    console::finish_progress();
+
+    // add in the order of the files so that the grid does not depend on the scheduling
+    let mut heatmap = Heatmap::new(opts.heatmap_cell_size);
+    let mut failed = FailedFiles::new(opts.filenames.len(), false);
+    for (filename, result) in opts.filenames.iter().zip(results) {
+        match result {
+            Ok(activities) => {
+                for lines in activities {
+                    heatmap.add_activity(&lines);
+                }
+            },
+            Err(e) => failed.add(FileFailure::new(std::slice::from_ref(filename), e)),
+        }
+    }
+
+    let stdout = io::stdout().lock();
+    let result = match opts.heatmap_format {
+        HeatmapFormat::Geojson => write_heatmap_geojson(stdout, &heatmap, opts.compact),
+        HeatmapFormat::Csv => write_heatmap_csv(stdout, &heatmap),
+    };
+    if let Err(e) = result {
+        console::error(format_args!("failed to write heatmap: {}", e));
+        return ExitCode::FAILURE;
+    }
+    finish_failures(opts, &failed)
+}
+
+
+/// Outputs the raw messages of each input file to the output file or `stdout`.
+fn dump(opts: DumpOpts) -> ExitCode {
+    console::init(verbosity(opts.quiet, opts.verbose), opts.log_level.to_level_filter());
+
+    let filter = DumpFilter {
+        kinds: (!opts.kinds.is_empty()).then(|| opts.kinds.iter().cloned().collect()),
+        fields: (!opts.fields.is_empty()).then(|| opts.fields.iter().cloned().collect()),
+    };
+    let mut writer: Box<dyn Write> = match &opts.output {
+        Some(path) => match File::create(path) {
+            Ok(f) => Box::new(BufWriter::new(f)),
+            Err(e) => {
+                console::error(format_args!("failed to create {}: {}", path.display(), e));
+                return ExitCode::FAILURE;
+            },
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for filename in &opts.filenames {
+        let result = read_fit_records(filename)
+            .and_then(|records| {
+                let records = filter.apply(&records);
+                match opts.format {
+                    DumpFormat::JsonLines => write_records_json_lines(&mut writer, &records),
+                    DumpFormat::Table => write_records_table(&mut writer, &records),
+                }
+            });
+        if let Err(e) = result {
+            console::error(format_args!("{}: {}", filename.display(), e));
+            exit_code = ExitCode::FAILURE;
+        }
+    }
+    exit_code
+}
+
+
+/// Compares two input files as requested by the options.
+fn compare(opts: CompareOpts) -> ExitCode {
+    console::init(verbosity(opts.quiet, opts.verbose), opts.log_level.to_level_filter());
+
+    if is_stdin(&opts.first) && is_stdin(&opts.second) {
+        console::error(format_args!("standard input (-) can only be read once"));
+        return ExitCode::FAILURE;
+    }
+    let mut activities = Vec::with_capacity(2);
+    for filename in [&opts.first, &opts.second] {
+        match read_activity(filename, &ReadOptions::default()) {
+            Ok(a) => activities.push(a),
+            Err(e) => {
+                console::error(format_args!("{}: {}", filename.display(), e));
+                return ExitCode::FAILURE;
+            },
+        }
+    }
+
+    let options = CompareOptions {
+        interval_m: opts.interval,
+        tolerance_m: opts.tolerance,
+        distance_algorithm: opts.distance_algo,
+        units: opts.units,
+    };
+    let samples = compare_tracks(&activities[0].lines, &activities[1].lines, &options);
+    if !samples.iter().any(|s| s.offset_m.is_some()) {
+        log::warn!("the tracks do not meet within {} m of each other", opts.tolerance);
+    }
+
+    let writer: Box<dyn Write> = match &opts.output {
+        Some(path) => match File::create(path) {
+            Ok(f) => Box::new(BufWriter::new(f)),
+            Err(e) => {
+                console::error(format_args!("failed to create {}: {}", path.display(), e));
+                return ExitCode::FAILURE;
+            },
+        },
+        None => Box::new(io::stdout().lock()),
+    };
+    let result = match opts.format {
+        CompareFormat::Json => write_comparison_json(writer, &samples, opts.units, opts.compact),
+        CompareFormat::Csv => write_comparison_csv(writer, &samples, opts.units),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            console::error(format_args!("failed to write comparison: {}", e));
+            ExitCode::FAILURE
+        },
+    }
+}
+
+
+/// Reads the options of a profile for the `watch` subcommand.
+///
+/// A profile is a text file with command-line options, separated by whitespace; empty lines and
+/// lines starting with `#` are ignored. Quoting is not supported.
+fn profile_args(profile: &Path) -> Result<Vec<OsString>, String> {
+    let text = std::fs::read_to_string(profile)
+        .map_err(|e| format!("{}: {}", profile.display(), e))?;
+    Ok(text.lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .flat_map(|l| l.split_whitespace())
+        .map(OsString::from)
+        .collect())
+}
+
+
+/// Returns the default options of the subcommand, read from the configuration file and (for
+/// `watch`) the profile, as command-line arguments, along with the censor profiles defined in the
+/// configuration file.
+fn default_args(command: &Command) -> Result<(Vec<OsString>, BTreeMap<String, CensorProfile>), String> {
+    let opts = match command.opts() {
+        Some(o) => o,
+        None => return Ok((Vec::new(), BTreeMap::new())),
+    };
+
+    // output options of the configuration do not apply to statistics, which are always written to
+    // stdout
+    let ignored_keys: &[&str] = match command {
+        Command::Stats(_) => &["output", "output_as_files", "output_dir", "report", "heatmap"],
+        Command::Tiles(_) => &["output", "output_as_files", "report", "heatmap", "summary_only", "merge"],
+        Command::Serve(_) => &["output", "output_as_files", "output_dir", "report", "heatmap", "summary_only", "merge"],
+        _ => &[],
+    };
+
+    let mut args = Vec::new();
+    let mut censor_profiles = BTreeMap::new();
+    let config_path = match &opts.config {
+        Some(path) => Some(path.clone()),
+        None => default_config_path().filter(|p| p.is_file()),
+    };
+    if let (Some(path), false) = (config_path, opts.no_config) {
+        let (config_args, profiles) = Config::load(&path)
+            .and_then(|c| Ok((c.to_args(ignored_keys)?, c.censor_profiles()?)))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        args.extend(config_args.into_iter().map(OsString::from));
+        censor_profiles = profiles;
+    }
+    if let Command::Watch(WatchOpts { profile: Some(profile), .. }) = command {
+        args.extend(profile_args(profile)?);
+    }
+    Ok((args, censor_profiles))
 }
-impl GeoPoint for Point {
-    #[inline]
-    fn latitude(&self) -> f64 {
-        self.coordinates_deg.y()
+
+
+/// Watches the directories given as input files and converts each FIT file appearing in them once
+/// its size and modification time have remained the same between two scans (so that files are not
+/// read while they are being copied).
+///
+/// Without a state file, the files present when watching starts are not converted; with one, all
+/// files not converted previously are.
+fn watch(opts: WatchOpts) -> ExitCode {
+    let mut convert_opts = opts.convert;
+    console::init(verbosity(convert_opts.quiet, convert_opts.verbose), convert_opts.log_level.to_level_filter());
+
+    if convert_opts.merge || convert_opts.summary_only || convert_opts.heatmap || convert_opts.report.is_some() || convert_opts.error_report.is_some() {
+        console::error(format_args!("--merge, --summary-only, --heatmap, --report and --error-report cannot be used when watching"));
+        return ExitCode::FAILURE;
+    }
+    if let Some(dir) = convert_opts.filenames.iter().find(|f| !f.is_dir()) {
+        console::error(format_args!("{}: not a directory", dir.display()));
+        return ExitCode::FAILURE;
+    }
+    let censor_areas = match prepare_conversion(&mut convert_opts) {
+        Ok(ca) => ca,
+        Err(e) => {
+            // never output anything without the requested censoring
+            console::error(e);
+            return ExitCode::FAILURE;
+        },
+    };
+    let mut state = match &convert_opts.state_file {
+        Some(path) => match StateFile::load(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                console::error(format_args!("{}: {}", path.display(), e));
+                return ExitCode::FAILURE;
+            },
+        },
+        None => None,
+    };
+
+    let scan = |dirs: &[PathBuf]| -> Vec<(PathBuf, FileState)> {
+        let mut files = Vec::new();
+        for dir in dirs {
+            match list_fit_files(dir) {
+                Ok(paths) => files.extend(paths.into_iter()
+                    .filter_map(|p| FileState::of_file(&p).ok().map(|s| (p, s)))),
+                Err(e) => log::warn!("{}: failed to list directory: {}", dir.display(), e),
+            }
+        }
+        files
+    };
+
+    // the state of each file when it was last converted (or found when watching started)
+    let mut seen: HashMap<PathBuf, FileState> = HashMap::new();
+    if state.is_none() {
+        seen.extend(scan(&convert_opts.filenames));
     }
+    // the state of each new file at the previous scan
+    let mut pending: HashMap<PathBuf, FileState> = HashMap::new();
+    let poll_interval = std::time::Duration::from_secs_f64(opts.poll_interval);
+    log::info!("watching {} directories", convert_opts.filenames.len());
+    loop {
+        for (path, file_state) in scan(&convert_opts.filenames) {
+            if seen.get(&path) == Some(&file_state) {
+                continue;
+            }
+            if state.as_ref().map(|s| s.is_converted(&path)).unwrap_or(false) {
+                seen.insert(path, file_state);
+                continue;
+            }
+            if pending.get(&path) != Some(&file_state) {
+                pending.insert(path, file_state);
+                continue;
+            }
 
-    #[inline]
-    fn longitude(&self) -> f64 {
-        self.coordinates_deg.x()
+            pending.remove(&path);
+            match isolated(|| process_file(&convert_opts, &path, &censor_areas, io::stdout().lock())) {
+                Ok(_) => {
+                    log::info!("{}: converted", path.display());
+                    record_converted(&mut state, &path);
+                    if !save_state(&state) {
+                        return ExitCode::FAILURE;
+                    }
+                },
+                // not retried until the file changes
+                Err(e) => console::error(format_args!("{}: {}", path.display(), e)),
+            }
+            seen.insert(path, file_state);
+        }
+        std::thread::sleep(poll_interval);
     }
 }
 
-fn avg<T, A, J>(v1: Option<T>, v2: Option<T>, mut average: A, mut jsonify: J) -> Option<serde_json::Value>
-    where
-        A : FnMut(T, T) -> T,
-        J : FnMut(T) -> serde_json::Value,
-{
-    let avg = match (v1, v2) {
-        (None, None) => None,
-        (Some(s1), None) => Some(s1),
-        (None, Some(s2)) => Some(s2),
-        (Some(s1), Some(s2)) => Some(average(s1, s2)),
+
+/// Converts the complete messages of a FIT file that is still being recorded into the messages
+/// pushed to live clients. Data that does not (yet) contain a FIT header produces no messages.
+fn live_messages(opts: &Opts, filename: &Path, data: &[u8], censor_areas: &[CensorArea]) -> Result<Vec<String>, WalkingError> {
+    let fit_data = match complete_fit_prefix(data) {
+        Some(d) => d,
+        None => return Ok(Vec::new()),
+    };
+    let mut activity = match read_fit(&mut fit_data.as_slice(), &read_options(opts))?.into_iter().next() {
+        Some(a) => a,
+        None => return Ok(Vec::new()),
     };
-    avg.map(|v| jsonify(v))
+    process_activity(opts, &mut activity, filename, censor_areas)?;
+    let timezone = output_timezone(opts).for_lines(&activity.lines);
+    Ok(feature_messages(&activity, &document_options(opts, timezone)))
 }
 
-fn f64_avg(f1: Option<f64>, f2: Option<f64>) -> Option<serde_json::Value> {
-    avg(
-        f1, f2,
-        |a, b| (a + b)/2.0,
-        |v| serde_json::Value::Number(serde_json::Number::from_f64(v).unwrap()),
-    )
-}
 
-fn i64_avg(i1: Option<i64>, i2: Option<i64>) -> Option<serde_json::Value> {
-    avg(
-        i1, i2,
-        |a, b| (a + b)/2,
-        |v| serde_json::Value::Number(serde_json::Number::from(v)),
-    )
+/// Starts a thread appending everything read from standard input to the returned buffer.
+fn spawn_stdin_reader() -> Arc<Mutex<Vec<u8>>> {
+    let buffer: Arc<Mutex<Vec<u8>>> = Arc::default();
+    let reader_buffer = Arc::clone(&buffer);
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut chunk = [0u8; 8192];
+        loop {
+            match stdin.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => reader_buffer.lock().unwrap().extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log::warn!("failed to read standard input: {}", e);
+                    break;
+                },
+            }
+        }
+    });
+    buffer
 }
 
-fn u64_avg(i1: Option<u64>, i2: Option<u64>) -> Option<serde_json::Value> {
-    avg(
-        i1, i2,
-        |a, b| (a + b)/2,
-        |v| serde_json::Value::Number(serde_json::Number::from(v)),
-    )
-}
 
-fn time_avg(t1: Option<DateTime<Local>>, t2: Option<DateTime<Local>>) -> Option<serde_json::Value> {
-    avg(
-        t1, t2,
-        |a, b| Local.timestamp_opt((a.timestamp() + b.timestamp()) / 2, 0).unwrap(),
-        |v| serde_json::Value::String(v.format("%Y-%m-%d %H:%M:%S").to_string()),
-    )
+/// Checks that the options can be used when following a file. Features are sent as soon as their
+/// points have been recorded, so the options must not change features depending on the points
+/// recorded after them.
+fn check_live_options(opts: &Opts) -> Result<(), String> {
+    if opts.merge || opts.summary_only || opts.heatmap || opts.report.is_some() || opts.error_report.is_some() {
+        return Err("--merge, --summary-only, --heatmap, --report and --error-report cannot be used when following a file".to_owned());
+    }
+    let options_looking_ahead = [
+        ("--trim-end", opts.trim_end > 0.0),
+        ("--max-speed", opts.max_speed.is_some()),
+        ("--derive-speed", opts.derive_speed),
+        ("--swim", opts.swim),
+        ("--smooth-speed", opts.smooth_speed.is_some()),
+        ("--smooth-hr", opts.smooth_hr.is_some()),
+        ("--color-by", opts.color_by.is_some()),
+    ];
+    let used: Vec<&str> = options_looking_ahead.iter()
+        .filter(|(_, used)| *used)
+        .map(|(name, _)| *name)
+        .collect();
+    if !used.is_empty() {
+        return Err(format!(
+            "{} cannot be used when following a file, as features already sent would change with the points recorded later",
+            used.join(", "),
+        ));
+    }
+    Ok(())
 }
 
-fn lines_to_track(lines: &Vec<Vec<Point>>) -> serde_json::Value {
-    let mut features = Vec::new();
-    for line in lines {
-        let coordinates: Vec<serde_json::Value> = line
-            .iter()
-            .map(|p| serde_json::json!([p.longitude(), p.latitude()]))
-            .collect();
-        let json_line = serde_json::json!({
-            "type": "Feature",
-            "properties": {},
-            "geometry": {
-                "type": "LineString",
-                "coordinates": coordinates,
+
+/// Follows a FIT file while it is being recorded (re-reading it whenever its size or modification
+/// time changes) or a FIT stream on standard input, and pushes the features of the newly recorded
+/// part of the track to the WebSocket clients connected to the listening address. Runs until
+/// interrupted.
+fn live(opts: LiveOpts) -> ExitCode {
+    let mut convert_opts = opts.convert;
+    console::init(verbosity(convert_opts.quiet, convert_opts.verbose), convert_opts.log_level.to_level_filter());
+
+    if let Err(e) = check_live_options(&convert_opts) {
+        console::error(e);
+        return ExitCode::FAILURE;
+    }
+    let path = match convert_opts.filenames.as_slice() {
+        [p] => p.clone(),
+        _ => {
+            console::error(format_args!("exactly one FIT file (or - for standard input) can be followed"));
+            return ExitCode::FAILURE;
+        },
+    };
+    let censor_areas = match prepare_conversion(&mut convert_opts) {
+        Ok(ca) => ca,
+        Err(e) => {
+            // never output anything without the requested censoring
+            console::error(e);
+            return ExitCode::FAILURE;
+        },
+    };
+    let server = match LiveServer::bind(&opts.listen) {
+        Ok(s) => s,
+        Err(e) => {
+            console::error(format_args!("{}: {}", opts.listen, e));
+            return ExitCode::FAILURE;
+        },
+    };
+    log::info!("listening on ws://{}/", server.local_addr());
+
+    let stdin_buffer = is_stdin(&path).then(spawn_stdin_reader);
+    let mut last_length = 0;
+    let mut last_state = None;
+    let poll_interval = std::time::Duration::from_secs_f64(opts.poll_interval);
+    loop {
+        let data = match &stdin_buffer {
+            Some(buffer) => {
+                let buffer = buffer.lock().unwrap();
+                (buffer.len() != last_length).then(|| buffer.clone())
             },
-        });
-        features.push(json_line);
+            None => match FileState::of_file(&path) {
+                Ok(file_state) if last_state != Some(file_state) => {
+                    last_state = Some(file_state);
+                    match fs::read(&path) {
+                        Ok(d) => Some(d),
+                        Err(e) => {
+                            log::warn!("{}: {}", path.display(), e);
+                            None
+                        },
+                    }
+                },
+                Ok(_) => None,
+                // the file may not have been created yet
+                Err(e) => {
+                    log::debug!("{}: {}", path.display(), e);
+                    None
+                },
+            },
+        };
+
+        if let Some(data) = data {
+            last_length = data.len();
+            match isolated(|| live_messages(&convert_opts, &path, &data, &censor_areas)) {
+                Ok(messages) => match server.publish_new(&messages) {
+                    Ok(0) => {},
+                    Ok(count) => log::info!("{}: sent {} new features", path.display(), count),
+                    Err(changed) => log::warn!("{}: feature {} has changed since it was sent; not sending any more features", path.display(), changed),
+                },
+                // e.g. no position recorded yet; the next read may succeed
+                Err(e) => log::info!("{}: {}", path.display(), e),
+            }
+        }
+        std::thread::sleep(poll_interval);
     }
-    serde_json::json!({
-        "type": "FeatureCollection",
-        "features": features,
-    })
 }
 
-fn lines_to_points(lines: &Vec<Vec<Point>>) -> serde_json::Value {
-    let mut features = Vec::new();
-    let mut running_dist_m = 0.0;
-    for line in lines {
-        for i in 0..line.len()-1 {
-            let point1 = &line[i];
-            let point2 = &line[i+1];
-            let dist_m = point1.coordinates_deg.vincenty_distance(&point2.coordinates_deg)
-                .expect("distance calculation failed to converge");
-            running_dist_m += dist_m;
-
-            let mut properties = serde_json::Map::new();
-            properties.insert("running_distance".to_owned(), running_dist_m.into());
-            if let Some(spd) = f64_avg(point1.speed_km_per_h, point2.speed_km_per_h) {
-                properties.insert("speed".to_owned(), spd);
-            }
-            if let Some(ele) = f64_avg(point1.elevation_m, point2.elevation_m) {
-                properties.insert("elevation".to_owned(), ele);
-            }
-            if let Some(hr) = u64_avg(point1.heart_rate_bpm, point2.heart_rate_bpm) {
-                properties.insert("heart_rate".to_owned(), hr);
-            }
-            if let Some(cad) = u64_avg(point1.cadence_rpm, point2.cadence_rpm) {
-                properties.insert("cadence".to_owned(), cad);
-            }
-            if let Some(temp) = i64_avg(point1.temperature_degc, point2.temperature_degc) {
-                properties.insert("temperature".to_owned(), temp);
-            }
-            if let Some(time) = time_avg(point1.timestamp, point2.timestamp) {
-                properties.insert("timestamp".to_owned(), time);
-            }
 
-            let feature = serde_json::json!({
-                "type": "Feature",
-                "properties": properties,
-                "geometry": {
-                    "type": "LineString",
-                    "coordinates": [
-                        point1.as_lonlat_list(),
-                        point2.as_lonlat_list(),
-                    ],
-                },
-            });
-            features.push(feature);
+/// Converts a file for `serve`: its activities are merged into one, which is summarized and
+/// converted into a document.
+fn serve_conversion(opts: &Opts, filename: &Path, censor_areas: &[CensorArea]) -> Result<Converted, WalkingError> {
+    let mut activity = Activity::merge(read_input(opts, filename)?);
+    process_activity(opts, &mut activity, filename, censor_areas)?;
+    if activity.point_count() == 0 && !indoor_output(opts, &activity) {
+        return Err(WalkingError::NoPoints);
+    }
+
+    // after censoring, so that censored places are not named
+    if let Some(geocoder) = &opts.geocoder {
+        if let Err(e) = geocode_activity(&mut activity, geocoder) {
+            log::warn!("{}: {}", filename.display(), e);
         }
     }
 
-    serde_json::json!({
-        "type": "FeatureCollection",
-        "features": features,
-    })
+    let name = filename.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let summary_options = summary_options(opts);
+    let summary = summary_to_json(&summarize_activity(&activity, &name, &summary_options), &summary_options);
+    let mut document = Vec::new();
+    write_document(opts, &mut document, &activity, &name, filename, false)?;
+    Ok(Converted { summary, document })
 }
 
-#[inline]
-fn semicircles_to_degrees(sc: f64) -> f64 {
-    sc * 180.0 / 2.0_f64.powi(31)
-}
 
-fn semicircle_value_to_degrees(sc_val: &fitparser::Value) -> f64 {
-    if let fitparser::Value::SInt32(sc) = sc_val {
-        semicircles_to_degrees((*sc).into())
-    } else {
-        panic!("unexpected value {:?}", sc_val);
+/// Serves the FIT files in the directories given as input files over HTTP (see
+/// [`fit2walking::serve`]), converting each of them when it is first requested or has changed.
+/// Runs until interrupted.
+fn serve(opts: ServeOpts) -> ExitCode {
+    let mut convert_opts = opts.convert;
+    console::init(verbosity(convert_opts.quiet, convert_opts.verbose), convert_opts.log_level.to_level_filter());
+
+    if convert_opts.merge || convert_opts.summary_only || convert_opts.heatmap || convert_opts.report.is_some() || convert_opts.error_report.is_some()
+            || convert_opts.output.is_some() || convert_opts.output_as_files || convert_opts.output_dir.is_some() {
+        console::error(format_args!("documents are only served; --merge, --summary-only, --heatmap, --report, --error-report and the output options cannot be used"));
+        return ExitCode::FAILURE;
+    }
+    if convert_opts.output_format != OutputFormat::Geojson || convert_opts.gzip {
+        console::error(format_args!("only GeoJSON documents can be served"));
+        return ExitCode::FAILURE;
+    }
+    if let Some(dir) = convert_opts.filenames.iter().find(|f| !f.is_dir()) {
+        console::error(format_args!("{}: not a directory", dir.display()));
+        return ExitCode::FAILURE;
+    }
+    let censor_areas = match prepare_conversion(&mut convert_opts) {
+        Ok(ca) => ca,
+        Err(e) => {
+            // never output anything without the requested censoring
+            console::error(e);
+            return ExitCode::FAILURE;
+        },
+    };
+    let listener = match TcpListener::bind(&opts.listen) {
+        Ok(l) => l,
+        Err(e) => {
+            console::error(format_args!("{}: {}", opts.listen, e));
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let dirs = convert_opts.filenames.clone();
+    let converter = move |filename: &Path| isolated(|| serve_conversion(&convert_opts, filename, &censor_areas));
+    let catalog = Catalog::new(dirs, Box::new(converter));
+    let activity_count = catalog.refresh();
+    log::info!("serving {} activities on http://{}/activities", activity_count, opts.listen);
+    let server_options = ServerOptions {
+        cors_origin: opts.cors_origin,
+        max_connections: opts.max_connections,
+    };
+    match http::serve(listener, server_options, move |request| catalog.handle(request)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            console::error(format_args!("{}: {}", opts.listen, e));
+            ExitCode::FAILURE
+        },
     }
 }
 
 
-fn coord_extrema<F>(lines: &Vec<Vec<Point>>, mut coord: F) -> Option<(f64, f64)>
-    where
-        F: FnMut(&Point) -> Option<f64>,
-{
-    let min = lines.iter()
-        .flat_map(|l| l.iter())
-        .filter_map(|p| coord(p))
-        .reduce(f64::min);
-    let max = lines.iter()
-        .flat_map(|l| l.iter())
-        .filter_map(|p| coord(p))
-        .reduce(f64::max);
+/// Downloads the most recent activities of the service into the directory given as the input file
+/// and converts those not downloaded before like `convert`.
+#[cfg(feature = "fetch")]
+fn fetch(opts: FetchOpts) -> ExitCode {
+    let mut convert_opts = opts.convert;
+    console::init(verbosity(convert_opts.quiet, convert_opts.verbose), convert_opts.log_level.to_level_filter());
 
-    if let (Some(mn), Some(mx)) = (min, max) {
-        Some((mn, mx))
-    } else {
-        None
+    let dir = match convert_opts.filenames.as_slice() {
+        [d] if d.is_dir() => d.clone(),
+        _ => {
+            console::error(format_args!("exactly one directory must be given, into which the activities are downloaded"));
+            return ExitCode::FAILURE;
+        },
+    };
+    let token_path = match opts.token_file.or_else(default_token_path) {
+        Some(p) => p,
+        None => {
+            console::error(format_args!("no token file given and no configuration directory found; use --token-file"));
+            return ExitCode::FAILURE;
+        },
+    };
+    let mut tokens = match TokenFile::load(&token_path) {
+        Ok(t) => t,
+        Err(e) => {
+            console::error(format_args!("{}: {}", token_path.display(), e));
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let downloaded = valid_token(opts.service, &mut tokens)
+        .and_then(|token| fetch_activities(opts.service, &token, opts.count, &dir));
+    convert_opts.filenames = match downloaded {
+        Ok(files) => files,
+        Err(e) => {
+            console::error(e);
+            return ExitCode::FAILURE;
+        },
+    };
+    if convert_opts.filenames.is_empty() {
+        log::info!("no new activities");
+        return ExitCode::SUCCESS;
     }
+    convert(convert_opts)
 }
 
 
-fn load_censor_polygon(path: &Path) -> geo::Polygon<f64> {
-    let buf = {
-        let mut file = File::open(path)
-            .expect("failed to open polygon file");
-        let mut buf = String::new();
-        file.read_to_string(&mut buf)
-            .expect("failed to read polygon file");
-        buf
+/// Copies the FIT files not imported before from the activity folder of the Garmin device mounted
+/// at `--device` (or of all detected devices) into the archive directory given as the input file,
+/// and converts them like `convert`.
+fn import(opts: ImportOpts) -> ExitCode {
+    let mut convert_opts = opts.convert;
+    console::init(verbosity(convert_opts.quiet, convert_opts.verbose), convert_opts.log_level.to_level_filter());
+
+    let archive = match convert_opts.filenames.as_slice() {
+        [d] if d.is_dir() => d.clone(),
+        _ => {
+            console::error(format_args!("exactly one directory must be given, into which the activities are imported"));
+            return ExitCode::FAILURE;
+        },
+    };
+    let folders = match &opts.device {
+        Some(device) => match activity_folder(device) {
+            Some(f) => vec![f],
+            None => {
+                console::error(format_args!("{}: no GARMIN/Activity folder found", device.display()));
+                return ExitCode::FAILURE;
+            },
+        },
+        None => detect_activity_folders(),
     };
+    if folders.is_empty() {
+        console::error(format_args!("no mounted Garmin device found; use --device to give its mount point"));
+        return ExitCode::FAILURE;
+    }
 
-    let mut points = Vec::new();
-    for (i, mut line) in buf.split('\n').enumerate() {
-        if let Some(hash_index) = line.find('#') {
-            line = &line[..hash_index];
+    let mut imported = Vec::new();
+    for folder in &folders {
+        log::info!("importing from {}", folder.display());
+        match import_activities(folder, &archive, &convert_opts.timezone) {
+            Ok(files) => imported.extend(files),
+            Err(e) => {
+                console::error(format_args!("{}: {}", folder.display(), e));
+                return ExitCode::FAILURE;
+            },
         }
-        line = line.trim();
+    }
+    if imported.is_empty() {
+        log::info!("no new activities");
+        return ExitCode::SUCCESS;
+    }
+    convert_opts.filenames = imported;
+    convert(convert_opts)
+}
 
-        if line.len() == 0 {
-            // empty line (or comment only)
-            continue;
-        }
 
-        let coord_pieces: Vec<&str> = line.split_whitespace().collect();
-        if coord_pieces.len() != 2 {
-            panic!("line {}: {} coordinate pieces; expected 2", i+1, coord_pieces.len());
+/// Slices the lines of all input files into vector tiles written into the output directory.
+fn tiles(opts: TilesOpts) -> ExitCode {
+    let mut convert_opts = opts.convert;
+    console::init(verbosity(convert_opts.quiet, convert_opts.verbose), convert_opts.log_level.to_level_filter());
+
+    if opts.min_zoom > opts.max_zoom {
+        console::error(format_args!("--min-zoom must not be greater than --max-zoom"));
+        return ExitCode::FAILURE;
+    }
+    if convert_opts.output.is_some() || convert_opts.output_as_files || convert_opts.report.is_some()
+            || convert_opts.merge || convert_opts.summary_only || convert_opts.heatmap {
+        console::error(format_args!("tiles are always written to --output-dir; --output, --output-as-files, --report, --merge, --summary-only and --heatmap are not supported"));
+        return ExitCode::FAILURE;
+    }
+    let output_dir = match convert_opts.output_dir.clone() {
+        Some(d) => d,
+        None => {
+            console::error(format_args!("--output-dir is required for tiles"));
+            return ExitCode::FAILURE;
+        },
+    };
+
+    convert_opts.filenames = match expand_input_paths(&convert_opts.filenames) {
+        Ok(f) => f,
+        Err(e) => {
+            console::error(format_args!("failed to list input directory: {}", e));
+            return ExitCode::FAILURE;
+        },
+    };
+    if convert_opts.filenames.is_empty() {
+        console::error(format_args!("no input files"));
+        return ExitCode::FAILURE;
+    }
+    let censor_areas = match prepare_conversion(&mut convert_opts) {
+        Ok(ca) => ca,
+        Err(e) => {
+            // never output anything without the requested censoring
+            console::error(e);
+            return ExitCode::FAILURE;
+        },
+    };
+
+    // 0 jobs = one thread per CPU
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(convert_opts.jobs).build() {
+        Ok(p) => p,
+        Err(e) => {
+            console::error(format_args!("failed to start worker threads: {}", e));
+            return ExitCode::FAILURE;
+        },
+    };
+    console::start_progress(convert_opts.filenames.len());
+    let results: Vec<_> = pool.install(|| {
+        convert_opts.filenames.par_iter()
+            .map(|filename| {
+                let result = isolated(|| processed_lines(&convert_opts, filename, &censor_areas));
+                console::finish_file(result.is_ok());
+                result
+            })
+            .collect()
+    });
+    console::finish_progress();
+
+    // add in the order of the files so that the tiles do not depend on the scheduling
+    let tile_options = TileOptions {
+        min_zoom: opts.min_zoom,
+        max_zoom: opts.max_zoom,
+        units: convert_opts.units,
+    };
+    let mut tile_set = TileSet::new(tile_options);
+    let mut failed = FailedFiles::new(convert_opts.filenames.len(), false);
+    for (filename, result) in convert_opts.filenames.iter().zip(results) {
+        match result {
+            Ok(activities) => {
+                let activity_count = activities.len();
+                for (index, lines) in activities.into_iter().enumerate() {
+                    let name_path = if activity_count > 1 {
+                        activity_input_path(filename, index + 1)
+                    } else {
+                        output_base(filename).to_owned()
+                    };
+                    let name = name_path.file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    tile_set.add_activity(&name, &lines);
+                }
+            },
+            Err(e) => failed.add(FileFailure::new(std::slice::from_ref(filename), e)),
         }
+    }
 
-        let lat: f64 = match coord_pieces[0].replace("_", "").parse() {
-            Ok(l) => l,
-            Err(e) => panic!("line {}: failed to parse {:?} as a latitude: {}", i + 1, coord_pieces[0], e),
-        };
-        let lon: f64 = match coord_pieces[1].replace("_", "").parse() {
-            Ok(l) => l,
-            Err(e) => panic!("line {}: failed to parse {:?} as a longitude: {}", i + 1, coord_pieces[1], e),
+    if let Err(e) = tile_set.write_tiles(&output_dir) {
+        console::error(format_args!("{}: failed to write tiles: {}", output_dir.display(), e));
+        return ExitCode::FAILURE;
+    }
+    log::info!("wrote {} tiles to {}", tile_set.tile_count(), output_dir.display());
+    finish_failures(&convert_opts, &failed)
+}
+
+
+fn main() -> ExitCode {
+    let args = with_subcommand(std::env::args_os().collect());
+    match parse_command_line(args) {
+        Ok(command) => run(command),
+        Err(code) => code,
+    }
+}
+
+
+/// Parses the command line (including the program name), taking the defaults from the
+/// configuration file and the censor profiles into account. Errors (as well as `--help` and
+/// `--version`) are reported on the console and turned into the exit code.
+fn parse_command_line(args: Vec<OsString>) -> Result<Command, ExitCode> {
+    let mut cli = match Cli::try_parse_from(&args) {
+        Ok(c) => c,
+        Err(e) => {
+            let _ = e.print();
+            return Err(ExitCode::from(e.exit_code() as u8));
+        },
+    };
+
+    // parse again with the defaults inserted after the subcommand, so that the options given on
+    // the command line override them
+    let (defaults, censor_profiles) = match default_args(&cli.command) {
+        Ok(d) => d,
+        Err(e) => {
+            console::error(e);
+            return Err(ExitCode::FAILURE);
+        },
+    };
+    if !defaults.is_empty() {
+        let mut all_args = args;
+        all_args.splice(2..2, defaults);
+        cli = match Cli::try_parse_from(all_args) {
+            Ok(c) => c,
+            Err(e) => {
+                let _ = e.print();
+                console::error(format_args!("note: the arguments include the defaults from the configuration file or profile"));
+                return Err(ExitCode::from(2));
+            },
         };
-        points.push(geo::Coord { x: lon, y: lat });
     }
+    if let Some(opts) = cli.command.opts_mut() {
+        opts.censor_profile_definitions = censor_profiles;
+    }
+    Ok(cli.command)
+}
+
 
-    geo::Polygon::new(geo::LineString::from(points), vec![])
+/// Runs the subcommand.
+fn run(command: Command) -> ExitCode {
+    match command {
+        Command::Convert(opts) => convert(opts),
+        Command::Stats(mut opts) => {
+            if opts.output.is_some() || opts.output_as_files || opts.report.is_some() || opts.heatmap {
+                console::error(format_args!("stats are always written to stdout; --output, --output-as-files, --report and --heatmap are not supported"));
+                return ExitCode::FAILURE;
+            }
+            opts.summary_only = true;
+            convert(opts)
+        },
+        Command::Censor(opts) => {
+            if opts.censor_polygons.is_empty() && opts.censor_geojsons.is_empty() && opts.censor_circles.is_empty() && opts.censor_profiles.is_empty() {
+                console::error(format_args!("no censor areas given; use --censor-polygon, --censor-geojson, --censor-circle or --censor-profile"));
+                return ExitCode::FAILURE;
+            }
+            convert(opts)
+        },
+        Command::Merge(mut opts) => {
+            opts.merge = true;
+            convert(opts)
+        },
+        Command::Dump(opts) => dump(opts),
+        Command::Compare(opts) => compare(opts),
+        Command::Watch(opts) => watch(opts),
+        Command::Tiles(opts) => tiles(opts),
+        Command::Live(opts) => live(opts),
+        Command::Serve(opts) => serve(opts),
+        #[cfg(feature = "fetch")]
+        Command::Fetch(opts) => fetch(opts),
+        Command::Import(opts) => import(opts),
+    }
 }
 
 
-#[derive(Clone, Debug, Eq, Hash, Parser, PartialEq)]
-struct Opts {
-    #[arg(short, long)] pub events: bool,
-    #[arg(short, long)] pub no_records: bool,
-    #[arg(short = 'O', long)] pub output_as_files: bool,
-    #[arg(short, long = "censor-polygon")] pub censor_polygons: Vec<PathBuf>,
-    #[arg(required = true)] pub filenames: Vec<PathBuf>,
+/// Loads the route, geocoder and censor areas requested by the options, storing the former two in
+/// the options and returning the latter.
+fn prepare_conversion(opts: &mut Opts) -> Result<Vec<CensorArea>, String> {
+    if let Some(route_path) = &opts.match_route {
+        let route = load_route_geojson(route_path, opts.match_tolerance)
+            .map_err(|e| format!("{}: {}", route_path.display(), e))?;
+        opts.route = Some(Arc::new(route));
+    }
+
+    if let Some(geocode_source) = &opts.geocode {
+        let geocoder = Geocoder::open(geocode_source)
+            .map_err(|e| format!("{}: {}", geocode_source, e))?;
+        opts.geocoder = Some(Arc::new(geocoder));
+    }
+
+    load_censor_areas(opts)
 }
 
 
-fn main() {
-    let opts = Opts::parse();
+/// Checks the options of a conversion (after the input paths have been expanded) for combinations
+/// that cannot work, returning the message to be output if there is one.
+fn check_convert_options(opts: &Opts) -> Result<(), String> {
+    if opts.filenames.is_empty() {
+        return Err("no input files".to_owned());
+    }
 
-    for filename in &opts.filenames {
-        let mut file = File::open(filename)
-            .expect("failed to open file");
+    if opts.filenames.iter().filter(|f| is_stdin(f)).count() > 1 {
+        return Err("standard input (-) can only be read once".to_owned());
+    }
 
-        let mut lines = Vec::new();
-        let mut line = Vec::new();
+    if let Some((resting_bpm, max_bpm)) = trimp_heart_rates_bpm(opts) {
+        if resting_bpm >= max_bpm {
+            return Err("--rest-hr must be lower than --max-hr".to_owned());
+        }
+    }
 
-        let censor_polygons: Vec<geo::Polygon<f64>> = opts.censor_polygons.iter()
-            .map(|cp| load_censor_polygon(cp))
+    if opts.merge && opts.output_format == OutputFormat::Fit {
+        return Err("FIT files cannot be merged".to_owned());
+    }
+    if opts.output_format == OutputFormat::Fit && (opts.strip_times || opts.shift_times.is_some() || opts.randomize_start) {
+        return Err("the timestamps of FIT files cannot be changed; --strip-times, --shift-times and --randomize-start are not supported".to_owned());
+    }
+    if opts.output_format == OutputFormat::Fit {
+        // FIT files are only censored, so these would leave the positions they remove in the output
+        let removing_positions = [
+            ("--trim-start", opts.trim_start > 0.0),
+            ("--trim-end", opts.trim_end > 0.0),
+            ("--clip-bbox", opts.clip_bbox.is_some()),
+            ("--start-time", opts.start_time.is_some()),
+            ("--end-time", opts.end_time.is_some()),
+        ];
+        let used: Vec<&str> = removing_positions.iter()
+            .filter(|(_, used)| *used)
+            .map(|(name, _)| *name)
             .collect();
+        if !used.is_empty() {
+            return Err(format!("{} cannot be used with --output-format fit, which only removes positions within censor areas", used.join(", ")));
+        }
+    }
 
-        for record in fitparser::from_reader(&mut file).expect("failed to read file") {
-            if opts.events && (!opts.no_records || record.kind() != MesgNum::Record) {
-                eprintln!("{:?}", record.kind());
-                for field in record.fields() {
-                    eprintln!("  {}[{}] = {:?} {}", field.name(), field.number(), field.value(), field.units());
-                }
-            }
+    if opts.strip_times && matches!(opts.output_format, OutputFormat::Trip | OutputFormat::TimestampedGeojson) {
+        return Err("--output-format trip and timestamped-geojson require timestamps, which --strip-times removes".to_owned());
+    }
 
-            if record.kind() == MesgNum::Event {
-                let event_category_opt = record.fields().iter()
-                    .filter(|f| f.number() == 0)
-                    .map(|f| f.value())
-                    .nth(0);
-                let event_type_opt = record.fields().iter()
-                    .filter(|f| f.number() == 1)
-                    .map(|f| f.value())
-                    .nth(0);
-
-                if let Some(fitparser::Value::String(ec)) = event_category_opt {
-                    if ec == "timer" {
-                        if let Some(fitparser::Value::String(et)) = event_type_opt {
-                            if et == "stop_all" {
-                                // timer stopped; show this as a discontinuity in the line
-                                if line.len() > 0 {
-                                    lines.push(line);
-                                }
-                                line = Vec::new();
-                            }
-                        }
-                    }
-                }
-            }
+    if sqlite_database(opts).is_some() {
+        // activities are identified by the hash of their file
+        if opts.merge || opts.filenames.iter().any(|f| is_stdin(f)) {
+            return Err(format!("--output {}... cannot be used with --merge or standard input", SQLITE_OUTPUT_PREFIX));
+        }
+        if opts.output_format == OutputFormat::Fit {
+            return Err(format!("--output {}... cannot be used with --output-format fit", SQLITE_OUTPUT_PREFIX));
+        }
+    }
 
-            if record.kind() != MesgNum::Record {
-                continue;
-            }
+    if opts.state_file.is_some() && (opts.merge || opts.summary_only || opts.heatmap) {
+        return Err("--state-file can only be used when converting files individually".to_owned());
+    }
 
-            let lat_semicirc_opt = record.fields().iter()
-                .filter(|df| df.name() == "position_lat")
-                .nth(0);
-            let lon_semicirc_opt = record.fields().iter()
-                .filter(|df| df.name() == "position_long")
-                .nth(0);
-
-            if lat_semicirc_opt.is_none() || lon_semicirc_opt.is_none() {
-                // position recording paused (probably went indoors)
-                // store the current line and try the next point
-                if line.len() > 0 {
-                    lines.push(line);
-                    line = Vec::new();
-                }
-                continue;
+    if let Some(template) = opts.output.as_ref().filter(|_| sqlite_database(opts).is_none()) {
+        // when merging, only the first file name is used for the output
+        let output_filenames = if opts.merge { &opts.filenames[..1] } else { &opts.filenames[..] };
+        let mut output_paths = HashSet::new();
+        for filename in output_filenames {
+            let path = expand_output_template(template, output_base(filename))?;
+            if !output_paths.insert(path) {
+                return Err(format!("output template {:?} produces the same file name for multiple input files", template));
             }
+        }
+    }
 
-            let lat_semicirc = lat_semicirc_opt.unwrap();
-            let lon_semicirc = lon_semicirc_opt.unwrap();
+    Ok(())
+}
 
-            let lat_deg = semicircle_value_to_degrees(lat_semicirc.value());
-            let lon_deg = semicircle_value_to_degrees(lon_semicirc.value());
-            let coords_deg = geo::Coord { x: lon_deg, y: lat_deg };
 
-            if censor_polygons.iter().any(|cp| cp.contains(&coords_deg)) {
-                // skip this point; it is censored
-                continue;
-            }
+/// Converts the input files as requested by the options.
+fn convert(mut opts: Opts) -> ExitCode {
+    console::init(verbosity(opts.quiet, opts.verbose), opts.log_level.to_level_filter());
 
-            let mut final_timestamp = None;
-            let timestamp_field_opt = record.fields().iter()
-                .filter(|df| df.name() == "timestamp")
-                .nth(0);
-            if let Some(tsfield) = timestamp_field_opt {
-                if let fitparser::Value::Timestamp(ts) = tsfield.value() {
-                    let ts_ms = ts.timestamp_millis();
-                    let ts_f64 = (ts_ms as f64) / 1000.0;
-                    final_timestamp = Some(ts_f64);
-                }
-            }
+    opts.filenames = match expand_input_paths(&opts.filenames) {
+        Ok(f) => f,
+        Err(e) => {
+            console::error(format_args!("failed to list input directory: {}", e));
+            return ExitCode::FAILURE;
+        },
+    };
+    if let Err(e) = check_convert_options(&opts) {
+        console::error(e);
+        return ExitCode::FAILURE;
+    }
 
-            let mut final_heart_rate = None;
-            let hr_field_opt = record.fields().iter()
-                .filter(|df| df.name() == "heart_rate")
-                .nth(0);
-            if let Some(hr_field) = hr_field_opt {
-                if let fitparser::Value::UInt8(hr) = hr_field.value() {
-                    final_heart_rate = Some((*hr) as u64);
-                }
-            }
+    let censor_areas = match prepare_conversion(&mut opts) {
+        Ok(ca) => ca,
+        Err(e) => {
+            // never output anything without the requested censoring
+            console::error(e);
+            return ExitCode::FAILURE;
+        },
+    };
 
-            let mut final_altitude = None;
-            let alt_field_opt = record.fields().iter()
-                .filter(|df| df.name() == "enhanced_altitude")
-                .nth(0);
-            if let Some(alt_field) = alt_field_opt {
-                if let fitparser::Value::Float64(alt) = alt_field.value() {
-                    final_altitude = Some(*alt);
-                }
-            }
+    if opts.summary_only {
+        return output_summaries(&opts, &censor_areas);
+    }
+    if opts.heatmap {
+        return output_heatmap(&opts, &censor_areas);
+    }
 
-            let mut final_speed_km_per_h = None;
-            let speed_field_opt = record.fields().iter()
-                .filter(|df| df.name() == "enhanced_speed")
-                .nth(0);
-            if let Some(speed_field) = speed_field_opt {
-                if let fitparser::Value::Float64(speed_mpers) = speed_field.value() {
-                    let speed_km_per_h = (*speed_mpers) * 3.6;
-                    final_speed_km_per_h = Some(speed_km_per_h);
-                }
-            }
+    let mut state = match &opts.state_file {
+        Some(path) => match StateFile::load(path) {
+            Ok(s) => Some(s),
+            Err(e) => {
+                console::error(format_args!("{}: {}", path.display(), e));
+                return ExitCode::FAILURE;
+            },
+        },
+        None => None,
+    };
+    if let Some(state) = &state {
+        let total = opts.filenames.len();
+        opts.filenames.retain(|f| is_stdin(f) || !state.is_converted(f));
+        if opts.filenames.len() < total {
+            log::info!("skipping {} unchanged files converted previously", total - opts.filenames.len());
+        }
+    }
 
-            let mut final_cadence = None;
-            let cadence_field_opt = record.fields().iter()
-                .filter(|df| df.name() == "cadence")
-                .nth(0);
-            if let Some(cadence_field) = cadence_field_opt {
-                if let fitparser::Value::UInt8(cad) = cadence_field.value() {
-                    final_cadence = Some((*cad) as u64);
-                }
-            }
+    let mut report_entries = Vec::new();
+    let mut failed = FailedFiles::new(opts.filenames.len(), opts.merge);
 
-            let mut final_temperature = None;
-            let temperature_field_opt = record.fields().iter()
-                .filter(|df| df.name() == "temperature")
-                .nth(0);
-            if let Some(temperature_field) = temperature_field_opt {
-                if let fitparser::Value::SInt8(temp) = temperature_field.value() {
-                    final_temperature = Some((*temp) as i64);
-                }
-            }
+    if opts.merge {
+        let result = process_merged(&opts, &censor_areas, io::stdout().lock());
+        report_entries.extend(file_report_entries(&opts.filenames, result.as_ref().map(std::slice::from_ref).map_err(|e| e.to_string())));
+        if let Err(failure) = result {
+            failed.add(failure);
+        }
+        if !finish_report(&opts, report_entries) {
+            return ExitCode::FAILURE;
+        }
+        return finish_failures(&opts, &failed);
+    }
 
-            let mut final_time = None;
-            let time_field_opt = record.fields().iter()
-                .filter(|df| df.name() == "timestamp")
-                .nth(0);
-            if let Some(time_field) = time_field_opt {
-                if let fitparser::Value::Timestamp(ts) = time_field.value() {
-                    final_time = Some(*ts);
-                }
+    if progress_fits(&opts) {
+        console::start_progress(opts.filenames.len());
+    }
+
+    if opts.jobs == 1 {
+        for (i, filename) in opts.filenames.iter().enumerate() {
+            let result = isolated(|| process_file(&opts, filename, &censor_areas, io::stdout().lock()));
+            console::finish_file(result.is_ok());
+            report_entries.extend(file_report_entries(std::slice::from_ref(filename), result.as_deref().map_err(|e| e.to_string())));
+            match result {
+                Ok(_) => record_converted(&mut state, filename),
+                Err(e) => failed.add(FileFailure::new(std::slice::from_ref(filename), e)),
+            }
+            if (i + 1) % STATE_SAVE_INTERVAL == 0 && !save_state(&state) {
+                console::finish_progress();
+                return ExitCode::FAILURE;
             }
+        }
+        console::finish_progress();
+        if !save_state(&state) || !finish_report(&opts, report_entries) {
+            return ExitCode::FAILURE;
+        }
+        return finish_failures(&opts, &failed);
+    }
 
-            let point = Point::new(
-                geo::Point::from((lon_deg, lat_deg)),
-                final_altitude,
-                final_timestamp,
-                final_heart_rate,
-                final_speed_km_per_h,
-                final_cadence,
-                final_temperature,
-                final_time,
-            );
-            //println!("{:?}", point);
-            line.push(point);
-        }
-
-        // store final line
-        if line.len() > 0 {
-            lines.push(line);
-        }
-
-        // convert to GeoJSON
-        let track = lines_to_track(&lines);
-        let points = lines_to_points(&lines);
-
-        // find coordinate extrema (assume we never go over the 180° meridian)
-        let (min_lat, max_lat) = coord_extrema(&lines, |p| Some(p.latitude())).unwrap();
-        let (min_lon, max_lon) = coord_extrema(&lines, |p| Some(p.longitude())).unwrap();
-        let avg_lat = (min_lat + max_lat)/2.0;
-        let avg_lon = (min_lon + max_lon)/2.0;
-        let (min_ele, max_ele) = coord_extrema(&lines, |p| p.elevation_m).unwrap();
-        let (min_hr, max_hr) = coord_extrema(&lines, |p| p.heart_rate_bpm.map(|hr| hr as f64))
-            .unwrap_or((80.0, 160.0));
-        let (min_speed, max_speed) = coord_extrema(&lines, |p| p.speed_km_per_h)
-            .unwrap_or((0.0, 10.0));
-        let (min_cad, max_cad) = coord_extrema(&lines, |p| p.cadence_rpm.map(|hr| hr as f64))
-            .unwrap_or((0.0, 120.0));
-        let (min_temp, max_temp) = coord_extrema(&lines, |p| p.temperature_degc.map(|hr| hr as f64))
-            .unwrap_or((-10.0, 45.0));
-
-        let final_json = serde_json::json!({
-            "center": [avg_lat, avg_lon],
-            "zoom": 12, // FIXME: estimate this
-            "track": track,
-            "points": points,
-            "elevation_range": [min_ele, max_ele],
-            "heart_rate_range": [min_hr, max_hr],
-            "speed_range": [min_speed, max_speed],
-            "cadence_range": [min_cad, max_cad],
-            "temperature_range": [min_temp, max_temp],
-        });
-        let final_string = serde_json::to_string_pretty(&final_json).unwrap();
+    // 0 jobs = one thread per CPU
+    let pool = match rayon::ThreadPoolBuilder::new().num_threads(opts.jobs).build() {
+        Ok(p) => p,
+        Err(e) => {
+            console::error(format_args!("failed to start worker threads: {}", e));
+            return ExitCode::FAILURE;
+        },
+    };
 
-        if opts.output_as_files {
-            let mut output_filename = filename.clone();
-            output_filename.set_extension("json");
-            std::fs::write(&output_filename, &final_string).unwrap();
-        } else {
-            println!("{}", final_string);
+    // documents destined for stdout are buffered so that they can be output in the order of the
+    // input files
+    let results: Vec<_> = pool.install(|| {
+        opts.filenames.par_iter()
+            .map(|filename| {
+                let mut buffer = Vec::new();
+                let result = isolated(|| process_file(&opts, filename, &censor_areas, &mut buffer));
+                console::finish_file(result.is_ok());
+                (buffer, result)
+            })
+            .collect()
+    });
+    console::finish_progress();
+
+    let mut stdout = io::stdout().lock();
+    for (filename, (buffer, result)) in opts.filenames.iter().zip(results) {
+        if let Err(e) = stdout.write_all(&buffer) {
+            console::error(format_args!("failed to write output: {}", e));
+            return ExitCode::FAILURE;
+        }
+        report_entries.extend(file_report_entries(std::slice::from_ref(filename), result.as_deref().map_err(|e| e.to_string())));
+        match result {
+            Ok(_) => record_converted(&mut state, filename),
+            Err(e) => failed.add(FileFailure::new(std::slice::from_ref(filename), e)),
+        }
+    }
+    if !save_state(&state) || !finish_report(&opts, report_entries) {
+        return ExitCode::FAILURE;
+    }
+
+    finish_failures(&opts, &failed)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Local;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn parse(args: &[&str]) -> Result<Command, ExitCode> {
+        let mut all_args = vec![OsString::from("fit2walking")];
+        all_args.extend(args.iter().map(OsString::from));
+        parse_command_line(with_subcommand(all_args))
+    }
+
+    /// Parses the options of a conversion, ignoring the configuration file.
+    fn convert_opts(args: &[&str]) -> Opts {
+        let mut all_args = vec!["convert", "--no-config"];
+        all_args.extend_from_slice(args);
+        match parse(&all_args) {
+            Ok(Command::Convert(opts)) => opts,
+            other => panic!("{:?} did not parse as a conversion: {:?}", args, other),
+        }
+    }
+
+    #[test]
+    fn convert_is_the_default_subcommand() {
+        let walk = fixture("walk.fit");
+        let implicit = parse(&["--no-config", &walk]).unwrap();
+        let explicit = parse(&["convert", "--no-config", &walk]).unwrap();
+        assert_eq!(implicit, explicit);
+        assert!(matches!(parse(&["stats", "--no-config", &walk]), Ok(Command::Stats(_))));
+    }
+
+    #[test]
+    fn invalid_arguments_fail_parsing() {
+        // parsed directly, as parse_command_line would print the errors
+        let try_parse = |args: &[&str]| Cli::try_parse_from(with_subcommand(
+            ["fit2walking"].iter().chain(args).map(OsString::from).collect()
+        ));
+        assert!(try_parse(&["--bogus", "a.fit"]).is_err());
+        assert!(try_parse(&["--strip-times", "--randomize-start", "a.fit"]).is_err());
+        assert!(try_parse(&["--stable", "--randomize-start", "a.fit"]).is_err());
+        assert!(try_parse(&["--clip-bbox", "1,2,3", "a.fit"]).is_err());
+        assert!(try_parse(&["--rest-hr", "60", "a.fit"]).is_err());
+        assert!(try_parse(&["--clip-bbox", "1,2,3,4", "--shift-times", "-1d2h", "a.fit"]).is_ok());
+    }
+
+    #[test]
+    fn cross_origin_reading_is_opt_in_when_serving() {
+        let serve_opts = |args: &[&str]| match Cli::try_parse_from(["fit2walking", "serve"].iter().chain(args)) {
+            Ok(Cli { command: Command::Serve(opts) }) => Ok(opts),
+            Ok(other) => panic!("{:?} did not parse as serving: {:?}", args, other),
+            Err(e) => Err(e),
+        };
+        let opts = serve_opts(&["dir"]).unwrap();
+        assert_eq!(opts.cors_origin, None);
+        assert_eq!(opts.max_connections, DEFAULT_MAX_CONNECTIONS);
+
+        let opts = serve_opts(&["--cors-origin", "http://localhost:3000", "--max-connections", "2", "dir"]).unwrap();
+        assert_eq!(opts.cors_origin.as_deref(), Some("http://localhost:3000"));
+        assert_eq!(opts.max_connections, 2);
+        assert_eq!(serve_opts(&["--cors-origin", "*", "dir"]).unwrap().cors_origin.as_deref(), Some("*"));
+
+        for origin in ["", "localhost:3000", "http://localhost:3000/", "https://example.com/viewer", "http://a\r\nSet-Cookie: x=y"] {
+            assert!(serve_opts(&["--cors-origin", origin, "dir"]).is_err(), "{:?}", origin);
         }
+        assert!(serve_opts(&["--max-connections", "0", "dir"]).is_err());
+    }
+
+    #[test]
+    fn conflicting_options_are_rejected() {
+        let walk = fixture("walk.fit");
+        assert!(check_convert_options(&convert_opts(&[&walk])).is_ok());
+        assert!(check_convert_options(&convert_opts(&["-", "-"])).is_err());
+        assert!(check_convert_options(&convert_opts(&["--merge", "--output-format", "fit", &walk])).is_err());
+        assert!(check_convert_options(&convert_opts(&["--output-format", "fit", "--shift-times", "1h", &walk])).is_err());
+        assert!(check_convert_options(&convert_opts(&["--output-format", "trip", "--strip-times", &walk])).is_err());
+        assert!(check_convert_options(&convert_opts(&["--max-hr", "60", "--rest-hr", "60", &walk])).is_err());
+        assert!(check_convert_options(&convert_opts(&["--state-file", "state.json", "--merge", &walk])).is_err());
+        assert!(check_convert_options(&convert_opts(&["--output", "out.json", &walk, &fixture("chained.fit")])).is_err());
+        assert!(check_convert_options(&convert_opts(&["--output", "{stem}.json", &walk, &fixture("chained.fit")])).is_ok());
+    }
+
+    #[test]
+    fn random_start_is_drawn_once_per_file() {
+        let walk = fixture("walk.fit");
+        let opts = convert_opts(&["--randomize-start", &walk]);
+        let activity = read_activity(Path::new(&walk), &ReadOptions::default()).unwrap();
+        // e.g. when the file is read again by `live` or `serve`
+        let offset = time_offset(&opts, &activity, Path::new(&walk));
+        assert!(offset.is_some());
+        assert_eq!(time_offset(&opts, &activity, Path::new(&walk)), offset);
+    }
+
+    #[test]
+    fn random_start_is_shared_by_chained_activities() {
+        let chained = fixture("chained.fit");
+        let start_times = |opts: &Opts| -> Vec<DateTime<Local>> {
+            summarize_file(opts, Path::new(&chained), &[]).unwrap()
+                .iter()
+                .map(|s| s.start_time.unwrap())
+                .collect()
+        };
+        let original = start_times(&convert_opts(&[&chained]));
+        let shifted = start_times(&convert_opts(&["--randomize-start", &chained]));
+        assert!(original.len() > 1);
+        let offset = shifted[0] - original[0];
+        assert!(shifted.iter().zip(&original).all(|(s, o)| *s - *o == offset));
+    }
+
+    #[test]
+    fn options_changing_sent_features_are_rejected_when_following() {
+        let walk = fixture("walk.fit");
+        assert!(check_live_options(&convert_opts(&["--gap-threshold", "60", "--jump-threshold", "100", &walk])).is_ok());
+        for args in [&["--trim-end", "100"][..], &["--max-speed", "20"], &["--derive-speed"], &["--swim"], &["--smooth-speed", "5"], &["--smooth-hr", "5"], &["--color-by", "hr"]] {
+            let mut all_args = args.to_vec();
+            all_args.push(&walk);
+            assert!(check_live_options(&convert_opts(&all_args)).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn features_sent_when_following_do_not_change() {
+        let walk = fixture("walk.fit");
+        let mut data = std::fs::read(&walk).unwrap();
+        data[4..8].copy_from_slice(&[0; 4]);
+        // short thresholds, so the lines are split many times
+        let opts = convert_opts(&["--gap-threshold", "2", "--jump-threshold", "5", &walk]);
+        let all_messages = live_messages(&opts, Path::new(&walk), &data, &[]).unwrap();
+        for length in (1..8).map(|i| data.len() * i / 8) {
+            let messages = live_messages(&opts, Path::new(&walk), &data[..length], &[]).unwrap();
+            assert!(messages.len() <= all_messages.len());
+            assert_eq!(messages[..], all_messages[..messages.len()], "{} bytes", length);
+        }
+    }
+
+    #[test]
+    fn compressed_output_files_end_in_gz() {
+        let walk = fixture("walk.fit");
+        let output_filename = |args: &[&str]| output_path(&convert_opts(args), Path::new(&walk))
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned());
+        assert_eq!(output_filename(&["--gzip", "-O", &walk]).as_deref(), Some("walk.json.gz"));
+        assert_eq!(output_filename(&["--gzip", "-o", "{stem}.json", &walk]).as_deref(), Some("walk.json.gz"));
+        assert_eq!(output_filename(&["--gzip", "-o", "{stem}.json.gz", &walk]).as_deref(), Some("walk.json.gz"));
+        assert_eq!(output_filename(&["-o", "{stem}.json", &walk]).as_deref(), Some("walk.json"));
+        assert_eq!(output_filename(&["--gzip", &walk]), None);
+    }
+
+    #[test]
+    fn postgis_rows_are_replaced_by_input_path() {
+        let walk = fixture("walk.fit");
+        let opts = convert_opts(&["--output-format", "postgis", &walk]);
+        let activity = read_activity(Path::new(&walk), &ReadOptions::default()).unwrap();
+        let sql = |source: &str| {
+            let mut document = Vec::new();
+            write_document(&opts, &mut document, &activity, "walk", Path::new(source), false).unwrap();
+            String::from_utf8(document).unwrap()
+        };
+        assert!(sql("tracks/walk.fit").contains("DELETE FROM walking_points WHERE source = 'tracks/walk.fit';"));
+        assert_eq!(sql("./tracks/walk.fit"), sql("tracks/walk.fit"));
+        assert_ne!(sql("other/walk.fit"), sql("tracks/walk.fit"));
+    }
+
+    #[test]
+    fn output_templates_are_expanded() {
+        let input = Path::new("tracks/walk.fit");
+        assert_eq!(expand_output_template("{stem}.json", input).unwrap(), PathBuf::from("walk.json"));
+        assert_eq!(expand_output_template("{dir}/{name}.{{x}}", input).unwrap(), PathBuf::from("tracks/walk.fit.{x}"));
+        assert!(expand_output_template("{unknown}", input).is_err());
+    }
+
+    #[test]
+    fn file_is_converted_into_the_writer() {
+        let opts = convert_opts(&["--stable", &fixture("walk.fit")]);
+        let mut output = Vec::new();
+        let outcomes = process_file(&opts, &opts.filenames[0], &[], &mut output).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].points_written, outcomes[0].points_read);
+
+        // one segment between each pair of consecutive points within each line
+        let document: Value = serde_json::from_slice(&output).unwrap();
+        let line_count = document["track"]["features"].as_array().unwrap().len();
+        let segment_count = document["points"]["features"].as_array().unwrap().len();
+        assert_eq!(segment_count, outcomes[0].points_written - line_count);
+    }
+
+    #[test]
+    fn each_chained_activity_is_converted() {
+        let opts = convert_opts(&["--stable", "--compact", &fixture("chained.fit")]);
+        let mut output = Vec::new();
+        let outcomes = process_file(&opts, &opts.filenames[0], &[], &mut output).unwrap();
+        let activity_indexes: Vec<Option<usize>> = outcomes.iter().map(|o| o.activity_index).collect();
+        assert_eq!(activity_indexes, [Some(1), Some(2)]);
+        assert_eq!(output.split(|b| *b == b'\n').filter(|l| !l.is_empty()).count(), 2);
+    }
+
+    #[test]
+    fn clipping_and_censoring_are_counted() {
+        let opts = convert_opts(&[
+            "--clip-bbox", "16.3745,48.2085,16.3755,48.2095", "--censor-circle", "48.2090,16.3750,20",
+            &fixture("walk.fit"),
+        ]);
+        let censor_areas = load_censor_areas(&opts).unwrap();
+        let mut output = Vec::new();
+        let outcome = process_file(&opts, &opts.filenames[0], &censor_areas, &mut output).unwrap().remove(0);
+        let dropped: usize = outcome.diagnostics.dropped_points.values().sum();
+        assert!(outcome.diagnostics.dropped_points.contains_key(&DropReason::OutsideBoundingBox));
+        assert!(outcome.diagnostics.dropped_points.contains_key(&DropReason::Censored));
+        // the points interpolated onto the boundary of the box are written in addition
+        assert!(outcome.points_written + dropped > outcome.points_read);
+    }
+
+    #[test]
+    fn failures_are_isolated_and_summarized() {
+        let opts = convert_opts(&[&fixture("walk.fit"), &fixture("missing.fit")]);
+        let mut failed = FailedFiles::new(opts.filenames.len(), false);
+        for filename in &opts.filenames {
+            if let Err(e) = isolated(|| process_file(&opts, filename, &[], io::sink())) {
+                failed.add(FileFailure::new(std::slice::from_ref(filename), e));
+            }
+        }
+        assert_eq!(failed.exit_code(), ExitCode::from(EXIT_SOME_FILES_FAILED));
+        let report = failed.to_json();
+        assert_eq!(report["files_failed"], 1);
+        assert_eq!(report["failures"][0]["kind"], "io");
+
+        let result: Result<(), WalkingError> = isolated(|| panic!("bug"));
+        assert_eq!(result.unwrap_err().kind(), "internal");
+        failed.merged = true;
+        assert_eq!(failed.exit_code(), ExitCode::from(EXIT_ALL_FILES_FAILED));
     }
 }
@@ -0,0 +1,116 @@
+//! Splitting multisport activities (such as triathlons) into their legs.
+//!
+//! Devices record each leg of a multisport activity as a session of its own, usually with a session
+//! of the sport `transition` between two legs. An activity is considered multisport if the sport
+//! changes between its (non-transition) sessions.
+
+
+use chrono::{DateTime, Local};
+
+use crate::activity::{Activity, Summary};
+use crate::point::Point;
+
+
+/// The sport of the sessions recorded between the legs of a multisport activity.
+pub const TRANSITION_SPORT: &str = "transition";
+
+
+/// One leg of a multisport activity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leg {
+    /// The session recorded for the leg.
+    pub session: Summary,
+
+    /// The parts of the track recorded during the leg.
+    pub lines: Vec<Vec<Point>>,
+}
+
+
+/// The interval between two consecutive legs, from the end of one to the start of the next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    /// The index of the leg before the transition; the leg after it has the following index.
+    pub leg_index: usize,
+
+    pub start_time: DateTime<Local>,
+    pub end_time: DateTime<Local>,
+}
+impl Transition {
+    /// The duration of the transition, in seconds.
+    pub fn duration_s(&self) -> f64 {
+        (self.end_time - self.start_time).num_milliseconds() as f64 / 1000.0
+    }
+}
+
+
+/// The legs of a multisport activity and the transitions between them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Legs {
+    pub legs: Vec<Leg>,
+    pub transitions: Vec<Transition>,
+}
+
+
+fn is_transition(session: &Summary) -> bool {
+    session.sport.as_deref() == Some(TRANSITION_SPORT)
+}
+
+
+/// Returns the points of the lines with timestamps between `start_time` and `end_time` (both
+/// inclusive). Consecutive points of a line stay together; points without timestamps are skipped.
+fn lines_between(lines: &[Vec<Point>], start_time: DateTime<Local>, end_time: DateTime<Local>) -> Vec<Vec<Point>> {
+    let mut ret = Vec::new();
+    for line in lines {
+        let mut current = Vec::new();
+        for point in line {
+            match point.timestamp {
+                Some(t) if t >= start_time && t <= end_time => current.push(point.clone()),
+                _ => {
+                    if !current.is_empty() {
+                        ret.push(std::mem::take(&mut current));
+                    }
+                },
+            }
+        }
+        if !current.is_empty() {
+            ret.push(current);
+        }
+    }
+    ret
+}
+
+
+/// Splits the activity into its legs if it is a multisport activity, i.e. if it has sessions of
+/// at least two different sports (not counting transitions). Each session with start and end times
+/// becomes a leg containing the points recorded during it; the transitions span the time between
+/// the end of one leg and the start of the next, including any transition sessions.
+///
+/// Returns `None` if the activity is not a multisport activity.
+pub fn split_legs(activity: &Activity) -> Option<Legs> {
+    let sessions: Vec<(&Summary, DateTime<Local>, DateTime<Local>)> = activity.sessions.iter()
+        .filter(|s| !is_transition(s))
+        .filter_map(|s| Some((s, s.start_time?, s.end_time?)))
+        .collect();
+    let first_sport = sessions.first()?.0.sport.as_ref();
+    if sessions.iter().all(|(s, _, _)| s.sport.as_ref() == first_sport) {
+        return None;
+    }
+
+    let mut legs = Legs::default();
+    for (session, start_time, end_time) in sessions {
+        if let Some(previous_end) = legs.legs.last().and_then(|l| l.session.end_time) {
+            if previous_end < start_time {
+                legs.transitions.push(Transition {
+                    leg_index: legs.legs.len() - 1,
+                    start_time: previous_end,
+                    end_time: start_time,
+                });
+            }
+        }
+        legs.legs.push(Leg {
+            session: session.clone(),
+            lines: lines_between(&activity.lines, start_time, end_time),
+        });
+    }
+    Some(legs)
+}
@@ -0,0 +1,456 @@
+//! Slicing of activities into Mapbox Vector Tiles (`z/x/y.pbf`), so that large collections of
+//! activities can be displayed quickly by MapLibre and similar map libraries.
+//!
+//! Each tile contains a `track` layer with the parts of the lines within it and a `points` layer
+//! with the points within it. At lower zoom levels, points falling onto the same position within a
+//! tile are only output once.
+
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::error::WalkingError;
+use crate::point::{GeoPoint, Point};
+use crate::units::Units;
+
+
+/// The default lowest zoom level for which tiles are generated.
+pub const DEFAULT_MIN_ZOOM: u8 = 8;
+
+/// The default highest zoom level for which tiles are generated; map libraries scale the tiles of
+/// this level up for higher ones.
+pub const DEFAULT_MAX_ZOOM: u8 = 16;
+
+/// The highest supported zoom level.
+pub const MAX_SUPPORTED_ZOOM: u8 = 22;
+
+/// The number of coordinate units along the edge of a tile.
+const EXTENT: i64 = 4096;
+
+/// The distance by which the geometries extend beyond the edges of a tile, in coordinate units, so
+/// that lines are not cut off visibly at the edges.
+const BUFFER: i64 = 64;
+
+/// The latitude beyond which the Web Mercator projection is cut off.
+const MAX_LATITUDE_DEG: f64 = 85.051_128_78;
+
+/// Geometry types of features.
+const GEOMETRY_TYPE_POINT: u64 = 1;
+const GEOMETRY_TYPE_LINE_STRING: u64 = 2;
+
+/// Geometry commands.
+const COMMAND_MOVE_TO: u32 = 1;
+const COMMAND_LINE_TO: u32 = 2;
+
+/// The version of the vector tile specification.
+const MVT_VERSION: u64 = 2;
+
+
+/// A position within a tile, in coordinate units from its top left corner.
+type TilePosition = (i64, i64);
+
+
+/// Options influencing the generated tiles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileOptions {
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+
+    /// The units in which the values of the points are output.
+    pub units: Units,
+}
+impl Default for TileOptions {
+    fn default() -> Self {
+        Self {
+            min_zoom: DEFAULT_MIN_ZOOM,
+            max_zoom: DEFAULT_MAX_ZOOM,
+            units: Units::default(),
+        }
+    }
+}
+
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_varint(buf, u64::from(field) << 3);
+    write_varint(buf, value);
+}
+
+fn write_bytes_field(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_varint(buf, (u64::from(field) << 3) | 2);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_double_field(buf: &mut Vec<u8>, field: u32, value: f64) {
+    write_varint(buf, (u64::from(field) << 3) | 1);
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+
+/// A property value of a feature, hashable so that equal values are stored once per layer.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+enum TileValue {
+    String(String),
+
+    /// A floating-point value, by its bits.
+    Double(u64),
+    Uint(u64),
+}
+impl TileValue {
+    fn double(value: f64) -> Self {
+        Self::Double(value.to_bits())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            Self::String(s) => write_bytes_field(&mut buf, 1, s.as_bytes()),
+            Self::Double(bits) => write_double_field(&mut buf, 3, f64::from_bits(*bits)),
+            Self::Uint(u) => write_varint_field(&mut buf, 5, *u),
+        }
+        buf
+    }
+}
+
+
+/// A layer of a tile under construction.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct LayerBuilder {
+    keys: Vec<String>,
+    key_indexes: HashMap<String, u32>,
+    values: Vec<TileValue>,
+    value_indexes: HashMap<TileValue, u32>,
+
+    /// The encoded features.
+    features: Vec<Vec<u8>>,
+
+    /// The positions of the points added to the layer, to skip duplicates.
+    point_positions: HashSet<TilePosition>,
+}
+impl LayerBuilder {
+    fn tags(&mut self, properties: &[(&str, TileValue)]) -> Vec<u8> {
+        let mut tags = Vec::new();
+        for (key, value) in properties {
+            let key_count = self.keys.len() as u32;
+            let key_index = *self.key_indexes.entry((*key).to_owned()).or_insert(key_count);
+            if key_index == key_count {
+                self.keys.push((*key).to_owned());
+            }
+            let value_count = self.values.len() as u32;
+            let value_index = *self.value_indexes.entry(value.clone()).or_insert(value_count);
+            if value_index == value_count {
+                self.values.push(value.clone());
+            }
+            write_varint(&mut tags, key_index.into());
+            write_varint(&mut tags, value_index.into());
+        }
+        tags
+    }
+
+    /// Adds a feature with the given geometry type and encoded geometry.
+    fn add_feature(&mut self, geometry_type: u64, geometry: &[u8], properties: &[(&str, TileValue)]) {
+        let mut feature = Vec::new();
+        write_bytes_field(&mut feature, 2, &self.tags(properties));
+        write_varint_field(&mut feature, 3, geometry_type);
+        write_bytes_field(&mut feature, 4, geometry);
+        self.features.push(feature);
+    }
+
+    fn encode(&self, name: &str) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint_field(&mut buf, 15, MVT_VERSION);
+        write_bytes_field(&mut buf, 1, name.as_bytes());
+        for feature in &self.features {
+            write_bytes_field(&mut buf, 2, feature);
+        }
+        for key in &self.keys {
+            write_bytes_field(&mut buf, 3, key.as_bytes());
+        }
+        for value in &self.values {
+            write_bytes_field(&mut buf, 4, &value.encode());
+        }
+        write_varint_field(&mut buf, 5, EXTENT as u64);
+        buf
+    }
+}
+
+
+/// The layers of a tile under construction.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct TileBuilder {
+    track: LayerBuilder,
+    points: LayerBuilder,
+}
+impl TileBuilder {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (name, layer) in [("track", &self.track), ("points", &self.points)] {
+            if !layer.features.is_empty() {
+                write_bytes_field(&mut buf, 3, &layer.encode(name));
+            }
+        }
+        buf
+    }
+}
+
+
+/// Encodes a geometry command with its repeat count.
+fn command(id: u32, count: usize) -> u64 {
+    u64::from(id & 0x7) | ((count as u64) << 3)
+}
+
+
+/// Encodes the commands of a geometry consisting of the given parts; each part with more than one
+/// position becomes a line, each part with one position a point.
+fn encode_geometry(parts: &[Vec<TilePosition>]) -> Vec<u8> {
+    let mut geometry = Vec::new();
+    let mut cursor = (0, 0);
+    for part in parts {
+        for (i, &(x, y)) in part.iter().enumerate() {
+            if i == 0 {
+                write_varint(&mut geometry, command(COMMAND_MOVE_TO, 1));
+            } else if i == 1 {
+                write_varint(&mut geometry, command(COMMAND_LINE_TO, part.len() - 1));
+            }
+            write_varint(&mut geometry, zigzag(x - cursor.0));
+            write_varint(&mut geometry, zigzag(y - cursor.1));
+            cursor = (x, y);
+        }
+    }
+    geometry
+}
+
+
+/// Projects a point into Web Mercator world coordinates at the given zoom level, in tile
+/// coordinate units (so that the tile with column `x` spans `x * EXTENT` to `(x + 1) * EXTENT`).
+fn project(point: &Point, zoom: u8) -> (f64, f64) {
+    let scale = (EXTENT << zoom) as f64;
+    let lat_rad = point.latitude().clamp(-MAX_LATITUDE_DEG, MAX_LATITUDE_DEG).to_radians();
+    let x = (point.longitude() + 180.0) / 360.0;
+    let y = (1.0 - (lat_rad.tan() + 1.0 / lat_rad.cos()).ln() / std::f64::consts::PI) / 2.0;
+    (x * scale, y * scale)
+}
+
+
+/// Clips the segment to the given box using the Liang-Barsky algorithm. Returns the clipped
+/// segment, or `None` if the segment lies outside the box.
+fn clip_segment(p1: (f64, f64), p2: (f64, f64), min: (f64, f64), max: (f64, f64)) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (p2.0 - p1.0, p2.1 - p1.1);
+    let mut t0: f64 = 0.0;
+    let mut t1: f64 = 1.0;
+    for (p, q) in [(-dx, p1.0 - min.0), (dx, max.0 - p1.0), (-dy, p1.1 - min.1), (dy, max.1 - p1.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+    (t0 <= t1).then_some((
+        (p1.0 + t0 * dx, p1.1 + t0 * dy),
+        (p1.0 + t1 * dx, p1.1 + t1 * dy),
+    ))
+}
+
+
+/// Returns the range of tile indexes whose buffered extent contains the given world coordinates.
+fn tile_range(min: f64, max: f64, zoom: u8) -> std::ops::RangeInclusive<i64> {
+    let last = (1_i64 << zoom) - 1;
+    let first_tile = ((min - BUFFER as f64) / EXTENT as f64).floor() as i64;
+    let last_tile = ((max + BUFFER as f64) / EXTENT as f64).floor() as i64;
+    first_tile.clamp(0, last)..=last_tile.clamp(0, last)
+}
+
+
+/// A set of vector tiles to which activities are added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TileSet {
+    options: TileOptions,
+    tiles: BTreeMap<(u8, i64, i64), TileBuilder>,
+
+    /// The bounds of all points added (west, south, east, north).
+    bounds: Option<[f64; 4]>,
+}
+impl TileSet {
+    pub fn new(options: TileOptions) -> Self {
+        Self {
+            options,
+            tiles: BTreeMap::new(),
+            bounds: None,
+        }
+    }
+
+    /// Returns the number of tiles containing at least one feature.
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    /// Adds the lines of an activity with the given name to the tiles of all zoom levels.
+    pub fn add_activity(&mut self, name: &str, lines: &[Vec<Point>]) {
+        for point in lines.iter().flatten() {
+            let (lon, lat) = (point.longitude(), point.latitude());
+            self.bounds = Some(match self.bounds {
+                None => [lon, lat, lon, lat],
+                Some([west, south, east, north]) => [west.min(lon), south.min(lat), east.max(lon), north.max(lat)],
+            });
+        }
+        for zoom in self.options.min_zoom..=self.options.max_zoom {
+            for (line_index, line) in lines.iter().enumerate() {
+                self.add_line(name, line_index, line, zoom);
+                self.add_points(name, line_index, line, zoom);
+            }
+        }
+    }
+
+    fn add_line(&mut self, name: &str, line_index: usize, line: &[Point], zoom: u8) {
+        let projected: Vec<(f64, f64)> = line.iter()
+            .map(|p| project(p, zoom))
+            .collect();
+
+        // the parts of the line within each tile
+        let mut tile_parts: BTreeMap<(i64, i64), Vec<Vec<TilePosition>>> = BTreeMap::new();
+        for segment in projected.windows(2) {
+            let (p1, p2) = (segment[0], segment[1]);
+            for tile_y in tile_range(p1.1.min(p2.1), p1.1.max(p2.1), zoom) {
+                for tile_x in tile_range(p1.0.min(p2.0), p1.0.max(p2.0), zoom) {
+                    let origin = ((tile_x * EXTENT) as f64, (tile_y * EXTENT) as f64);
+                    let min = (origin.0 - BUFFER as f64, origin.1 - BUFFER as f64);
+                    let max = (origin.0 + (EXTENT + BUFFER) as f64, origin.1 + (EXTENT + BUFFER) as f64);
+                    let (c1, c2) = match clip_segment(p1, p2, min, max) {
+                        Some(c) => c,
+                        None => continue,
+                    };
+                    let local = |c: (f64, f64)| ((c.0 - origin.0).round() as i64, (c.1 - origin.1).round() as i64);
+                    let (l1, l2) = (local(c1), local(c2));
+
+                    let parts = tile_parts.entry((tile_x, tile_y)).or_default();
+                    match parts.last_mut() {
+                        Some(part) if part.last() == Some(&l1) => {
+                            if l2 != l1 {
+                                part.push(l2);
+                            }
+                        },
+                        _ => parts.push(vec![l1, l2]),
+                    }
+                }
+            }
+        }
+
+        for ((tile_x, tile_y), mut parts) in tile_parts {
+            for part in &mut parts {
+                part.dedup();
+            }
+            parts.retain(|p| p.len() >= 2);
+            if parts.is_empty() {
+                continue;
+            }
+            let properties = [
+                ("activity", TileValue::String(name.to_owned())),
+                ("line", TileValue::Uint(line_index as u64)),
+            ];
+            self.tiles.entry((zoom, tile_x, tile_y))
+                .or_default()
+                .track
+                .add_feature(GEOMETRY_TYPE_LINE_STRING, &encode_geometry(&parts), &properties);
+        }
+    }
+
+    fn add_points(&mut self, name: &str, line_index: usize, line: &[Point], zoom: u8) {
+        let units = self.options.units;
+        let tile_count = 1_i64 << zoom;
+        for point in line {
+            let (x, y) = project(point, zoom);
+            let tile_x = ((x / EXTENT as f64).floor() as i64).clamp(0, tile_count - 1);
+            let tile_y = ((y / EXTENT as f64).floor() as i64).clamp(0, tile_count - 1);
+            let local = (
+                (x - (tile_x * EXTENT) as f64).round() as i64,
+                (y - (tile_y * EXTENT) as f64).round() as i64,
+            );
+            let layer = &mut self.tiles.entry((zoom, tile_x, tile_y)).or_default().points;
+            if !layer.point_positions.insert(local) {
+                continue;
+            }
+
+            let mut properties = vec![
+                ("activity", TileValue::String(name.to_owned())),
+                ("line", TileValue::Uint(line_index as u64)),
+            ];
+            if let Some(ele) = point.elevation_m {
+                properties.push(("elevation", TileValue::double(units.elevation(ele))));
+            }
+            if let Some(hr) = point.heart_rate_bpm {
+                properties.push(("heart_rate", TileValue::Uint(hr)));
+            }
+            if let Some(spd) = point.speed_km_per_h {
+                properties.push(("speed", TileValue::double(units.speed(spd))));
+            }
+            if let Some(ts) = point.unix_timestamp {
+                properties.push(("timestamp", TileValue::double(ts)));
+            }
+            layer.add_feature(GEOMETRY_TYPE_POINT, &encode_geometry(&[vec![local]]), &properties);
+        }
+    }
+
+    /// Writes the tiles into the directory as `z/x/y.pbf`, along with a `metadata.json` file
+    /// describing the tile set in the manner of TileJSON.
+    pub fn write_tiles(&self, dir: &Path) -> Result<(), WalkingError> {
+        for ((zoom, x, y), tile) in &self.tiles {
+            let tile_dir = dir.join(zoom.to_string()).join(x.to_string());
+            fs::create_dir_all(&tile_dir)?;
+            fs::write(tile_dir.join(format!("{}.pbf", y)), tile.encode())?;
+        }
+
+        let bounds = self.bounds.unwrap_or([-180.0, -MAX_LATITUDE_DEG, 180.0, MAX_LATITUDE_DEG]);
+        let center_zoom = self.options.min_zoom.max(self.options.max_zoom.saturating_sub(2));
+        let metadata = json!({
+            "tilejson": "3.0.0",
+            "format": "pbf",
+            "minzoom": self.options.min_zoom,
+            "maxzoom": self.options.max_zoom,
+            "bounds": bounds,
+            "center": [(bounds[0] + bounds[2]) / 2.0, (bounds[1] + bounds[3]) / 2.0, center_zoom],
+            "tiles": ["{z}/{x}/{y}.pbf"],
+            "vector_layers": [
+                {
+                    "id": "track",
+                    "fields": {"activity": "String", "line": "Number"},
+                },
+                {
+                    "id": "points",
+                    "fields": {
+                        "activity": "String", "line": "Number", "elevation": "Number",
+                        "heart_rate": "Number", "speed": "Number", "timestamp": "Number",
+                    },
+                },
+            ],
+        });
+        fs::create_dir_all(dir)?;
+        let mut writer = std::io::BufWriter::new(fs::File::create(dir.join("metadata.json"))?);
+        serde_json::to_writer_pretty(&mut writer, &metadata)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
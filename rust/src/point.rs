@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use geo::{Bearing, Distance, Geodesic, Haversine};
+use geo::vincenty_distance::VincentyDistance;
+
+
+/// A type with geographic coordinates in degrees.
+pub trait GeoPoint {
+    fn latitude(&self) -> f64;
+    fn longitude(&self) -> f64;
+    fn as_lonlat_list(&self) -> serde_json::Value {
+        serde_json::json!([
+            self.longitude(),
+            self.latitude(),
+        ])
+    }
+}
+
+
+/// The method by which distances between points are calculated.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum DistanceAlgorithm {
+    /// Vincenty's formulae on the WGS84 ellipsoid. Falls back to haversine for the rare pairs of
+    /// points for which the iteration does not converge.
+    #[default]
+    Vincenty,
+
+    /// Great-circle distance on a spherical earth; fast but up to 0.5% off.
+    Haversine,
+
+    /// Karney's geodesic algorithm on the WGS84 ellipsoid; accurate and always converges.
+    Geodesic,
+}
+impl DistanceAlgorithm {
+    /// Calculates the distance between the two points (in degrees) in meters.
+    pub fn distance_m(&self, p1: geo::Point<f64>, p2: geo::Point<f64>) -> f64 {
+        match self {
+            Self::Vincenty => p1.vincenty_distance(&p2)
+                .unwrap_or_else(|_| Haversine::distance(p1, p2)),
+            Self::Haversine => Haversine::distance(p1, p2),
+            Self::Geodesic => Geodesic::distance(p1, p2),
+        }
+    }
+}
+
+
+/// A single recorded point of a track, along with the measurements taken at it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+    pub coordinates_deg: geo::Point<f64>,
+    pub elevation_m: Option<f64>,
+    pub unix_timestamp: Option<f64>,
+    pub heart_rate_bpm: Option<u64>,
+    pub speed_km_per_h: Option<f64>,
+    pub cadence_rpm: Option<u64>,
+    pub temperature_degc: Option<i64>,
+    pub power_w: Option<u64>,
+    pub respiration_rate_per_min: Option<f64>,
+    pub spo2_percent: Option<u64>,
+    pub timestamp: Option<DateTime<Local>>,
+
+    /// The share of the power contributed by the left leg, in percent.
+    pub left_balance_percent: Option<f64>,
+    pub left_torque_effectiveness_percent: Option<f64>,
+    pub right_torque_effectiveness_percent: Option<f64>,
+    pub left_pedal_smoothness_percent: Option<f64>,
+    pub right_pedal_smoothness_percent: Option<f64>,
+
+    /// The pedal smoothness of pedals or power meters that do not distinguish between the legs.
+    pub combined_pedal_smoothness_percent: Option<f64>,
+
+    /// The time for which the foot is on the ground during each step, in milliseconds.
+    pub stance_time_ms: Option<f64>,
+    pub vertical_oscillation_mm: Option<f64>,
+    pub step_length_mm: Option<f64>,
+
+    /// The cumulative distance recorded by the device (e.g. using a wheel sensor), in meters.
+    pub device_distance_m: Option<f64>,
+
+    /// Numeric values of developer fields (e.g. from Connect IQ apps), keyed by field name.
+    pub developer_fields: BTreeMap<String, f64>,
+
+    /// Whether the segment leading to this point bridges points that have been censored.
+    pub follows_censored_gap: bool,
+
+    /// The heart rate as recorded, if `heart_rate_bpm` has been smoothed.
+    pub raw_heart_rate_bpm: Option<u64>,
+
+    /// The speed as recorded, if `speed_km_per_h` has been smoothed.
+    pub raw_speed_km_per_h: Option<f64>,
+}
+impl Point {
+    pub fn new(coordinates_deg: geo::Point<f64>) -> Self {
+        Self {
+            coordinates_deg,
+            elevation_m: None,
+            unix_timestamp: None,
+            heart_rate_bpm: None,
+            speed_km_per_h: None,
+            cadence_rpm: None,
+            temperature_degc: None,
+            power_w: None,
+            respiration_rate_per_min: None,
+            spo2_percent: None,
+            timestamp: None,
+            left_balance_percent: None,
+            left_torque_effectiveness_percent: None,
+            right_torque_effectiveness_percent: None,
+            left_pedal_smoothness_percent: None,
+            right_pedal_smoothness_percent: None,
+            combined_pedal_smoothness_percent: None,
+            stance_time_ms: None,
+            vertical_oscillation_mm: None,
+            step_length_mm: None,
+            device_distance_m: None,
+            developer_fields: BTreeMap::new(),
+            follows_censored_gap: false,
+            raw_heart_rate_bpm: None,
+            raw_speed_km_per_h: None,
+        }
+    }
+
+    /// Calculates the distance to the other point in meters using the given algorithm.
+    pub fn distance_m(&self, other: &Point, algorithm: DistanceAlgorithm) -> f64 {
+        algorithm.distance_m(self.coordinates_deg, other.coordinates_deg)
+    }
+
+    /// Calculates the initial bearing of the geodesic towards the other point, in degrees clockwise
+    /// from north (0 to 360). Returns `None` if both points have the same coordinates.
+    pub fn bearing_deg(&self, other: &Point) -> Option<f64> {
+        if self.coordinates_deg == other.coordinates_deg {
+            return None;
+        }
+        Some(Geodesic::bearing(self.coordinates_deg, other.coordinates_deg).rem_euclid(360.0))
+    }
+
+    /// Calculates the apparent temperature in °C, taking the speed of travel as the speed of the
+    /// wind.
+    ///
+    /// At or below 10 °C and above 4.8 km/h, this is the wind chill index used by the weather
+    /// services of Canada and the US. Otherwise, the air temperature is returned unchanged; in
+    /// particular, the heat index cannot be calculated as the devices do not record the humidity.
+    /// Returns `None` if the point has no temperature.
+    pub fn apparent_temperature_degc(&self) -> Option<f64> {
+        let temp_degc = self.temperature_degc? as f64;
+        match self.speed_km_per_h {
+            Some(speed) if temp_degc <= 10.0 && speed > 4.8 => {
+                let speed_factor = speed.powf(0.16);
+                Some(13.12 + 0.6215 * temp_degc - 11.37 * speed_factor + 0.3965 * temp_degc * speed_factor)
+            },
+            _ => Some(temp_degc),
+        }
+    }
+}
+impl GeoPoint for Point {
+    #[inline]
+    fn latitude(&self) -> f64 {
+        self.coordinates_deg.y()
+    }
+
+    #[inline]
+    fn longitude(&self) -> f64 {
+        self.coordinates_deg.x()
+    }
+}
@@ -0,0 +1,129 @@
+//! Export of the track as a Google Encoded Polyline, the compact representation of a path expected
+//! by many routing and map embedding APIs, along with a small JSON document of summary statistics.
+
+
+use std::io::Write;
+
+use serde_json::{Map, Value};
+
+use crate::activity::Activity;
+use crate::elevation::{DEFAULT_ELEVATION_THRESHOLD_M, elevation_gain_loss};
+use crate::error::WalkingError;
+use crate::geojson::write_json;
+use crate::point::{DistanceAlgorithm, GeoPoint};
+use crate::stats::{compute_statistics, DEFAULT_MOVING_THRESHOLD_KM_PER_H, statistics_to_json};
+use crate::units::Units;
+
+
+/// The default number of decimal places of the encoded coordinates.
+pub const DEFAULT_POLYLINE_PRECISION: u32 = 5;
+
+
+/// Options influencing the encoded polyline and its statistics.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PolylineOptions {
+    /// The number of decimal places of the encoded coordinates; 5 is the original Google format,
+    /// 6 is used e.g. by OSRM and Valhalla.
+    pub precision: u32,
+
+    /// The method by which distances between points are calculated.
+    pub distance_algorithm: DistanceAlgorithm,
+
+    /// The speed below which a point does not count as moving, in km/h.
+    pub moving_threshold_km_per_h: f64,
+
+    /// The minimum elevation change counted towards elevation gain or loss, in meters.
+    pub elevation_threshold_m: f64,
+
+    /// The units in which the statistics are output.
+    pub units: Units,
+
+    /// Whether to output minified instead of pretty-printed JSON statistics.
+    pub compact: bool,
+}
+impl Default for PolylineOptions {
+    fn default() -> Self {
+        Self {
+            precision: DEFAULT_POLYLINE_PRECISION,
+            distance_algorithm: DistanceAlgorithm::default(),
+            moving_threshold_km_per_h: DEFAULT_MOVING_THRESHOLD_KM_PER_H,
+            elevation_threshold_m: DEFAULT_ELEVATION_THRESHOLD_M,
+            units: Units::default(),
+            compact: false,
+        }
+    }
+}
+
+
+/// Appends the encoding of the difference between two consecutive coordinate values.
+fn encode_value(delta: i64, encoded: &mut String) {
+    let mut value = if delta < 0 { !(delta << 1) } else { delta << 1 };
+    while value >= 0x20 {
+        encoded.push(char::from((0x20 | (value & 0x1f)) as u8 + 63));
+        value >>= 5;
+    }
+    encoded.push(char::from(value as u8 + 63));
+}
+
+
+/// Encodes a sequence of (latitude, longitude) pairs as a Google Encoded Polyline with the given
+/// number of decimal places.
+pub fn encode_polyline<I: IntoIterator<Item = (f64, f64)>>(coordinates: I, precision: u32) -> String {
+    let factor = 10f64.powi(precision as i32);
+    let mut encoded = String::new();
+    let mut previous = (0i64, 0i64);
+    for (lat, lon) in coordinates {
+        let current = ((lat * factor).round() as i64, (lon * factor).round() as i64);
+        encode_value(current.0 - previous.0, &mut encoded);
+        encode_value(current.1 - previous.1, &mut encoded);
+        previous = current;
+    }
+    encoded
+}
+
+
+/// Encodes all points of the activity as a single polyline. Consecutive lines are joined by a
+/// straight connection, as the format has no notion of discontinuities.
+pub fn activity_to_polyline(activity: &Activity, options: &PolylineOptions) -> String {
+    let coordinates = activity.lines.iter()
+        .flatten()
+        .map(|p| (p.latitude(), p.longitude()));
+    encode_polyline(coordinates, options.precision)
+}
+
+
+/// Returns the summary statistics accompanying the polyline of the activity.
+pub fn polyline_statistics(activity: &Activity, options: &PolylineOptions) -> Map<String, Value> {
+    let stats = compute_statistics(&activity.lines, options.moving_threshold_km_per_h, options.distance_algorithm);
+    let (elevation_gain, elevation_loss) = elevation_gain_loss(&activity.lines, options.elevation_threshold_m);
+
+    let mut ret = Map::new();
+    ret.insert("precision".to_owned(), options.precision.into());
+    ret.insert("point_count".to_owned(), activity.point_count().into());
+    ret.insert("elevation_gain".to_owned(), options.units.elevation(elevation_gain).into());
+    ret.insert("elevation_loss".to_owned(), options.units.elevation(elevation_loss).into());
+    ret.insert("stats".to_owned(), statistics_to_json(&stats, options.units).into());
+    ret.insert("units".to_owned(), options.units.names_to_json().into());
+    ret
+}
+
+
+/// Writes the encoded polyline of the activity, followed by a newline.
+pub fn write_polyline<W: Write>(mut writer: W, activity: &Activity, options: &PolylineOptions) -> Result<(), WalkingError> {
+    writeln!(writer, "{}", activity_to_polyline(activity, options))?;
+    writer.flush()?;
+    Ok(())
+}
+
+
+/// Writes the summary statistics accompanying the polyline of the activity as JSON
+/// (pretty-printed unless `options.compact` is set).
+pub fn write_polyline_statistics<W: Write>(writer: W, activity: &Activity, options: &PolylineOptions) -> Result<(), WalkingError> {
+    let mut statistics = Value::Object(polyline_statistics(activity, options));
+    statistics.sort_all_objects();
+    if options.compact {
+        write_json(&statistics, writer, serde_json::ser::CompactFormatter)
+    } else {
+        write_json(&statistics, writer, serde_json::ser::PrettyFormatter::new())
+    }
+}
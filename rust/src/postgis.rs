@@ -0,0 +1,123 @@
+//! Export of activities as SQL statements for PostGIS, e.g. `psql -d gis -f walk.sql`.
+//!
+//! The lines of the track are stored as `LINESTRING ZM` geometries, whose Z coordinate is the
+//! elevation (0 if unknown) and whose M coordinate is the Unix timestamp (0 if unknown), so that
+//! GIS tools can interpolate heights and times along the track. The points are stored as
+//! `POINT ZM` geometries along with their measurements. All geometries are in WGS 84 (SRID 4326).
+
+
+use std::io::Write;
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::point::{GeoPoint, Point};
+use crate::sql::{ROWS_PER_STATEMENT, sql_optional_f64, sql_optional_integer, sql_string, sql_timestamp};
+
+
+/// The tables receiving the activities. Each row carries the name of the activity (the stem of the
+/// input file name) and the path of the input file, which identifies the rows to replace when the
+/// activity is exported again; the name alone would mix up files of the same name in different
+/// directories.
+const SCHEMA: &str = "\
+CREATE EXTENSION IF NOT EXISTS postgis;
+CREATE TABLE IF NOT EXISTS walking_tracks (
+    id SERIAL PRIMARY KEY,
+    activity TEXT NOT NULL,
+    source TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    start_time TIMESTAMPTZ,
+    end_time TIMESTAMPTZ,
+    geom GEOMETRY(LINESTRINGZM, 4326) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS walking_tracks_geom_idx ON walking_tracks USING GIST (geom);
+CREATE TABLE IF NOT EXISTS walking_points (
+    id SERIAL PRIMARY KEY,
+    activity TEXT NOT NULL,
+    source TEXT NOT NULL,
+    line INTEGER NOT NULL,
+    seq INTEGER NOT NULL,
+    timestamp TIMESTAMPTZ,
+    elevation DOUBLE PRECISION,
+    heart_rate INTEGER,
+    speed DOUBLE PRECISION,
+    cadence INTEGER,
+    temperature INTEGER,
+    power INTEGER,
+    geom GEOMETRY(POINTZM, 4326) NOT NULL
+);
+CREATE INDEX IF NOT EXISTS walking_points_geom_idx ON walking_points USING GIST (geom);
+";
+
+
+/// Formats the coordinates of a point as the longitude, latitude, Z and M of WKT.
+fn wkt_coordinates(point: &Point) -> String {
+    format!(
+        "{} {} {} {}",
+        point.longitude(),
+        point.latitude(),
+        point.elevation_m.filter(|e| e.is_finite()).unwrap_or(0.0),
+        point.unix_timestamp.filter(|t| t.is_finite()).unwrap_or(0.0),
+    )
+}
+
+
+/// Writes the SQL statements creating the tables (if necessary) and replacing the activity read from
+/// the given source path, within a transaction. Lines with fewer than two points are only exported
+/// as points.
+pub fn write_postgis<W: Write>(mut writer: W, activity: &Activity, name: &str, source: &str) -> Result<(), WalkingError> {
+    let activity_name = sql_string(name);
+    let source = sql_string(source);
+    write!(writer, "{}", SCHEMA)?;
+    writeln!(writer, "BEGIN;")?;
+    writeln!(writer, "DELETE FROM walking_tracks WHERE source = {};", source)?;
+    writeln!(writer, "DELETE FROM walking_points WHERE source = {};", source)?;
+
+    for (line_index, line) in activity.lines.iter().enumerate() {
+        if line.len() < 2 {
+            continue;
+        }
+        let coordinates: Vec<String> = line.iter()
+            .map(wkt_coordinates)
+            .collect();
+        let start_time = line.iter().find_map(|p| p.timestamp);
+        let end_time = line.iter().rev().find_map(|p| p.timestamp);
+        writeln!(
+            writer,
+            "INSERT INTO walking_tracks (activity, source, line, start_time, end_time, geom) VALUES ({}, {}, {}, {}, {}, ST_GeomFromText('LINESTRING ZM ({})', 4326));",
+            activity_name, source, line_index,
+            sql_timestamp(start_time.as_ref()), sql_timestamp(end_time.as_ref()),
+            coordinates.join(", "),
+        )?;
+    }
+
+    let point_rows: Vec<String> = activity.lines.iter()
+        .enumerate()
+        .flat_map(|(line_index, line)| line.iter().map(move |p| (line_index, p)))
+        .enumerate()
+        .map(|(seq, (line_index, p))| format!(
+            "({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, ST_GeomFromText('POINT ZM ({})', 4326))",
+            activity_name,
+            source,
+            line_index,
+            seq,
+            sql_timestamp(p.timestamp.as_ref()),
+            sql_optional_f64(p.elevation_m),
+            sql_optional_integer(p.heart_rate_bpm),
+            sql_optional_f64(p.speed_km_per_h),
+            sql_optional_integer(p.cadence_rpm),
+            sql_optional_integer(p.temperature_degc),
+            sql_optional_integer(p.power_w),
+            wkt_coordinates(p),
+        ))
+        .collect();
+    for chunk in point_rows.chunks(ROWS_PER_STATEMENT) {
+        writeln!(
+            writer,
+            "INSERT INTO walking_points (activity, source, line, seq, timestamp, elevation, heart_rate, speed, cadence, temperature, power, geom) VALUES {};",
+            chunk.join(", "),
+        )?;
+    }
+
+    writeln!(writer, "COMMIT;")?;
+    Ok(())
+}
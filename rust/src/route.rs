@@ -0,0 +1,226 @@
+//! Matching of recorded tracks against reference routes, to find out whether (and how quickly) a
+//! route has been covered.
+
+
+use std::fs::File;
+use std::path::Path;
+
+use chrono::{DateTime, Local};
+
+use crate::censor::geojson_position;
+use crate::error::WalkingError;
+use crate::point::{DistanceAlgorithm, GeoPoint, Point};
+
+
+/// The default maximum distance between the track and the route at which the route counts as
+/// covered, in meters.
+pub const DEFAULT_MATCH_TOLERANCE_M: f64 = 25.0;
+
+/// The approximate length of a degree of latitude, in meters.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+
+/// A reference route against which tracks are matched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Route {
+    /// The parts of the route, in order.
+    pub line_strings: Vec<geo::LineString<f64>>,
+
+    /// The maximum distance between the track and the route at which the route counts as covered,
+    /// in meters.
+    pub tolerance_m: f64,
+}
+
+
+/// The result of matching a track against a route.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RouteMatch {
+    /// The length of the route, in meters.
+    pub route_length_m: f64,
+
+    /// The length of the parts of the route that the track passes within the tolerance, in
+    /// meters.
+    pub covered_length_m: f64,
+
+    /// The timestamp of the track point at which the start of the route was passed.
+    pub start_time: Option<DateTime<Local>>,
+
+    /// The timestamp of the track point at which the end of the route was subsequently passed.
+    pub end_time: Option<DateTime<Local>>,
+}
+impl RouteMatch {
+    /// The percentage of the route covered by the track.
+    pub fn coverage_percent(&self) -> f64 {
+        if self.route_length_m > 0.0 {
+            100.0 * self.covered_length_m / self.route_length_m
+        } else {
+            0.0
+        }
+    }
+
+    /// The time taken from the start to the end of the route, in seconds, if the track passes both.
+    pub fn elapsed_time_s(&self) -> Option<f64> {
+        match (self.start_time, self.end_time) {
+            (Some(start), Some(end)) => Some((end - start).num_milliseconds() as f64 / 1000.0),
+            _ => None,
+        }
+    }
+}
+
+
+/// Collects the line strings from a GeoJSON object, descending into features, feature collections
+/// and geometry collections. Geometries other than line strings are ignored.
+fn collect_geojson_line_strings(object: &serde_json::Value, line_strings: &mut Vec<geo::LineString<f64>>) -> Result<(), WalkingError> {
+    let object_type = object.get("type")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| WalkingError::InvalidGeoJson("object without type".to_owned()))?;
+    let positions_to_line_string = |positions: &serde_json::Value| -> Result<geo::LineString<f64>, WalkingError> {
+        let positions = positions.as_array()
+            .ok_or_else(|| WalkingError::InvalidGeoJson("line string coordinates are not an array".to_owned()))?;
+        let coords: Vec<geo::Coord<f64>> = positions.iter()
+            .map(geojson_position)
+            .collect::<Result<_, _>>()?;
+        Ok(geo::LineString::from(coords))
+    };
+    match object_type {
+        "FeatureCollection" => {
+            let features = object.get("features")
+                .and_then(|f| f.as_array())
+                .ok_or_else(|| WalkingError::InvalidGeoJson("FeatureCollection without features".to_owned()))?;
+            for feature in features {
+                collect_geojson_line_strings(feature, line_strings)?;
+            }
+        },
+        "Feature" => {
+            match object.get("geometry") {
+                None|Some(serde_json::Value::Null) => {},
+                Some(geometry) => collect_geojson_line_strings(geometry, line_strings)?,
+            }
+        },
+        "GeometryCollection" => {
+            let geometries = object.get("geometries")
+                .and_then(|g| g.as_array())
+                .ok_or_else(|| WalkingError::InvalidGeoJson("GeometryCollection without geometries".to_owned()))?;
+            for geometry in geometries {
+                collect_geojson_line_strings(geometry, line_strings)?;
+            }
+        },
+        "LineString" => {
+            let coordinates = object.get("coordinates")
+                .ok_or_else(|| WalkingError::InvalidGeoJson("LineString without coordinates".to_owned()))?;
+            line_strings.push(positions_to_line_string(coordinates)?);
+        },
+        "MultiLineString" => {
+            let coordinates = object.get("coordinates")
+                .and_then(|c| c.as_array())
+                .ok_or_else(|| WalkingError::InvalidGeoJson("MultiLineString without coordinates".to_owned()))?;
+            for line_string_coordinates in coordinates {
+                line_strings.push(positions_to_line_string(line_string_coordinates)?);
+            }
+        },
+        _ => {},
+    }
+    Ok(())
+}
+
+
+/// Loads a reference route from a GeoJSON file. All LineString and MultiLineString geometries
+/// (including those within features and collections) are taken as consecutive parts of the route.
+pub fn load_route_geojson(path: &Path, tolerance_m: f64) -> Result<Route, WalkingError> {
+    let file = File::open(path)?;
+    let document: serde_json::Value = serde_json::from_reader(std::io::BufReader::new(file))?;
+    let mut line_strings = Vec::new();
+    collect_geojson_line_strings(&document, &mut line_strings)?;
+    line_strings.retain(|ls| !ls.0.is_empty());
+    if line_strings.is_empty() {
+        return Err(WalkingError::InvalidGeoJson("route contains no line strings".to_owned()));
+    }
+    Ok(Route {
+        line_strings,
+        tolerance_m,
+    })
+}
+
+
+/// Returns whether the point is within the tolerance of the position.
+fn is_near(point: &Point, position: geo::Point<f64>, tolerance_m: f64, distance_algorithm: DistanceAlgorithm) -> bool {
+    // cheap rejection of far-away points before calculating the distance
+    let tolerance_lat_deg = tolerance_m / METERS_PER_DEGREE;
+    let tolerance_lon_deg = tolerance_lat_deg / position.y().to_radians().cos().max(0.01);
+    if (point.latitude() - position.y()).abs() > tolerance_lat_deg * 1.01
+            || (point.longitude() - position.x()).abs() > tolerance_lon_deg * 1.01 {
+        return false;
+    }
+    distance_algorithm.distance_m(point.coordinates_deg, position) <= tolerance_m
+}
+
+
+/// Returns the index of the point closest to the position within the first run of consecutive
+/// points (starting at `from_index`) that are within the tolerance of the position.
+fn closest_in_first_pass(points: &[&Point], from_index: usize, position: geo::Point<f64>, tolerance_m: f64, distance_algorithm: DistanceAlgorithm) -> Option<usize> {
+    let first_index = (from_index..points.len())
+        .find(|i| is_near(points[*i], position, tolerance_m, distance_algorithm))?;
+    (first_index..points.len())
+        .take_while(|i| is_near(points[*i], position, tolerance_m, distance_algorithm))
+        .min_by(|a, b| {
+            let distance_a = distance_algorithm.distance_m(points[*a].coordinates_deg, position);
+            let distance_b = distance_algorithm.distance_m(points[*b].coordinates_deg, position);
+            distance_a.total_cmp(&distance_b)
+        })
+}
+
+
+/// Matches the lines of a track against the route.
+///
+/// The route is sampled at intervals of half the tolerance; a sample counts as covered if any
+/// point of the track is within the tolerance of it. The timing starts at the track point closest
+/// to the start of the route during the first pass and ends at the track point closest to the end
+/// of the route during the first subsequent pass (after leaving the vicinity of the start, so that
+/// round trips are timed completely).
+pub fn match_route(lines: &[Vec<Point>], route: &Route, distance_algorithm: DistanceAlgorithm) -> RouteMatch {
+    let points: Vec<&Point> = lines.iter()
+        .flatten()
+        .collect();
+    let mut route_match = RouteMatch::default();
+
+    let sample_interval_m = (route.tolerance_m / 2.0).max(1.0);
+    for line_string in &route.line_strings {
+        for segment in line_string.lines() {
+            let (start, end) = (geo::Point::from(segment.start), geo::Point::from(segment.end));
+            let segment_length_m = distance_algorithm.distance_m(start, end);
+            route_match.route_length_m += segment_length_m;
+
+            let sample_count = (segment_length_m / sample_interval_m).ceil().max(1.0) as usize;
+            let sample_length_m = segment_length_m / sample_count as f64;
+            for k in 0..sample_count {
+                let fraction = (k as f64 + 0.5) / sample_count as f64;
+                let sample = geo::Point::new(
+                    start.x() + fraction * (end.x() - start.x()),
+                    start.y() + fraction * (end.y() - start.y()),
+                );
+                if points.iter().any(|p| is_near(p, sample, route.tolerance_m, distance_algorithm)) {
+                    route_match.covered_length_m += sample_length_m;
+                }
+            }
+        }
+    }
+
+    let route_start = route.line_strings.first().and_then(|ls| ls.0.first());
+    let route_end = route.line_strings.last().and_then(|ls| ls.0.last());
+    if let (Some(route_start), Some(route_end)) = (route_start, route_end) {
+        let start_index_opt = closest_in_first_pass(&points, 0, (*route_start).into(), route.tolerance_m, distance_algorithm);
+        if let Some(start_index) = start_index_opt {
+            route_match.start_time = points[start_index].timestamp;
+            let mut search_index = start_index + 1;
+            while search_index < points.len() && is_near(points[search_index], (*route_start).into(), route.tolerance_m, distance_algorithm) {
+                search_index += 1;
+            }
+            let end_index_opt = closest_in_first_pass(&points, search_index, (*route_end).into(), route.tolerance_m, distance_algorithm);
+            if let Some(end_index) = end_index_opt {
+                route_match.end_time = points[end_index].timestamp;
+            }
+        }
+    }
+
+    route_match
+}
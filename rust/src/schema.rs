@@ -0,0 +1,539 @@
+//! The typed structure of the JSON documents output for the map viewer, and the version of their
+//! format.
+//!
+//! Every document has a `format_version` member containing [`FORMAT_VERSION`]. Members may be added
+//! without a new version, but the version is incremented whenever a member is removed or renamed
+//! or the meaning or unit of a value changes, so that consumers can check whether they understand a
+//! document.
+//!
+//! The members of a map document are:
+//!
+//! * `format_version`
+//! * `center` (as latitude and longitude) and `zoom`: the initial view of the map
+//! * `track`: a GeoJSON FeatureCollection with one LineString per line of the track
+//! * `points`: a GeoJSON FeatureCollection with one feature per segment (or point) and its
+//!   measurements as properties
+//! * `*_range`: the minimum and maximum of each measurement, for color scales
+//! * `elevation_gain`, `elevation_loss`, `stats`: totals over the whole track
+//! * `units`: the units of distances, elevations, speeds, paces, temperatures and vertical speeds
+//! * `laps`, `session`, `metadata`, `pauses`: what the device recorded (documents of bare lines
+//!   lack these)
+//! * optionally, depending on the options: `legs`, `transitions`, `lap_markers`, `pause_markers`,
+//!   `stops`, `splits`, `best_efforts`, `direction_arrows`, `route_match`, `series`
+//!
+//! Documents of indoor activities have `"indoor": true` and a `samples` series instead of the map
+//! members (see [`crate::indoor`]).
+//!
+//! All JSON objects are output with their members sorted by key: the types of this module are
+//! converted using [`to_json`], which sorts them, before being written.
+
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+
+/// The version of the format of the output documents.
+pub const FORMAT_VERSION: u64 = 1;
+
+
+/// Converts a value into JSON, with the members of all objects sorted by key.
+pub fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+    let mut json = serde_json::to_value(value)
+        .expect("failed to convert value to JSON");
+    json.sort_all_objects();
+    json
+}
+
+
+/// Converts a value that is serialized as a JSON object (such as the structs of this module) into
+/// a map of its members, to which further members can be added.
+pub fn to_json_object<T: Serialize>(value: &T) -> serde_json::Map<String, serde_json::Value> {
+    match to_json(value) {
+        serde_json::Value::Object(map) => map,
+        _ => panic!("value is not serialized as a JSON object"),
+    }
+}
+
+
+/// The geometry of a GeoJSON feature; coordinates are `[longitude, latitude]` pairs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", content = "coordinates")]
+pub enum Geometry {
+    Point(serde_json::Value),
+    LineString(Vec<serde_json::Value>),
+}
+
+
+/// A GeoJSON feature. Its properties are a [`SegmentProperties`] or [`PointProperties`] in the
+/// `points` collection and a JSON object elsewhere.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub struct Feature<P = serde_json::Map<String, serde_json::Value>> {
+    pub geometry: Geometry,
+    pub properties: P,
+
+    /// The bounding box of the feature (`[west, south, east, north]`, or `[west, south, lowest,
+    /// east, north, highest]` with elevations), if it is output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<Vec<f64>>,
+}
+
+
+/// A temperature; it is output as an integer if it is in degrees Celsius as recorded by the device,
+/// and as a fraction if it has been converted into another unit.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Temperature {
+    Recorded(i64),
+    Converted(f64),
+}
+
+
+/// The properties of a feature of the `points` collection with segment geometry: the averaged
+/// measurements of both points of the segment, in the units of the document. Values that are not
+/// known or not requested are omitted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SegmentProperties {
+    /// The values of the developer fields by their names. They are serialized first, so that
+    /// built-in properties of the same name replace them when the properties are converted into a
+    /// JSON object.
+    #[serde(flatten)]
+    pub developer_fields: BTreeMap<String, f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_distance: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_distance_computed: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_distance_device: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment_length: Option<f64>,
+
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment_duration: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pace: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swim_pace: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gap_pace: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elevation: Option<f64>,
+
+    /// In percent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gradient: Option<f64>,
+
+    /// In elevation units per hour.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertical_speed: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heart_rate: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_raw: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heart_rate_raw: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub respiration_rate: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spo2: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hr_zone: Option<usize>,
+
+    /// The cadence; swims have a `stroke_rate` instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cadence: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke_rate: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<Temperature>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apparent_temperature: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<u64>,
+
+    #[serde(flatten)]
+    pub cycling_dynamics: CyclingDynamicsProperties,
+
+    #[serde(flatten)]
+    pub running_dynamics: RunningDynamicsProperties,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+
+    /// In degrees clockwise from north.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bearing: Option<f64>,
+
+    /// The class of the value by which the features are colored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<usize>,
+
+    /// The color of the class, as `#rrggbb`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+
+    /// Whether positions have been censored between the points of the segment.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub censored: bool,
+}
+
+
+/// The properties of a feature of the `points` collection with point geometry: the measurements of
+/// the point as recorded, in the units of the document. Values that are not known or not requested
+/// are omitted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct PointProperties {
+    /// The values of the developer fields by their names. They are serialized first, so that
+    /// built-in properties of the same name replace them when the properties are converted into a
+    /// JSON object.
+    #[serde(flatten)]
+    pub developer_fields: BTreeMap<String, f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_distance: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_distance_computed: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub running_distance_device: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pace: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swim_pace: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elevation: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heart_rate: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_raw: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub heart_rate_raw: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub respiration_rate: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spo2: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hr_zone: Option<usize>,
+
+    /// The cadence; swims have a `stroke_rate` instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cadence: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke_rate: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<Temperature>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apparent_temperature: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub power: Option<u64>,
+
+    #[serde(flatten)]
+    pub cycling_dynamics: CyclingDynamicsProperties,
+
+    #[serde(flatten)]
+    pub running_dynamics: RunningDynamicsProperties,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+
+    /// The class of the value by which the features are colored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<usize>,
+
+    /// The color of the class, as `#rrggbb`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+
+/// The cycling dynamics of a feature of the `points` collection, in percent.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct CyclingDynamicsProperties {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left_right_balance: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left_torque_effectiveness: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right_torque_effectiveness: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub left_pedal_smoothness: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub right_pedal_smoothness: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub combined_pedal_smoothness: Option<f64>,
+}
+
+
+/// The running dynamics of a feature of the `points` collection.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RunningDynamicsProperties {
+    /// In milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stance_time: Option<f64>,
+
+    /// In millimeters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertical_oscillation: Option<f64>,
+
+    /// In millimeters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_length: Option<f64>,
+}
+
+
+/// A GeoJSON FeatureCollection.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub struct FeatureCollection {
+    pub features: Vec<serde_json::Value>,
+
+    /// The bounding box of all features (`[west, south, east, north]`, or `[west, south, lowest,
+    /// east, north, highest]` with elevations); omitted if there are none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<Vec<f64>>,
+}
+
+
+/// The members of a map document that are always present, except for `track` and `points`. Ranges
+/// are `[minimum, maximum]` pairs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct DocumentEntries {
+    pub format_version: u64,
+
+    /// The center of the map, as latitude and longitude.
+    pub center: [f64; 2],
+
+    pub zoom: u32,
+    pub elevation_range: [f64; 2],
+    pub elevation_gain: f64,
+    pub elevation_loss: f64,
+    pub gradient_range: [f64; 2],
+    pub vertical_speed_range: [f64; 2],
+    pub heart_rate_range: [f64; 2],
+    pub respiration_rate_range: [f64; 2],
+    pub spo2_range: [f64; 2],
+    pub speed_range: [f64; 2],
+    pub pace_range: [f64; 2],
+
+    /// The cadence range; swims have a `stroke_rate_range` instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cadence_range: Option<[f64; 2]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stroke_rate_range: Option<[f64; 2]>,
+
+    pub temperature_range: [f64; 2],
+    pub power_range: [f64; 2],
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apparent_temperature_range: Option<[f64; 2]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swim_pace_range: Option<[f64; 2]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stance_time_range: Option<[f64; 2]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertical_oscillation_range: Option<[f64; 2]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step_length_range: Option<[f64; 2]>,
+
+    pub stats: serde_json::Map<String, serde_json::Value>,
+    pub units: serde_json::Map<String, serde_json::Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub series: Option<Series>,
+}
+
+
+/// Lap or session totals as recorded by the device. Values that have not been recorded are
+/// omitted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct SummaryEntries {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sport: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_sport: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swim_stroke: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<String>,
+
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elapsed_time: Option<f64>,
+
+    /// In seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timer_time: Option<f64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+
+    /// In kilocalories.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calories: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_heart_rate: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_heart_rate: Option<u64>,
+}
+
+
+/// The kind of activity, the recording device and the time at which recording started. Values that
+/// are not known are omitted.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MetadataEntries {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sport: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub_sport: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial_number: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software_version: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_place: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_place: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<String>,
+}
+
+
+/// A pause of the timer, with its position as latitude and longitude.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PauseEntries {
+    pub start_time: String,
+    pub end_time: String,
+
+    /// In seconds.
+    pub duration: f64,
+
+    pub position: [f64; 2],
+}
+
+
+/// One leg of a multisport activity.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LegEntries {
+    pub sport: Option<String>,
+    pub session: SummaryEntries,
+    pub stats: serde_json::Map<String, serde_json::Value>,
+
+    /// The part of the track recorded during the leg, as a GeoJSON FeatureCollection.
+    pub track: serde_json::Value,
+}
+
+
+/// The interval between two legs of a multisport activity.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TransitionEntries {
+    pub from_sport: Option<String>,
+    pub to_sport: Option<String>,
+    pub start_time: String,
+    pub end_time: String,
+
+    /// In seconds.
+    pub duration: f64,
+}
+
+
+/// The downsampled chart series of the measurements, as `[x, value]` pairs per measurement.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Series {
+    /// Indexed by the distance from the start.
+    pub by_distance: serde_json::Map<String, serde_json::Value>,
+
+    /// Indexed by the time since the first timestamp, in seconds.
+    pub by_time: serde_json::Map<String, serde_json::Value>,
+}
+
+
+/// The document of an indoor activity. Ranges are `[minimum, maximum]` pairs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct IndoorDocument {
+    pub format_version: u64,
+
+    /// Always `true`, to distinguish the document from map documents.
+    pub indoor: bool,
+
+    pub samples: Vec<serde_json::Value>,
+    pub speed_range: [f64; 2],
+    pub pace_range: [f64; 2],
+    pub heart_rate_range: [f64; 2],
+    pub cadence_range: [f64; 2],
+    pub power_range: [f64; 2],
+    pub stats: serde_json::Map<String, serde_json::Value>,
+    pub laps: Vec<SummaryEntries>,
+    pub session: Option<SummaryEntries>,
+    pub metadata: MetadataEntries,
+    pub units: serde_json::Map<String, serde_json::Value>,
+}
@@ -0,0 +1,131 @@
+//! Downsampled series of the measurements, from which charts can be drawn without processing every
+//! recorded point.
+//!
+//! The series are downsampled using the Largest-Triangle-Three-Buckets algorithm (Steinarsson,
+//! 2013), which keeps the peaks and troughs that make up the shape of a chart.
+
+
+use crate::point::{DistanceAlgorithm, Point};
+use crate::schema::Series;
+use crate::units::Units;
+
+
+/// The measurements output as series, with their keys.
+const SERIES_MEASUREMENTS: [&str; 5] = ["elevation", "heart_rate", "speed", "cadence", "power"];
+
+
+/// Returns the indexes of the data points (sorted by their x values) that are kept when downsampling
+/// them to `target_count` points using the Largest-Triangle-Three-Buckets algorithm. The first and
+/// last data points are always kept. If there are no more than `target_count` data points or
+/// `target_count` is less than 3, all of them are kept.
+pub fn lttb_indexes(data: &[(f64, f64)], target_count: usize) -> Vec<usize> {
+    if target_count < 3 || data.len() <= target_count {
+        return (0..data.len()).collect();
+    }
+
+    // the data points between the first and the last one are split into buckets, from each of
+    // which the point forming the largest triangle with the previously kept point and the average
+    // of the next bucket is kept
+    let bucket_size = (data.len() - 2) as f64 / (target_count - 2) as f64;
+    let bucket_start = |b: usize| (b as f64 * bucket_size) as usize + 1;
+
+    let mut ret = Vec::with_capacity(target_count);
+    ret.push(0);
+    let mut previous = 0;
+    for b in 0..(target_count - 2) {
+        let (start, end) = (bucket_start(b), bucket_start(b + 1));
+        let (next_start, next_end) = (end, bucket_start(b + 2).min(data.len()));
+        let next = &data[next_start..next_end];
+        let next_count = next.len() as f64;
+        let avg_x = next.iter().map(|d| d.0).sum::<f64>() / next_count;
+        let avg_y = next.iter().map(|d| d.1).sum::<f64>() / next_count;
+
+        let (ax, ay) = data[previous];
+        let area = |i: usize| {
+            let (x, y) = data[i];
+            ((ax - avg_x) * (y - ay) - (ax - x) * (avg_y - ay)).abs()
+        };
+        let chosen = (start..end)
+            .max_by(|&i, &j| area(i).total_cmp(&area(j)))
+            .unwrap_or(start);
+        ret.push(chosen);
+        previous = chosen;
+    }
+    ret.push(data.len() - 1);
+    ret
+}
+
+
+/// Downsamples the data points to `target_count` points (see [`lttb_indexes`]).
+pub fn lttb(data: &[(f64, f64)], target_count: usize) -> Vec<(f64, f64)> {
+    lttb_indexes(data, target_count).into_iter()
+        .map(|i| data[i])
+        .collect()
+}
+
+
+/// Returns the value of the measurement with the given key (one of [`SERIES_MEASUREMENTS`]) at the
+/// point, in the given units.
+fn measurement(point: &Point, key: &str, units: Units) -> Option<f64> {
+    match key {
+        "elevation" => point.elevation_m.map(|e| units.elevation(e)),
+        "heart_rate" => point.heart_rate_bpm.map(|hr| hr as f64),
+        "speed" => point.speed_km_per_h.map(|s| units.speed(s)),
+        "cadence" => point.cadence_rpm.map(|c| c as f64),
+        "power" => point.power_w.map(|p| p as f64),
+        _ => None,
+    }
+}
+
+
+/// Converts the measurements of the points into series of `[x, value]` pairs, each downsampled to
+/// `target_count` pairs. Points without a value or without an x value are skipped; measurements
+/// without any values are omitted.
+fn measurement_series(points: &[(&Point, Option<f64>)], target_count: usize, units: Units) -> serde_json::Map<String, serde_json::Value> {
+    let mut ret = serde_json::Map::new();
+    for key in SERIES_MEASUREMENTS {
+        let data: Vec<(f64, f64)> = points.iter()
+            .filter_map(|(p, x)| Some(((*x)?, measurement(p, key, units)?)))
+            .collect();
+        if data.is_empty() {
+            continue;
+        }
+        let pairs: Vec<serde_json::Value> = lttb(&data, target_count).into_iter()
+            .map(|(x, y)| serde_json::json!([x, y]))
+            .collect();
+        ret.insert(key.to_owned(), pairs.into());
+    }
+    ret
+}
+
+
+/// Converts the lines into the `series` section of the document: the elevation, heart rate, speed,
+/// cadence and power as `[x, value]` pairs indexed by the distance from the start (`by_distance`)
+/// and by the time since the first timestamp in seconds (`by_time`), each downsampled to
+/// `target_count` pairs.
+pub fn lines_to_series(lines: &[Vec<Point>], target_count: usize, units: Units, distance_algorithm: DistanceAlgorithm) -> Series {
+    let mut by_distance = Vec::new();
+    let mut distance_m = 0.0;
+    for line in lines {
+        for (i, point) in line.iter().enumerate() {
+            if i > 0 {
+                distance_m += line[i-1].distance_m(point, distance_algorithm);
+            }
+            by_distance.push((point, Some(units.distance(distance_m))));
+        }
+    }
+
+    let start_unix_timestamp = lines.iter()
+        .flatten()
+        .find_map(|p| p.unix_timestamp);
+    let by_time: Vec<(&Point, Option<f64>)> = lines.iter()
+        .flatten()
+        .map(|p| (p, start_unix_timestamp.zip(p.unix_timestamp).map(|(start, t)| t - start)))
+        .collect();
+
+    Series {
+        by_distance: measurement_series(&by_distance, target_count, units),
+        by_time: measurement_series(&by_time, target_count, units),
+    }
+}
+
@@ -0,0 +1,195 @@
+//! Serving the activities in directories over HTTP, converting them on demand.
+//!
+//! The API consists of:
+//!
+//! * `GET /activities`: a JSON array with one object per FIT file, sorted by path, containing its
+//!   `id`, its `file` name and either its summary (as output by `stats --summary-format json`) or
+//!   the `error` that prevented its conversion
+//! * `GET /activities/<id>`: the object of a single activity
+//! * `GET /activities/<id>/geojson`: the document of the activity
+//!
+//! An activity is identified by the SHA-256 hash of its file, so that its id remains the same when
+//! the file is renamed. Files are converted when they are first found (or when they have changed)
+//! and the results are kept in memory.
+
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use crate::error::WalkingError;
+use crate::http::{Request, Response};
+use crate::input::list_fit_files;
+use crate::sha256::sha256_hex;
+use crate::state::FileState;
+
+
+/// The result of converting a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Converted {
+    /// The summary of the activity, as listed by `/activities`.
+    pub summary: serde_json::Map<String, Value>,
+
+    /// The document of the activity, as served by `/activities/<id>/geojson`.
+    pub document: Vec<u8>,
+}
+
+
+/// Converts the file at the given path.
+pub type Converter = dyn Fn(&Path) -> Result<Converted, WalkingError> + Send + Sync;
+
+
+#[derive(Clone, Debug)]
+struct CatalogEntry {
+    state: FileState,
+    id: String,
+
+    /// The conversion result, or the message of the error preventing it.
+    converted: Result<Arc<Converted>, String>,
+}
+impl CatalogEntry {
+    fn to_json(&self, path: &Path) -> Value {
+        let mut ret = match &self.converted {
+            Ok(c) => c.summary.clone(),
+            Err(message) => {
+                let mut map = serde_json::Map::new();
+                map.insert("error".to_owned(), message.clone().into());
+                map
+            },
+        };
+        ret.insert("id".to_owned(), self.id.clone().into());
+        ret.insert(
+            "file".to_owned(),
+            path.file_name().map(|n| n.to_string_lossy().into_owned()).into(),
+        );
+        let mut json = Value::Object(ret);
+        json.sort_all_objects();
+        json
+    }
+}
+
+
+/// The activities in a set of directories.
+pub struct Catalog {
+    dirs: Vec<PathBuf>,
+    converter: Box<Converter>,
+    entries: Mutex<BTreeMap<PathBuf, CatalogEntry>>,
+}
+impl Catalog {
+    /// Creates a catalog of the FIT files in the directories (not descending into subdirectories),
+    /// which are converted using the converter. The directories are not scanned until the catalog
+    /// is first refreshed.
+    pub fn new(dirs: Vec<PathBuf>, converter: Box<Converter>) -> Self {
+        Self {
+            dirs,
+            converter,
+            entries: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Scans the directories, converting the files that are new or have changed and forgetting
+    /// those that have disappeared. Returns the number of files in the catalog.
+    pub fn refresh(&self) -> usize {
+        let mut found = Vec::new();
+        for dir in &self.dirs {
+            match list_fit_files(dir) {
+                Ok(paths) => found.extend(paths.into_iter()
+                    .filter_map(|p| FileState::of_file(&p).ok().map(|s| (p, s)))),
+                Err(e) => log::warn!("{}: failed to list directory: {}", dir.display(), e),
+            }
+        }
+
+        // converted without holding the lock, so that other requests can still be answered
+        let known: BTreeMap<PathBuf, FileState> = self.entries.lock().unwrap().iter()
+            .map(|(p, e)| (p.clone(), e.state))
+            .collect();
+        let mut new_entries = BTreeMap::new();
+        for (path, state) in found {
+            if known.get(&path) == Some(&state) {
+                continue;
+            }
+            match self.convert(&path, state) {
+                Ok(entry) => {
+                    new_entries.insert(path, entry);
+                },
+                Err(e) => log::warn!("{}: {}", path.display(), e),
+            }
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|p, _| p.is_file());
+        entries.extend(new_entries);
+        entries.len()
+    }
+
+    fn convert(&self, path: &Path, state: FileState) -> Result<CatalogEntry, WalkingError> {
+        let id = sha256_hex(&fs::read(path)?);
+        let converted = (self.converter)(path)
+            .map(Arc::new)
+            .map_err(|e| e.to_string());
+        match &converted {
+            Ok(_) => log::info!("{}: converted", path.display()),
+            Err(message) => log::warn!("{}: {}", path.display(), message),
+        }
+        Ok(CatalogEntry { state, id, converted })
+    }
+
+    /// Returns the list of activities served by `/activities`.
+    pub fn activities_json(&self) -> Value {
+        self.entries.lock().unwrap().iter()
+            .map(|(path, entry)| entry.to_json(path))
+            .collect()
+    }
+
+    fn find(&self, id: &str) -> Option<(PathBuf, CatalogEntry)> {
+        self.entries.lock().unwrap().iter()
+            .find(|(_, e)| e.id == id)
+            .map(|(p, e)| (p.clone(), e.clone()))
+    }
+
+    /// Answers a request to the API.
+    pub fn handle(&self, request: &Request) -> Response {
+        if request.method != "GET" {
+            return Response::error("405 Method Not Allowed", "only GET requests are supported");
+        }
+        let path = request.path.split(['?', '#']).next().unwrap_or_default();
+        let segments: Vec<&str> = path.split('/')
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        match segments.as_slice() {
+            ["activities"] => {
+                self.refresh();
+                Response::json("200 OK", &self.activities_json())
+            },
+            ["activities", id] | ["activities", id, "geojson"] => {
+                // the activity may have appeared since the list was last requested
+                let (path, entry) = match self.find(id) {
+                    Some(found) => found,
+                    None => {
+                        self.refresh();
+                        match self.find(id) {
+                            Some(found) => found,
+                            None => return Response::error("404 Not Found", "no such activity"),
+                        }
+                    },
+                };
+                if segments.len() == 2 {
+                    return Response::json("200 OK", &entry.to_json(&path));
+                }
+                match &entry.converted {
+                    Ok(converted) => Response {
+                        status: "200 OK",
+                        content_type: "application/geo+json",
+                        body: converted.document.clone(),
+                    },
+                    Err(message) => Response::error("422 Unprocessable Content", message),
+                }
+            },
+            _ => Response::error("404 Not Found", "unknown path"),
+        }
+    }
+}
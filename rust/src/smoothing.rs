@@ -0,0 +1,152 @@
+//! Smoothing of jittery per-point measurements such as GPS speed and heart rate.
+
+
+use clap::ValueEnum;
+
+use crate::point::{DistanceAlgorithm, Point};
+
+
+/// The size of the window over which speeds derived from positions are smoothed.
+pub const DERIVED_SPEED_WINDOW: usize = 5;
+
+
+/// How values are smoothed.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum SmoothingMethod {
+    /// The average over a window of points centered on each point.
+    #[default]
+    MovingAverage,
+
+    /// Exponential smoothing with a smoothing factor of `2 / (N + 1)`, where `N` is the window size.
+    Exponential,
+}
+
+
+/// Smooths a series of values using a window of the given size. Missing values remain missing and
+/// are skipped when smoothing the others.
+fn smooth_values(values: &[Option<f64>], window: usize, method: SmoothingMethod) -> Vec<Option<f64>> {
+    if window <= 1 {
+        return values.to_vec();
+    }
+
+    match method {
+        SmoothingMethod::MovingAverage => {
+            let before = (window - 1) / 2;
+            let after = window / 2;
+            (0..values.len())
+                .map(|i| {
+                    values[i]?;
+                    let window_values: Vec<f64> = values[i.saturating_sub(before)..(i + after + 1).min(values.len())]
+                        .iter()
+                        .flatten()
+                        .copied()
+                        .collect();
+                    Some(window_values.iter().sum::<f64>() / window_values.len() as f64)
+                })
+                .collect()
+        },
+        SmoothingMethod::Exponential => {
+            let alpha = 2.0 / (window as f64 + 1.0);
+            let mut state: Option<f64> = None;
+            values.iter()
+                .map(|value_opt| {
+                    let value = (*value_opt)?;
+                    let smoothed = match state {
+                        Some(s) => alpha * value + (1.0 - alpha) * s,
+                        None => value,
+                    };
+                    state = Some(smoothed);
+                    Some(smoothed)
+                })
+                .collect()
+        },
+    }
+}
+
+
+/// Smooths the speeds of the points of each line. The original speeds are kept in
+/// `raw_speed_km_per_h`.
+pub fn smooth_speed(lines: &mut [Vec<Point>], window: usize, method: SmoothingMethod) {
+    for line in lines.iter_mut() {
+        let speeds: Vec<Option<f64>> = line.iter()
+            .map(|p| p.speed_km_per_h)
+            .collect();
+        let smoothed = smooth_values(&speeds, window, method);
+        for (point, speed) in line.iter_mut().zip(smoothed) {
+            point.raw_speed_km_per_h = point.speed_km_per_h;
+            point.speed_km_per_h = speed;
+        }
+    }
+}
+
+
+/// Smooths the positions of the points of each line, averaging longitudes and latitudes separately
+/// (assuming that no line crosses the 180° meridian). Elevations are left as they are.
+pub fn smooth_positions(lines: &mut [Vec<Point>], window: usize, method: SmoothingMethod) {
+    for line in lines.iter_mut() {
+        let longitudes: Vec<Option<f64>> = line.iter()
+            .map(|p| Some(p.coordinates_deg.x()))
+            .collect();
+        let latitudes: Vec<Option<f64>> = line.iter()
+            .map(|p| Some(p.coordinates_deg.y()))
+            .collect();
+        let smoothed_longitudes = smooth_values(&longitudes, window, method);
+        let smoothed_latitudes = smooth_values(&latitudes, window, method);
+        for ((point, lon), lat) in line.iter_mut().zip(smoothed_longitudes).zip(smoothed_latitudes) {
+            if let (Some(lon), Some(lat)) = (lon, lat) {
+                point.coordinates_deg = geo::Point::new(lon, lat);
+            }
+        }
+    }
+}
+
+
+/// Fills in the speeds of the points of each line that have none, deriving them from the positions
+/// and timestamps of the neighboring points (smoothed over [`DERIVED_SPEED_WINDOW`] points, as
+/// positions are too jittery to be used directly). Returns the number of points whose speed has
+/// been derived.
+pub fn derive_speed(lines: &mut [Vec<Point>], method: SmoothingMethod, distance_algorithm: DistanceAlgorithm) -> usize {
+    let mut derived_count = 0;
+    for line in lines.iter_mut() {
+        if line.iter().all(|p| p.speed_km_per_h.is_some()) {
+            continue;
+        }
+
+        // speed between the previous and the next point (or the point itself at either end)
+        let speeds: Vec<Option<f64>> = (0..line.len())
+            .map(|i| {
+                let before = &line[i.saturating_sub(1)];
+                let after = &line[(i + 1).min(line.len() - 1)];
+                let duration_s = after.unix_timestamp? - before.unix_timestamp?;
+                if duration_s <= 0.0 {
+                    return None;
+                }
+                Some(before.distance_m(after, distance_algorithm) / duration_s * 3.6)
+            })
+            .collect();
+        let smoothed = smooth_values(&speeds, DERIVED_SPEED_WINDOW, method);
+        for (point, speed) in line.iter_mut().zip(smoothed) {
+            if point.speed_km_per_h.is_none() && speed.is_some() {
+                point.speed_km_per_h = speed;
+                derived_count += 1;
+            }
+        }
+    }
+    derived_count
+}
+
+
+/// Smooths the heart rates of the points of each line, rounding to whole beats per minute. The
+/// original heart rates are kept in `raw_heart_rate_bpm`.
+pub fn smooth_heart_rate(lines: &mut [Vec<Point>], window: usize, method: SmoothingMethod) {
+    for line in lines.iter_mut() {
+        let heart_rates: Vec<Option<f64>> = line.iter()
+            .map(|p| p.heart_rate_bpm.map(|hr| hr as f64))
+            .collect();
+        let smoothed = smooth_values(&heart_rates, window, method);
+        for (point, heart_rate) in line.iter_mut().zip(smoothed) {
+            point.raw_heart_rate_bpm = point.heart_rate_bpm;
+            point.heart_rate_bpm = heart_rate.map(|hr| hr.round() as u64);
+        }
+    }
+}
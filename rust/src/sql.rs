@@ -0,0 +1,61 @@
+//! Formatting of values as SQL literals, for the database outputs.
+
+
+use std::fmt::Display;
+
+use chrono::{DateTime, Local, Utc};
+
+
+/// The maximum number of rows inserted by a single statement, keeping statements at a size that
+/// database servers and tools handle comfortably.
+pub const ROWS_PER_STATEMENT: usize = 500;
+
+
+/// Formats a string as an SQL string literal.
+pub fn sql_string(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+
+/// Formats an optional string as an SQL string literal or `NULL`.
+pub fn sql_optional_string(value: Option<&str>) -> String {
+    match value {
+        Some(v) => sql_string(v),
+        None => "NULL".to_owned(),
+    }
+}
+
+
+/// Formats a floating-point number as an SQL literal; values that are not finite become `NULL`.
+pub fn sql_f64(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "NULL".to_owned()
+    }
+}
+
+
+/// Formats an optional floating-point number as an SQL literal or `NULL`.
+pub fn sql_optional_f64(value: Option<f64>) -> String {
+    value.map(sql_f64).unwrap_or_else(|| "NULL".to_owned())
+}
+
+
+/// Formats an optional integer as an SQL literal or `NULL`.
+pub fn sql_optional_integer<T: Display>(value: Option<T>) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => "NULL".to_owned(),
+    }
+}
+
+
+/// Formats a timestamp as an SQL string literal in UTC (`2024-05-01T08:30:00Z`), which sorts and
+/// compares correctly as text.
+pub fn sql_timestamp(value: Option<&DateTime<Local>>) -> String {
+    match value {
+        Some(ts) => sql_string(&ts.with_timezone(&Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+        None => "NULL".to_owned(),
+    }
+}
@@ -0,0 +1,268 @@
+//! Output of activities into an SQLite database, so that the whole history can be queried with
+//! SQL.
+//!
+//! The database is written by piping SQL statements into the `sqlite3` command-line tool, which
+//! must be installed. Each activity is identified by the SHA-256 hash of its input file along with
+//! its index within the file; converting the same file again replaces the activity instead of
+//! adding it a second time.
+
+
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::activity::{Activity, Summary};
+use crate::elevation::elevation_gain_loss;
+use crate::error::WalkingError;
+use crate::point::{DistanceAlgorithm, GeoPoint};
+use crate::sql::{
+    ROWS_PER_STATEMENT, sql_f64, sql_optional_f64, sql_optional_integer, sql_optional_string, sql_string,
+    sql_timestamp,
+};
+use crate::stats::compute_statistics;
+
+
+/// The prefix of the `--output` option selecting a database instead of an output file, e.g.
+/// `sqlite:activities.db`.
+pub const SQLITE_OUTPUT_PREFIX: &str = "sqlite:";
+
+/// How long to wait for other processes writing to the database, in milliseconds.
+const BUSY_TIMEOUT_MS: u32 = 10_000;
+
+/// The tables of the database. Timestamps are stored as text in UTC; distances in meters, speeds
+/// in km/h and temperatures in degrees Celsius.
+const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS activities (
+    id INTEGER PRIMARY KEY,
+    file_hash TEXT NOT NULL,
+    activity_index INTEGER NOT NULL,
+    source_file TEXT NOT NULL,
+    sport TEXT,
+    sub_sport TEXT,
+    manufacturer TEXT,
+    product TEXT,
+    start_time TEXT,
+    end_time TEXT,
+    elapsed_time REAL,
+    moving_time REAL,
+    distance REAL,
+    elevation_gain REAL,
+    elevation_loss REAL,
+    avg_heart_rate INTEGER,
+    max_heart_rate INTEGER,
+    calories INTEGER,
+    point_count INTEGER NOT NULL,
+    UNIQUE (file_hash, activity_index)
+);
+CREATE TABLE IF NOT EXISTS points (
+    activity_id INTEGER NOT NULL REFERENCES activities (id),
+    seq INTEGER NOT NULL,
+    line INTEGER NOT NULL,
+    timestamp TEXT,
+    latitude REAL NOT NULL,
+    longitude REAL NOT NULL,
+    elevation REAL,
+    heart_rate INTEGER,
+    speed REAL,
+    cadence INTEGER,
+    temperature INTEGER,
+    power INTEGER,
+    PRIMARY KEY (activity_id, seq)
+);
+CREATE TABLE IF NOT EXISTS laps (
+    activity_id INTEGER NOT NULL REFERENCES activities (id),
+    lap INTEGER NOT NULL,
+    start_time TEXT,
+    end_time TEXT,
+    elapsed_time REAL,
+    timer_time REAL,
+    distance REAL,
+    calories INTEGER,
+    avg_heart_rate INTEGER,
+    max_heart_rate INTEGER,
+    PRIMARY KEY (activity_id, lap)
+);
+";
+
+
+/// Options influencing the values stored in the database.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SqliteOptions {
+    /// The method by which distances between points are calculated.
+    pub distance_algorithm: DistanceAlgorithm,
+
+    /// The speed below which a segment does not count towards the moving time, in km/h.
+    pub moving_threshold_km_per_h: f64,
+
+    /// The minimum elevation change counted towards elevation gain or loss, in meters.
+    pub elevation_threshold_m: f64,
+}
+
+
+/// The input file from which an activity has been read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ActivitySource<'a> {
+    /// The path of the input file, as given.
+    pub path: &'a Path,
+
+    /// The SHA-256 hash of the contents of the input file, in hexadecimal.
+    pub file_hash: &'a str,
+
+    /// The (one-based) index of the activity within the input file.
+    pub activity_index: usize,
+}
+
+
+/// Returns an SQL expression selecting the ID of the activity from the given source.
+fn activity_id_query(source: &ActivitySource) -> String {
+    format!(
+        "(SELECT id FROM activities WHERE file_hash = {} AND activity_index = {})",
+        sql_string(source.file_hash), source.activity_index,
+    )
+}
+
+
+fn lap_values(lap: &Summary, index: usize) -> String {
+    format!(
+        "({}, {}, {}, {}, {}, {}, {}, {}, {})",
+        index,
+        sql_timestamp(lap.start_time.as_ref()),
+        sql_timestamp(lap.end_time.as_ref()),
+        sql_optional_f64(lap.total_elapsed_time_s),
+        sql_optional_f64(lap.total_timer_time_s),
+        sql_optional_f64(lap.total_distance_m),
+        sql_optional_integer(lap.total_calories_kcal),
+        sql_optional_integer(lap.avg_heart_rate_bpm),
+        sql_optional_integer(lap.max_heart_rate_bpm),
+    )
+}
+
+
+/// Writes the SQL statements creating the tables (if necessary) and replacing the activity from
+/// the given source, within a transaction.
+pub fn write_activity_sql<W: Write>(mut writer: W, activity: &Activity, source: &ActivitySource, options: &SqliteOptions) -> Result<(), WalkingError> {
+    write!(writer, "{}", SCHEMA)?;
+    writeln!(writer, "BEGIN;")?;
+
+    let activity_id = activity_id_query(source);
+    writeln!(writer, "DELETE FROM points WHERE activity_id = {};", activity_id)?;
+    writeln!(writer, "DELETE FROM laps WHERE activity_id = {};", activity_id)?;
+    writeln!(
+        writer, "DELETE FROM activities WHERE file_hash = {} AND activity_index = {};",
+        sql_string(source.file_hash), source.activity_index,
+    )?;
+
+    let statistics = compute_statistics(&activity.lines, options.moving_threshold_km_per_h, options.distance_algorithm);
+    let (elevation_gain_m, elevation_loss_m) = elevation_gain_loss(&activity.lines, options.elevation_threshold_m);
+    let end_time = activity.lines.iter()
+        .flat_map(|l| l.iter())
+        .filter_map(|p| p.timestamp)
+        .next_back();
+    let session = activity.sessions.first();
+    let metadata = &activity.metadata;
+    writeln!(
+        writer,
+        "INSERT INTO activities (file_hash, activity_index, source_file, sport, sub_sport, manufacturer, product, start_time, end_time, elapsed_time, moving_time, distance, elevation_gain, elevation_loss, avg_heart_rate, max_heart_rate, calories, point_count) VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {});",
+        sql_string(source.file_hash),
+        source.activity_index,
+        sql_string(&source.path.display().to_string()),
+        sql_optional_string(metadata.sport.as_deref()),
+        sql_optional_string(metadata.sub_sport.as_deref()),
+        sql_optional_string(metadata.manufacturer.as_deref()),
+        sql_optional_string(metadata.product.as_deref()),
+        sql_timestamp(activity.start_time().as_ref()),
+        sql_timestamp(end_time.as_ref()),
+        sql_f64(statistics.elapsed_time_s),
+        sql_f64(statistics.moving_time_s),
+        sql_f64(statistics.total_distance_m),
+        sql_f64(elevation_gain_m),
+        sql_f64(elevation_loss_m),
+        sql_optional_integer(session.and_then(|s| s.avg_heart_rate_bpm)),
+        sql_optional_integer(session.and_then(|s| s.max_heart_rate_bpm)),
+        sql_optional_integer(session.and_then(|s| s.total_calories_kcal)),
+        activity.point_count(),
+    )?;
+
+    let point_values: Vec<String> = activity.lines.iter()
+        .enumerate()
+        .flat_map(|(line_index, line)| line.iter().map(move |p| (line_index, p)))
+        .enumerate()
+        .map(|(seq, (line_index, p))| format!(
+            "({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {})",
+            seq,
+            line_index,
+            sql_timestamp(p.timestamp.as_ref()),
+            sql_f64(p.latitude()),
+            sql_f64(p.longitude()),
+            sql_optional_f64(p.elevation_m),
+            sql_optional_integer(p.heart_rate_bpm),
+            sql_optional_f64(p.speed_km_per_h),
+            sql_optional_integer(p.cadence_rpm),
+            sql_optional_integer(p.temperature_degc),
+            sql_optional_integer(p.power_w),
+        ))
+        .collect();
+    for chunk in point_values.chunks(ROWS_PER_STATEMENT) {
+        writeln!(
+            writer,
+            "INSERT INTO points (activity_id, seq, line, timestamp, latitude, longitude, elevation, heart_rate, speed, cadence, temperature, power) SELECT {}, v.* FROM (VALUES {}) AS v;",
+            activity_id, chunk.join(", "),
+        )?;
+    }
+
+    if !activity.laps.is_empty() {
+        let lap_rows: Vec<String> = activity.laps.iter()
+            .enumerate()
+            .map(|(i, lap)| lap_values(lap, i + 1))
+            .collect();
+        writeln!(
+            writer,
+            "INSERT INTO laps (activity_id, lap, start_time, end_time, elapsed_time, timer_time, distance, calories, avg_heart_rate, max_heart_rate) SELECT {}, v.* FROM (VALUES {}) AS v;",
+            activity_id, lap_rows.join(", "),
+        )?;
+    }
+
+    writeln!(writer, "COMMIT;")?;
+    Ok(())
+}
+
+
+/// Executes the SQL statements on the SQLite database at the given path (which is created if
+/// necessary) using the `sqlite3` command-line tool, stopping at the first error.
+pub fn execute_sql(database: &Path, sql: &[u8]) -> Result<(), WalkingError> {
+    let mut child = Command::new("sqlite3")
+        .arg("-bail")
+        .arg(database)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => WalkingError::Database("the sqlite3 command-line tool is required to write SQLite databases".to_owned()),
+            _ => WalkingError::Io(e),
+        })?;
+
+    // sqlite3 stops reading at the first error, so its message is more useful than a failed write
+    let write_result = {
+        let mut stdin = child.stdin.take().unwrap();
+        writeln!(stdin, ".timeout {}", BUSY_TIMEOUT_MS)
+            .and_then(|_| stdin.write_all(sql))
+    };
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(WalkingError::Database(format!("{}: {}", database.display(), message.trim())));
+    }
+    write_result?;
+    Ok(())
+}
+
+
+/// Inserts the activity into the SQLite database at the given path (which is created if
+/// necessary), replacing an activity from the same source.
+pub fn insert_activity(database: &Path, activity: &Activity, source: &ActivitySource, options: &SqliteOptions) -> Result<(), WalkingError> {
+    let mut sql = Vec::new();
+    write_activity_sql(&mut sql, activity, source, options)?;
+    execute_sql(database, &sql)
+}
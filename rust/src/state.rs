@@ -0,0 +1,135 @@
+//! Tracking of the input files that have already been converted, so that batch conversions can be
+//! resumed and repeated incrementally.
+
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde_json::{json, Map, Value};
+
+use crate::error::WalkingError;
+
+
+/// The size and modification time of an input file when it was converted.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct FileState {
+    pub size: u64,
+
+    /// The modification time, in nanoseconds since the Unix epoch.
+    pub modified_ns: u128,
+}
+impl FileState {
+    /// Returns the current state of the file at the given path.
+    pub fn of_file(path: &Path) -> Result<Self, WalkingError> {
+        let metadata = fs::metadata(path)?;
+        let modified_ns = metadata.modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        Ok(Self {
+            size: metadata.len(),
+            modified_ns,
+        })
+    }
+}
+
+
+/// The input files converted so far, stored as a JSON file.
+///
+/// A file counts as converted as long as its size and modification time are unchanged. The options
+/// with which it was converted are not recorded; delete the state file to convert all files again
+/// after changing them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StateFile {
+    path: PathBuf,
+    files: BTreeMap<String, FileState>,
+}
+impl StateFile {
+    /// Loads the state file at the given path. A missing file is treated as an empty state.
+    pub fn load(path: &Path) -> Result<Self, WalkingError> {
+        let mut state = Self {
+            path: path.to_owned(),
+            files: BTreeMap::new(),
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(state),
+            Err(e) => return Err(e.into()),
+        };
+
+        let document: Value = serde_json::from_str(&text)?;
+        let invalid = || WalkingError::Io(std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} is not a state file", path.display()),
+        ));
+        let files = document.get("files")
+            .and_then(|f| f.as_object())
+            .ok_or_else(invalid)?;
+        for (name, entry) in files {
+            let size = entry.get("size").and_then(|s| s.as_u64());
+            let modified_ns = entry.get("modified_ns")
+                .and_then(|m| m.as_str())
+                .and_then(|m| m.parse().ok());
+            match (size, modified_ns) {
+                (Some(size), Some(modified_ns)) => {
+                    state.files.insert(name.clone(), FileState { size, modified_ns });
+                },
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(state)
+    }
+
+    /// Returns the key under which the file at the given path is stored: its canonical path, or the
+    /// path as given if it cannot be canonicalized.
+    fn key(path: &Path) -> String {
+        fs::canonicalize(path)
+            .unwrap_or_else(|_| path.to_owned())
+            .display()
+            .to_string()
+    }
+
+    /// Returns whether the file at the given path has been converted and not changed since.
+    pub fn is_converted(&self, path: &Path) -> bool {
+        match (self.files.get(&Self::key(path)), FileState::of_file(path)) {
+            (Some(recorded), Ok(current)) => *recorded == current,
+            _ => false,
+        }
+    }
+
+    /// Records that the file at the given path has been converted in its current state.
+    pub fn mark_converted(&mut self, path: &Path) -> Result<(), WalkingError> {
+        let state = FileState::of_file(path)?;
+        self.files.insert(Self::key(path), state);
+        Ok(())
+    }
+
+    /// Writes the state to its file. The file is replaced only once the new state has been written
+    /// completely, so an interruption cannot corrupt it.
+    pub fn save(&self) -> Result<(), WalkingError> {
+        let mut files = Map::new();
+        for (name, state) in &self.files {
+            files.insert(name.clone(), json!({
+                "size": state.size,
+                // as a string, since JSON numbers cannot represent all 128-bit values exactly
+                "modified_ns": state.modified_ns.to_string(),
+            }));
+        }
+        let document = json!({ "files": files });
+
+        let mut temp_name = self.path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+        {
+            let mut writer = BufWriter::new(File::create(&temp_path)?);
+            serde_json::to_writer_pretty(&mut writer, &document)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+        fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,243 @@
+use crate::elevation::{grade_adjustment_factor, gradient_percent};
+use crate::point::{DistanceAlgorithm, Point};
+use crate::units::Units;
+
+
+/// The default speed below which a segment is considered to be standing still, in km/h.
+pub const DEFAULT_MOVING_THRESHOLD_KM_PER_H: f64 = 1.0;
+
+/// The default age assumed when estimating the energy expenditure, in years.
+pub const DEFAULT_AGE_YEARS: f64 = 35.0;
+
+/// The length of the rolling average of the power from which the normalized power is calculated,
+/// in seconds.
+const NORMALIZED_POWER_WINDOW_S: usize = 30;
+
+
+/// Statistics calculated over all segments of a track.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Statistics {
+    /// The time between the first and the last timestamp, in seconds.
+    pub elapsed_time_s: f64,
+
+    /// The total duration of the segments traversed at or above the moving threshold speed, in
+    /// seconds.
+    pub moving_time_s: f64,
+
+    /// The total length of all segments, in meters.
+    pub total_distance_m: f64,
+
+    /// The distance covered while moving divided by the moving time.
+    pub avg_moving_speed_km_per_h: Option<f64>,
+
+    /// The grade-adjusted distance covered while moving divided by the moving time; only
+    /// calculated if the gradient of at least one moving segment is known.
+    pub avg_grade_adjusted_speed_km_per_h: Option<f64>,
+
+    /// The maximum speed recorded by the device or, if the device did not record any speed, the
+    /// maximum speed calculated between consecutive points.
+    pub max_speed_km_per_h: Option<f64>,
+}
+
+
+/// Calculates statistics over the segments (pairs of consecutive points) of the lines.
+pub fn compute_statistics(lines: &[Vec<Point>], moving_threshold_km_per_h: f64, distance_algorithm: DistanceAlgorithm) -> Statistics {
+    let mut stats = Statistics::default();
+    let mut moving_distance_m = 0.0;
+    let mut grade_adjusted_distance_m = 0.0;
+    let mut any_gradient = false;
+    let mut max_recorded_speed: Option<f64> = None;
+    let mut max_computed_speed: Option<f64> = None;
+
+    for line in lines {
+        for point in line {
+            if let Some(speed) = point.speed_km_per_h {
+                max_recorded_speed = Some(max_recorded_speed.map_or(speed, |m| m.max(speed)));
+            }
+        }
+
+        for segment in line.windows(2) {
+            let distance_m = segment[0].distance_m(&segment[1], distance_algorithm);
+            stats.total_distance_m += distance_m;
+
+            let duration_s = match (segment[0].unix_timestamp, segment[1].unix_timestamp) {
+                (Some(t1), Some(t2)) => t2 - t1,
+                _ => continue,
+            };
+            if duration_s <= 0.0 {
+                continue;
+            }
+
+            let speed_km_per_h = distance_m / duration_s * 3.6;
+            max_computed_speed = Some(max_computed_speed.map_or(speed_km_per_h, |m| m.max(speed_km_per_h)));
+            if speed_km_per_h >= moving_threshold_km_per_h {
+                stats.moving_time_s += duration_s;
+                moving_distance_m += distance_m;
+
+                // segments of unknown gradient count as flat
+                let gradient_opt = gradient_percent(&segment[0], &segment[1], distance_algorithm);
+                any_gradient |= gradient_opt.is_some();
+                grade_adjusted_distance_m += distance_m * gradient_opt.map_or(1.0, grade_adjustment_factor);
+            }
+        }
+    }
+
+    let first_timestamp = lines.iter()
+        .flat_map(|l| l.iter())
+        .find_map(|p| p.unix_timestamp);
+    let last_timestamp = lines.iter()
+        .rev()
+        .flat_map(|l| l.iter().rev())
+        .find_map(|p| p.unix_timestamp);
+    if let (Some(first), Some(last)) = (first_timestamp, last_timestamp) {
+        stats.elapsed_time_s = last - first;
+    }
+
+    if stats.moving_time_s > 0.0 {
+        stats.avg_moving_speed_km_per_h = Some(moving_distance_m / stats.moving_time_s * 3.6);
+        if any_gradient {
+            stats.avg_grade_adjusted_speed_km_per_h = Some(grade_adjusted_distance_m / stats.moving_time_s * 3.6);
+        }
+    }
+    stats.max_speed_km_per_h = max_recorded_speed.or(max_computed_speed);
+
+    stats
+}
+
+
+/// Estimates the energy expended over the lines from the heart rate, in kilocalories.
+///
+/// Uses the heart rate equations of Keytel et al. (2005), averaging the coefficients for men and
+/// women. Each segment with timestamps contributes according to the mean heart rate of its points;
+/// segments without any heart rate do not contribute. Returns `None` if no segment contributes.
+pub fn estimate_calories_kcal(lines: &[Vec<Point>], weight_kg: f64, age_years: f64) -> Option<f64> {
+    let mut total_kcal = None;
+    for segment in lines.iter().flat_map(|l| l.windows(2)) {
+        let duration_s = match (segment[0].unix_timestamp, segment[1].unix_timestamp) {
+            (Some(t1), Some(t2)) if t2 > t1 => t2 - t1,
+            _ => continue,
+        };
+        let heart_rate_bpm = match (segment[0].heart_rate_bpm, segment[1].heart_rate_bpm) {
+            (Some(hr1), Some(hr2)) => (hr1 + hr2) as f64 / 2.0,
+            (Some(hr), None) | (None, Some(hr)) => hr as f64,
+            (None, None) => continue,
+        };
+        let kj_per_min = -37.74955 + 0.53905 * heart_rate_bpm + 0.03625 * weight_kg + 0.13785 * age_years;
+        let kcal = kj_per_min.max(0.0) / 4.184 * duration_s / 60.0;
+        *total_kcal.get_or_insert(0.0) += kcal;
+    }
+    total_kcal
+}
+
+
+/// Calculates Banister's training impulse (TRIMP) over the lines from the heart rate.
+///
+/// Each segment with timestamps contributes its duration in minutes, weighted by its fraction of the
+/// heart rate reserve (between the resting and the maximum heart rate) and by the factor
+/// `0.64 · e^(1.92 · fraction)`, which models the disproportionate load of high intensities. Returns
+/// `None` if no segment contributes or the maximum heart rate is not above the resting heart rate.
+pub fn training_impulse(lines: &[Vec<Point>], resting_heart_rate_bpm: u64, max_heart_rate_bpm: u64) -> Option<f64> {
+    if max_heart_rate_bpm <= resting_heart_rate_bpm {
+        return None;
+    }
+    let reserve_bpm = (max_heart_rate_bpm - resting_heart_rate_bpm) as f64;
+
+    let mut total = None;
+    for segment in lines.iter().flat_map(|l| l.windows(2)) {
+        let duration_s = match (segment[0].unix_timestamp, segment[1].unix_timestamp) {
+            (Some(t1), Some(t2)) if t2 > t1 => t2 - t1,
+            _ => continue,
+        };
+        let heart_rate_bpm = match (segment[0].heart_rate_bpm, segment[1].heart_rate_bpm) {
+            (Some(hr1), Some(hr2)) => (hr1 + hr2) as f64 / 2.0,
+            (Some(hr), None) | (None, Some(hr)) => hr as f64,
+            (None, None) => continue,
+        };
+        let fraction = ((heart_rate_bpm - resting_heart_rate_bpm as f64) / reserve_bpm).clamp(0.0, 1.0);
+        *total.get_or_insert(0.0) += duration_s / 60.0 * fraction * 0.64 * (1.92 * fraction).exp();
+    }
+    total
+}
+
+
+/// The normalized power of an activity along with the duration over which it was calculated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NormalizedPower {
+    pub normalized_power_w: f64,
+
+    /// The recorded time (excluding pauses) covered by the power values, in seconds.
+    pub duration_s: f64,
+}
+impl NormalizedPower {
+    /// Calculates the intensity factor, the ratio of the normalized power to the functional
+    /// threshold power.
+    pub fn intensity_factor(&self, ftp_w: f64) -> f64 {
+        self.normalized_power_w / ftp_w
+    }
+
+    /// Calculates the training stress score: the duration in hours, weighted by the square of the
+    /// intensity factor, times 100. An hour at the functional threshold power scores 100.
+    pub fn training_stress_score(&self, ftp_w: f64) -> f64 {
+        let intensity_factor = self.intensity_factor(ftp_w);
+        self.duration_s / 3600.0 * intensity_factor * intensity_factor * 100.0
+    }
+}
+
+
+/// Calculates the normalized power over the lines.
+///
+/// The power is resampled to one value per second, each point's power holding until the next
+/// point. The fourth root of the mean of the fourth powers of the 30-second rolling average of
+/// these values is the normalized power, which reflects the physiological cost of varying efforts
+/// better than the mean power. Pauses between lines are skipped. Returns `None` if less than 30
+/// seconds of power have been recorded.
+pub fn normalized_power(lines: &[Vec<Point>]) -> Option<NormalizedPower> {
+    let mut samples_w: Vec<f64> = Vec::new();
+    for segment in lines.iter().flat_map(|l| l.windows(2)) {
+        let duration_s = match (segment[0].unix_timestamp, segment[1].unix_timestamp) {
+            (Some(t1), Some(t2)) if t2 > t1 => (t2 - t1).round() as usize,
+            _ => continue,
+        };
+        if let Some(power_w) = segment[0].power_w {
+            samples_w.extend(std::iter::repeat_n(power_w as f64, duration_s));
+        }
+    }
+    if samples_w.len() < NORMALIZED_POWER_WINDOW_S {
+        return None;
+    }
+
+    let mut window_sum_w: f64 = samples_w[..NORMALIZED_POWER_WINDOW_S].iter().sum();
+    let mut fourth_power_sum = 0.0;
+    let mut window_count = 0;
+    for i in NORMALIZED_POWER_WINDOW_S..=samples_w.len() {
+        if i > NORMALIZED_POWER_WINDOW_S {
+            window_sum_w += samples_w[i - 1] - samples_w[i - 1 - NORMALIZED_POWER_WINDOW_S];
+        }
+        fourth_power_sum += (window_sum_w / NORMALIZED_POWER_WINDOW_S as f64).powi(4);
+        window_count += 1;
+    }
+    Some(NormalizedPower {
+        normalized_power_w: (fourth_power_sum / window_count as f64).powf(0.25),
+        duration_s: samples_w.len() as f64,
+    })
+}
+
+
+/// Converts the statistics into a JSON object, with distances and speeds in the given units.
+/// Values that cannot be calculated are omitted.
+pub fn statistics_to_json(stats: &Statistics, units: Units) -> serde_json::Map<String, serde_json::Value> {
+    let mut ret = serde_json::Map::new();
+    ret.insert("elapsed_time".to_owned(), stats.elapsed_time_s.into());
+    ret.insert("moving_time".to_owned(), stats.moving_time_s.into());
+    ret.insert("distance".to_owned(), units.distance(stats.total_distance_m).into());
+    if let Some(avg_speed) = stats.avg_moving_speed_km_per_h {
+        ret.insert("avg_moving_speed".to_owned(), units.speed(avg_speed).into());
+    }
+    if let Some(gap) = stats.avg_grade_adjusted_speed_km_per_h.and_then(|s| units.pace_min_per_unit(s)) {
+        ret.insert("avg_gap_pace".to_owned(), gap.into());
+    }
+    if let Some(max_speed) = stats.max_speed_km_per_h {
+        ret.insert("max_speed".to_owned(), units.speed(max_speed).into());
+    }
+    ret
+}
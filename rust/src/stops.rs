@@ -0,0 +1,98 @@
+//! Detection of stops (such as lunch breaks or photo stops) during which the timer kept running.
+
+
+use chrono::{DateTime, Local};
+
+use crate::activity::Pause;
+use crate::point::{DistanceAlgorithm, GeoPoint, Point};
+
+
+/// The default radius within which the position must stay for a stop, in meters.
+pub const DEFAULT_STOP_RADIUS_M: f64 = 50.0;
+
+
+/// An interval during which the position stayed within a small area while the timer was running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {
+    /// The time of the first point of the stop.
+    pub start_time: DateTime<Local>,
+
+    /// The time of the last point of the stop.
+    pub end_time: DateTime<Local>,
+
+    /// The mean position of the points of the stop.
+    pub position_deg: geo::Point<f64>,
+
+    /// The number of points recorded during the stop.
+    pub point_count: usize,
+}
+impl Stop {
+    /// The duration of the stop, in seconds.
+    pub fn duration_s(&self) -> f64 {
+        (self.end_time - self.start_time).num_milliseconds() as f64 / 1000.0
+    }
+}
+
+
+/// Returns whether the timer was stopped between the two times.
+fn paused_between(pauses: &[Pause], time1: &DateTime<Local>, time2: &DateTime<Local>) -> bool {
+    pauses.iter()
+        .any(|p| p.start_time >= *time1 && p.start_time < *time2)
+}
+
+
+/// Finds the stops within the lines.
+///
+/// Starting at each point, the following points are collected as long as they are within
+/// `radius_m` of it; if they span at least `min_duration_s` seconds, they form a stop and the
+/// search continues after them. Points without timestamps end a stop, as do the ends of lines and
+/// pauses of the timer, so that only the time during which the timer was running is counted.
+pub fn detect_stops(lines: &[Vec<Point>], pauses: &[Pause], radius_m: f64, min_duration_s: f64, distance_algorithm: DistanceAlgorithm) -> Vec<Stop> {
+    let mut stops = Vec::new();
+    for line in lines {
+        let mut start_index = 0;
+        while start_index < line.len() {
+            let anchor = &line[start_index];
+            let start_time = match anchor.timestamp {
+                Some(t) => t,
+                None => {
+                    start_index += 1;
+                    continue;
+                },
+            };
+
+            let mut end_index = start_index;
+            let mut end_time = start_time;
+            for (i, point) in line.iter().enumerate().skip(start_index + 1) {
+                let time = match point.timestamp {
+                    Some(t) => t,
+                    None => break,
+                };
+                if anchor.distance_m(point, distance_algorithm) > radius_m || paused_between(pauses, &end_time, &time) {
+                    break;
+                }
+                end_index = i;
+                end_time = time;
+            }
+
+            let duration_s = (end_time - start_time).num_milliseconds() as f64 / 1000.0;
+            if end_index == start_index || duration_s < min_duration_s {
+                start_index += 1;
+                continue;
+            }
+
+            let points = &line[start_index..=end_index];
+            let count = points.len() as f64;
+            let longitude = points.iter().map(|p| p.longitude()).sum::<f64>() / count;
+            let latitude = points.iter().map(|p| p.latitude()).sum::<f64>() / count;
+            stops.push(Stop {
+                start_time,
+                end_time,
+                position_deg: geo::Point::new(longitude, latitude),
+                point_count: points.len(),
+            });
+            start_index = end_index + 1;
+        }
+    }
+    stops
+}
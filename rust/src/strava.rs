@@ -0,0 +1,129 @@
+//! Export of the per-point data in the format of the streams returned by the Strava API, so that
+//! tools built for Strava data can consume local activities.
+//!
+//! The streams are keyed by type (as with `key_by_type=true`) and are parallel arrays with one
+//! value per point; values that have not been recorded for a point are `null`. Streams without any
+//! values are omitted.
+
+
+use std::io::Write;
+
+use serde_json::{Map, Value};
+
+use crate::activity::Activity;
+use crate::elevation::gradient_percent;
+use crate::error::WalkingError;
+use crate::geojson::write_json;
+use crate::point::{DistanceAlgorithm, GeoPoint, Point};
+use crate::stats::DEFAULT_MOVING_THRESHOLD_KM_PER_H;
+
+
+/// Options influencing the generated streams.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamsOptions {
+    /// The method by which distances between points are calculated.
+    pub distance_algorithm: DistanceAlgorithm,
+
+    /// The speed below which a point does not count as moving, in km/h.
+    pub moving_threshold_km_per_h: f64,
+
+    /// Whether to output minified instead of pretty-printed JSON.
+    pub compact: bool,
+}
+impl Default for StreamsOptions {
+    fn default() -> Self {
+        Self {
+            distance_algorithm: DistanceAlgorithm::default(),
+            moving_threshold_km_per_h: DEFAULT_MOVING_THRESHOLD_KM_PER_H,
+            compact: false,
+        }
+    }
+}
+
+
+/// Wraps the values of a stream into a stream object. Returns `None` if no point has a value.
+fn stream(data: Vec<Value>) -> Option<Value> {
+    if data.iter().all(|v| v.is_null()) {
+        return None;
+    }
+    Some(serde_json::json!({
+        "original_size": data.len(),
+        "resolution": "high",
+        "series_type": "distance",
+        "data": data,
+    }))
+}
+
+/// Extracts one value per point into a stream.
+fn point_stream<F: Fn(&Point) -> Option<Value>>(points: &[&Point], value: F) -> Option<Value> {
+    stream(
+        points.iter()
+            .map(|p| value(p).unwrap_or(Value::Null))
+            .collect()
+    )
+}
+
+
+/// Converts the points of the activity into Strava-style streams.
+///
+/// `time` counts the seconds since the first timestamp of the activity; `distance` is the distance
+/// travelled along the lines, not counting the gaps between lines. `grade_smooth` is the gradient
+/// from the previous point of the same line.
+pub fn activity_to_streams(activity: &Activity, options: &StreamsOptions) -> Map<String, Value> {
+    let points: Vec<&Point> = activity.lines.iter()
+        .flatten()
+        .collect();
+
+    let mut distances_m = Vec::with_capacity(points.len());
+    let mut gradients_percent = Vec::with_capacity(points.len());
+    let mut distance_m = 0.0;
+    for line in &activity.lines {
+        for (i, point) in line.iter().enumerate() {
+            if i > 0 {
+                distance_m += line[i-1].distance_m(point, options.distance_algorithm);
+                gradients_percent.push(gradient_percent(&line[i-1], point, options.distance_algorithm));
+            } else {
+                gradients_percent.push(None);
+            }
+            distances_m.push(distance_m);
+        }
+    }
+
+    let start_timestamp = points.iter()
+        .find_map(|p| p.unix_timestamp);
+
+    let mut streams = Map::new();
+    let mut insert = |name: &str, stream_opt: Option<Value>| {
+        if let Some(s) = stream_opt {
+            streams.insert(name.to_owned(), s);
+        }
+    };
+    insert("latlng", point_stream(&points, |p| Some(serde_json::json!([p.latitude(), p.longitude()]))));
+    insert("time", point_stream(&points, |p| {
+        let start = start_timestamp?;
+        p.unix_timestamp.map(|t| ((t - start).round() as i64).into())
+    }));
+    insert("distance", stream(distances_m.iter().map(|d| (*d).into()).collect()));
+    insert("altitude", point_stream(&points, |p| p.elevation_m.map(|e| e.into())));
+    insert("heartrate", point_stream(&points, |p| p.heart_rate_bpm.map(|hr| hr.into())));
+    insert("cadence", point_stream(&points, |p| p.cadence_rpm.map(|c| c.into())));
+    insert("velocity_smooth", point_stream(&points, |p| p.speed_km_per_h.map(|s| (s / 3.6).into())));
+    insert("moving", point_stream(&points, |p| p.speed_km_per_h.map(|s| (s >= options.moving_threshold_km_per_h).into())));
+    insert("temp", point_stream(&points, |p| p.temperature_degc.map(|t| t.into())));
+    insert("watts", point_stream(&points, |p| p.power_w.map(|w| w.into())));
+    insert("grade_smooth", stream(gradients_percent.iter().map(|g| g.map(|g| g.into()).unwrap_or(Value::Null)).collect()));
+    streams
+}
+
+
+/// Writes the Strava-style streams of the activity as JSON (pretty-printed unless
+/// `options.compact` is set).
+pub fn write_streams<W: Write>(writer: W, activity: &Activity, options: &StreamsOptions) -> Result<(), WalkingError> {
+    let mut streams = Value::Object(activity_to_streams(activity, options));
+    streams.sort_all_objects();
+    if options.compact {
+        write_json(&streams, writer, serde_json::ser::CompactFormatter)
+    } else {
+        write_json(&streams, writer, serde_json::ser::PrettyFormatter::new())
+    }
+}
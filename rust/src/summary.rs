@@ -0,0 +1,321 @@
+//! Condensed statistics of whole activities, for overviews over many files.
+
+
+use std::fmt::Write as _;
+use std::io::Write;
+
+use chrono::{DateTime, Local};
+use clap::ValueEnum;
+use serde_json::{Map, Value};
+
+use crate::activity::Activity;
+use crate::elevation::{DEFAULT_ELEVATION_THRESHOLD_M, elevation_gain_loss};
+use crate::error::WalkingError;
+use crate::indoor::{is_indoor, sample_statistics};
+use crate::point::DistanceAlgorithm;
+use crate::stats::{compute_statistics, DEFAULT_MOVING_THRESHOLD_KM_PER_H, training_impulse};
+use crate::timezone::Timezone;
+use crate::units::Units;
+
+
+/// The format in which summaries are output.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum SummaryFormat {
+    /// A plain-text table with aligned columns.
+    #[default]
+    Table,
+
+    /// A JSON array with one object per row.
+    Json,
+}
+
+
+/// Options influencing the calculation and output of summaries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SummaryOptions {
+    /// The method by which distances between points are calculated.
+    pub distance_algorithm: DistanceAlgorithm,
+
+    /// The speed below which a segment does not count towards the moving time, in km/h.
+    pub moving_threshold_km_per_h: f64,
+
+    /// The minimum change in elevation that counts towards the ascent, in meters.
+    pub elevation_threshold_m: f64,
+
+    /// The units in which distances and speeds are output in the table.
+    pub units: Units,
+
+    /// The time zone in which dates are output.
+    pub timezone: Timezone,
+
+    /// The resting and maximum heart rates with which the training impulse (TRIMP) is calculated,
+    /// if it is to be output.
+    pub trimp_heart_rates_bpm: Option<(u64, u64)>,
+}
+impl Default for SummaryOptions {
+    fn default() -> Self {
+        Self {
+            distance_algorithm: DistanceAlgorithm::default(),
+            moving_threshold_km_per_h: DEFAULT_MOVING_THRESHOLD_KM_PER_H,
+            elevation_threshold_m: DEFAULT_ELEVATION_THRESHOLD_M,
+            units: Units::default(),
+            timezone: Timezone::default(),
+            trimp_heart_rates_bpm: None,
+        }
+    }
+}
+
+
+/// The condensed statistics of an activity (or of multiple activities combined).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ActivitySummary {
+    /// The name of the row, usually the input file name.
+    pub name: String,
+
+    /// The time at which recording started.
+    pub start_time: Option<DateTime<Local>>,
+
+    /// The time zone derived from the position of the activity, in which its date is output if the
+    /// time zone of the options is `Auto`.
+    pub timezone: Option<Timezone>,
+
+    pub sport: Option<String>,
+
+    /// The total length of all lines, in meters.
+    pub distance_m: f64,
+
+    /// The time between the first and the last timestamp, in seconds.
+    pub elapsed_time_s: f64,
+
+    /// The time spent at or above the moving threshold speed, in seconds.
+    pub moving_time_s: f64,
+
+    /// The mean of the heart rates of all points.
+    pub avg_heart_rate_bpm: Option<f64>,
+
+    /// The distance covered while moving divided by the moving time.
+    pub avg_moving_speed_km_per_h: Option<f64>,
+
+    /// The elevation gain, in meters.
+    pub ascent_m: f64,
+
+    /// Banister's training impulse, if requested and the activity has heart rates.
+    pub trimp: Option<f64>,
+}
+
+
+/// Calculates the summary of an activity. The distance and durations of activities recorded indoors
+/// are calculated from their samples.
+pub fn summarize_activity(activity: &Activity, name: &str, options: &SummaryOptions) -> ActivitySummary {
+    let indoor = is_indoor(activity);
+    let stats = if indoor {
+        sample_statistics(&activity.samples, options.moving_threshold_km_per_h)
+    } else {
+        compute_statistics(&activity.lines, options.moving_threshold_km_per_h, options.distance_algorithm)
+    };
+    let (ascent_m, _descent_m) = elevation_gain_loss(&activity.lines, options.elevation_threshold_m);
+
+    let heart_rates: Vec<u64> = if indoor {
+        activity.samples.iter()
+            .filter_map(|s| s.heart_rate_bpm)
+            .collect()
+    } else {
+        activity.lines.iter()
+            .flatten()
+            .filter_map(|p| p.heart_rate_bpm)
+            .collect()
+    };
+    let avg_heart_rate_bpm = if heart_rates.is_empty() {
+        None
+    } else {
+        Some(heart_rates.iter().sum::<u64>() as f64 / heart_rates.len() as f64)
+    };
+
+    ActivitySummary {
+        name: name.to_owned(),
+        start_time: activity.recording_start_time(),
+        timezone: (options.timezone == Timezone::Auto).then(|| options.timezone.for_lines(&activity.lines)),
+        sport: activity.metadata.sport.clone(),
+        distance_m: stats.total_distance_m,
+        elapsed_time_s: stats.elapsed_time_s,
+        moving_time_s: stats.moving_time_s,
+        avg_heart_rate_bpm,
+        avg_moving_speed_km_per_h: stats.avg_moving_speed_km_per_h,
+        ascent_m,
+        trimp: options.trimp_heart_rates_bpm
+            .and_then(|(resting_bpm, max_bpm)| training_impulse(&activity.lines, resting_bpm, max_bpm)),
+    }
+}
+
+
+/// Combines the summaries of multiple activities into a total. The average heart rate is weighted
+/// by the elapsed time of each activity; the sport is only kept if all activities share it. The
+/// training impulses add up. The date is that of the first activity, in its time zone.
+pub fn combine_summaries(summaries: &[ActivitySummary], name: &str) -> ActivitySummary {
+    let first = summaries.iter()
+        .filter(|s| s.start_time.is_some())
+        .min_by_key(|s| s.start_time);
+    let mut total = ActivitySummary {
+        name: name.to_owned(),
+        start_time: first.and_then(|s| s.start_time),
+        timezone: first.and_then(|s| s.timezone.clone()),
+        sport: summaries.first().and_then(|s| s.sport.clone()),
+        ..ActivitySummary::default()
+    };
+
+    let mut moving_distance_m = 0.0;
+    let mut heart_rate_weighted_sum = 0.0;
+    let mut heart_rate_time_s = 0.0;
+    for summary in summaries {
+        if summary.sport != total.sport {
+            total.sport = None;
+        }
+        total.distance_m += summary.distance_m;
+        total.elapsed_time_s += summary.elapsed_time_s;
+        total.moving_time_s += summary.moving_time_s;
+        total.ascent_m += summary.ascent_m;
+        if let Some(trimp) = summary.trimp {
+            *total.trimp.get_or_insert(0.0) += trimp;
+        }
+        if let Some(speed) = summary.avg_moving_speed_km_per_h {
+            moving_distance_m += speed / 3.6 * summary.moving_time_s;
+        }
+        if let Some(hr) = summary.avg_heart_rate_bpm {
+            heart_rate_weighted_sum += hr * summary.elapsed_time_s;
+            heart_rate_time_s += summary.elapsed_time_s;
+        }
+    }
+
+    if total.moving_time_s > 0.0 {
+        total.avg_moving_speed_km_per_h = Some(moving_distance_m / total.moving_time_s * 3.6);
+    }
+    if heart_rate_time_s > 0.0 {
+        total.avg_heart_rate_bpm = Some(heart_rate_weighted_sum / heart_rate_time_s);
+    }
+    total
+}
+
+
+/// Returns the time zone in which the date of the summary is output.
+fn summary_timezone<'a>(summary: &'a ActivitySummary, options: &'a SummaryOptions) -> &'a Timezone {
+    summary.timezone.as_ref().unwrap_or(&options.timezone)
+}
+
+
+/// Formats a duration as hours, minutes and seconds.
+fn format_duration(duration_s: f64) -> String {
+    let total_s = duration_s.round() as u64;
+    format!("{}:{:02}:{:02}", total_s / 3600, (total_s / 60) % 60, total_s % 60)
+}
+
+
+/// Converts a summary into a JSON object. Distances and elevations are in meters, durations in
+/// seconds and speeds in km/h, regardless of the units option; missing values are `null`.
+pub fn summary_to_json(summary: &ActivitySummary, options: &SummaryOptions) -> Map<String, Value> {
+    let mut ret = Map::new();
+    ret.insert("name".to_owned(), summary.name.clone().into());
+    ret.insert(
+        "date".to_owned(),
+        summary.start_time
+            .map(|t| summary_timezone(summary, options).convert(&t).format("%Y-%m-%d").to_string().into())
+            .unwrap_or(Value::Null),
+    );
+    ret.insert("sport".to_owned(), summary.sport.clone().into());
+    ret.insert("distance".to_owned(), summary.distance_m.into());
+    ret.insert("elapsed_time".to_owned(), summary.elapsed_time_s.into());
+    ret.insert("moving_time".to_owned(), summary.moving_time_s.into());
+    ret.insert("avg_heart_rate".to_owned(), summary.avg_heart_rate_bpm.into());
+    ret.insert("avg_moving_speed".to_owned(), summary.avg_moving_speed_km_per_h.into());
+    ret.insert("ascent".to_owned(), summary.ascent_m.into());
+    if options.trimp_heart_rates_bpm.is_some() {
+        ret.insert("trimp".to_owned(), summary.trimp.into());
+    }
+    ret
+}
+
+
+/// Writes the summaries as a JSON array (pretty-printed unless `compact` is set).
+pub fn write_summary_json<W: Write>(mut writer: W, summaries: &[ActivitySummary], options: &SummaryOptions, compact: bool) -> Result<(), WalkingError> {
+    let mut rows: Value = summaries.iter()
+        .map(|s| Value::Object(summary_to_json(s, options)))
+        .collect();
+    rows.sort_all_objects();
+    if compact {
+        serde_json::to_writer(&mut writer, &rows)?;
+    } else {
+        serde_json::to_writer_pretty(&mut writer, &rows)?;
+    }
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+
+/// Writes the summaries as a plain-text table with one row per summary. Distances and speeds are
+/// output in kilometers or miles according to `options.units`.
+pub fn write_summary_table<W: Write>(mut writer: W, summaries: &[ActivitySummary], options: &SummaryOptions) -> Result<(), WalkingError> {
+    let (distance_unit, speed_unit) = match options.units {
+        Units::Metric => ("km", "km/h"),
+        Units::Imperial => ("mi", "mph"),
+    };
+    let unit_km = options.units.distance_unit_km();
+
+    let mut header = vec![
+        "name".to_owned(),
+        "date".to_owned(),
+        "sport".to_owned(),
+        format!("distance ({})", distance_unit),
+        "duration".to_owned(),
+        "avg HR".to_owned(),
+        format!("avg speed ({})", speed_unit),
+        "ascent (m)".to_owned(),
+    ];
+    if options.trimp_heart_rates_bpm.is_some() {
+        header.push("TRIMP".to_owned());
+    }
+    let mut rows = vec![header];
+    for summary in summaries {
+        let mut row = vec![
+            summary.name.clone(),
+            summary.start_time
+                .map(|t| summary_timezone(summary, options).convert(&t).format("%Y-%m-%d").to_string())
+                .unwrap_or_default(),
+            summary.sport.clone().unwrap_or_default(),
+            format!("{:.2}", summary.distance_m / 1000.0 / unit_km),
+            format_duration(summary.elapsed_time_s),
+            summary.avg_heart_rate_bpm
+                .map(|hr| format!("{:.0}", hr))
+                .unwrap_or_default(),
+            summary.avg_moving_speed_km_per_h
+                .map(|s| format!("{:.1}", s / unit_km))
+                .unwrap_or_default(),
+            format!("{:.0}", summary.ascent_m),
+        ];
+        if options.trimp_heart_rates_bpm.is_some() {
+            row.push(summary.trimp.map(|t| format!("{:.0}", t)).unwrap_or_default());
+        }
+        rows.push(row);
+    }
+
+    let column_count = rows[0].len();
+    let widths: Vec<usize> = (0..column_count)
+        .map(|c| rows.iter().map(|r| r[c].chars().count()).max().unwrap_or(0))
+        .collect();
+    for row in &rows {
+        let mut line = String::new();
+        for (c, cell) in row.iter().enumerate() {
+            if c > 0 {
+                line.push_str("  ");
+            }
+            // text columns are left-aligned, numeric columns right-aligned
+            if c < 3 {
+                write!(line, "{:<width$}", cell, width = widths[c]).unwrap();
+            } else {
+                write!(line, "{:>width$}", cell, width = widths[c]).unwrap();
+            }
+        }
+        writeln!(writer, "{}", line.trim_end())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,39 @@
+//! Handling of open-water swims, whose positions are far noisier than on land (the watch only
+//! receives a GPS signal while the arm is out of the water) and whose cadence is the stroke rate.
+
+
+use std::collections::BTreeMap;
+
+use crate::activity::Summary;
+use crate::point::{DistanceAlgorithm, Point};
+use crate::smoothing::{derive_speed, smooth_positions, smooth_speed, SmoothingMethod};
+
+
+/// The size of the window over which the positions of a swim are smoothed.
+pub const SWIM_POSITION_WINDOW: usize = 9;
+
+/// The size of the window over which the speeds of a swim are smoothed.
+pub const SWIM_SPEED_WINDOW: usize = 15;
+
+
+/// Smooths the positions and speeds of a swim, deriving the speeds of points without one from the
+/// smoothed positions. Returns the number of points whose speed has been derived.
+pub fn smooth_swim(lines: &mut [Vec<Point>], method: SmoothingMethod, distance_algorithm: DistanceAlgorithm) -> usize {
+    smooth_positions(lines, SWIM_POSITION_WINDOW, method);
+    let derived_count = derive_speed(lines, method, distance_algorithm);
+    smooth_speed(lines, SWIM_SPEED_WINDOW, method);
+    derived_count
+}
+
+
+/// Returns the time spent swimming each stroke (e.g. `freestyle`), in seconds, according to the
+/// timer times of the laps. Laps without a stroke are not counted.
+pub fn stroke_times_s(laps: &[Summary]) -> BTreeMap<String, f64> {
+    let mut times = BTreeMap::new();
+    for lap in laps {
+        if let (Some(stroke), Some(time_s)) = (&lap.swim_stroke, lap.total_timer_time_s.or(lap.total_elapsed_time_s)) {
+            *times.entry(stroke.clone()).or_insert(0.0) += time_s;
+        }
+    }
+    times
+}
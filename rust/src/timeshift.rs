@@ -0,0 +1,76 @@
+//! Moving the timestamps of activities, which hides when they took place.
+
+
+use std::fmt;
+use std::hash::{BuildHasher, Hash, RandomState};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use chrono::TimeDelta;
+
+
+/// The largest offset by which `--randomize-start` moves an activity, in seconds (a year).
+const MAX_RANDOM_TIME_OFFSET_S: u64 = 365 * 24 * 60 * 60;
+
+
+/// An offset by which all timestamps are moved, given as a sequence of numbers with units (`d`,
+/// `h`, `min` or `s`) with an optional sign, e.g. `-3d`, `+2h30min` or `90s`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TimeOffset(pub TimeDelta);
+impl fmt::Display for TimeOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}s", self.0.num_seconds())
+    }
+}
+impl FromStr for TimeOffset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let trimmed = s.trim();
+        let (negative, mut rest) = match trimmed.strip_prefix('-') {
+            Some(r) => (true, r),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        if rest.is_empty() {
+            return Err(format!("time offset {:?} is empty", s));
+        }
+
+        let mut seconds: i64 = 0;
+        while !rest.is_empty() {
+            let unit_start = rest.find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let number: i64 = rest[..unit_start].parse()
+                .map_err(|_| format!("time offset {:?} is not in a format such as -3d or +2h30min", s))?;
+            rest = &rest[unit_start..];
+            let unit_end = rest.find(|c: char| c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let unit_s = match &rest[..unit_end] {
+                "d" => 24 * 60 * 60,
+                "h" => 60 * 60,
+                "min" => 60,
+                "s"|"" => 1,
+                other => return Err(format!("unknown unit {:?} of time offset {:?} (use d, h, min or s)", other, s)),
+            };
+            rest = &rest[unit_end..];
+            seconds = number.checked_mul(unit_s)
+                .and_then(|n| seconds.checked_add(n))
+                .ok_or_else(|| format!("time offset {:?} is too large", s))?;
+        }
+        let delta = TimeDelta::try_seconds(if negative { -seconds } else { seconds })
+            .ok_or_else(|| format!("time offset {:?} is too large", s))?;
+        Ok(Self(delta))
+    }
+}
+
+
+/// Returns a random offset of whole seconds of up to a year in either direction, which hides the
+/// date as well as the time of day at which an activity took place.
+///
+/// The offset is drawn once per run for each key (such as the path of the input file), so that an
+/// activity that is read repeatedly keeps its offset.
+pub fn random_time_offset<K: Hash>(key: K) -> TimeDelta {
+    static RANDOM_STATE: OnceLock<RandomState> = OnceLock::new();
+    let random = RANDOM_STATE.get_or_init(RandomState::new).hash_one(key);
+    let seconds = (random % (2 * MAX_RANDOM_TIME_OFFSET_S + 1)) as i64 - MAX_RANDOM_TIME_OFFSET_S as i64;
+    TimeDelta::seconds(seconds)
+}
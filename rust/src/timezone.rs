@@ -0,0 +1,583 @@
+//! Time zones in which timestamps are output.
+//!
+//! Named time zones are read from the system's time zone database (TZif files in
+//! `/usr/share/zoneinfo` or the directory given in the `TZDIR` environment variable).
+//!
+//! The time zone database does not contain the boundaries of the time zones, only the position of
+//! the principal location of each zone (e.g. Vienna for `Europe/Vienna`). Time zones derived from
+//! the position of an activity are therefore those of the nearest principal location, which can be
+//! wrong close to the boundaries of zones whose offsets differ.
+
+
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, Offset, Utc};
+use geo::{Distance, Haversine};
+
+use crate::point::Point;
+
+
+/// The directory containing the time zone database if `TZDIR` is not set.
+const DEFAULT_ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// The file of the time zone database listing the zones along with the positions of their
+/// principal locations.
+const ZONE_TABLE_FILE: &str = "zone1970.tab";
+
+const SECONDS_PER_HOUR: i32 = 60 * 60;
+
+
+/// The day of the year on which a daylight saving time rule takes effect.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum RuleDate {
+    /// `Jn`: day 1 to 365; February 29 is never counted.
+    JulianWithoutLeap(u16),
+
+    /// `n`: day 0 to 365, counting February 29 in leap years.
+    JulianWithLeap(u16),
+
+    /// `Mm.w.d`: day `d` (0 = Sunday) of week `w` (1 to 5, 5 = last) of month `m`.
+    MonthWeekDay { month: u32, week: u32, weekday: u32 },
+}
+impl RuleDate {
+    /// Returns the date on which the rule takes effect in the given year.
+    fn date_in_year(&self, year: i32) -> Option<NaiveDate> {
+        let jan1 = NaiveDate::from_ymd_opt(year, 1, 1)?;
+        match *self {
+            Self::JulianWithoutLeap(n) => {
+                let is_leap_year = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+                let day_index = if is_leap_year && n >= 60 { n } else { n - 1 };
+                jan1.checked_add_days(chrono::Days::new(day_index.into()))
+            },
+            Self::JulianWithLeap(n) => jan1.checked_add_days(chrono::Days::new(n.into())),
+            Self::MonthWeekDay { month, week, weekday } => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let first_weekday = first.weekday().num_days_from_sunday();
+                let mut day = 1 + (weekday + 7 - first_weekday) % 7 + (week - 1) * 7;
+                // week 5 means the last such weekday of the month
+                while NaiveDate::from_ymd_opt(year, month, day).is_none() {
+                    day -= 7;
+                }
+                NaiveDate::from_ymd_opt(year, month, day)
+            },
+        }
+    }
+}
+
+
+/// A transition between standard and daylight saving time according to a rule.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct RuleTransition {
+    date: RuleDate,
+
+    /// The local time of the transition, in seconds after midnight (may be negative or exceed one
+    /// day).
+    time_s: i32,
+}
+impl RuleTransition {
+    /// Returns the Unix timestamp of the transition in the given year, given the UTC offset in
+    /// effect before it.
+    fn unix_timestamp(&self, year: i32, utc_offset_s: i32) -> Option<i64> {
+        let date = self.date.date_in_year(year)?;
+        let midnight = date.and_hms_opt(0, 0, 0)?.and_utc().timestamp();
+        Some(midnight + i64::from(self.time_s) - i64::from(utc_offset_s))
+    }
+}
+
+
+/// Daylight saving time according to a POSIX TZ rule.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct DstRule {
+    utc_offset_s: i32,
+    start: RuleTransition,
+    end: RuleTransition,
+}
+
+
+/// A POSIX TZ rule, as found at the end of TZif files, which applies after the last explicit
+/// transition.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct PosixRule {
+    std_utc_offset_s: i32,
+    dst: Option<DstRule>,
+}
+impl PosixRule {
+    fn utc_offset_s(&self, unix_timestamp: i64) -> i32 {
+        let dst = match &self.dst {
+            Some(d) => d,
+            None => return self.std_utc_offset_s,
+        };
+
+        let local_date = DateTime::from_timestamp(unix_timestamp + i64::from(self.std_utc_offset_s), 0)
+            .map(|dt| dt.date_naive());
+        let year = match local_date {
+            Some(d) => d.year(),
+            None => return self.std_utc_offset_s,
+        };
+        let start = dst.start.unix_timestamp(year, self.std_utc_offset_s);
+        let end = dst.end.unix_timestamp(year, dst.utc_offset_s);
+        let (start, end) = match (start, end) {
+            (Some(s), Some(e)) => (s, e),
+            _ => return self.std_utc_offset_s,
+        };
+
+        let is_dst = if start < end {
+            // northern hemisphere: DST in the middle of the year
+            unix_timestamp >= start && unix_timestamp < end
+        } else {
+            // southern hemisphere: DST around the turn of the year
+            !(unix_timestamp >= end && unix_timestamp < start)
+        };
+        if is_dst {
+            dst.utc_offset_s
+        } else {
+            self.std_utc_offset_s
+        }
+    }
+
+    /// Parses a POSIX TZ rule such as `CET-1CEST,M3.5.0,M10.5.0/3`.
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = PosixRuleParser { rest: text };
+
+        parser.name()?;
+        let std_utc_offset_s = -parser.offset()?;
+        if parser.rest.is_empty() {
+            return Ok(Self {
+                std_utc_offset_s,
+                dst: None,
+            });
+        }
+
+        parser.name()?;
+        let dst_utc_offset_s = if parser.rest.starts_with(',') || parser.rest.is_empty() {
+            std_utc_offset_s + SECONDS_PER_HOUR
+        } else {
+            -parser.offset()?
+        };
+        let (start, end) = if parser.rest.is_empty() {
+            // POSIX default (US rules)
+            (
+                RuleTransition { date: RuleDate::MonthWeekDay { month: 3, week: 2, weekday: 0 }, time_s: 2 * SECONDS_PER_HOUR },
+                RuleTransition { date: RuleDate::MonthWeekDay { month: 11, week: 1, weekday: 0 }, time_s: 2 * SECONDS_PER_HOUR },
+            )
+        } else {
+            parser.expect(',')?;
+            let start = parser.transition()?;
+            parser.expect(',')?;
+            let end = parser.transition()?;
+            (start, end)
+        };
+        if !parser.rest.is_empty() {
+            return Err(format!("trailing characters {:?} in time zone rule", parser.rest));
+        }
+
+        Ok(Self {
+            std_utc_offset_s,
+            dst: Some(DstRule {
+                utc_offset_s: dst_utc_offset_s,
+                start,
+                end,
+            }),
+        })
+    }
+}
+
+
+struct PosixRuleParser<'a> {
+    rest: &'a str,
+}
+impl PosixRuleParser<'_> {
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.rest.strip_prefix(c) {
+            Some(r) => {
+                self.rest = r;
+                Ok(())
+            },
+            None => Err(format!("expected {:?} in time zone rule at {:?}", c, self.rest)),
+        }
+    }
+
+    fn take_while<P: Fn(char) -> bool>(&mut self, predicate: P) -> &str {
+        let end = self.rest.find(|c| !predicate(c)).unwrap_or(self.rest.len());
+        let (taken, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        taken
+    }
+
+    fn number(&mut self) -> Result<i32, String> {
+        let digits = self.take_while(|c| c.is_ascii_digit());
+        digits.parse()
+            .map_err(|_| format!("expected a number in time zone rule at {:?}", self.rest))
+    }
+
+    /// Skips a time zone abbreviation, which is either alphabetic or enclosed in angle brackets.
+    fn name(&mut self) -> Result<(), String> {
+        if self.rest.starts_with('<') {
+            let end = self.rest.find('>')
+                .ok_or_else(|| "unterminated time zone abbreviation".to_owned())?;
+            self.rest = &self.rest[end+1..];
+        } else if self.take_while(|c| c.is_ascii_alphabetic()).is_empty() {
+            return Err(format!("expected a time zone abbreviation at {:?}", self.rest));
+        }
+        Ok(())
+    }
+
+    /// Parses `[+-]hh[:mm[:ss]]` into seconds.
+    fn offset(&mut self) -> Result<i32, String> {
+        let sign = if self.rest.starts_with('-') {
+            self.rest = &self.rest[1..];
+            -1
+        } else {
+            if self.rest.starts_with('+') {
+                self.rest = &self.rest[1..];
+            }
+            1
+        };
+        let mut seconds = self.number()? * SECONDS_PER_HOUR;
+        if self.rest.starts_with(':') {
+            self.rest = &self.rest[1..];
+            seconds += self.number()? * 60;
+            if self.rest.starts_with(':') {
+                self.rest = &self.rest[1..];
+                seconds += self.number()?;
+            }
+        }
+        Ok(sign * seconds)
+    }
+
+    fn transition(&mut self) -> Result<RuleTransition, String> {
+        let date = if self.rest.starts_with('M') {
+            self.rest = &self.rest[1..];
+            let month = self.number()?;
+            self.expect('.')?;
+            let week = self.number()?;
+            self.expect('.')?;
+            let weekday = self.number()?;
+            if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&weekday) {
+                return Err(format!("invalid date M{}.{}.{} in time zone rule", month, week, weekday));
+            }
+            RuleDate::MonthWeekDay { month: month as u32, week: week as u32, weekday: weekday as u32 }
+        } else if self.rest.starts_with('J') {
+            self.rest = &self.rest[1..];
+            let day = self.number()?;
+            if !(1..=365).contains(&day) {
+                return Err(format!("invalid date J{} in time zone rule", day));
+            }
+            RuleDate::JulianWithoutLeap(day as u16)
+        } else {
+            let day = self.number()?;
+            if !(0..=365).contains(&day) {
+                return Err(format!("invalid date {} in time zone rule", day));
+            }
+            RuleDate::JulianWithLeap(day as u16)
+        };
+
+        let time_s = if self.rest.starts_with('/') {
+            self.rest = &self.rest[1..];
+            self.offset()?
+        } else {
+            2 * SECONDS_PER_HOUR
+        };
+        Ok(RuleTransition { date, time_s })
+    }
+}
+
+
+/// Returns the directory containing the time zone database.
+fn zoneinfo_dir() -> PathBuf {
+    std::env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_ZONEINFO_DIR))
+}
+
+
+/// Parses a position in the ISO 6709 format of the zone tables (`±DDMM±DDDMM` or
+/// `±DDMMSS±DDDMMSS`) into a point of longitude and latitude in degrees.
+fn parse_iso6709(text: &str) -> Option<geo::Point<f64>> {
+    let parse_part = |part: &str, degree_digits: usize| -> Option<f64> {
+        let (sign, digits) = match part.split_at_checked(1)? {
+            ("+", d) => (1.0, d),
+            ("-", d) => (-1.0, d),
+            _ => return None,
+        };
+        if !digits.bytes().all(|b| b.is_ascii_digit()) || ![degree_digits + 2, degree_digits + 4].contains(&digits.len()) {
+            return None;
+        }
+        let number = |range: std::ops::Range<usize>| digits.get(range).map_or(0.0, |d| d.parse::<f64>().unwrap_or(0.0));
+        let degrees = number(0..degree_digits)
+            + number(degree_digits..degree_digits + 2) / 60.0
+            + number(degree_digits + 2..degree_digits + 4) / 3600.0;
+        Some(sign * degrees)
+    };
+    let longitude_start = text[1..].find(['+', '-'])? + 1;
+    let (latitude, longitude) = text.split_at(longitude_start);
+    Some(geo::Point::new(parse_part(longitude, 3)?, parse_part(latitude, 2)?))
+}
+
+
+/// Returns the name of the zone in the zone table (in the format of `zone1970.tab`) whose
+/// principal location is nearest to the position, or `None` if the table lists no zones.
+pub fn nearest_zone_name(zone_table: &str, position: geo::Point<f64>) -> Option<&str> {
+    zone_table.lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| {
+            let mut columns = l.split('\t').skip(1);
+            let location = parse_iso6709(columns.next()?)?;
+            let name = columns.next()?;
+            Some((name, Haversine::distance(location, position)))
+        })
+        .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2))
+        .map(|(name, _)| name)
+}
+
+
+/// A time zone from the time zone database.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ZoneInfo {
+    name: String,
+
+    /// The Unix timestamps at which the UTC offset changes, with the index of the offset that
+    /// applies from then on, in ascending order.
+    transitions: Vec<(i64, usize)>,
+
+    /// The UTC offsets in seconds referenced by the transitions.
+    utc_offsets_s: Vec<i32>,
+
+    /// The rule that applies after the last transition.
+    rule: Option<PosixRule>,
+}
+impl ZoneInfo {
+    /// Loads the time zone with the given name (e.g. `Europe/Vienna`) from the time zone database.
+    pub fn load(name: &str) -> Result<Self, String> {
+        let relative_path = Path::new(name);
+        let is_plain_relative = relative_path.components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if name.is_empty() || !is_plain_relative {
+            return Err(format!("invalid time zone name {:?}", name));
+        }
+
+        let data = std::fs::read(zoneinfo_dir().join(relative_path))
+            .map_err(|e| format!("unknown time zone {:?}: {}", name, e))?;
+        Self::from_tzif(name, &data)
+            .map_err(|e| format!("invalid time zone file for {:?}: {}", name, e))
+    }
+
+    /// Reads a time zone from the contents of a TZif file (RFC 8536).
+    pub fn from_tzif(name: &str, data: &[u8]) -> Result<Self, String> {
+        const HEADER_LENGTH: usize = 44;
+
+        let read_u32 = |offset: usize| -> Result<usize, String> {
+            data.get(offset..offset+4)
+                .map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+                .ok_or_else(|| "file too short".to_owned())
+        };
+        let counts = |header_offset: usize| -> Result<[usize; 6], String> {
+            if data.get(header_offset..header_offset+4) != Some(b"TZif") {
+                return Err("missing TZif magic".to_owned());
+            }
+            // isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+            let mut ret = [0; 6];
+            for (i, count) in ret.iter_mut().enumerate() {
+                *count = read_u32(header_offset + 20 + 4*i)?;
+            }
+            Ok(ret)
+        };
+
+        let version = *data.get(4).ok_or_else(|| "file too short".to_owned())?;
+
+        // version 2 and later repeat the data with 64-bit timestamps, followed by a rule
+        let is_v2 = version >= b'2';
+        let (header_offset, time_size, leap_size) = if is_v2 {
+            let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts(0)?;
+            let v1_length = timecnt*4 + timecnt + typecnt*6 + charcnt + leapcnt*8 + isstdcnt + isutcnt;
+            (HEADER_LENGTH + v1_length, 8, 12)
+        } else {
+            (0, 4, 8)
+        };
+        let [isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt] = counts(header_offset)?;
+
+        let times_offset = header_offset + HEADER_LENGTH;
+        let indexes_offset = times_offset + timecnt*time_size;
+        let types_offset = indexes_offset + timecnt;
+        let footer_offset = types_offset + typecnt*6 + charcnt + leapcnt*leap_size + isstdcnt + isutcnt;
+        if data.len() < footer_offset {
+            return Err("file too short".to_owned());
+        }
+
+        let mut utc_offsets_s = Vec::with_capacity(typecnt);
+        for i in 0..typecnt {
+            let type_offset = types_offset + 6*i;
+            let utc_offset = i32::from_be_bytes(data[type_offset..type_offset+4].try_into().unwrap());
+            utc_offsets_s.push(utc_offset);
+        }
+        if utc_offsets_s.is_empty() {
+            return Err("no local time types".to_owned());
+        }
+
+        let mut transitions = Vec::with_capacity(timecnt);
+        for i in 0..timecnt {
+            let time_offset = times_offset + i*time_size;
+            let time = if time_size == 8 {
+                i64::from_be_bytes(data[time_offset..time_offset+8].try_into().unwrap())
+            } else {
+                i64::from(i32::from_be_bytes(data[time_offset..time_offset+4].try_into().unwrap()))
+            };
+            let type_index = usize::from(data[indexes_offset + i]);
+            if type_index >= utc_offsets_s.len() {
+                return Err(format!("transition {} references unknown local time type {}", i, type_index));
+            }
+            transitions.push((time, type_index));
+        }
+
+        let rule = match data.get(footer_offset..) {
+            Some(footer) if is_v2 && footer.len() > 2 && footer[0] == b'\n' => {
+                let footer_text = std::str::from_utf8(&footer[1..])
+                    .map_err(|_| "footer is not valid UTF-8".to_owned())?;
+                let rule_text = footer_text.trim_end_matches('\n');
+                if rule_text.is_empty() {
+                    None
+                } else {
+                    Some(PosixRule::parse(rule_text)?)
+                }
+            },
+            _ => None,
+        };
+
+        Ok(Self {
+            name: name.to_owned(),
+            transitions,
+            utc_offsets_s,
+            rule,
+        })
+    }
+
+    /// Loads the time zone whose principal location is nearest to the position from the time zone
+    /// database.
+    pub fn nearest(position: geo::Point<f64>) -> Result<Self, String> {
+        let table_path = zoneinfo_dir().join(ZONE_TABLE_FILE);
+        let zone_table = std::fs::read_to_string(&table_path)
+            .map_err(|e| format!("failed to read {}: {}", table_path.display(), e))?;
+        let name = nearest_zone_name(&zone_table, position)
+            .ok_or_else(|| format!("{} lists no time zones", table_path.display()))?;
+        Self::load(name)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the UTC offset in effect at the given Unix timestamp, in seconds.
+    pub fn utc_offset_s(&self, unix_timestamp: i64) -> i32 {
+        let transition_count = self.transitions.partition_point(|(t, _)| *t <= unix_timestamp);
+        if transition_count == self.transitions.len() {
+            if let Some(rule) = &self.rule {
+                return rule.utc_offset_s(unix_timestamp);
+            }
+        }
+        match transition_count.checked_sub(1) {
+            Some(i) => self.utc_offsets_s[self.transitions[i].1],
+            // before the first transition, the first local time type applies
+            None => self.utc_offsets_s[0],
+        }
+    }
+}
+
+
+/// The time zone in which timestamps are output.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Timezone {
+    /// The time zone of the machine running the conversion.
+    #[default]
+    Local,
+
+    Utc,
+
+    /// A constant offset from UTC.
+    Fixed(FixedOffset),
+
+    /// A time zone from the time zone database.
+    Named(Arc<ZoneInfo>),
+
+    /// The time zone is derived from the position of the activity; see [`Timezone::for_lines`].
+    Auto,
+}
+impl Timezone {
+    /// Replaces `Auto` by the time zone whose principal location is nearest to the first point of
+    /// the lines, or UTC if the lines have no points or the time zone database cannot be read.
+    /// Other time zones are returned unchanged.
+    pub fn for_lines(&self, lines: &[Vec<Point>]) -> Self {
+        if *self != Self::Auto {
+            return self.clone();
+        }
+        let first_point = match lines.iter().flatten().next() {
+            Some(p) => p,
+            None => return Self::Utc,
+        };
+        match ZoneInfo::nearest(first_point.coordinates_deg) {
+            Ok(zone_info) => Self::Named(Arc::new(zone_info)),
+            Err(e) => {
+                log::warn!("failed to determine the time zone of the activity, using UTC: {}", e);
+                Self::Utc
+            },
+        }
+    }
+
+    /// Returns the UTC offset in effect at the given timestamp. An unresolved `Auto` time zone is
+    /// treated as UTC.
+    pub fn offset_at(&self, timestamp: &DateTime<Local>) -> FixedOffset {
+        match self {
+            Self::Local => timestamp.offset().fix(),
+            Self::Utc|Self::Auto => Utc.fix(),
+            Self::Fixed(offset) => *offset,
+            Self::Named(zone_info) => {
+                let offset_s = zone_info.utc_offset_s(timestamp.timestamp());
+                FixedOffset::east_opt(offset_s).unwrap_or_else(|| Utc.fix())
+            },
+        }
+    }
+
+    /// Converts the timestamp into this time zone.
+    pub fn convert(&self, timestamp: &DateTime<Local>) -> DateTime<FixedOffset> {
+        timestamp.with_timezone(&self.offset_at(timestamp))
+    }
+}
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local => write!(f, "local"),
+            Self::Utc => write!(f, "utc"),
+            Self::Fixed(offset) => write!(f, "{}", offset),
+            Self::Named(zone_info) => write!(f, "{}", zone_info.name()),
+            Self::Auto => write!(f, "auto"),
+        }
+    }
+}
+impl FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "local" => return Ok(Self::Local),
+            "utc" => return Ok(Self::Utc),
+            "auto" => return Ok(Self::Auto),
+            _ => {},
+        }
+
+        if s.starts_with(['+', '-']) {
+            let mut parser = PosixRuleParser { rest: s };
+            let offset_s = parser.offset()?;
+            if !parser.rest.is_empty() {
+                return Err(format!("invalid UTC offset {:?}", s));
+            }
+            return FixedOffset::east_opt(offset_s)
+                .map(Self::Fixed)
+                .ok_or_else(|| format!("UTC offset {:?} is out of range", s));
+        }
+
+        ZoneInfo::load(s)
+            .map(|zi| Self::Named(Arc::new(zi)))
+    }
+}
@@ -0,0 +1,354 @@
+//! A minimal TOML parser, just capable enough to read configuration files.
+//!
+//! Supported are comments, bare, quoted and dotted keys, table headers, basic and literal strings
+//! (on a single line), integers, floats, booleans and arrays (which may span multiple lines).
+//! Multi-line strings, inline tables, arrays of tables and dates are not.
+
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+
+/// A table, mapping keys to values.
+pub type Table = BTreeMap<String, Value>;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+impl Value {
+    /// Returns a short description of the type of the value, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "a string",
+            Self::Integer(_) => "an integer",
+            Self::Float(_) => "a float",
+            Self::Boolean(_) => "a boolean",
+            Self::Array(_) => "an array",
+            Self::Table(_) => "a table",
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&Table> {
+        match self {
+            Self::Table(t) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TomlError {
+    /// The line at which the error occurred, counting from 1.
+    pub line: usize,
+    pub message: String,
+}
+impl TomlError {
+    fn new<M: Into<String>>(line: usize, message: M) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+impl fmt::Display for TomlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for TomlError {
+}
+
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn error<M: Into<String>>(&self, message: M) -> TomlError {
+        TomlError::new(self.line, message)
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), TomlError> {
+        // the line before a newline that is found instead
+        let line = self.line;
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(TomlError::new(line, format!("expected {:?}, found {:?}", expected, c))),
+            None => Err(TomlError::new(line, format!("expected {:?}, found end of file", expected))),
+        }
+    }
+
+    /// Skips spaces and tabs within a line.
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    /// Skips a comment up to (but not including) the end of the line.
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.next();
+            }
+        }
+    }
+
+    /// Skips whitespace (including newlines) and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            match self.peek() {
+                Some(' ' | '\t' | '\r' | '\n') => { self.next(); },
+                Some('#') => self.skip_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    /// Expects the end of the line, optionally preceded by spaces and a comment.
+    fn expect_line_end(&mut self) -> Result<(), TomlError> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.next();
+        }
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(format!("expected end of line, found {:?}", c))),
+        }
+    }
+
+    fn parse_key_part(&mut self) -> Result<String, TomlError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string(),
+            Some('\'') => self.parse_literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        key.push(c);
+                        self.next();
+                    } else {
+                        break;
+                    }
+                }
+                if key.is_empty() {
+                    Err(self.error("expected a key"))
+                } else {
+                    Ok(key)
+                }
+            },
+        }
+    }
+
+    /// Parses a (possibly dotted) key into its parts.
+    fn parse_key(&mut self) -> Result<Vec<String>, TomlError> {
+        let mut parts = Vec::new();
+        loop {
+            self.skip_spaces();
+            parts.push(self.parse_key_part()?);
+            self.skip_spaces();
+            if self.peek() == Some('.') {
+                self.next();
+            } else {
+                return Ok(parts);
+            }
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, TomlError> {
+        self.expect('"')?;
+        // strings are on a single line, which is where errors are reported
+        let line = self.line;
+        let error = |message: String| TomlError::new(line, message);
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err(error("unterminated string".to_owned())),
+                Some('"') => return Ok(value),
+                Some('\\') => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(u @ ('u' | 'U')) => {
+                            let length = if u == 'u' { 4 } else { 8 };
+                            let hex: String = (0..length).filter_map(|_| self.next()).collect();
+                            u32::from_str_radix(&hex, 16).ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| error(format!("invalid Unicode escape \\{}{}", u, hex)))?
+                        },
+                        Some(c) => return Err(error(format!("invalid escape \\{}", c))),
+                        None => return Err(error("unterminated string".to_owned())),
+                    };
+                    value.push(escaped);
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, TomlError> {
+        self.expect('\'')?;
+        let line = self.line;
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err(TomlError::new(line, "unterminated string")),
+                Some('\'') => return Ok(value),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, TomlError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            values.push(self.parse_value()?);
+            self.skip_blank();
+            match self.next() {
+                Some(',') => {},
+                Some(']') => return Ok(Value::Array(values)),
+                Some(c) => return Err(self.error(format!("expected ',' or ']' in array, found {:?}", c))),
+                None => return Err(self.error("unterminated array")),
+            }
+        }
+    }
+
+    /// Parses a number, boolean or other bare word.
+    fn parse_bare_value(&mut self) -> Result<Value, TomlError> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.' | '_') {
+                word.push(c);
+                self.next();
+            } else {
+                break;
+            }
+        }
+        match word.as_str() {
+            "" => return Err(self.error("expected a value")),
+            "true" => return Ok(Value::Boolean(true)),
+            "false" => return Ok(Value::Boolean(false)),
+            _ => {},
+        }
+        let digits = word.replace('_', "");
+        if let Ok(i) = digits.parse::<i64>() {
+            return Ok(Value::Integer(i));
+        }
+        let is_float = digits.chars().all(|c| c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'e' | 'E'));
+        match digits.parse::<f64>() {
+            Ok(f) if is_float => Ok(Value::Float(f)),
+            _ => Err(self.error(format!("invalid value {:?}", word))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, TomlError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string().map(Value::String),
+            Some('\'') => self.parse_literal_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => Err(self.error("inline tables are not supported")),
+            _ => self.parse_bare_value(),
+        }
+    }
+}
+
+
+/// Returns the table at the given path within the root table, creating tables as necessary.
+fn table_at<'t>(root: &'t mut Table, path: &[String], line: usize) -> Result<&'t mut Table, TomlError> {
+    let mut table = root;
+    for part in path {
+        let entry = table.entry(part.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match entry {
+            Value::Table(t) => t,
+            other => return Err(TomlError::new(line, format!("{:?} is {}, not a table", part, other.type_name()))),
+        };
+    }
+    Ok(table)
+}
+
+
+/// Parses a TOML document into its root table.
+pub fn parse(text: &str) -> Result<Table, TomlError> {
+    let mut parser = Parser::new(text);
+    let mut root = Table::new();
+    let mut current_path: Vec<String> = Vec::new();
+    loop {
+        parser.skip_blank();
+        match parser.peek() {
+            None => return Ok(root),
+            Some('[') => {
+                let line = parser.line;
+                parser.next();
+                if parser.peek() == Some('[') {
+                    return Err(parser.error("arrays of tables are not supported"));
+                }
+                current_path = parser.parse_key()?;
+                parser.expect(']')?;
+                parser.expect_line_end()?;
+                table_at(&mut root, &current_path, line)?;
+            },
+            Some(_) => {
+                let line = parser.line;
+                let mut key = parser.parse_key()?;
+                parser.expect('=')?;
+                parser.skip_spaces();
+                let value = parser.parse_value()?;
+                parser.expect_line_end()?;
+
+                let last = key.pop().unwrap();
+                let mut path = current_path.clone();
+                path.extend(key);
+                let table = table_at(&mut root, &path, line)?;
+                if table.contains_key(&last) {
+                    return Err(TomlError::new(line, format!("duplicate key {:?}", last)));
+                }
+                table.insert(last, value);
+            },
+        }
+    }
+}
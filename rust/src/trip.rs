@@ -0,0 +1,110 @@
+//! Export of the track with a timestamp per position, for replaying the activity as an animation.
+//!
+//! Two formats are supported:
+//!
+//! * the GeoJSON accepted by the trip layer of kepler.gl (and deck.gl's `TripsLayer`): one
+//!   LineString per line of the track, whose positions are `[longitude, latitude, elevation,
+//!   timestamp]` with the timestamp in seconds since the Unix epoch
+//! * the GeoJSON accepted by Leaflet.Playback: a single Feature with a MultiPoint geometry and a
+//!   `time` property listing the timestamp of each position in milliseconds since the Unix epoch
+//!
+//! Points without timestamps cannot be placed in time and are skipped.
+
+
+use std::io::Write;
+
+use serde_json::Value;
+
+use crate::activity::Activity;
+use crate::error::WalkingError;
+use crate::geojson::write_json;
+use crate::point::{GeoPoint, Point};
+
+
+/// Options influencing the generated documents.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TripOptions {
+    /// Whether to output minified instead of pretty-printed JSON.
+    pub compact: bool,
+}
+
+
+/// Returns the points of the line that have timestamps, along with their timestamps in seconds
+/// since the Unix epoch.
+fn timed_points(line: &[Point]) -> impl Iterator<Item = (&Point, f64)> {
+    line.iter()
+        .filter_map(|p| p.unix_timestamp.map(|t| (p, t)))
+}
+
+
+/// Converts the activity into a FeatureCollection for the trip layer of kepler.gl. Points without
+/// an elevation are placed at an elevation of 0; lines with fewer than two timed points are
+/// skipped.
+pub fn activity_to_trips(activity: &Activity, name: &str) -> Value {
+    let features: Vec<Value> = activity.lines.iter()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let coordinates: Vec<Value> = timed_points(line)
+                .map(|(p, t)| serde_json::json!([p.longitude(), p.latitude(), p.elevation_m.unwrap_or(0.0), t]))
+                .collect();
+            if coordinates.len() < 2 {
+                return None;
+            }
+            Some(serde_json::json!({
+                "type": "Feature",
+                "geometry": {
+                    "type": "LineString",
+                    "coordinates": coordinates,
+                },
+                "properties": {
+                    "name": name,
+                    "line": i + 1,
+                },
+            }))
+        })
+        .collect();
+    serde_json::json!({
+        "type": "FeatureCollection",
+        "features": features,
+    })
+}
+
+/// Converts the activity into a Feature for Leaflet.Playback. The lines are joined, as the
+/// positions are only ever shown one at a time.
+pub fn activity_to_playback(activity: &Activity, name: &str) -> Value {
+    let (coordinates, times): (Vec<Value>, Vec<i64>) = activity.lines.iter()
+        .flat_map(|l| timed_points(l))
+        .map(|(p, t)| (p.as_lonlat_list(), (t * 1000.0).round() as i64))
+        .unzip();
+    serde_json::json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "MultiPoint",
+            "coordinates": coordinates,
+        },
+        "properties": {
+            "title": name,
+            "time": times,
+        },
+    })
+}
+
+
+fn write_document<W: Write>(writer: W, mut document: Value, options: &TripOptions) -> Result<(), WalkingError> {
+    document.sort_all_objects();
+    if options.compact {
+        write_json(&document, writer, serde_json::ser::CompactFormatter)
+    } else {
+        write_json(&document, writer, serde_json::ser::PrettyFormatter::new())
+    }
+}
+
+/// Writes the activity as a FeatureCollection for the trip layer of kepler.gl.
+pub fn write_trips<W: Write>(writer: W, activity: &Activity, name: &str, options: &TripOptions) -> Result<(), WalkingError> {
+    write_document(writer, activity_to_trips(activity, name), options)
+}
+
+/// Writes the activity as a Feature for Leaflet.Playback.
+pub fn write_playback<W: Write>(writer: W, activity: &Activity, name: &str, options: &TripOptions) -> Result<(), WalkingError> {
+    write_document(writer, activity_to_playback(activity, name), options)
+}
@@ -0,0 +1,123 @@
+use clap::ValueEnum;
+
+
+/// Kilometers per statute mile.
+pub const KM_PER_MILE: f64 = 1.609344;
+
+/// Meters per international foot.
+pub const M_PER_FOOT: f64 = 0.3048;
+
+/// Meters per international yard, the unit of imperial swimming distances.
+pub const M_PER_YARD: f64 = 0.9144;
+
+/// Below this speed, the activity is considered to be standing still and no pace is calculated.
+pub const MIN_PACE_SPEED_KM_PER_H: f64 = 0.5;
+
+
+/// The system of units in which measurements and derived values such as pace are output.
+///
+/// Metric output uses meters for distances and elevations, km/h for speeds and degrees Celsius for
+/// temperatures; imperial output uses miles, feet, mph and degrees Fahrenheit, respectively.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, ValueEnum)]
+pub enum Units {
+    #[default]
+    Metric,
+    Imperial,
+}
+impl Units {
+    /// The length of this system's distance unit (kilometer or mile) in kilometers.
+    pub fn distance_unit_km(&self) -> f64 {
+        match self {
+            Self::Metric => 1.0,
+            Self::Imperial => KM_PER_MILE,
+        }
+    }
+
+    /// Converts a distance in meters into meters or miles.
+    pub fn distance(&self, distance_m: f64) -> f64 {
+        match self {
+            Self::Metric => distance_m,
+            Self::Imperial => distance_m / 1000.0 / KM_PER_MILE,
+        }
+    }
+
+    /// Converts an elevation (or a difference in elevation) in meters into meters or feet.
+    pub fn elevation(&self, elevation_m: f64) -> f64 {
+        match self {
+            Self::Metric => elevation_m,
+            Self::Imperial => elevation_m / M_PER_FOOT,
+        }
+    }
+
+    /// Converts a speed in km/h into km/h or mph.
+    pub fn speed(&self, speed_km_per_h: f64) -> f64 {
+        speed_km_per_h / self.distance_unit_km()
+    }
+
+    /// Converts a temperature in degrees Celsius into degrees Celsius or Fahrenheit.
+    pub fn temperature(&self, temperature_degc: f64) -> f64 {
+        match self {
+            Self::Metric => temperature_degc,
+            Self::Imperial => temperature_degc * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// The names of the units of distances, elevations, speeds, paces, temperatures and vertical
+    /// speeds, in that order.
+    pub fn names(&self) -> [(&'static str, &'static str); 6] {
+        match self {
+            Self::Metric => [
+                ("distance", "m"),
+                ("elevation", "m"),
+                ("speed", "km/h"),
+                ("pace", "min/km"),
+                ("temperature", "\u{B0}C"),
+                ("vertical_speed", "m/h"),
+            ],
+            Self::Imperial => [
+                ("distance", "mi"),
+                ("elevation", "ft"),
+                ("speed", "mph"),
+                ("pace", "min/mi"),
+                ("temperature", "\u{B0}F"),
+                ("vertical_speed", "ft/h"),
+            ],
+        }
+    }
+
+    /// Returns the unit names (see [`Units::names`]) as a JSON object.
+    pub fn names_to_json(&self) -> serde_json::Map<String, serde_json::Value> {
+        self.names().iter()
+            .map(|(quantity, unit)| ((*quantity).to_owned(), (*unit).into()))
+            .collect()
+    }
+
+    /// Converts a speed into a pace in minutes per kilometer or mile. Returns `None` if the speed
+    /// is too low for a pace to be meaningful.
+    pub fn pace_min_per_unit(&self, speed_km_per_h: f64) -> Option<f64> {
+        if speed_km_per_h < MIN_PACE_SPEED_KM_PER_H {
+            None
+        } else {
+            Some(60.0 * self.distance_unit_km() / speed_km_per_h)
+        }
+    }
+
+    /// The name of the unit of swimming paces.
+    pub fn swim_pace_name(&self) -> &'static str {
+        match self {
+            Self::Metric => "min/100m",
+            Self::Imperial => "min/100yd",
+        }
+    }
+
+    /// Converts a speed into a swimming pace in minutes per 100 meters or 100 yards. Returns `None`
+    /// if the speed is too low for a pace to be meaningful.
+    pub fn swim_pace_min_per_100(&self, speed_km_per_h: f64) -> Option<f64> {
+        let distance_km = match self {
+            Self::Metric => 0.1,
+            Self::Imperial => 100.0 * M_PER_YARD / 1000.0,
+        };
+        self.pace_min_per_unit(speed_km_per_h)
+            .map(|pace| pace / self.distance_unit_km() * distance_km)
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt;
+use std::str::FromStr;
+
+
+/// The size of the tiles used by web maps, in pixels.
+const TILE_SIZE_PX: f64 = 256.0;
+
+/// The maximum zoom level that tile servers commonly provide.
+const MAX_ZOOM: u32 = 18;
+
+
+/// The size of the map viewport in pixels.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Viewport {
+    pub width_px: u32,
+    pub height_px: u32,
+}
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            width_px: 800,
+            height_px: 600,
+        }
+    }
+}
+impl fmt::Display for Viewport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width_px, self.height_px)
+    }
+}
+impl FromStr for Viewport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width_str, height_str) = s.split_once(['x', 'X'])
+            .ok_or_else(|| format!("viewport {:?} is not in the format WIDTHxHEIGHT", s))?;
+        let width_px: u32 = width_str.trim().parse()
+            .map_err(|e| format!("failed to parse viewport width {:?}: {}", width_str, e))?;
+        let height_px: u32 = height_str.trim().parse()
+            .map_err(|e| format!("failed to parse viewport height {:?}: {}", height_str, e))?;
+        if width_px == 0 || height_px == 0 {
+            return Err(format!("viewport {:?} has no area", s));
+        }
+        Ok(Self {
+            width_px,
+            height_px,
+        })
+    }
+}
+
+
+/// Projects a latitude onto the Web Mercator Y axis, normalized to 0.0 (north) through 1.0
+/// (south).
+fn mercator_y(lat_deg: f64) -> f64 {
+    let lat_rad = lat_deg.to_radians();
+    (1.0 - (lat_rad.tan() + 1.0/lat_rad.cos()).ln() / std::f64::consts::PI) / 2.0
+}
+
+
+/// Estimates the largest Web Mercator zoom level at which the given bounding box fits entirely
+/// within the viewport.
+pub fn estimate_zoom(min_lat: f64, max_lat: f64, min_lon: f64, max_lon: f64, viewport: Viewport) -> u32 {
+    let x_fraction = (max_lon - min_lon) / 360.0;
+    let y_fraction = (mercator_y(min_lat) - mercator_y(max_lat)).abs();
+
+    // at zoom level z, the whole world is TILE_SIZE_PX * 2^z pixels wide and high
+    let zoom_for = |fraction: f64, viewport_px: u32| -> f64 {
+        if fraction <= 0.0 {
+            f64::INFINITY
+        } else {
+            (f64::from(viewport_px) / (TILE_SIZE_PX * fraction)).log2()
+        }
+    };
+    let zoom = zoom_for(x_fraction, viewport.width_px)
+        .min(zoom_for(y_fraction, viewport.height_px))
+        .floor();
+
+    if zoom < 0.0 {
+        0
+    } else if zoom > f64::from(MAX_ZOOM) {
+        MAX_ZOOM
+    } else {
+        zoom as u32
+    }
+}
@@ -0,0 +1,117 @@
+//! The server side of the WebSocket protocol (RFC 6455), as far as needed to push text messages to
+//! clients. Messages sent by clients are not read.
+
+
+use std::io::{BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use crate::error::WalkingError;
+use crate::http::{Request, write_response};
+use crate::sha1::sha1;
+
+
+/// The GUID appended to the key of the client to calculate the accept key of the handshake.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The time after which sending to a client that does not receive is given up.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The first byte of a single-frame text message.
+const TEXT_FRAME: u8 = 0x81;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+
+/// Encodes the data as base64 with padding.
+pub fn base64(data: &[u8]) -> String {
+    let mut ret = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(BASE64_ALPHABET[((group >> (18 - 6 * i)) & 0x3F) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+
+/// Calculates the value of the `Sec-WebSocket-Accept` header answering the `Sec-WebSocket-Key`
+/// header of the client.
+pub fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()))
+}
+
+
+/// Encodes a frame sent by the server (which is not masked).
+fn frame(first_byte: u8, payload: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(payload.len() + 10);
+    ret.push(first_byte);
+    match payload.len() {
+        length @ 0..=125 => ret.push(length as u8),
+        length @ 126..=0xFFFF => {
+            ret.push(126);
+            ret.extend_from_slice(&(length as u16).to_be_bytes());
+        },
+        length => {
+            ret.push(127);
+            ret.extend_from_slice(&(length as u64).to_be_bytes());
+        },
+    }
+    ret.extend_from_slice(payload);
+    ret
+}
+
+
+/// A connection to a WebSocket client.
+#[derive(Debug)]
+pub struct WebSocket {
+    stream: TcpStream,
+    peer: Option<SocketAddr>,
+}
+impl WebSocket {
+    /// Performs the handshake on a newly accepted connection. Requests that are not WebSocket
+    /// handshakes are answered with an error status before failing.
+    pub fn accept(stream: TcpStream) -> Result<Self, WalkingError> {
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let peer = stream.peer_addr().ok();
+        let request = Request::read(&mut BufReader::new(&stream))?;
+
+        if !request.header_contains("Upgrade", "websocket") {
+            write_response(&stream, "426 Upgrade Required", &[("Upgrade", "websocket")], b"WebSocket connections only\n")?;
+            return Err(WalkingError::Http("not a WebSocket handshake".to_owned()));
+        }
+        let key = match request.header("Sec-WebSocket-Key") {
+            Some(k) if request.method == "GET" => k,
+            _ => {
+                write_response(&stream, "400 Bad Request", &[], b"invalid WebSocket handshake\n")?;
+                return Err(WalkingError::Http("invalid WebSocket handshake".to_owned()));
+            },
+        };
+
+        let accept = accept_key(key);
+        write_response(
+            &stream,
+            "101 Switching Protocols",
+            &[("Upgrade", "websocket"), ("Connection", "Upgrade"), ("Sec-WebSocket-Accept", &accept)],
+            b"",
+        )?;
+        Ok(Self { stream, peer })
+    }
+
+    /// The address of the client, if known.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// Sends a text message.
+    pub fn send_text(&mut self, text: &str) -> Result<(), WalkingError> {
+        self.stream.write_all(&frame(TEXT_FRAME, text.as_bytes()))?;
+        Ok(())
+    }
+}
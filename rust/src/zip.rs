@@ -0,0 +1,234 @@
+//! Just enough of the ZIP file format to package KMZ files and to read activities from archives.
+//!
+//! Entries are written without compression, which every ZIP reader supports. When reading, stored
+//! and deflated entries are supported; ZIP64 and encryption are not.
+
+
+use std::io::Write;
+
+use crate::deflate::inflate;
+
+
+/// The signature of a local file header.
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034B50;
+
+/// The signature of a central directory file header.
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: u32 = 0x02014B50;
+
+/// The signature of the end of central directory record.
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054B50;
+
+/// The ZIP version required to extract stored entries (2.0).
+const VERSION_NEEDED: u16 = 20;
+
+/// General purpose flag: file names are encoded in UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+
+/// MS-DOS date of 1980-01-01, the earliest representable date.
+const DOS_DATE_EPOCH: u16 = (1 << 5) | 1;
+
+/// Compression method: no compression.
+const METHOD_STORED: u16 = 0;
+
+/// Compression method: DEFLATE.
+const METHOD_DEFLATED: u16 = 8;
+
+/// General purpose flag: the entry is encrypted.
+const FLAG_ENCRYPTED: u16 = 1 << 0;
+
+/// The first four bytes of a ZIP file (unless it is empty).
+pub const ZIP_MAGIC: [u8; 4] = LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes();
+
+
+/// Calculates the CRC-32 (as used by ZIP and gzip) of the given data.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_continue(0, data)
+}
+
+/// Continues the calculation of a CRC-32 with more data; `previous` is the CRC-32 of the data
+/// before (0 for no data).
+pub fn crc32_continue(previous: u32, data: &[u8]) -> u32 {
+    let mut crc = !previous;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+
+fn too_large(what: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} too large for a ZIP file", what))
+}
+
+
+/// Writes a ZIP archive containing the given entries, each consisting of a file name and the file
+/// contents.
+pub fn write_stored_zip<W: Write>(mut writer: W, entries: &[(&str, &[u8])]) -> std::io::Result<()> {
+    let mut central_directory = Vec::new();
+    let mut offset: u32 = 0;
+
+    for (name, data) in entries {
+        let name_len: u16 = name.len().try_into().map_err(|_| too_large("file name"))?;
+        let data_len: u32 = data.len().try_into().map_err(|_| too_large("file"))?;
+        let crc = crc32(data);
+
+        let mut header = Vec::with_capacity(30 + name.len());
+        header.extend_from_slice(&LOCAL_FILE_HEADER_SIGNATURE.to_le_bytes());
+        header.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        header.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // compression method: stored
+        header.extend_from_slice(&0u16.to_le_bytes()); // modification time
+        header.extend_from_slice(&DOS_DATE_EPOCH.to_le_bytes()); // modification date
+        header.extend_from_slice(&crc.to_le_bytes());
+        header.extend_from_slice(&data_len.to_le_bytes()); // compressed size
+        header.extend_from_slice(&data_len.to_le_bytes()); // uncompressed size
+        header.extend_from_slice(&name_len.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        header.extend_from_slice(name.as_bytes());
+        writer.write_all(&header)?;
+        writer.write_all(data)?;
+
+        central_directory.extend_from_slice(&CENTRAL_DIRECTORY_HEADER_SIGNATURE.to_le_bytes());
+        central_directory.extend_from_slice(&VERSION_NEEDED.to_le_bytes()); // version made by
+        central_directory.extend_from_slice(&header[4..30]); // same as in the local header
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // file comment length
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        central_directory.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        central_directory.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central_directory.extend_from_slice(&offset.to_le_bytes());
+        central_directory.extend_from_slice(name.as_bytes());
+
+        let header_len: u32 = header.len().try_into().unwrap();
+        offset = offset.checked_add(header_len)
+            .and_then(|o| o.checked_add(data_len))
+            .ok_or_else(|| too_large("archive"))?;
+    }
+
+    let entry_count: u16 = entries.len().try_into().map_err(|_| too_large("entry count"))?;
+    let central_directory_len: u32 = central_directory.len().try_into().map_err(|_| too_large("central directory"))?;
+    writer.write_all(&central_directory)?;
+
+    let mut end = Vec::with_capacity(22);
+    end.extend_from_slice(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes()); // number of this disk
+    end.extend_from_slice(&0u16.to_le_bytes()); // disk with the central directory
+    end.extend_from_slice(&entry_count.to_le_bytes()); // entries on this disk
+    end.extend_from_slice(&entry_count.to_le_bytes()); // total entries
+    end.extend_from_slice(&central_directory_len.to_le_bytes());
+    end.extend_from_slice(&offset.to_le_bytes()); // central directory offset
+    end.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    writer.write_all(&end)
+}
+
+
+/// An entry in the central directory of a ZIP archive.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ZipEntry {
+    pub name: String,
+    method: u16,
+    flags: u16,
+    crc: u32,
+    compressed_size: u32,
+    uncompressed_size: u32,
+    local_header_offset: u32,
+}
+impl ZipEntry {
+    /// Whether the entry is a directory rather than a file.
+    pub fn is_directory(&self) -> bool {
+        self.name.ends_with('/')
+    }
+
+    /// Extracts (and, if necessary, decompresses) the entry from the archive data.
+    pub fn extract(&self, archive: &[u8]) -> Result<Vec<u8>, String> {
+        if self.flags & FLAG_ENCRYPTED != 0 {
+            return Err(format!("{}: encrypted entries are not supported", self.name));
+        }
+
+        let header_start = self.local_header_offset as usize;
+        let header = archive.get(header_start..header_start+30)
+            .ok_or_else(|| format!("{}: local header out of bounds", self.name))?;
+        if read_u32(header, 0) != LOCAL_FILE_HEADER_SIGNATURE {
+            return Err(format!("{}: invalid local header signature", self.name));
+        }
+        let data_start = header_start + 30
+            + usize::from(read_u16(header, 26))
+            + usize::from(read_u16(header, 28));
+        let data_end = data_start + self.compressed_size as usize;
+        let compressed = archive.get(data_start..data_end)
+            .ok_or_else(|| format!("{}: entry data out of bounds", self.name))?;
+
+        let data = match self.method {
+            METHOD_STORED => compressed.to_vec(),
+            METHOD_DEFLATED => inflate(compressed)
+                .map_err(|e| format!("{}: {}", self.name, e))?
+                .0,
+            other => return Err(format!("{}: unsupported compression method {}", self.name, other)),
+        };
+        if data.len() != self.uncompressed_size as usize || crc32(&data) != self.crc {
+            return Err(format!("{}: CRC or size mismatch", self.name));
+        }
+        Ok(data)
+    }
+}
+
+
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset+1]])
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([data[offset], data[offset+1], data[offset+2], data[offset+3]])
+}
+
+
+/// Reads the central directory of a ZIP archive.
+pub fn read_zip_entries(archive: &[u8]) -> Result<Vec<ZipEntry>, String> {
+    // the end of central directory record is at the end, possibly followed by a comment
+    const END_LEN: usize = 22;
+    if archive.len() < END_LEN {
+        return Err("too short to be a ZIP file".to_owned());
+    }
+    let search_start = archive.len().saturating_sub(END_LEN + usize::from(u16::MAX));
+    let end_start = (search_start..=archive.len() - END_LEN)
+        .rev()
+        .find(|&i| read_u32(archive, i) == END_OF_CENTRAL_DIRECTORY_SIGNATURE)
+        .ok_or_else(|| "end of central directory not found".to_owned())?;
+    let end = &archive[end_start..end_start+END_LEN];
+
+    let entry_count = read_u16(end, 10);
+    let directory_offset = read_u32(end, 16);
+    if entry_count == u16::MAX || directory_offset == u32::MAX {
+        return Err("ZIP64 archives are not supported".to_owned());
+    }
+
+    let mut entries = Vec::with_capacity(usize::from(entry_count));
+    let mut pos = directory_offset as usize;
+    for _ in 0..entry_count {
+        let header = archive.get(pos..pos+46)
+            .ok_or_else(|| "central directory out of bounds".to_owned())?;
+        if read_u32(header, 0) != CENTRAL_DIRECTORY_HEADER_SIGNATURE {
+            return Err("invalid central directory header signature".to_owned());
+        }
+        let name_len = usize::from(read_u16(header, 28));
+        let extra_len = usize::from(read_u16(header, 30));
+        let comment_len = usize::from(read_u16(header, 32));
+        let name_bytes = archive.get(pos+46..pos+46+name_len)
+            .ok_or_else(|| "central directory out of bounds".to_owned())?;
+
+        entries.push(ZipEntry {
+            name: String::from_utf8_lossy(name_bytes).into_owned(),
+            flags: read_u16(header, 8),
+            method: read_u16(header, 10),
+            crc: read_u32(header, 16),
+            compressed_size: read_u32(header, 20),
+            uncompressed_size: read_u32(header, 24),
+            local_header_offset: read_u32(header, 42),
+        });
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
@@ -0,0 +1,89 @@
+//! Classification of heart rates into training zones.
+
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::point::Point;
+
+
+/// The number of heart rate zones.
+pub const ZONE_COUNT: usize = 5;
+
+/// The lower bounds of zones 2 to 5 as fractions of the maximum heart rate.
+const MAX_HR_ZONE_FRACTIONS: [f64; ZONE_COUNT - 1] = [0.6, 0.7, 0.8, 0.9];
+
+
+/// Five heart rate zones, defined by the lowest heart rates of zones 2 to 5. Zone 1 contains
+/// everything below the lower bound of zone 2.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct HeartRateZones {
+    pub lower_bounds_bpm: [u64; ZONE_COUNT - 1],
+}
+impl HeartRateZones {
+    /// Derives the zones from the maximum heart rate; zones 2 to 5 start at 60%, 70%, 80% and 90%
+    /// of the maximum.
+    pub fn from_max_heart_rate(max_heart_rate_bpm: u64) -> Self {
+        let lower_bounds_bpm = MAX_HR_ZONE_FRACTIONS
+            .map(|f| (max_heart_rate_bpm as f64 * f).round() as u64);
+        Self { lower_bounds_bpm }
+    }
+
+    /// Returns the zone (1 to 5) in which the given heart rate lies.
+    pub fn zone(&self, heart_rate_bpm: u64) -> usize {
+        1 + self.lower_bounds_bpm.iter()
+            .filter(|b| heart_rate_bpm >= **b)
+            .count()
+    }
+}
+impl fmt::Display for HeartRateZones {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [z2, z3, z4, z5] = self.lower_bounds_bpm;
+        write!(f, "{},{},{},{}", z2, z3, z4, z5)
+    }
+}
+impl FromStr for HeartRateZones {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bounds: Vec<u64> = s.split(',')
+            .map(|b| b.trim().parse()
+                .map_err(|e| format!("failed to parse heart rate zone bound {:?}: {}", b, e)))
+            .collect::<Result<_, _>>()?;
+        let lower_bounds_bpm: [u64; ZONE_COUNT - 1] = bounds.try_into()
+            .map_err(|_| format!("heart rate zones {:?} must consist of exactly {} bounds", s, ZONE_COUNT - 1))?;
+        if lower_bounds_bpm.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(format!("heart rate zone bounds {:?} are not strictly increasing", s));
+        }
+        Ok(Self { lower_bounds_bpm })
+    }
+}
+
+
+/// The heart rate of the segment between two points: the (truncated) mean of both points' heart
+/// rates or, if only one of them has a heart rate, that heart rate.
+pub fn segment_heart_rate_bpm(point1: &Point, point2: &Point) -> Option<u64> {
+    match (point1.heart_rate_bpm, point2.heart_rate_bpm) {
+        (Some(hr1), Some(hr2)) => Some((hr1 + hr2) / 2),
+        (hr1, hr2) => hr1.or(hr2),
+    }
+}
+
+
+/// Calculates how long (in seconds) was spent in each zone. Segments without heart rate or
+/// timestamps are not counted.
+pub fn time_in_zones(lines: &[Vec<Point>], zones: &HeartRateZones) -> [f64; ZONE_COUNT] {
+    let mut times_s = [0.0; ZONE_COUNT];
+    for segment in lines.iter().flat_map(|l| l.windows(2)) {
+        let heart_rate_bpm = match segment_heart_rate_bpm(&segment[0], &segment[1]) {
+            Some(hr) => hr,
+            None => continue,
+        };
+        let duration_s = match (segment[0].unix_timestamp, segment[1].unix_timestamp) {
+            (Some(t1), Some(t2)) if t2 > t1 => t2 - t1,
+            _ => continue,
+        };
+        times_s[zones.zone(heart_rate_bpm) - 1] += duration_s;
+    }
+    times_s
+}
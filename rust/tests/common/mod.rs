@@ -0,0 +1,50 @@
+//! Helpers shared by the integration tests.
+//!
+//! The fixtures in `tests/fixtures` are small synthetic FIT files of a walk through Vienna:
+//!
+//! * `walk.fit`: 60 records five seconds apart with a one-minute pause halfway through, along with
+//!   heart rate, cadence, temperature, power and elevation, one lap and one session.
+//! * `dynamics.fit`: the same walk with cycling dynamics (pedal balance, torque effectiveness,
+//!   pedal smoothness) and running dynamics (stance time, vertical oscillation, step length).
+//! * `chained.fit`: two copies of the walk chained into one file.
+//! * `multisport.fit`: a short triathlon with swimming, cycling and running sessions separated by
+//!   transition sessions.
+//! * `swim.fit`: an open-water swim with jittery positions, the stroke rate as the cadence and two
+//!   laps of different strokes.
+//! * `indoor.fit`: an indoor cycling session on a trainer, whose records have speed, distance,
+//!   heart rate, cadence and power but no positions.
+//! * `locations.fit`: the walk preceded by two saved locations (location messages), one of them
+//!   close to the start.
+//!
+//! Besides, `walk.fit.gz` is `walk.fit` compressed by the system gzip (`gzip -9`, keeping the file
+//! name), as a known vector for the gzip reader.
+
+// each test crate uses only some of the helpers
+#![allow(dead_code)]
+
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+
+/// The directory containing the integration tests, relative to which the fixtures are given.
+pub fn tests_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+
+/// Returns the path of the fixture with the given file name.
+pub fn fixture_path(name: &str) -> PathBuf {
+    tests_dir().join("fixtures").join(name)
+}
+
+
+/// Runs the command-line tool with the given arguments from within the tests directory, so that
+/// fixtures can be given as `fixtures/walk.fit` and do not end up in the output as absolute paths.
+pub fn run_fit2walking(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fit2walking"))
+        .args(args)
+        .current_dir(tests_dir())
+        .output()
+        .expect("failed to run fit2walking")
+}
@@ -0,0 +1,181 @@
+//! Tests of the DEFLATE, gzip and ZIP implementations.
+
+
+mod common;
+
+
+use std::fs;
+use std::io::Write;
+
+use fit2walking::deflate::{deflate, Deflater, inflate};
+use fit2walking::gzip::{gunzip, GzipWriter};
+use fit2walking::zip::{crc32, crc32_continue, read_zip_entries, write_stored_zip};
+
+use crate::common::fixture_path;
+
+
+/// Text with enough repetition for back-references, including ones that overlap their target.
+fn sample_text() -> Vec<u8> {
+    let mut text = b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\n".to_vec();
+    for i in 0..200 {
+        writeln!(text, r#"{{"type":"Feature","properties":{{"heart_rate":{},"speed":4.5}}}}"#, 100 + i % 40).unwrap();
+    }
+    text
+}
+
+/// Encodes the data as a stored (uncompressed) DEFLATE block.
+fn stored_block(data: &[u8], is_final: bool) -> Vec<u8> {
+    let length = u16::try_from(data.len()).unwrap();
+    let mut block = vec![u8::from(is_final)];
+    block.extend_from_slice(&length.to_le_bytes());
+    block.extend_from_slice(&(!length).to_le_bytes());
+    block.extend_from_slice(data);
+    block
+}
+
+
+#[test]
+fn crc32_of_check_string() {
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32_continue(crc32(b"1234"), b"56789"), 0xCBF4_3926);
+}
+
+#[test]
+fn stored_blocks_are_inflated() {
+    let mut data = stored_block(b"stored, ", false);
+    data.extend(stored_block(b"not compressed", true));
+    // anything after the final block is not consumed
+    let consumed_len = data.len();
+    data.extend_from_slice(b"trailer");
+
+    let (output, consumed) = inflate(&data).unwrap();
+    assert_eq!(output, b"stored, not compressed");
+    assert_eq!(consumed, consumed_len);
+}
+
+#[test]
+fn fixed_huffman_blocks_round_trip() {
+    let text = sample_text();
+    let compressed = deflate(&text);
+    assert!(compressed.len() < text.len() / 4, "{} bytes compressed into {}", text.len(), compressed.len());
+    // block type 1: fixed Huffman codes
+    assert_eq!((compressed[0] >> 1) & 0b11, 1);
+    assert_eq!(inflate(&compressed).unwrap(), (text.clone(), compressed.len()));
+
+    // a stream of several blocks, as written by the gzip writer
+    let mut deflater = Deflater::new();
+    let (first, second) = text.split_at(text.len() / 3);
+    deflater.compress_block(first, false);
+    deflater.compress_block(b"", false);
+    deflater.compress_block(second, true);
+    let compressed = deflater.finish();
+    assert_eq!(inflate(&compressed).unwrap().0, text);
+
+    assert_eq!(inflate(&deflate(b"")).unwrap().0, b"");
+}
+
+#[test]
+fn dynamic_huffman_blocks_are_inflated() {
+    // compressed by the system gzip; the FIT data is compressed with dynamic Huffman codes
+    let compressed = fs::read(fixture_path("walk.fit.gz")).unwrap();
+    let original = fs::read(fixture_path("walk.fit")).unwrap();
+    // flags: file name; skip the 10-byte header and the zero-terminated name
+    assert_eq!(compressed[3], 0x08);
+    let deflate_start = 10 + compressed[10..].iter().position(|b| *b == 0).unwrap() + 1;
+    assert_eq!((compressed[deflate_start] >> 1) & 0b11, 2);
+
+    let (output, consumed) = inflate(&compressed[deflate_start..]).unwrap();
+    assert_eq!(output, original);
+    // followed by the CRC and the size
+    assert_eq!(deflate_start + consumed + 8, compressed.len());
+
+    assert_eq!(gunzip(&compressed).unwrap(), original);
+}
+
+#[test]
+fn gzip_round_trip() {
+    let text = sample_text();
+    let mut writer = GzipWriter::new(Vec::new());
+    // in pieces, as written by the JSON serializer
+    for chunk in text.chunks(100) {
+        writer.write_all(chunk).unwrap();
+    }
+    let compressed = writer.finish().unwrap();
+    assert_eq!(compressed[..2], [0x1F, 0x8B]);
+    assert_eq!(gunzip(&compressed).unwrap(), text);
+
+    // concatenated members are decompressed one after the other
+    let mut concatenated = compressed.clone();
+    concatenated.extend_from_slice(&compressed);
+    assert_eq!(gunzip(&concatenated).unwrap(), [text.as_slice(), text.as_slice()].concat());
+}
+
+#[test]
+fn truncated_compressed_data_is_rejected() {
+    let compressed = fs::read(fixture_path("walk.fit.gz")).unwrap();
+    for length in 1..compressed.len() {
+        assert!(gunzip(&compressed[..length]).is_err(), "truncated to {} bytes", length);
+    }
+
+    let deflated = deflate(&sample_text());
+    for length in 0..deflated.len() {
+        assert!(inflate(&deflated[..length]).is_err(), "truncated to {} bytes", length);
+    }
+}
+
+#[test]
+fn corrupt_compressed_data_is_rejected() {
+    let compressed = fs::read(fixture_path("walk.fit.gz")).unwrap();
+    let original = fs::read(fixture_path("walk.fit")).unwrap();
+
+    // any change is caught by the decoder or the CRC, without panicking
+    for index in 0..compressed.len() {
+        for bit in [0x01, 0x10, 0x80] {
+            let mut corrupt = compressed.clone();
+            corrupt[index] ^= bit;
+            if let Ok(output) = gunzip(&corrupt) {
+                // only header fields that are not checked, such as the time
+                assert_eq!(output, original, "byte {} changed by {:#x}", index, bit);
+            }
+        }
+    }
+
+    let mut stored = stored_block(b"stored", true);
+    stored[3] ^= 0xFF;
+    assert!(inflate(&stored).is_err());
+    // block type 3 is reserved
+    assert!(inflate(&[0b111]).is_err());
+    // a fixed Huffman block starting with a back-reference (length 3, distance 1) to before the
+    // start of the data
+    assert_eq!(inflate(&[0b0000_0011, 0b0000_0010]).unwrap_err(), "distance reaches back before the start of the data");
+
+    assert!(gunzip(b"not gzip data").is_err());
+}
+
+#[test]
+fn zip_round_trip() {
+    let text = sample_text();
+    let mut archive = Vec::new();
+    write_stored_zip(&mut archive, &[("doc.kml", &text), ("files/empty.txt", b"")]).unwrap();
+
+    let entries = read_zip_entries(&archive).unwrap();
+    let names: Vec<&str> = entries.iter()
+        .map(|e| e.name.as_str())
+        .collect();
+    assert_eq!(names, ["doc.kml", "files/empty.txt"]);
+    assert_eq!(entries[0].extract(&archive).unwrap(), text);
+    assert_eq!(entries[1].extract(&archive).unwrap(), b"");
+
+    for length in 0..archive.len() {
+        let truncated = &archive[..length];
+        if let Ok(entries) = read_zip_entries(truncated) {
+            assert!(entries.iter().any(|e| e.extract(truncated).is_err()), "truncated to {} bytes", length);
+        }
+    }
+
+    let mut corrupt = archive.clone();
+    let data_start = 30 + "doc.kml".len();
+    corrupt[data_start] ^= 0xFF;
+    assert!(read_zip_entries(&corrupt).unwrap()[0].extract(&corrupt).is_err());
+}
@@ -0,0 +1,33 @@
+//! Tests of downloading activities (only built with the `fetch` feature).
+
+#![cfg(feature = "fetch")]
+
+
+use serde_json::json;
+
+use fit2walking::fetch::strava_streams_to_gpx;
+use fit2walking::input::gpx::read_gpx;
+
+
+#[test]
+fn strava_streams_are_converted_into_gpx() {
+    let activity = json!({"id": 1, "name": "Morning <Walk>", "sport_type": "Walk", "start_date": "2023-11-14T07:30:00Z"});
+    let streams = json!({
+        "time": {"data": [0, 5, 10]},
+        "latlng": {"data": [[48.2082, 16.3738], [48.2083, 16.3739], [48.2084, 16.3740]]},
+        "altitude": {"data": [200.0, 200.5, 201.0]},
+        "heartrate": {"data": [110, 112, 115]},
+        "watts": {"data": [null, 90, 95]},
+    });
+    let gpx = strava_streams_to_gpx(&activity, &streams).unwrap();
+    let activity = read_gpx(&gpx).unwrap();
+
+    assert_eq!(activity.metadata.sport.as_deref(), Some("walk"));
+    let points: Vec<_> = activity.lines.iter().flatten().collect();
+    assert_eq!(points.len(), 3);
+    assert_eq!(points[2].unix_timestamp, Some(1_699_947_010.0));
+    assert_eq!(points[1].elevation_m, Some(200.5));
+    assert_eq!(points[2].heart_rate_bpm, Some(115));
+    assert_eq!(points[0].power_w, None);
+    assert_eq!(points[1].power_w, Some(90));
+}
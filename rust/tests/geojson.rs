@@ -0,0 +1,147 @@
+//! Tests of the features of the `points` collection.
+
+
+mod common;
+
+
+use std::collections::BTreeSet;
+
+use fit2walking::color::{COLOR_CLASSES, ColorBy, ColorScale};
+use fit2walking::geojson::{lines_to_points, DistanceSource, DocumentOptions, PointsGeometry};
+use fit2walking::input::{read_activity, ReadOptions};
+
+use common::fixture_path;
+
+
+/// Returns the set of property keys of each feature of the `points` collection.
+fn property_key_sets(points: &serde_json::Value) -> Vec<BTreeSet<String>> {
+    points["features"].as_array().unwrap().iter()
+        .map(|f| f["properties"].as_object().unwrap().keys().cloned().collect())
+        .collect()
+}
+
+
+/// Asserts that the properties of the features are the same, taking missing properties as `null`.
+fn assert_features_agree(points: &serde_json::Value, sparse_points: &serde_json::Value) {
+    for (feature, sparse_feature) in points["features"].as_array().unwrap().iter().zip(sparse_points["features"].as_array().unwrap()) {
+        for (key, value) in feature["properties"].as_object().unwrap() {
+            assert_eq!(value, sparse_feature["properties"].get(key).unwrap_or(&serde_json::Value::Null), "{}", key);
+        }
+    }
+}
+
+
+#[test]
+fn null_missing_gives_all_features_the_same_properties() {
+    // the walk has neither respiration rate nor SpO2
+    let activity = read_activity(&fixture_path("walk.fit"), &ReadOptions::default()).unwrap();
+    for points_geometry in [PointsGeometry::Segment, PointsGeometry::Point] {
+        let sparse_options = DocumentOptions {
+            points_geometry,
+            ..DocumentOptions::default()
+        };
+        let sparse_points = lines_to_points(&activity.lines, &sparse_options);
+        let options = DocumentOptions { null_missing: true, ..sparse_options };
+        let points = lines_to_points(&activity.lines, &options);
+
+        let key_sets = property_key_sets(&points);
+        assert!(key_sets.iter().all(|k| k == &key_sets[0]), "{:?}: properties differ", points_geometry);
+        assert!(key_sets[0].contains("spo2") && key_sets[0].contains("respiration_rate"), "{:?}: {:?}", points_geometry, key_sets[0]);
+        assert!(property_key_sets(&sparse_points).iter().all(|k| k.is_subset(&key_sets[0])));
+        assert_features_agree(&points, &sparse_points);
+    }
+}
+
+
+#[test]
+fn non_finite_values_are_unknown() {
+    let mut activity = read_activity(&fixture_path("walk.fit"), &ReadOptions::default()).unwrap();
+    activity.lines[0][1].elevation_m = Some(f64::NAN);
+    for points_geometry in [PointsGeometry::Segment, PointsGeometry::Point] {
+        for null_missing in [false, true] {
+            let options = DocumentOptions {
+                points_geometry,
+                null_missing,
+                ..DocumentOptions::default()
+            };
+            let points = lines_to_points(&activity.lines, &options);
+            let features = points["features"].as_array().unwrap();
+            let elevations: Vec<Option<&serde_json::Value>> = features.iter()
+                .map(|f| f["properties"].get("elevation"))
+                .collect();
+            match points_geometry {
+                // the segments around the point take the elevation of the other point
+                PointsGeometry::Segment => {
+                    assert!(elevations[..2].iter().all(|e| e.is_some_and(|e| e.is_f64())), "{:?}", elevations);
+                    assert!(features[0]["properties"].get("gradient").is_none_or(|g| g.is_null()));
+                },
+                PointsGeometry::Point => match null_missing {
+                    true => assert_eq!(elevations[1], Some(&serde_json::Value::Null)),
+                    false => assert_eq!(elevations[1], None),
+                },
+            }
+        }
+    }
+}
+
+
+#[test]
+fn segment_lengths_follow_the_distance_source() {
+    let mut activity = read_activity(&fixture_path("walk.fit"), &ReadOptions::default()).unwrap();
+    for (i, point) in activity.lines[0].iter_mut().enumerate() {
+        point.device_distance_m = Some(100.0 + 3.0 * i as f64);
+    }
+    // where the device has not recorded a distance, the calculated one is used
+    activity.lines[0][2].device_distance_m = None;
+
+    let segment_lengths = |distance_source| {
+        let options = DocumentOptions {
+            distance_source,
+            ..DocumentOptions::default()
+        };
+        let points = lines_to_points(&activity.lines, &options);
+        points["features"].as_array().unwrap().iter()
+            .map(|f| f["properties"]["segment_length"].as_f64().unwrap())
+            .collect::<Vec<f64>>()
+    };
+    let computed = segment_lengths(DistanceSource::Computed);
+    let device = segment_lengths(DistanceSource::Device);
+    assert_eq!(computed.len(), device.len());
+    assert_eq!(device[0], 3.0);
+    assert_eq!(device[1..3], computed[1..3]);
+    // the segments of the first line
+    let segment_count = activity.lines[0].len() - 1;
+    assert!(device[3..segment_count].iter().all(|l| *l == 3.0), "{:?}", device);
+    assert!(computed[..segment_count].iter().all(|l| *l != 3.0), "{:?}", computed);
+}
+
+
+#[test]
+fn features_are_classified_by_the_measurement() {
+    let activity = read_activity(&fixture_path("walk.fit"), &ReadOptions::default()).unwrap();
+    let options = DocumentOptions {
+        points_geometry: PointsGeometry::Point,
+        color_by: Some(ColorBy::HeartRate),
+        ..DocumentOptions::default()
+    };
+    let points = lines_to_points(&activity.lines, &options);
+    let mut classified: Vec<(u64, u64, String)> = points["features"].as_array().unwrap().iter()
+        .map(|f| (
+            f["properties"]["heart_rate"].as_u64().unwrap(),
+            f["properties"]["class"].as_u64().unwrap(),
+            f["properties"]["color"].as_str().unwrap().to_owned(),
+        ))
+        .collect();
+    classified.sort();
+
+    // the classes span the range of heart rates and never decrease with the heart rate
+    let (_, lowest_class, lowest_color) = &classified[0];
+    let (_, highest_class, highest_color) = &classified[classified.len() - 1];
+    assert_eq!(*lowest_class, 0);
+    assert_eq!(*highest_class, COLOR_CLASSES as u64 - 1);
+    assert_eq!(lowest_color, &ColorScale::Viridis.color_hex(0.0));
+    assert_eq!(highest_color, &ColorScale::Viridis.color_hex(1.0));
+    assert!(classified.windows(2).all(|w| w[0].1 <= w[1].1));
+    // features of the same class have the same color
+    assert!(classified.windows(2).all(|w| w[0].1 != w[1].1 || w[0].2 == w[1].2));
+}
@@ -0,0 +1,185 @@
+//! Golden-file tests: the fixtures are converted by the command-line tool and the output is
+//! compared to the expected output in `tests/golden`.
+//!
+//! After an intentional change to the output, the golden files are rewritten by running the tests
+//! with the environment variable `UPDATE_GOLDEN=1`; the changes are then reviewed using `git diff`.
+
+
+mod common;
+
+
+use std::fs;
+
+use fit2walking::gzip::gunzip;
+
+use common::{run_fit2walking, tests_dir};
+
+
+/// Runs the subcommand with the given arguments and compares its standard output with the golden
+/// file of the given name. The configuration file is ignored and `--stable` is passed so that the
+/// output does not depend on the machine.
+fn assert_golden(golden_name: &str, subcommand: &str, args: &[&str]) {
+    let mut all_args = vec![subcommand, "--no-config", "--stable"];
+    all_args.extend_from_slice(args);
+
+    let output = run_fit2walking(&all_args);
+    assert!(
+        output.status.success(),
+        "fit2walking {} failed: {}", all_args.join(" "), String::from_utf8_lossy(&output.stderr),
+    );
+
+    let golden_path = tests_dir().join("golden").join(golden_name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden_path, &output.stdout)
+            .unwrap_or_else(|e| panic!("failed to write {}: {}", golden_path.display(), e));
+        return;
+    }
+
+    let expected = fs::read(&golden_path)
+        .unwrap_or_else(|e| panic!("failed to read {} (run with UPDATE_GOLDEN=1 to create it): {}", golden_path.display(), e));
+    if output.stdout == expected {
+        return;
+    }
+
+    // point out the first difference instead of dumping both documents
+    let actual_text = String::from_utf8_lossy(&output.stdout);
+    let expected_text = String::from_utf8_lossy(&expected);
+    let mismatch = actual_text.lines()
+        .map(Some)
+        .chain(std::iter::repeat(None))
+        .zip(expected_text.lines().map(Some).chain(std::iter::repeat(None)))
+        .take_while(|(a, e)| a.is_some() || e.is_some())
+        .enumerate()
+        .find(|(_, (a, e))| a != e);
+    if let Some((line_index, (actual_line, expected_line))) = mismatch {
+        panic!(
+            "output of fit2walking {} differs from {} in line {}:\n  expected: {}\n  actual:   {}\n(run with UPDATE_GOLDEN=1 if the change is intended)",
+            all_args.join(" "), golden_path.display(), line_index + 1,
+            expected_line.unwrap_or("(end of file)"), actual_line.unwrap_or("(end of file)"),
+        );
+    }
+    panic!("output of fit2walking {} differs from {}", all_args.join(" "), golden_path.display());
+}
+
+
+#[test]
+fn geojson_default() {
+    assert_golden("walk.json", "convert", &["fixtures/walk.fit"]);
+}
+
+#[test]
+fn geojson_points_and_markers() {
+    assert_golden("walk-points.json", "convert", &[
+        "--points-as", "point", "--lap-markers", "--pause-markers", "--splits", "fixtures/walk.fit",
+    ]);
+}
+
+#[test]
+fn geojson_censored() {
+    assert_golden("walk-censored.json", "convert", &[
+        "--censor-circle", "48.2088,16.3748,40", "--censor-mode", "split", "fixtures/walk.fit",
+    ]);
+}
+
+#[test]
+fn geojson_training_load() {
+    assert_golden("walk-training.json", "convert", &[
+        "--compact", "--max-hr", "190", "--rest-hr", "60", "--ftp", "200", "--best-efforts", "100m,1min",
+        "fixtures/walk.fit",
+    ]);
+}
+
+#[test]
+fn geojson_dynamics() {
+    assert_golden("dynamics.json", "convert", &[
+        "--compact", "--points-as", "point", "--cycling-dynamics", "--running-dynamics", "fixtures/dynamics.fit",
+    ]);
+}
+
+#[test]
+fn geojson_chained() {
+    assert_golden("chained.json", "convert", &["--compact", "fixtures/chained.fit"]);
+}
+
+#[test]
+fn geojson_multisport() {
+    assert_golden("multisport.json", "convert", &["--compact", "fixtures/multisport.fit"]);
+}
+
+#[test]
+fn geojson_swim() {
+    assert_golden("swim.json", "convert", &["--compact", "--swim", "fixtures/swim.fit"]);
+}
+
+#[test]
+fn geojson_series() {
+    assert_golden("series.json", "convert", &["--compact", "--series", "10", "fixtures/walk.fit"]);
+}
+
+#[test]
+fn geojson_indoor() {
+    assert_golden("indoor.json", "convert", &["--compact", "fixtures/indoor.fit"]);
+}
+
+#[test]
+fn geojson_lines() {
+    assert_golden("walk.geojsonl", "convert", &["--output-format", "geojsonl", "fixtures/walk.fit"]);
+}
+
+#[test]
+fn csv() {
+    assert_golden("walk.csv", "convert", &["--output-format", "csv", "fixtures/walk.fit"]);
+}
+
+#[test]
+fn kml() {
+    assert_golden("walk.kml", "convert", &["--output-format", "kml", "fixtures/walk.fit"]);
+}
+
+#[test]
+fn polyline() {
+    assert_golden("walk.polyline", "convert", &["--output-format", "polyline", "fixtures/walk.fit"]);
+}
+
+#[test]
+fn trip() {
+    assert_golden("walk.trip.json", "convert", &["--compact", "--output-format", "trip", "fixtures/walk.fit"]);
+}
+
+#[test]
+fn timestamped_geojson() {
+    assert_golden("walk.timestamped.json", "convert", &["--compact", "--output-format", "timestamped-geojson", "fixtures/walk.fit"]);
+}
+
+#[test]
+fn stats_table() {
+    assert_golden("stats.txt", "stats", &["--summary-format", "table", "fixtures/walk.fit", "fixtures/chained.fit", "fixtures/indoor.fit"]);
+}
+
+#[test]
+fn gzip_output_file() {
+    let dir = std::env::temp_dir().join(format!("fit2walking-gzip-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    // the template does not end in .gz, so it is appended
+    let template = dir.join("{stem}.json");
+    for extra_args in [&["--gzip"][..], &[]] {
+        let mut args = vec!["convert", "--no-config", "--stable", "-o", template.to_str().unwrap(), "fixtures/walk.fit"];
+        args.extend_from_slice(extra_args);
+        let output = run_fit2walking(&args);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(output.stdout.is_empty());
+    }
+
+    let mut names: Vec<String> = fs::read_dir(&dir).unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["walk.json", "walk.json.gz"]);
+
+    let compressed = fs::read(dir.join("walk.json.gz")).unwrap();
+    let expected = fs::read(dir.join("walk.json")).unwrap();
+    assert_eq!(gunzip(&compressed).unwrap(), expected);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,44 @@
+//! Tests of importing activities from a mounted Garmin device.
+
+
+mod common;
+
+
+use std::fs;
+use std::path::PathBuf;
+
+use fit2walking::import::{activity_folder, import_activities};
+use fit2walking::timezone::Timezone;
+
+use common::fixture_path;
+
+
+/// Creates an empty directory for the test within the temporary directory.
+fn empty_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fit2walking-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+
+#[test]
+fn new_activities_are_archived_by_start_time() {
+    let device = empty_dir("device");
+    let archive = empty_dir("archive");
+    // the case of the folder names depends on the device
+    let folder = device.join("Garmin").join("ACTIVITY");
+    fs::create_dir_all(&folder).unwrap();
+    fs::copy(fixture_path("walk.fit"), folder.join("B8E91234.FIT")).unwrap();
+    assert_eq!(activity_folder(&device), Some(folder.clone()));
+
+    let imported = import_activities(&folder, &archive, &Timezone::Utc).unwrap();
+    assert_eq!(imported, [archive.join("2023").join("2023-11-14-221320.fit")]);
+    assert_eq!(fs::read(&imported[0]).unwrap(), fs::read(fixture_path("walk.fit")).unwrap());
+
+    // already imported
+    assert!(import_activities(&folder, &archive, &Timezone::Utc).unwrap().is_empty());
+
+    fs::remove_dir_all(&device).unwrap();
+    fs::remove_dir_all(&archive).unwrap();
+}
@@ -0,0 +1,118 @@
+//! Tests of reading the text-based input formats and of the XML parser underlying them.
+
+
+use fit2walking::input::gpx::read_gpx;
+use fit2walking::input::xml::{parse, Element, Node};
+
+
+/// Returns the position and message of the error in the document.
+fn xml_error(text: &str) -> (usize, String) {
+    let e = parse(text).unwrap_err();
+    (e.position, e.message)
+}
+
+fn text_node(text: &str) -> Node {
+    Node::Text(text.to_owned())
+}
+
+
+#[test]
+fn non_finite_values_are_ignored_with_a_warning() {
+    let gpx = r#"<?xml version="1.0"?>
+<gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1"><trk><trkseg>
+<trkpt lat="48.2000" lon="16.3700"><ele>200</ele><time>2023-07-01T09:00:00Z</time></trkpt>
+<trkpt lat="48.2001" lon="16.3701"><ele>NaN</ele><time>2023-07-01T09:00:05Z</time></trkpt>
+<trkpt lat="48.2002" lon="16.3702"><ele>-inf</ele><time>2023-07-01T09:00:10Z</time></trkpt>
+</trkseg></trk></gpx>"#;
+    let activity = read_gpx(gpx).unwrap();
+    let elevations: Vec<Option<f64>> = activity.lines.iter()
+        .flatten()
+        .map(|p| p.elevation_m)
+        .collect();
+    assert_eq!(elevations, [Some(200.0), None, None]);
+
+    let warnings = &activity.diagnostics.warnings;
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(warnings.iter().all(|w| w.field.as_deref() == Some("ele")), "{:?}", warnings);
+}
+
+#[test]
+fn xml_entity_and_character_references() {
+    let root = parse(r#"<name title="&quot;R&amp;D&quot; &#x263A;">Fish &amp; chips &lt;&gt; &apos;&#233;t&#xE9;&apos; &#x1F6B6;</name>"#).unwrap();
+    assert_eq!(root.attribute("title"), Some("\"R&D\" \u{263A}"));
+    assert_eq!(root.text(), "Fish & chips <> 'été' \u{1F6B6}");
+
+    assert_eq!(xml_error("<a>&nbsp;</a>"), (3, "unknown entity \"nbsp\"".to_owned()));
+    assert_eq!(xml_error("<a>&#xD800;</a>"), (3, "unknown entity \"#xD800\"".to_owned()));
+    assert_eq!(xml_error("<a>&#12a;</a>"), (3, "unknown entity \"#12a\"".to_owned()));
+    assert_eq!(xml_error("<a b=\"&amp\"/>"), (6, "unterminated entity reference".to_owned()));
+}
+
+#[test]
+fn xml_cdata() {
+    let root = parse("<desc>before <![CDATA[<b>bold</b> & ]]> ]]>after</desc>").unwrap();
+    // CDATA is neither parsed as markup nor decoded, and merges with the surrounding text
+    assert_eq!(root.children, [text_node("before <b>bold</b> &  ]]>after")]);
+    assert_eq!(parse("<a><![CDATA[]]></a>").unwrap().text(), "");
+    assert_eq!(xml_error("<a><![CDATA[never ends</a>"), (12, "missing \"]]>\"".to_owned()));
+}
+
+#[test]
+fn xml_namespace_prefixes_are_stripped() {
+    let root = parse(r#"<gpx xmlns:gpxtpx="http://www.garmin.com/xmlschemas/TrackPointExtension/v1" xsi:schemaLocation="x">
+<extensions><gpxtpx:TrackPointExtension><gpxtpx:hr>140</gpxtpx:hr><gpxtpx:cad>80</gpxtpx:cad></gpxtpx:TrackPointExtension></extensions>
+</gpx>"#).unwrap();
+    assert_eq!(root.name, "gpx");
+    assert_eq!(root.attribute("schemaLocation"), Some("x"));
+    assert_eq!(root.attribute("gpxtpx"), Some("http://www.garmin.com/xmlschemas/TrackPointExtension/v1"));
+    let extension = root.descendant("TrackPointExtension").unwrap();
+    assert_eq!(extension.child_text("hr").as_deref(), Some("140"));
+    assert_eq!(extension.child_text("cad").as_deref(), Some("80"));
+
+    // the prefixes of closing tags must still match those of the opening tags
+    assert_eq!(
+        xml_error("<gpxtpx:hr>140</hr>"),
+        (14, "closing tag \"hr\" does not match opening tag \"gpxtpx:hr\"".to_owned()),
+    );
+}
+
+#[test]
+fn xml_doctype_with_internal_subset() {
+    let root = parse(r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE gpx [
+    <!ELEMENT gpx (trk*)>
+    <!ATTLIST gpx version CDATA #REQUIRED>
+    <!-- a comment > within the subset -->
+]>
+<!-- a comment before the root -->
+<gpx version="1.1"><trk/></gpx>
+"#).unwrap();
+    assert_eq!(
+        root,
+        Element {
+            name: "gpx".to_owned(),
+            attributes: vec![("version".to_owned(), "1.1".to_owned())],
+            children: vec![Node::Element(Element { name: "trk".to_owned(), ..Element::default() })],
+        },
+    );
+
+    assert_eq!(xml_error("<!DOCTYPE gpx [ <!ELEMENT gpx ANY> <gpx/>"), (0, "unterminated DOCTYPE".to_owned()));
+}
+
+#[test]
+fn xml_malformed_documents_are_rejected() {
+    assert_eq!(
+        xml_error("<gpx><trk></gpx>"),
+        (10, "closing tag \"gpx\" does not match opening tag \"trk\"".to_owned()),
+    );
+    assert_eq!(xml_error("</gpx>"), (0, "unexpected closing tag \"gpx\"".to_owned()));
+    assert_eq!(xml_error("<gpx><trk></trk>"), (16, "unexpected end of document".to_owned()));
+    assert_eq!(xml_error(""), (0, "unexpected end of document".to_owned()));
+    assert_eq!(xml_error("<gpx><trk"), (9, "expected a name".to_owned()));
+    assert_eq!(xml_error("<gpx><trk></trk"), (12, "missing \">\"".to_owned()));
+    assert_eq!(xml_error("<gpx version=\"1.1></gpx>"), (14, "missing \"\\\"\"".to_owned()));
+    assert_eq!(xml_error("<gpx version></gpx>"), (12, "expected '=' after attribute name".to_owned()));
+    assert_eq!(xml_error("<gpx version=1.1></gpx>"), (13, "expected quoted attribute value".to_owned()));
+    assert_eq!(xml_error("<gpx><!-- unterminated</gpx>"), (5, "missing \"-->\"".to_owned()));
+    assert_eq!(parse("<gpx></trk>").unwrap_err().to_string(), "at byte 5: closing tag \"trk\" does not match opening tag \"gpx\"");
+}
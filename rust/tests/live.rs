@@ -0,0 +1,136 @@
+//! Tests of following FIT files while they are being recorded and pushing features over WebSocket.
+
+
+mod common;
+
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+use fit2walking::geojson::DocumentOptions;
+use fit2walking::input::fit::read_fit;
+use fit2walking::input::ReadOptions;
+use fit2walking::live::{complete_fit_prefix, feature_messages, LiveServer};
+use fit2walking::websocket::accept_key;
+
+use common::fixture_path;
+
+
+/// Connects a WebSocket client to the server, returning the stream positioned after the handshake.
+fn connect(server: &LiveServer) -> BufReader<TcpStream> {
+    let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n").unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    assert_eq!(status, "HTTP/1.1 101 Switching Protocols\r\n");
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        if header == "\r\n" {
+            break;
+        }
+    }
+    reader
+}
+
+
+/// Reads an unmasked text frame sent by the server.
+fn read_text_frame(reader: &mut BufReader<TcpStream>) -> String {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head).unwrap();
+    assert_eq!(head[0], 0x81);
+    let length = match head[1] {
+        126 => {
+            let mut length = [0u8; 2];
+            reader.read_exact(&mut length).unwrap();
+            u16::from_be_bytes(length) as usize
+        },
+        127 => {
+            let mut length = [0u8; 8];
+            reader.read_exact(&mut length).unwrap();
+            u64::from_be_bytes(length) as usize
+        },
+        length => length as usize,
+    };
+    let mut payload = vec![0u8; length];
+    reader.read_exact(&mut payload).unwrap();
+    String::from_utf8(payload).unwrap()
+}
+
+
+#[test]
+fn handshake_accept_key_matches_rfc() {
+    // the example of RFC 6455, section 1.3
+    assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+}
+
+
+#[test]
+fn file_being_recorded_is_read_up_to_its_last_complete_message() {
+    let data = std::fs::read(fixture_path("walk.fit")).unwrap();
+    let read_features = |fit_data: &[u8]| {
+        let activities = read_fit(&mut &fit_data[..], &ReadOptions::default()).unwrap();
+        feature_messages(&activities[0], &DocumentOptions::default())
+    };
+    let all_features = read_features(&data);
+
+    // as written by a device during recording: no data size in the header, cut off mid-message
+    let mut growing = data[..data.len() * 2 / 3].to_vec();
+    growing[4..8].copy_from_slice(&[0; 4]);
+    let prefix = complete_fit_prefix(&growing).expect("header not recognized");
+    let features = read_features(&prefix);
+    assert!(!features.is_empty() && features.len() < all_features.len());
+    assert_eq!(features[..], all_features[..features.len()]);
+
+    // complete files are kept as they are
+    assert_eq!(complete_fit_prefix(&data).unwrap(), data);
+    assert_eq!(complete_fit_prefix(&data[..8]), None);
+}
+
+
+#[test]
+fn clients_receive_earlier_and_new_messages() {
+    let server = LiveServer::bind("127.0.0.1:0").unwrap();
+    server.publish(&["first".to_owned()]);
+
+    let mut reader = connect(&server);
+    assert_eq!(read_text_frame(&mut reader), "first");
+
+    server.publish(&["second".to_owned()]);
+    assert_eq!(read_text_frame(&mut reader), "second");
+}
+
+
+#[test]
+fn clients_receive_each_feature_of_a_growing_file_once() {
+    let data = std::fs::read(fixture_path("walk.fit")).unwrap();
+    let read_features = |fit_data: &[u8]| {
+        let activities = read_fit(&mut &fit_data[..], &ReadOptions::default()).unwrap();
+        feature_messages(&activities[0], &DocumentOptions::default())
+    };
+    let all_features = read_features(&data);
+
+    let server = LiveServer::bind("127.0.0.1:0").unwrap();
+    let mut reader = connect(&server);
+    let mut received = Vec::new();
+    let mut growing = data.clone();
+    growing[4..8].copy_from_slice(&[0; 4]);
+    for length in (1..=4).map(|i| 14 + (growing.len() - 14) * i / 4) {
+        let prefix = match complete_fit_prefix(&growing[..length]) {
+            Some(prefix) => prefix,
+            None => continue,
+        };
+        let count = server.publish_new(&read_features(&prefix)).unwrap();
+        received.extend((0..count).map(|_| read_text_frame(&mut reader)));
+    }
+    assert_eq!(received, all_features);
+
+    // features that have changed since they were sent are not sent again, nor is anything after them
+    let mut changed = all_features.clone();
+    changed[1] = "changed".to_owned();
+    changed.push("new".to_owned());
+    assert_eq!(server.publish_new(&changed), Err(1));
+    server.publish(&["last".to_owned()]);
+    assert_eq!(read_text_frame(&mut reader), "last");
+}
@@ -0,0 +1,32 @@
+//! Tests of splitting multisport activities into their legs.
+
+
+mod common;
+
+
+use fit2walking::input::{read_activity, ReadOptions};
+use fit2walking::multisport::split_legs;
+
+use common::fixture_path;
+
+
+#[test]
+fn legs_are_split_at_sport_changes() {
+    let activity = read_activity(&fixture_path("multisport.fit"), &ReadOptions::default()).unwrap();
+    let legs = split_legs(&activity).expect("not recognized as multisport");
+
+    let sports: Vec<Option<&str>> = legs.legs.iter().map(|l| l.session.sport.as_deref()).collect();
+    assert_eq!(sports, [Some("swimming"), Some("cycling"), Some("running")]);
+    let leg_point_counts: Vec<usize> = legs.legs.iter().map(|l| l.lines.iter().map(|line| line.len()).sum()).collect();
+    assert_eq!(leg_point_counts, [20, 30, 20]);
+
+    // the transitions include the transition sessions and the gaps around them
+    let durations: Vec<f64> = legs.transitions.iter().map(|t| t.duration_s()).collect();
+    assert_eq!(durations, [50.0, 35.0]);
+    assert_eq!(legs.transitions[1].leg_index, 1);
+
+    // a single sport is not split, however many sessions there are
+    let chained = read_activity(&fixture_path("chained.fit"), &ReadOptions::default()).unwrap();
+    assert_eq!(chained.sessions.len(), 2);
+    assert!(split_legs(&chained).is_none());
+}
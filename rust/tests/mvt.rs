@@ -0,0 +1,214 @@
+//! Tests of the Mapbox Vector Tile writer. The tiles are decoded by a minimal protocol buffer
+//! reader, independently of the writer.
+
+
+use std::fs;
+use std::path::PathBuf;
+
+use fit2walking::Point;
+use fit2walking::mvt::{TileOptions, TileSet};
+
+
+/// The value of a protocol buffer field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+}
+
+
+fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= u64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+/// Splits the message into its fields, in order.
+fn fields(data: &[u8]) -> Vec<(u64, Field<'_>)> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let key = read_varint(data, &mut pos);
+        let value = match key & 0b111 {
+            0 => Field::Varint(read_varint(data, &mut pos)),
+            1 => {
+                let bytes: [u8; 8] = data[pos..pos + 8].try_into().unwrap();
+                pos += 8;
+                Field::Fixed64(u64::from_le_bytes(bytes))
+            },
+            2 => {
+                let length = read_varint(data, &mut pos) as usize;
+                pos += length;
+                Field::Bytes(&data[pos - length..pos])
+            },
+            other => panic!("unexpected wire type {}", other),
+        };
+        fields.push((key >> 3, value));
+    }
+    fields
+}
+
+/// Returns the encoded layer of the tile with the given name.
+fn layer<'a>(tile: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    fields(tile).into_iter()
+        .filter_map(|(number, value)| match (number, value) {
+            (3, Field::Bytes(layer)) => Some(layer),
+            _ => None,
+        })
+        .find(|layer| fields(layer).contains(&(1, Field::Bytes(name.as_bytes()))))
+}
+
+/// Decodes the geometries of the features of the layer into the positions of their parts.
+fn geometries(layer: &[u8]) -> Vec<Vec<Vec<(i64, i64)>>> {
+    let mut geometries = Vec::new();
+    for (_, feature) in fields(layer).into_iter().filter(|(number, _)| *number == 2) {
+        let feature = match feature {
+            Field::Bytes(f) => f,
+            other => panic!("unexpected feature {:?}", other),
+        };
+        let geometry = fields(feature).into_iter()
+            .find_map(|(number, value)| match (number, value) {
+                (4, Field::Bytes(g)) => Some(g),
+                _ => None,
+            })
+            .unwrap();
+
+        let mut parts: Vec<Vec<(i64, i64)>> = Vec::new();
+        let mut cursor = (0, 0);
+        let mut pos = 0;
+        while pos < geometry.len() {
+            let command = read_varint(geometry, &mut pos);
+            for _ in 0..command >> 3 {
+                let mut delta = || {
+                    let zigzag = read_varint(geometry, &mut pos);
+                    ((zigzag >> 1) as i64) ^ -((zigzag & 1) as i64)
+                };
+                cursor = (cursor.0 + delta(), cursor.1 + delta());
+                match command & 0b111 {
+                    1 => parts.push(vec![cursor]),
+                    2 => parts.last_mut().unwrap().push(cursor),
+                    other => panic!("unexpected command {}", other),
+                }
+            }
+        }
+        geometries.push(parts);
+    }
+    geometries
+}
+
+fn point(lon: f64, lat: f64) -> Point {
+    Point::new(geo::Point::new(lon, lat))
+}
+
+/// Writes the tiles of the given zoom level into a temporary directory and returns their paths
+/// relative to it along with their contents.
+fn write_tiles(name: &str, zoom: u8, lines: &[Vec<Point>]) -> Vec<(String, Vec<u8>)> {
+    let mut tile_set = TileSet::new(TileOptions {
+        min_zoom: zoom,
+        max_zoom: zoom,
+        ..TileOptions::default()
+    });
+    tile_set.add_activity("walk", lines);
+
+    let dir = std::env::temp_dir().join(format!("fit2walking-mvt-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    tile_set.write_tiles(&dir).unwrap();
+
+    let mut tiles = Vec::new();
+    let mut pending: Vec<PathBuf> = vec![dir.clone()];
+    while let Some(path) = pending.pop() {
+        if path.is_dir() {
+            pending.extend(fs::read_dir(&path).unwrap().map(|e| e.unwrap().path()));
+        } else if path.extension().is_some_and(|e| e == "pbf") {
+            let relative = path.strip_prefix(&dir).unwrap().to_string_lossy().replace('\\', "/");
+            tiles.push((relative, fs::read(&path).unwrap()));
+        }
+    }
+    tiles.sort();
+    assert_eq!(tiles.len(), tile_set.tile_count());
+    fs::remove_dir_all(&dir).unwrap();
+    tiles
+}
+
+
+#[test]
+fn single_line_string_tile() {
+    // at zoom 0, the world is a single tile of 4096 units: (0, 0) lies at its center, 90° east a
+    // quarter further right and 66.51° north (where the Mercator y is 0.25) a quarter further up
+    let tiles = write_tiles("single", 0, &[vec![
+        point(0.0, 0.0),
+        point(90.0, 0.0),
+        point(45.0, 66.513_260_443_111_86),
+    ]]);
+    assert_eq!(tiles.len(), 1);
+    let (path, tile) = &tiles[0];
+    assert_eq!(path, "0/0/0.pbf");
+
+    let geometry = [
+        9, 0x80, 0x20, 0x80, 0x20,  // MoveTo(1): +2048 +2048
+        18, 0x80, 0x10, 0x00,  // LineTo(2): +1024 +0
+        0xFF, 0x07, 0xFF, 0x0F,  // -512 -1024
+    ];
+    let mut feature = vec![
+        0x12, 4, 0, 0, 1, 1,  // tags: activity = "walk", line = 0
+        0x18, 2,  // type: line string
+        0x22, geometry.len() as u8,
+    ];
+    feature.extend_from_slice(&geometry);
+    let mut expected_layer = vec![
+        0x78, 2,  // version
+        0x0A, 5, b't', b'r', b'a', b'c', b'k',
+        0x12, feature.len() as u8,
+    ];
+    expected_layer.extend_from_slice(&feature);
+    expected_layer.extend_from_slice(b"\x1A\x08activity\x1A\x04line");
+    expected_layer.extend_from_slice(b"\x22\x06\x0A\x04walk\x22\x02\x28\x00");
+    expected_layer.extend_from_slice(&[0x28, 0x80, 0x20]);  // extent 4096
+
+    // the track layer comes first
+    let mut expected_start = vec![0x1A, expected_layer.len() as u8];
+    expected_start.extend_from_slice(&expected_layer);
+    assert_eq!(tile[..expected_start.len()], expected_start);
+    assert_eq!(layer(tile, "track"), Some(expected_layer.as_slice()));
+
+    assert_eq!(geometries(&expected_layer), [[[(2048, 2048), (3072, 2048), (2560, 1024)]]]);
+    let points = layer(tile, "points").unwrap();
+    assert_eq!(geometries(points), [[[(2048, 2048)]], [[(3072, 2048)]], [[(2560, 1024)]]]);
+}
+
+#[test]
+fn points_are_placed_into_their_tile() {
+    // St. Stephen's Cathedral in Vienna
+    let tiles = write_tiles("point", 16, &[vec![point(16.3731, 48.2085)]]);
+    assert_eq!(tiles.len(), 1);
+    let (path, tile) = &tiles[0];
+    assert_eq!(path, "16/35748/22724.pbf");
+    assert_eq!(layer(tile, "track"), None);
+    assert_eq!(geometries(layer(tile, "points").unwrap()), [[[(2588, 1714)]]]);
+}
+
+#[test]
+fn lines_are_clipped_at_the_tile_buffer() {
+    // crosses the boundary between the tile columns 0 and 1 at zoom 1; the parts extend 64 units
+    // beyond the edges of the tiles
+    let tiles = write_tiles("clipped", 1, &[vec![point(-10.0, 10.0), point(10.0, 10.0)]]);
+    let paths: Vec<&str> = tiles.iter()
+        .map(|(path, _)| path.as_str())
+        .collect();
+    assert_eq!(paths, ["1/0/0.pbf", "1/1/0.pbf"]);
+
+    let west = geometries(layer(&tiles[0].1, "track").unwrap());
+    let east = geometries(layer(&tiles[1].1, "track").unwrap());
+    let y = west[0][0][0].1;
+    assert_eq!(west, [[[(3868, y), (4160, y)]]]);
+    assert_eq!(east, [[[(-64, y), (228, y)]]]);
+}
@@ -0,0 +1,295 @@
+//! Property-based tests of the censoring, clipping, distance and downsampling code: invariants are checked on
+//! many randomly generated tracks. The generator is seeded, so failures are reproducible; the
+//! number of the failing case is part of the assertion message.
+
+
+use fit2walking::{GeoPoint, Point};
+use fit2walking::censor::{censor_lines, trim_lines, CensorArea, CensorCircle, CensorMode};
+use fit2walking::filter::{clip_to_bbox, BoundingBox};
+use fit2walking::point::DistanceAlgorithm;
+use fit2walking::series::lttb_indexes;
+
+
+/// The number of random cases checked per property.
+const CASES: usize = 200;
+
+const DISTANCE_ALGORITHMS: [DistanceAlgorithm; 3] = [
+    DistanceAlgorithm::Vincenty, DistanceAlgorithm::Haversine, DistanceAlgorithm::Geodesic,
+];
+
+
+/// A small xorshift generator, which is good enough for generating test cases.
+struct Rng(u64);
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // the state must never be zero
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in the range `[min, max)`.
+    fn range(&mut self, min: f64, max: f64) -> f64 {
+        let fraction = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        min + fraction * (max - min)
+    }
+
+    fn below(&mut self, max: usize) -> usize {
+        (self.next_u64() % max as u64) as usize
+    }
+
+    /// Returns a position anywhere on Earth, avoiding the poles.
+    fn position(&mut self) -> geo::Point<f64> {
+        geo::Point::new(self.range(-180.0, 180.0), self.range(-85.0, 85.0))
+    }
+
+    /// Returns a track of a few lines, each a random walk with steps of up to about 100 m around
+    /// the given center, with a timestamp every five seconds.
+    fn lines_around(&mut self, center: geo::Point<f64>) -> Vec<Vec<Point>> {
+        let mut unix_timestamp = 1_700_000_000.0;
+        let line_count = 1 + self.below(3);
+        let mut lines = Vec::with_capacity(line_count);
+        for _ in 0..line_count {
+            let mut lon = center.x() + self.range(-0.005, 0.005);
+            let mut lat = center.y() + self.range(-0.005, 0.005);
+            let point_count = 1 + self.below(60);
+            let mut line = Vec::with_capacity(point_count);
+            for _ in 0..point_count {
+                lon += self.range(-0.001, 0.001);
+                lat += self.range(-0.001, 0.001);
+                let mut point = Point::new(geo::Point::new(lon, lat));
+                point.unix_timestamp = Some(unix_timestamp);
+                point.elevation_m = Some(self.range(100.0, 300.0));
+                line.push(point);
+                unix_timestamp += 5.0;
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+
+fn point_count(lines: &[Vec<Point>]) -> usize {
+    lines.iter()
+        .map(|l| l.len())
+        .sum()
+}
+
+
+fn track_distance_m(lines: &[Vec<Point>]) -> f64 {
+    lines.iter()
+        .flat_map(|l| l.windows(2))
+        .map(|pair| pair[0].distance_m(&pair[1], DistanceAlgorithm::Geodesic))
+        .sum()
+}
+
+
+#[test]
+fn distance_is_a_metric() {
+    let mut rng = Rng::new(1);
+    for case in 0..CASES {
+        let p1 = rng.position();
+        let p2 = rng.position();
+        let p3 = rng.position();
+        for algorithm in DISTANCE_ALGORITHMS {
+            let d12 = algorithm.distance_m(p1, p2);
+            let d21 = algorithm.distance_m(p2, p1);
+            assert!(d12 >= 0.0, "case {}: negative {:?} distance", case, algorithm);
+            assert_eq!(algorithm.distance_m(p1, p1), 0.0, "case {}: {:?} distance to itself", case, algorithm);
+            assert!((d12 - d21).abs() <= 1e-6 * d12.max(1.0), "case {}: asymmetric {:?} distance", case, algorithm);
+            if algorithm == DistanceAlgorithm::Geodesic {
+                let via = algorithm.distance_m(p1, p3) + algorithm.distance_m(p3, p2);
+                assert!(d12 <= via + 1e-6, "case {}: triangle inequality violated", case);
+            }
+        }
+    }
+}
+
+
+#[test]
+fn distance_algorithms_agree() {
+    let mut rng = Rng::new(2);
+    for case in 0..CASES {
+        let p1 = rng.position();
+        // nearby points, as within a track, as well as far-away ones
+        let p2 = if case % 2 == 0 {
+            geo::Point::new(p1.x() + rng.range(-0.01, 0.01), p1.y() + rng.range(-0.01, 0.01))
+        } else {
+            rng.position()
+        };
+        let geodesic = DistanceAlgorithm::Geodesic.distance_m(p1, p2);
+        let vincenty = DistanceAlgorithm::Vincenty.distance_m(p1, p2);
+        let haversine = DistanceAlgorithm::Haversine.distance_m(p1, p2);
+
+        // Vincenty may fall back to haversine for nearly antipodal points
+        let tolerance = 0.006 * geodesic + 1e-6;
+        assert!((vincenty - geodesic).abs() <= tolerance, "case {}: Vincenty {} vs. geodesic {}", case, vincenty, geodesic);
+        assert!((haversine - geodesic).abs() <= tolerance, "case {}: haversine {} vs. geodesic {}", case, haversine, geodesic);
+    }
+}
+
+
+#[test]
+fn censoring_removes_exactly_the_points_within_the_areas() {
+    let mut rng = Rng::new(3);
+    for case in 0..CASES {
+        let center = rng.position();
+        let lines = rng.lines_around(center);
+        let circle = CensorCircle {
+            center_deg: geo::Point::new(center.x() + rng.range(-0.005, 0.005), center.y() + rng.range(-0.005, 0.005)),
+            radius_m: rng.range(0.0, 500.0),
+        };
+        let areas = [CensorArea::Circle(circle)];
+        let expected: Vec<&Point> = lines.iter()
+            .flatten()
+            .filter(|p| !circle.contains(&p.coordinates_deg))
+            .collect();
+
+        for mode in [CensorMode::Skip, CensorMode::Split, CensorMode::Bridge] {
+            let mut censored = lines.clone();
+            censor_lines(&mut censored, &areas, mode);
+            let remaining: Vec<&Point> = censored.iter().flatten().collect();
+            assert_eq!(remaining.len(), expected.len(), "case {}: points remaining after censoring in {:?} mode", case, mode);
+            for (r, e) in remaining.iter().zip(expected.iter()) {
+                assert_eq!(r.coordinates_deg, e.coordinates_deg, "case {}: {:?} mode", case, mode);
+            }
+            assert!(censored.iter().all(|l| !l.is_empty()), "case {}: empty line in {:?} mode", case, mode);
+
+            // the number of runs of points outside of the area, per line
+            let runs: Vec<usize> = lines.iter()
+                .map(|l| l.split(|p| circle.contains(&p.coordinates_deg)).filter(|r| !r.is_empty()).count())
+                .collect();
+            let lines_with_points = runs.iter().filter(|r| **r > 0).count();
+            let bridged = remaining.iter().filter(|p| p.follows_censored_gap).count();
+            match mode {
+                CensorMode::Skip => {
+                    assert_eq!(censored.len(), lines_with_points, "case {}: lines after skipping", case);
+                    assert_eq!(bridged, 0, "case {}: bridges after skipping", case);
+                },
+                CensorMode::Split => {
+                    assert_eq!(censored.len(), runs.iter().sum::<usize>(), "case {}: lines after splitting", case);
+                    assert_eq!(bridged, 0, "case {}: bridges after splitting", case);
+                },
+                CensorMode::Bridge => {
+                    assert_eq!(censored.len(), lines_with_points, "case {}: lines after bridging", case);
+                    assert_eq!(bridged, runs.iter().map(|r| r.saturating_sub(1)).sum::<usize>(), "case {}: bridges", case);
+                },
+            }
+        }
+    }
+}
+
+
+#[test]
+fn censoring_nothing_changes_nothing() {
+    let mut rng = Rng::new(4);
+    for case in 0..CASES {
+        let center = rng.position();
+        let lines = rng.lines_around(center);
+        let far_away = CensorCircle {
+            center_deg: geo::Point::new(-center.x(), -center.y()),
+            radius_m: 1000.0,
+        };
+        for mode in [CensorMode::Skip, CensorMode::Split, CensorMode::Bridge] {
+            let mut censored = lines.clone();
+            censor_lines(&mut censored, &[CensorArea::Circle(far_away)], mode);
+            assert_eq!(censored, lines, "case {}: {:?} mode", case, mode);
+        }
+    }
+}
+
+
+#[test]
+fn trimming_shortens_the_track() {
+    let mut rng = Rng::new(5);
+    for case in 0..CASES {
+        let center = rng.position();
+        let lines = rng.lines_around(center);
+        let start_m = rng.range(0.0, 500.0);
+        let end_m = rng.range(0.0, 500.0);
+
+        let mut trimmed = lines.clone();
+        trim_lines(&mut trimmed, start_m, end_m, DistanceAlgorithm::Geodesic);
+        assert!(point_count(&trimmed) <= point_count(&lines), "case {}", case);
+        assert!(track_distance_m(&trimmed) <= track_distance_m(&lines) + 1e-6, "case {}", case);
+        assert!(trimmed.iter().all(|l| !l.is_empty()), "case {}: empty line", case);
+
+        // the points that remain are the ones in the middle, in order
+        let original: Vec<&Point> = lines.iter().flatten().collect();
+        let remaining: Vec<&Point> = trimmed.iter().flatten().collect();
+        if let Some(first) = remaining.first() {
+            let offset = original.iter().position(|p| p == first).unwrap();
+            assert_eq!(&original[offset..offset + remaining.len()], &remaining[..], "case {}", case);
+        }
+
+        let mut untrimmed = lines.clone();
+        trim_lines(&mut untrimmed, 0.0, 0.0, DistanceAlgorithm::Geodesic);
+        assert_eq!(untrimmed, lines, "case {}", case);
+    }
+}
+
+
+#[test]
+fn clipping_keeps_the_track_within_the_bounding_box() {
+    let mut rng = Rng::new(6);
+    for case in 0..CASES {
+        let center = rng.position();
+        let lines = rng.lines_around(center);
+        let (lon, lat) = (center.x() + rng.range(-0.005, 0.005), center.y() + rng.range(-0.005, 0.005));
+        let bbox = BoundingBox {
+            min_lon: lon - rng.range(0.0, 0.005),
+            min_lat: lat - rng.range(0.0, 0.005),
+            max_lon: lon + rng.range(0.0, 0.005),
+            max_lat: lat + rng.range(0.0, 0.005),
+        };
+
+        let mut clipped = lines.clone();
+        let boundary_count = clip_to_bbox(&mut clipped, &bbox);
+        let inside_count = lines.iter().flatten().filter(|p| bbox.contains(p)).count();
+        assert_eq!(point_count(&clipped), inside_count + boundary_count, "case {}", case);
+        assert!(clipped.iter().all(|l| !l.is_empty()), "case {}: empty line", case);
+
+        let epsilon = 1e-9;
+        for point in clipped.iter().flatten() {
+            assert!(
+                point.longitude() >= bbox.min_lon - epsilon && point.longitude() <= bbox.max_lon + epsilon
+                    && point.latitude() >= bbox.min_lat - epsilon && point.latitude() <= bbox.max_lat + epsilon,
+                "case {}: point {:?} outside of {}", case, point.coordinates_deg, bbox,
+            );
+            if let (Some(t), Some(ts)) = (point.unix_timestamp, point.timestamp) {
+                assert!((ts.timestamp_millis() as f64 / 1000.0 - t).abs() < 1e-3, "case {}: inconsistent timestamps", case);
+            }
+        }
+
+        // the original points within the box are kept in order
+        let kept: Vec<&Point> = clipped.iter().flatten().filter(|p| lines.iter().flatten().any(|o| o == *p)).collect();
+        let inside: Vec<&Point> = lines.iter().flatten().filter(|p| bbox.contains(p)).collect();
+        assert_eq!(kept, inside, "case {}", case);
+    }
+}
+
+#[test]
+fn downsampling_keeps_the_ends_and_a_single_peak() {
+    let mut rng = Rng::new(7);
+    for case in 0..CASES {
+        let count = rng.range(3.0, 1000.0) as usize;
+        let target_count = rng.range(3.0, 100.0) as usize;
+        let peak = rng.range(0.0, count as f64) as usize;
+        let data: Vec<(f64, f64)> = (0..count)
+            .map(|i| (i as f64, if i == peak { 100.0 } else { rng.range(0.0, 1.0) }))
+            .collect();
+
+        let indexes = lttb_indexes(&data, target_count);
+        assert_eq!(indexes.len(), count.min(target_count), "case {}", case);
+        assert_eq!(indexes.first(), Some(&0), "case {}", case);
+        assert_eq!(indexes.last(), Some(&(count - 1)), "case {}", case);
+        assert!(indexes.windows(2).all(|w| w[0] < w[1]), "case {}: indexes out of order", case);
+        assert!(indexes.contains(&peak), "case {}: peak at {} dropped", case, peak);
+    }
+}
@@ -0,0 +1,181 @@
+//! Round-trip tests: activities written in one of the output formats that can also be read are
+//! read back and compared to the original.
+
+
+mod common;
+
+
+use std::fs;
+
+use fit2walking::{Activity, GeoPoint, Point};
+use fit2walking::censor::{CensorArea, CensorCircle};
+use fit2walking::fitcensor::{censor_fit, FitCensorMode};
+use fit2walking::geojson::{activity_to_document, DocumentOptions, PointsGeometry};
+use fit2walking::input::{read_activities, read_activity, ReadOptions};
+use fit2walking::input::fit::read_fit;
+use fit2walking::input::geojson::read_geojson;
+
+use common::{fixture_path, run_fit2walking};
+
+
+fn read_fixture(name: &str) -> Activity {
+    read_activity(&fixture_path(name), &ReadOptions::default())
+        .unwrap_or_else(|e| panic!("failed to read fixture {}: {}", name, e))
+}
+
+
+fn points(activity: &Activity) -> Vec<&Point> {
+    activity.lines.iter()
+        .flatten()
+        .collect()
+}
+
+
+#[test]
+fn geojson_points_read_back() {
+    let activity = read_fixture("walk.fit");
+    let options = DocumentOptions {
+        points_geometry: PointsGeometry::Point,
+        ..DocumentOptions::default()
+    };
+    let document = activity_to_document(&activity, &options).unwrap();
+    let read_back = read_geojson(&document["points"].to_string()).unwrap();
+
+    let original_points = points(&activity);
+    let read_back_points = points(&read_back);
+    assert_eq!(read_back_points.len(), original_points.len());
+    for (original, read) in original_points.iter().zip(read_back_points.iter()) {
+        assert!((original.longitude() - read.longitude()).abs() < 1e-9);
+        assert!((original.latitude() - read.latitude()).abs() < 1e-9);
+        assert_eq!(read.timestamp, original.timestamp);
+        assert_eq!(read.heart_rate_bpm, original.heart_rate_bpm);
+        assert_eq!(read.cadence_rpm, original.cadence_rpm);
+        assert_eq!(read.temperature_degc, original.temperature_degc);
+        assert_eq!(read.power_w, original.power_w);
+        match (original.elevation_m, read.elevation_m) {
+            (Some(e1), Some(e2)) => assert!((e1 - e2).abs() < 1e-6),
+            (e1, e2) => assert_eq!(e1, e2),
+        }
+    }
+}
+
+
+#[test]
+fn geojson_3d_track_read_back() {
+    let activity = read_fixture("walk.fit");
+    let options = DocumentOptions {
+        coords_3d: true,
+        ..DocumentOptions::default()
+    };
+    let document = activity_to_document(&activity, &options).unwrap();
+    assert_eq!(document["track"]["bbox"].as_array().unwrap().len(), 6);
+    // the track has no properties, so the elevations can only come from the coordinates
+    let read_back = read_geojson(&document["track"].to_string()).unwrap();
+
+    let original_points = points(&activity);
+    let read_back_points = points(&read_back);
+    assert_eq!(read_back_points.len(), original_points.len());
+    assert!(original_points.iter().any(|p| p.elevation_m.is_some()));
+    for (original, read) in original_points.iter().zip(read_back_points.iter()) {
+        match (original.elevation_m, read.elevation_m) {
+            (Some(e1), Some(e2)) => assert!((e1 - e2).abs() < 1e-6),
+            (e1, e2) => assert_eq!(e1, e2),
+        }
+    }
+}
+
+
+#[test]
+fn censored_fit_read_back() {
+    let data = fs::read(fixture_path("walk.fit")).unwrap();
+    let original = read_fit(&mut &data[..], &ReadOptions::default()).unwrap();
+    let circle: CensorCircle = "48.2088,16.3748,40".parse().unwrap();
+    let areas = [CensorArea::Circle(circle)];
+
+    for mode in [FitCensorMode::Remove, FitCensorMode::Blank] {
+        let (censored, counts) = censor_fit(&data, &areas, mode).unwrap();
+        let read_back = read_fit(&mut &censored[..], &ReadOptions::default()).unwrap();
+        assert_eq!(read_back.len(), original.len());
+
+        let expected: Vec<&Point> = points(&original[0]).into_iter()
+            .filter(|p| !circle.contains(&p.coordinates_deg))
+            .collect();
+        let actual = points(&read_back[0]);
+        assert!(counts.records_censored > 0);
+        assert_eq!(actual, expected, "censoring in {:?} mode", mode);
+
+        // the other messages are preserved
+        assert_eq!(read_back[0].laps, original[0].laps);
+        assert_eq!(read_back[0].sessions, original[0].sessions);
+        assert_eq!(read_back[0].metadata, original[0].metadata);
+    }
+}
+
+
+#[test]
+fn censored_fit_saved_locations() {
+    // the saved locations "Home" (within the circle) and "Cafe" (outside it)
+    let data = fs::read(fixture_path("locations.fit")).unwrap();
+    let circle: CensorCircle = "48.2088,16.3748,40".parse().unwrap();
+    let areas = [CensorArea::Circle(circle)];
+
+    // location messages are not known to fitparser, so their fields are only numbered
+    let locations = |fit_data: &[u8]| -> Vec<(String, bool)> {
+        fitparser::from_bytes(fit_data).unwrap().iter()
+            .filter(|r| r.kind().to_string() == "29")
+            .map(|r| {
+                let field = |name: &str| r.fields().iter().find(|f| f.name() == name).map(|f| f.value().clone());
+                let name = match field("unknown_field_0") {
+                    Some(fitparser::Value::String(s)) => s,
+                    other => panic!("unexpected name {:?}", other),
+                };
+                let has_position = matches!(field("unknown_field_1"), Some(fitparser::Value::SInt32(_)));
+                (name, has_position)
+            })
+            .collect()
+    };
+    assert_eq!(locations(&data), [("Home".to_owned(), true), ("Cafe".to_owned(), true)]);
+
+    let (removed, _) = censor_fit(&data, &areas, FitCensorMode::Remove).unwrap();
+    assert_eq!(locations(&removed), [("Cafe".to_owned(), true)]);
+    let (blanked, _) = censor_fit(&data, &areas, FitCensorMode::Blank).unwrap();
+    assert_eq!(locations(&blanked), [("Home".to_owned(), false), ("Cafe".to_owned(), true)]);
+}
+
+
+#[test]
+fn options_removing_positions_are_rejected_for_fit_output() {
+    let options: [&[&str]; 6] = [
+        &["--trim-start", "200"],
+        &["--trim-end", "200"],
+        &["--clip-bbox", "16.37,48.20,16.38,48.21"],
+        &["--start-time", "22:14"],
+        &["--end-time", "22:14"],
+        &["--trim-start", "200", "--trim-end", "200"],
+    ];
+    for option in options {
+        let mut args = vec!["convert", "--no-config", "--output-format", "fit"];
+        args.extend_from_slice(option);
+        args.push("fixtures/walk.fit");
+        let output = run_fit2walking(&args);
+        assert!(!output.status.success(), "{:?} was accepted", option);
+        assert!(output.stdout.is_empty(), "{:?} produced output", option);
+    }
+}
+
+
+#[test]
+fn chained_fit_activities() {
+    let walk = read_fixture("walk.fit");
+    let activities = read_activities(&fixture_path("chained.fit"), &ReadOptions::default()).unwrap();
+    assert_eq!(activities.len(), 2);
+    for activity in &activities {
+        assert_eq!(activity.point_count(), walk.point_count());
+        assert_eq!(activity.sessions.len(), 1);
+    }
+
+    // reading the file as one activity merges them
+    let merged = read_fixture("chained.fit");
+    assert_eq!(merged.point_count(), 2 * walk.point_count());
+    assert_eq!(merged.lines.len(), activities.iter().map(|a| a.lines.len()).sum::<usize>());
+}
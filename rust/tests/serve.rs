@@ -0,0 +1,121 @@
+//! Tests of serving the activities in a directory over HTTP.
+
+
+mod common;
+
+
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use fit2walking::geojson::{DocumentOptions, write_activity_document};
+use fit2walking::http::{self, Request, Response, ServerOptions};
+use fit2walking::input::{read_activity, ReadOptions};
+use fit2walking::serve::{Catalog, Converted};
+use fit2walking::summary::{summarize_activity, summary_to_json, SummaryOptions};
+
+use common::tests_dir;
+
+
+fn get(catalog: &Catalog, path: &str) -> (&'static str, Vec<u8>) {
+    let head = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    let request = Request::read(&mut BufReader::new(head.as_bytes())).unwrap();
+    let response = catalog.handle(&request);
+    (response.status, response.body)
+}
+
+/// Starts a server in the background answering every request with an empty JSON object, and
+/// returns its address.
+fn start_server(options: ServerOptions) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || http::serve(listener, options, |_| Response::json("200 OK", &serde_json::json!({}))));
+    address
+}
+
+/// Sends a request over the connection and returns the head of the response.
+fn request_head(mut stream: &TcpStream) -> std::io::Result<String> {
+    stream.write_all(b"GET /activities HTTP/1.1\r\nHost: localhost\r\n\r\n")?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response.split("\r\n\r\n").next().unwrap().to_owned())
+}
+
+
+#[test]
+fn activities_are_listed_and_converted_by_id() {
+    let catalog = Catalog::new(
+        vec![tests_dir().join("fixtures")],
+        Box::new(|path| {
+            let activity = read_activity(path, &ReadOptions::default())?;
+            let mut document = Vec::new();
+            write_activity_document(&mut document, &activity, &DocumentOptions::default())?;
+            let summary_options = SummaryOptions::default();
+            let summary = summary_to_json(&summarize_activity(&activity, "test", &summary_options), &summary_options);
+            Ok(Converted { summary, document })
+        }),
+    );
+
+    let (status, body) = get(&catalog, "/activities");
+    assert_eq!(status, "200 OK");
+    let activities: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let walk = activities.as_array().unwrap().iter()
+        .find(|a| a["file"] == "walk.fit")
+        .expect("walk.fit not listed");
+    assert_eq!(walk["sport"], "walking");
+    // the indoor activity cannot be converted into a map document
+    let indoor = activities.as_array().unwrap().iter()
+        .find(|a| a["file"] == "indoor.fit")
+        .expect("indoor.fit not listed");
+    assert!(indoor["error"].is_string());
+
+    let id = walk["id"].as_str().unwrap();
+    let (status, body) = get(&catalog, &format!("/activities/{}/geojson", id));
+    assert_eq!(status, "200 OK");
+    let document: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(document["points"]["type"], "FeatureCollection");
+    let (status, _) = get(&catalog, &format!("/activities/{}/geojson", indoor["id"].as_str().unwrap()));
+    assert_eq!(status, "422 Unprocessable Content");
+
+    assert_eq!(get(&catalog, "/activities/0123/geojson").0, "404 Not Found");
+    assert_eq!(get(&catalog, "/nothing").0, "404 Not Found");
+}
+
+#[test]
+fn cross_origin_reading_is_opt_in() {
+    let address = start_server(ServerOptions::default());
+    let head = request_head(&TcpStream::connect(&address).unwrap()).unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+    assert!(!head.contains("Access-Control-Allow-Origin"), "{}", head);
+
+    let address = start_server(ServerOptions {
+        cors_origin: Some("http://localhost:3000".to_owned()),
+        ..ServerOptions::default()
+    });
+    let head = request_head(&TcpStream::connect(&address).unwrap()).unwrap();
+    assert!(head.contains("\r\nAccess-Control-Allow-Origin: http://localhost:3000\r\n"), "{}", head);
+}
+
+#[test]
+fn connections_beyond_the_maximum_wait() {
+    let address = start_server(ServerOptions {
+        max_connections: 1,
+        ..ServerOptions::default()
+    });
+
+    // occupies the only connection without sending a request
+    let idle = TcpStream::connect(&address).unwrap();
+    // give the server time to accept it
+    std::thread::sleep(Duration::from_millis(100));
+    let waiting = TcpStream::connect(&address).unwrap();
+    waiting.set_read_timeout(Some(Duration::from_millis(300))).unwrap();
+    let e = request_head(&waiting).unwrap_err();
+    assert!(matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut), "{}", e);
+
+    // answered once the idle connection is closed
+    drop(idle);
+    waiting.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut response = String::new();
+    (&waiting).read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+}
@@ -0,0 +1,167 @@
+//! Tests of the time zones in which timestamps are output.
+
+
+use fit2walking::timezone::{nearest_zone_name, Timezone, ZoneInfo};
+
+
+/// An excerpt of `zone1970.tab`.
+const ZONE_TABLE: &str = "\
+# tzdb timezone descriptions
+AT\t+4813+01620\tEurope/Vienna
+CZ,SK\t+5005+01426\tEurope/Prague
+HU\t+4730+01905\tEurope/Budapest
+US\t+404251-0740023\tAmerica/New_York\tEastern (most areas)
+US\t+394421-1045903\tAmerica/Denver\tMountain (most areas)
+";
+
+
+#[test]
+fn zone_of_the_nearest_principal_location_is_found() {
+    // Graz, Pilsen, Boulder
+    assert_eq!(nearest_zone_name(ZONE_TABLE, geo::Point::new(15.44, 47.07)), Some("Europe/Vienna"));
+    assert_eq!(nearest_zone_name(ZONE_TABLE, geo::Point::new(13.38, 49.74)), Some("Europe/Prague"));
+    assert_eq!(nearest_zone_name(ZONE_TABLE, geo::Point::new(-105.27, 40.01)), Some("America/Denver"));
+    assert_eq!(nearest_zone_name("# no zones\n", geo::Point::new(0.0, 0.0)), None);
+}
+
+
+#[test]
+fn auto_is_resolved_from_the_lines() {
+    let auto: Timezone = "auto".parse().unwrap();
+    assert_eq!(auto, Timezone::Auto);
+    assert_eq!(auto.to_string(), "auto");
+    // without points, there is no position to derive the time zone from
+    assert_eq!(auto.for_lines(&[]), Timezone::Utc);
+    assert_eq!(Timezone::Utc.for_lines(&[]), Timezone::Utc);
+}
+
+
+/// Builds a TZif file with the given transitions (Unix timestamp and index of the local time
+/// type), local time types (UTC offset in seconds and abbreviation) and footer rule. Version 1
+/// files have no footer.
+fn tzif(version: u8, transitions: &[(i64, u8)], types: &[(i32, &str)], rule: &str) -> Vec<u8> {
+    let mut abbreviations = Vec::new();
+    let mut type_records = Vec::new();
+    for (utc_offset_s, abbreviation) in types {
+        type_records.extend_from_slice(&utc_offset_s.to_be_bytes());
+        type_records.push(u8::from(*utc_offset_s != types[0].0)); // is DST
+        type_records.push(abbreviations.len() as u8);
+        abbreviations.extend_from_slice(abbreviation.as_bytes());
+        abbreviations.push(0);
+    }
+
+    let block = |time_size: usize| -> Vec<u8> {
+        let mut block = b"TZif".to_vec();
+        block.push(version);
+        block.extend_from_slice(&[0; 15]);
+        // isutcnt, isstdcnt, leapcnt, timecnt, typecnt, charcnt
+        for count in [0, 0, 0, transitions.len(), types.len(), abbreviations.len()] {
+            block.extend_from_slice(&(count as u32).to_be_bytes());
+        }
+        for (time, _) in transitions {
+            if time_size == 8 {
+                block.extend_from_slice(&time.to_be_bytes());
+            } else {
+                block.extend_from_slice(&(*time as i32).to_be_bytes());
+            }
+        }
+        block.extend(transitions.iter().map(|(_, type_index)| *type_index));
+        block.extend_from_slice(&type_records);
+        block.extend_from_slice(&abbreviations);
+        block
+    };
+
+    let mut data = block(4);
+    if version >= b'2' {
+        data.extend(block(8));
+        data.push(b'\n');
+        data.extend_from_slice(rule.as_bytes());
+        data.push(b'\n');
+    }
+    data
+}
+
+/// Central European Time with the transitions of 2024.
+fn vienna(version: u8) -> ZoneInfo {
+    let data = tzif(
+        version,
+        &[(1711846800, 1), (1729990800, 0)],
+        &[(3600, "CET"), (7200, "CEST")],
+        "CET-1CEST,M3.5.0,M10.5.0/3",
+    );
+    ZoneInfo::from_tzif("Europe/Vienna", &data).unwrap()
+}
+
+
+#[test]
+fn offsets_change_at_transitions() {
+    for version in [0, b'2', b'3'] {
+        let zone = vienna(version);
+        assert_eq!(zone.name(), "Europe/Vienna");
+        // 2024-03-31 01:00 UTC: 02:00 CET becomes 03:00 CEST
+        assert_eq!(zone.utc_offset_s(1711846800 - 1), 3600);
+        assert_eq!(zone.utc_offset_s(1711846800), 7200);
+        // 2024-10-27 01:00 UTC: 03:00 CEST becomes 02:00 CET
+        assert_eq!(zone.utc_offset_s(1729990800 - 1), 7200);
+        assert_eq!(zone.utc_offset_s(1729990800), 3600);
+        // before the first transition, the first local time type applies
+        assert_eq!(zone.utc_offset_s(0), 3600);
+    }
+}
+
+#[test]
+fn offsets_past_the_last_transition_follow_the_footer_rule() {
+    let zone = vienna(b'2');
+    // 2030-03-31 01:00 UTC
+    assert_eq!(zone.utc_offset_s(1901149200 - 1), 3600);
+    assert_eq!(zone.utc_offset_s(1901149200), 7200);
+    // 2030-07-01
+    assert_eq!(zone.utc_offset_s(1909094400), 7200);
+    // 2030-10-27 01:00 UTC
+    assert_eq!(zone.utc_offset_s(1919293200 - 1), 7200);
+    assert_eq!(zone.utc_offset_s(1919293200), 3600);
+
+    // without a footer, the last transition keeps applying
+    let zone = vienna(0);
+    assert_eq!(zone.utc_offset_s(1909094400), 3600);
+}
+
+#[test]
+fn offsets_of_quarter_hours() {
+    // Kathmandu: +05:30 until the end of 1985, +05:45 since
+    let data = tzif(b'2', &[(504901800, 1)], &[(19800, "+0530"), (20700, "+0545")], "<+0545>-5:45");
+    let zone = ZoneInfo::from_tzif("Asia/Kathmandu", &data).unwrap();
+    assert_eq!(zone.utc_offset_s(504901800 - 1), 5*3600 + 30*60);
+    assert_eq!(zone.utc_offset_s(504901800), 5*3600 + 45*60);
+    assert_eq!(zone.utc_offset_s(1909094400), 5*3600 + 45*60);
+
+    // a zone without transitions, only a rule
+    let data = tzif(b'2', &[], &[(20700, "+0545")], "<+0545>-5:45");
+    let zone = ZoneInfo::from_tzif("Asia/Kathmandu", &data).unwrap();
+    assert_eq!(zone.utc_offset_s(0), 20700);
+    assert_eq!(zone.utc_offset_s(1909094400), 20700);
+}
+
+#[test]
+fn malformed_tzif_files_are_rejected() {
+    let data = tzif(b'2', &[(1711846800, 1), (1729990800, 0)], &[(3600, "CET"), (7200, "CEST")], "CET-1CEST,M3.5.0,M10.5.0/3");
+    // everything up to the footer is required
+    let footer_start = data.len() - "\nCET-1CEST,M3.5.0,M10.5.0/3\n".len();
+    for length in 0..footer_start {
+        assert!(ZoneInfo::from_tzif("Europe/Vienna", &data[..length]).is_err(), "truncated to {} bytes", length);
+    }
+
+    let mut not_tzif = data.clone();
+    not_tzif[..4].copy_from_slice(b"TZiF");
+    assert!(ZoneInfo::from_tzif("Europe/Vienna", &not_tzif).is_err());
+
+    // a transition to the third of two local time types
+    let data = tzif(b'2', &[(1711846800, 2)], &[(3600, "CET"), (7200, "CEST")], "CET-1CEST,M3.5.0,M10.5.0/3");
+    assert!(ZoneInfo::from_tzif("Europe/Vienna", &data).is_err());
+
+    let data = tzif(b'2', &[], &[], "");
+    assert!(ZoneInfo::from_tzif("Etc/Empty", &data).is_err());
+
+    let data = tzif(b'2', &[], &[(3600, "CET")], "CET-1CEST,M13.5.0,M10.5.0/3");
+    assert!(ZoneInfo::from_tzif("Europe/Vienna", &data).is_err());
+}
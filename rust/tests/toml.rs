@@ -0,0 +1,134 @@
+//! Tests of the TOML parser used for configuration files.
+
+
+use fit2walking::toml::{parse, Table, Value};
+
+
+/// Returns the line and message of the error in the document.
+fn error(text: &str) -> (usize, String) {
+    let e = parse(text).unwrap_err();
+    (e.line, e.message)
+}
+
+fn table(entries: &[(&str, Value)]) -> Value {
+    Value::Table(entries.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_owned())
+}
+
+
+#[test]
+fn strings_with_escapes() {
+    let document = parse(r#"
+basic = "tab\there \"quoted\" back\\slash\nnew line"
+unicode = "\u00e9t\u00E9 \U0001F6B6"
+literal = 'C:\no\escapes "here"'
+empty = ""
+"#).unwrap();
+    assert_eq!(document["basic"], string("tab\there \"quoted\" back\\slash\nnew line"));
+    assert_eq!(document["unicode"], string("été 🚶"));
+    assert_eq!(document["literal"], string(r#"C:\no\escapes "here""#));
+    assert_eq!(document["empty"], string(""));
+}
+
+#[test]
+fn numbers_and_booleans() {
+    let document = parse("
+integer = -42
+grouped = 1_000_000
+float = 3.5
+exponent = 1e-3
+yes = true
+no = false
+").unwrap();
+    assert_eq!(document["integer"], Value::Integer(-42));
+    assert_eq!(document["grouped"], Value::Integer(1_000_000));
+    assert_eq!(document["float"], Value::Float(3.5));
+    assert_eq!(document["exponent"], Value::Float(0.001));
+    assert_eq!(document["yes"], Value::Boolean(true));
+    assert_eq!(document["no"], Value::Boolean(false));
+}
+
+#[test]
+fn arrays() {
+    let document = parse(r#"
+empty = []
+numbers = [1, 2.5, -3]
+nested = [["a", 'b'], []]
+multi_line = [
+    "first",  # comment within the array
+
+    "second",
+]
+"#).unwrap();
+    assert_eq!(document["empty"], Value::Array(vec![]));
+    assert_eq!(document["numbers"], Value::Array(vec![Value::Integer(1), Value::Float(2.5), Value::Integer(-3)]));
+    assert_eq!(
+        document["nested"],
+        Value::Array(vec![Value::Array(vec![string("a"), string("b")]), Value::Array(vec![])]),
+    );
+    assert_eq!(document["multi_line"], Value::Array(vec![string("first"), string("second")]));
+}
+
+#[test]
+fn tables_and_dotted_keys() {
+    let document = parse(r#"
+top = 1
+censor.mode = "split"
+
+[convert]
+units = "imperial"
+output.format = "kml"
+
+[ profiles . "home sweet home" ]
+radius = 150
+
+[convert.extra]
+gzip = true
+"#).unwrap();
+    let expected: Table = [
+        ("top".to_owned(), Value::Integer(1)),
+        ("censor".to_owned(), table(&[("mode", string("split"))])),
+        ("convert".to_owned(), table(&[
+            ("units", string("imperial")),
+            ("output", table(&[("format", string("kml"))])),
+            ("extra", table(&[("gzip", Value::Boolean(true))])),
+        ])),
+        ("profiles".to_owned(), table(&[("home sweet home", table(&[("radius", Value::Integer(150))]))])),
+    ].into_iter().collect();
+    assert_eq!(document, expected);
+    assert_eq!(document["convert"].as_table().unwrap()["units"].as_str(), Some("imperial"));
+}
+
+#[test]
+fn comments_and_line_endings() {
+    let document = parse("# leading comment\r\nkey = \"value # not a comment\" # trailing comment\r\n\t# indented comment\n[table] # comment after a header\nother = 'x'").unwrap();
+    assert_eq!(document["key"], string("value # not a comment"));
+    assert_eq!(document["table"], table(&[("other", string("x"))]));
+    assert_eq!(parse("").unwrap(), Table::new());
+    assert_eq!(parse("# only a comment").unwrap(), Table::new());
+}
+
+#[test]
+fn errors_are_reported_at_their_line() {
+    assert_eq!(error("a = 1\nb = \"unterminated\nc = 3\n"), (2, "unterminated string".to_owned()));
+    assert_eq!(error("a = 1\nb = 'unterminated"), (2, "unterminated string".to_owned()));
+    assert_eq!(error("a = \"\\x\""), (1, "invalid escape \\x".to_owned()));
+    assert_eq!(error("\n\na = \"\\uD800\""), (3, "invalid Unicode escape \\uD800".to_owned()));
+    assert_eq!(error("a = 1\nkey\nb = 2\n"), (2, "expected '=', found '\\n'".to_owned()));
+    assert_eq!(error("a = 1\nb = \n"), (2, "expected a value".to_owned()));
+    assert_eq!(error("a = 1 2\n"), (1, "expected end of line, found '2'".to_owned()));
+    assert_eq!(error("a = 1\nb = yes\n"), (2, "invalid value \"yes\"".to_owned()));
+    assert_eq!(error("a = [1, 2\nb = 3\n"), (2, "expected ',' or ']' in array, found 'b'".to_owned()));
+    assert_eq!(error("a = [\n1\n"), (3, "unterminated array".to_owned()));
+    assert_eq!(error("a = { b = 1 }"), (1, "inline tables are not supported".to_owned()));
+    assert_eq!(error("\n[table\nkey = 1\n"), (2, "expected ']', found '\\n'".to_owned()));
+    assert_eq!(error("[[tables]]\n"), (1, "arrays of tables are not supported".to_owned()));
+    assert_eq!(error("= 1\n"), (1, "expected a key".to_owned()));
+    assert_eq!(error("a = 1\n\na = 2\n"), (3, "duplicate key \"a\"".to_owned()));
+    assert_eq!(error("a = 1\n[a]\n"), (2, "\"a\" is an integer, not a table".to_owned()));
+    assert_eq!(error("a = 1\na.b = 2\n"), (2, "\"a\" is an integer, not a table".to_owned()));
+    assert_eq!(parse("a = 1\n\na = 2\n").unwrap_err().to_string(), "line 3: duplicate key \"a\"");
+}
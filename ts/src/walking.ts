@@ -8,7 +8,11 @@ export module Walking {
     const LINE_OPACITY: number = 1;
     const LINE_WIDTH: number = 8;
 
+    // the newest version of the document format that is understood
+    const FORMAT_VERSION: number = 1;
+
     interface WalkingData {
+        format_version?: number,
         center?: [number, number],
         zoom?: number,
         track?: geojson.FeatureCollection,
@@ -18,16 +22,37 @@ export module Walking {
         speed_range?: [number, number],
         cadence_range?: [number, number],
         temperature_range?: [number, number],
+        units?: WalkingDataUnits,
+    };
+
+    interface WalkingDataUnits {
+        distance: string,
+        elevation: string,
+        speed: string,
+        temperature: string,
     };
 
+    // documents without a units block are metric
+    const METRIC_UNITS: WalkingDataUnits = {
+        distance: "m",
+        elevation: "m",
+        speed: "km/h",
+        temperature: "\u00B0C",
+    };
+
+    // with --null-missing, properties without a value are null instead of missing
     interface WalkingDataFeatureProperties {
-        speed?: number,
-        heart_rate?: number,
-        elevation?: number,
-        running_distance?: number,
-        cadence?: number,
-        temperature?: number,
-        timestamp?: string,
+        speed?: number|null,
+        heart_rate?: number|null,
+        elevation?: number|null,
+        running_distance?: number|null,
+        segment_length?: number|null,
+        segment_duration?: number|null,
+        // precomputed by --color-by
+        color?: string|null,
+        cadence?: number|null,
+        temperature?: number|null,
+        timestamp?: string|null,
     };
 
     type ColorTriplet = [number, number, number];
@@ -66,6 +91,9 @@ export module Walking {
     function mapDownloaded(xhr: XMLHttpRequest): void {
         // store downloaded map
         data = JSON.parse(xhr.responseText);
+        if (data.format_version !== undefined && data.format_version > FORMAT_VERSION) {
+            console.warn(`map has format version ${data.format_version}; only up to ${FORMAT_VERSION} is supported`);
+        }
 
         // set up Leaflet
         let baseLayers = obtainBaseLayers();
@@ -75,10 +103,13 @@ export module Walking {
         let speedLayer = obtainSpeedLayer();
         let cadenceLayer = obtainCadenceLayer();
         let temperatureLayer = obtainTemperatureLayer();
+        let colorLayer = obtainColorLayer();
 
         // take the first layer in the base layer list by default
         let layers: leaflet.Layer[] = [baseLayers[0][1]];
-        if (heartRateLayer !== null) {
+        if (colorLayer !== null) {
+            layers.push(colorLayer);
+        } else if (heartRateLayer !== null) {
             layers.push(heartRateLayer);
         } else {
             layers.push(trackLayer);
@@ -96,6 +127,9 @@ export module Walking {
         let overlayMaps: any = {
             "track": trackLayer,
         };
+        if (colorLayer !== null) {
+            overlayMaps["colored"] = colorLayer;
+        }
         if (heartRateLayer !== null) {
             overlayMaps["heart rate"] = heartRateLayer;
         }
@@ -120,8 +154,8 @@ export module Walking {
         ];
     }
 
-    function mixColor(value: number|undefined, minVal: number, maxVal: number, bottomColor: ColorTriplet, midColor: ColorTriplet, topColor: ColorTriplet): ColorTriplet|undefined {
-        if (value === undefined) {
+    function mixColor(value: number|null|undefined, minVal: number, maxVal: number, bottomColor: ColorTriplet, midColor: ColorTriplet, topColor: ColorTriplet): ColorTriplet|undefined {
+        if (value == null) {
             return undefined;
         }
 
@@ -145,7 +179,7 @@ export module Walking {
     }
 
     // green-white-red
-    function mixColorGWR(value: number|undefined, minVal: number, maxVal: number): ColorTriplet|undefined {
+    function mixColorGWR(value: number|null|undefined, minVal: number, maxVal: number): ColorTriplet|undefined {
         let bottomColor: ColorTriplet = [0.0, 1.0, 0.0];
         let midColor: ColorTriplet = [1.0, 1.0, 1.0];
         let topColor: ColorTriplet = [1.0, 0.0, 0.0];
@@ -153,7 +187,7 @@ export module Walking {
     }
 
     // blue-white-red
-    function mixColorBWR(value: number|undefined, minVal: number, maxVal: number): ColorTriplet|undefined {
+    function mixColorBWR(value: number|null|undefined, minVal: number, maxVal: number): ColorTriplet|undefined {
         let bottomColor: ColorTriplet = [0.0, 0.0, 1.0];
         let midColor: ColorTriplet = [1.0, 1.0, 1.0];
         let topColor: ColorTriplet = [1.0, 0.0, 0.0];
@@ -161,7 +195,7 @@ export module Walking {
     }
 
     // brown-white
-    function mixColorBW(value: number|undefined, minVal: number, maxVal: number): ColorTriplet|undefined {
+    function mixColorBW(value: number|null|undefined, minVal: number, maxVal: number): ColorTriplet|undefined {
         let bottomColor: ColorTriplet = [0.4, 0.2, 0.0];
         let midColor: ColorTriplet = [0.7, 0.6, 0.5];
         let topColor: ColorTriplet = [1.0, 1.0, 1.0];
@@ -184,31 +218,39 @@ export module Walking {
         return "#" + hexTuple.join("");
     }
 
+    function formatDistance(distance: number, units: WalkingDataUnits): string {
+        if (units.distance === "m") {
+            return `${(distance/1000).toFixed(3)} km`;
+        }
+        return `${distance.toFixed(3)} ${units.distance}`;
+    }
+
     function popup(feature: geojson.Feature, layer: leaflet.Layer) {
         let props = <WalkingDataFeatureProperties|null>feature.properties;
         if (props === null) {
             return;
         }
+        let units = data.units || METRIC_UNITS;
         let popupText = "";
-        if (props.speed !== undefined) {
-            popupText += `<p>${props.speed.toFixed(1)} km/h</p>`;
+        if (props.speed != null) {
+            popupText += `<p>${props.speed.toFixed(1)} ${units.speed}</p>`;
         }
-        if (props.heart_rate !== undefined) {
+        if (props.heart_rate != null) {
             popupText += `<p>${props.heart_rate} BPM</p>`;
         }
-        if (props.elevation !== undefined) {
-            popupText += `<p>${props.elevation.toFixed(1)} m ASL</p>`;
+        if (props.elevation != null) {
+            popupText += `<p>${props.elevation.toFixed(1)} ${units.elevation} ASL</p>`;
         }
-        if (props.running_distance !== undefined) {
-            popupText += `<p>${(props.running_distance/1000).toFixed(3)} km distance from beginning</p>`;
+        if (props.running_distance != null) {
+            popupText += `<p>${formatDistance(props.running_distance, units)} distance from beginning</p>`;
         }
-        if (props.cadence !== undefined) {
+        if (props.cadence != null) {
             popupText += `<p>${props.cadence} RPM cadence</p>`;
         }
-        if (props.temperature !== undefined) {
-            popupText += `<p>${props.temperature} \u00B0C</p>`;
+        if (props.temperature != null) {
+            popupText += `<p>${props.temperature} ${units.temperature}</p>`;
         }
-        if (props.timestamp !== undefined) {
+        if (props.timestamp != null) {
             popupText += `<p>${props.timestamp}</p>`;
         }
         layer.bindPopup(popupText);
@@ -264,7 +306,7 @@ export module Walking {
                 continue;
             }
 
-            if (valueFunc(feature.properties) !== undefined) {
+            if (valueFunc(feature.properties) != null) {
                 haveValue = true;
                 break;
             }
@@ -337,6 +379,22 @@ export module Walking {
             onEachFeature: popup,
         });
     }
+
+    function obtainColorLayer(): leaflet.GeoJSON<any>|null {
+        if (!haveLayer(props => props.color)) {
+            return null;
+        }
+
+        return leaflet.geoJSON(data.points, {
+            style: styleFunc(props => ({
+                // like the other layers, segments without a value are black
+                color: props.color ?? "#000000",
+                opacity: LINE_OPACITY,
+                weight: LINE_WIDTH,
+            })),
+            onEachFeature: popup,
+        });
+    }
 }
 
 document.addEventListener("DOMContentLoaded", () => Walking.initializeMap());